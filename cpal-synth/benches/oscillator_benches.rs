@@ -27,7 +27,7 @@ pub fn oscillator_benchmark(c: &mut Criterion) {
     ] {
         for &freq in &frequencies {
             group.bench_function(format!("basic_{:?}_{:.0}Hz", osc_type, freq), |b| {
-                let mut osc = Oscillator::new(osc_type);
+                let mut osc = Oscillator::new(osc_type).unwrap();
                 osc.frequency().set_value(freq);
                 let mut sample: u64 = 0;
                 b.iter(|| {
@@ -3,28 +3,107 @@
 // First, make the synth module public
 pub mod synth {
     // Re-export public types from each module
+    pub use self::additive::{AdditiveOscillator, Partial, MAX_PARTIALS};
+    pub use self::analyser::{AnalyserNode, AnalyserWindow, MAX_FFT_SIZE, MIN_FFT_SIZE};
+    pub use self::audio_buffer::AudioBuffer;
     pub use self::audio_context::AudioContext;
+    pub use self::audio_decoder::{decode_audio_data, decode_audio_file, AudioFileFormat};
     pub use self::audio_graph::AudioGraph;
     pub use self::audio_node::AudioNode; // Make the trait public
     pub use self::audio_param::AudioParam;
+    #[cfg(feature = "cpal-output")]
+    pub use self::backend::CpalBackend;
+    pub use self::backend::{
+        AudioBackend, AudioRenderer, BackendConfig, NullBackend, PullBackend, SampleFormat,
+    };
     pub use self::bandlimited_wavetableoscillator::{
         initialize_wave_banks, BandlimitedWavetableOscillator,
     };
+    pub use self::buffer_source::BufferSourceNode;
+    #[cfg(feature = "cpal-output")]
+    pub use self::devices::{
+        available_hosts, input_devices, output_devices, DeviceInfo, HostInfo, SupportedConfig,
+    };
+    pub use self::dither::{Dither, NoiseShaping, Quantizer};
+    pub use self::fm::{FmAlgorithm, FmOperator, FmVoice, OperatorMode};
+    pub use self::granular::{GrainWindow, GranularNode};
+    pub use self::hard_sync::SyncOut;
+    #[cfg(feature = "cpal-output")]
+    pub use self::live_input::CpalInput;
+    pub use self::live_input::{
+        AudioInput, BufferInput, InputWriter, LiveInputNode, LiveInputStatus,
+    };
+    pub use self::meter::{integrated_loudness, Meter, MeterNode};
+    pub use self::modal::{ModalResonator, Mode, ModeTable, MAX_MODES};
     pub use self::oscillator::{Oscillator, OscillatorType};
+    pub use self::periodic_wave::{
+        load_wavetable, load_wavetable_file, PeriodicWave, WAVETABLE_FRAME_SIZE,
+    };
     pub use self::processor::AudioProcessor;
+    pub use self::resampler::{resample, resample_buffer};
+    pub use self::stats::{EngineStats, NodeStats, PerformanceMonitor};
+    pub use self::step_sequencer::{ParameterLock, Pattern, Step, StepSequencer};
+    pub use self::streaming_player::{StreamStatus, StreamingPlayerNode};
+    pub use self::string_model::{Excitation, StringNode};
+    pub use self::unison::{DetuneCurve, UnisonOscillator, MAX_UNISON_VOICES};
+    pub use self::wav::{encode_wav, write_wav_file, WavSampleFormat};
+    pub use self::wave_bank::WaveBankRegistry;
 
     // Declare the modules
+    pub mod additive;
+    pub mod analyser;
+    pub mod audio_buffer;
     pub mod audio_context; // Make this public if needed
+    pub mod audio_decoder;
     pub mod audio_graph;
     pub mod audio_node; // Make this public
     pub mod audio_param;
+    pub mod backend;
     pub mod bandlimited_wavetableoscillator;
+    pub mod buffer_source;
+    #[cfg(feature = "cpal-output")]
+    pub mod devices;
+    pub mod dither;
+    mod event_queue;
+    pub mod fm;
+    pub mod granular;
+    mod hard_sync;
+    mod interpolation;
+    pub mod live_input;
+    pub mod meter;
+    pub mod modal;
     pub mod oscillator;
+    pub mod periodic_wave;
     pub mod processor;
+    mod random;
+    pub mod resampler;
+    mod ring_buffer;
+    pub mod stats;
+    pub mod step_sequencer;
+    pub mod streaming_player;
+    pub mod string_model;
+    pub mod unison;
+    pub mod wav;
+    pub mod wave_bank;
 }
 
 // Re-export everything at the crate root level
+#[cfg(feature = "cpal-output")]
+pub use synth::{
+    available_hosts, input_devices, output_devices, CpalBackend, CpalInput, DeviceInfo, HostInfo,
+    SupportedConfig,
+};
 pub use synth::{
-    initialize_wave_banks, AudioContext, AudioGraph, AudioNode, AudioParam, AudioProcessor,
-    BandlimitedWavetableOscillator, Oscillator, OscillatorType,
+    decode_audio_data, decode_audio_file, encode_wav, initialize_wave_banks, integrated_loudness,
+    load_wavetable, load_wavetable_file, resample, resample_buffer, write_wav_file,
+    AdditiveOscillator, AnalyserNode, AnalyserWindow, AudioBackend, AudioBuffer, AudioContext,
+    AudioFileFormat, AudioGraph, AudioInput, AudioNode, AudioParam, AudioProcessor, AudioRenderer,
+    BackendConfig, BandlimitedWavetableOscillator, BufferInput, BufferSourceNode, DetuneCurve,
+    Dither, EngineStats, Excitation, FmAlgorithm, FmOperator, FmVoice, GrainWindow, GranularNode,
+    InputWriter, LiveInputNode, LiveInputStatus, Meter, MeterNode, ModalResonator, Mode, ModeTable,
+    NodeStats, NoiseShaping, NullBackend, OperatorMode, Oscillator, OscillatorType, ParameterLock,
+    Partial, Pattern, PerformanceMonitor, PeriodicWave, PullBackend, Quantizer, SampleFormat, Step,
+    StepSequencer, StreamStatus, StreamingPlayerNode, StringNode, SyncOut, UnisonOscillator,
+    WavSampleFormat, WaveBankRegistry, MAX_FFT_SIZE, MAX_MODES, MAX_PARTIALS, MAX_UNISON_VOICES,
+    MIN_FFT_SIZE, WAVETABLE_FRAME_SIZE,
 };
@@ -0,0 +1,341 @@
+// src/synth/additive.rs
+
+use crate::synth::audio_context::AudioContext;
+use crate::synth::audio_node::AudioNode;
+use crate::synth::audio_param::AudioParam;
+use lazy_static::lazy_static;
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+pub const MAX_PARTIALS: usize = 512;
+
+// Inverse FFT synthesis (Rodet & Depalle): each partial is drawn into a spectrum
+// as the main lobe of a Blackman-Harris window, the frame is transformed back,
+// and the centre half of it is reshaped from the window to a triangle so that
+// frames a hop apart overlap-add to a steady sinusoid.
+const FRAME_SIZE: usize = 1024;
+const HOP: usize = FRAME_SIZE / 4;
+
+// Half-width of the window's main lobe, in bins
+const LOBE: i64 = 4;
+// Kernel points per bin
+const KERNEL_STEPS: usize = 256;
+
+// Four-term Blackman-Harris, sidelobes at -92dB
+const WINDOW: [f64; 4] = [0.35875, 0.48829, 0.14128, 0.01168];
+
+lazy_static! {
+    // Spectrum of the zero-centred window at offsets of -LOBE to LOBE bins
+    static ref KERNEL: Vec<Complex<f32>> = {
+        let points = 2 * LOBE as usize * KERNEL_STEPS + 1;
+        (0..points)
+            .map(|i| {
+                let offset = i as f64 / KERNEL_STEPS as f64 - LOBE as f64;
+                let sum = (0..FRAME_SIZE).fold(Complex::new(0.0f64, 0.0), |sum, n| {
+                    let n = n as f64 - (FRAME_SIZE / 2) as f64;
+                    let angle = -2.0 * PI * offset * n / FRAME_SIZE as f64;
+                    sum + Complex::from_polar(window(n), angle)
+                });
+                Complex::new(sum.re as f32, sum.im as f32)
+            })
+            .collect()
+    };
+
+    // Triangle over window for the centre of the frame, from -HOP to HOP, with
+    // the inverse FFT's 1/N folded in
+    static ref SYNTHESIS_GAIN: Vec<f32> = (0..2 * HOP)
+        .map(|i| {
+            let n = i as f64 - HOP as f64;
+            let triangle = 1.0 - n.abs() / HOP as f64;
+            (triangle / window(n) / FRAME_SIZE as f64) as f32
+        })
+        .collect();
+}
+
+// Blackman-Harris window centred on 0
+fn window(n: f64) -> f64 {
+    let x = 2.0 * PI * n / FRAME_SIZE as f64;
+    WINDOW[0] + WINDOW[1] * x.cos() + WINDOW[2] * (2.0 * x).cos() + WINDOW[3] * (3.0 * x).cos()
+}
+
+#[inline]
+fn kernel(offset: f32) -> Complex<f32> {
+    let position = (offset + LOBE as f32) * KERNEL_STEPS as f32;
+    let index = (position as usize).min(KERNEL.len() - 2);
+    let frac = position - index as f32;
+    KERNEL[index] + (KERNEL[index + 1] - KERNEL[index]) * frac
+}
+
+/// One sine partial: `amplitude * sin(2π * ratio * frequency * t + phase)`, with
+/// `phase` in cycles.
+pub struct Partial {
+    amplitude: AudioParam,
+    ratio: AudioParam,
+    phase: AudioParam,
+}
+
+impl Partial {
+    fn new(ratio: f32) -> Self {
+        Self {
+            amplitude: AudioParam::new(0.0, 0.0, 1.0),
+            ratio: AudioParam::new(ratio, 0.0, 1024.0),
+            phase: AudioParam::new(0.0, 0.0, 1.0),
+        }
+    }
+
+    pub fn amplitude(&self) -> &AudioParam {
+        &self.amplitude
+    }
+
+    pub fn ratio(&self) -> &AudioParam {
+        &self.ratio
+    }
+
+    pub fn phase(&self) -> &AudioParam {
+        &self.phase
+    }
+}
+
+impl Clone for Partial {
+    fn clone(&self) -> Self {
+        Self {
+            amplitude: self.amplitude.clone(),
+            ratio: self.ratio.clone(),
+            phase: self.phase.clone(),
+        }
+    }
+}
+
+/// Additive synthesis of up to `MAX_PARTIALS` sine partials.
+///
+/// Partials start as harmonics (ratio n + 1 for partial n) at zero amplitude, and
+/// any at or above Nyquist are left out. Output is rendered a block at a time by
+/// inverse FFT, so a frame costs about the same however many partials sound.
+/// Parameters are read once per block of 256 samples and changes crossfade in
+/// over a block.
+pub struct AdditiveOscillator {
+    frequency: AudioParam,
+    gain: AudioParam,
+    partials: Vec<Partial>,
+    // Running phase of each partial at the centre of the next frame, in cycles
+    phases: Vec<f64>,
+    ifft: Arc<dyn Fft<f32>>,
+    spectrum: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    // Finished samples of the current block, and the first half of the next
+    block: Vec<f32>,
+    pending: Vec<f32>,
+    block_position: usize,
+    started: bool,
+}
+
+impl AdditiveOscillator {
+    pub fn new(partials: usize) -> Self {
+        // Build the shared tables here rather than on the audio thread
+        lazy_static::initialize(&KERNEL);
+        lazy_static::initialize(&SYNTHESIS_GAIN);
+        let ifft = FftPlanner::new().plan_fft_inverse(FRAME_SIZE);
+        let scratch = vec![Complex::new(0.0, 0.0); ifft.get_inplace_scratch_len()];
+        let partials = partials.min(MAX_PARTIALS);
+        Self {
+            frequency: AudioParam::new(440.0, 0.01, 22050.0),
+            gain: AudioParam::new(1.0, 0.0, 1.0),
+            partials: (0..partials)
+                .map(|n| Partial::new((n + 1) as f32))
+                .collect(),
+            phases: vec![0.0; partials],
+            ifft,
+            spectrum: vec![Complex::new(0.0, 0.0); FRAME_SIZE],
+            scratch,
+            block: vec![0.0; HOP],
+            pending: vec![0.0; HOP],
+            block_position: HOP,
+            started: false,
+        }
+    }
+
+    pub fn frequency(&self) -> &AudioParam {
+        &self.frequency
+    }
+
+    pub fn gain(&self) -> &AudioParam {
+        &self.gain
+    }
+
+    pub fn partial(&self, index: usize) -> &Partial {
+        &self.partials[index]
+    }
+
+    pub fn partial_count(&self) -> usize {
+        self.partials.len()
+    }
+
+    // Adds harmonic partials or drops partials from the top
+    pub fn set_partial_count(&mut self, partials: usize) {
+        let partials = partials.min(MAX_PARTIALS);
+        while self.partials.len() < partials {
+            self.partials
+                .push(Partial::new((self.partials.len() + 1) as f32));
+        }
+        self.partials.truncate(partials);
+        self.phases.resize(partials, 0.0);
+    }
+
+    // Sets the amplitudes of the first partials, leaving the rest as they are
+    pub fn set_amplitudes(&self, amplitudes: &[f32]) {
+        for (partial, &amplitude) in self.partials.iter().zip(amplitudes) {
+            partial.amplitude.set_value(amplitude);
+        }
+    }
+
+    // Restarts every partial at its phase offset
+    pub fn reset(&mut self) {
+        self.phases.iter_mut().for_each(|phase| *phase = 0.0);
+        self.pending.iter_mut().for_each(|sample| *sample = 0.0);
+        self.block_position = HOP;
+        self.started = false;
+    }
+
+    // Splits "partial<n>.<name>" into the partial and its parameter
+    fn partial_parameter(&self, name: &str) -> Option<&AudioParam> {
+        let (partial, param) = name.strip_prefix("partial")?.split_once('.')?;
+        let partial = self.partials.get(partial.parse::<usize>().ok()?)?;
+        match param {
+            "amplitude" => Some(&partial.amplitude),
+            "ratio" => Some(&partial.ratio),
+            "phase" => Some(&partial.phase),
+            _ => None,
+        }
+    }
+
+    // Renders the frame a hop after the last, finishing the next block
+    fn render_frame(&mut self, sample_rate: f32, current_sample: u64) {
+        self.spectrum
+            .iter_mut()
+            .for_each(|bin| *bin = Complex::new(0.0, 0.0));
+
+        let frequency = self.frequency.get_value(current_sample);
+        let nyquist = sample_rate * 0.5;
+        let bins_per_hz = FRAME_SIZE as f32 / sample_rate;
+        for (partial, phase) in self.partials.iter().zip(self.phases.iter_mut()) {
+            let partial_frequency = frequency * partial.ratio.get_value(current_sample);
+            let amplitude = partial.amplitude.get_value(current_sample);
+            if amplitude != 0.0 && partial_frequency < nyquist {
+                // Half for each of the positive and negative frequencies, and a
+                // quarter cycle back to turn cosine into sine
+                let angle =
+                    2.0 * PI * (*phase + partial.phase.get_value(current_sample) as f64) - PI / 2.0;
+                let weight = Complex::from_polar(amplitude * 0.5, angle as f32);
+
+                let bin = partial_frequency * bins_per_hz;
+                let first = (bin - LOBE as f32).ceil() as i64;
+                let last = (bin + LOBE as f32).floor() as i64;
+                for k in first..=last {
+                    let value = weight * kernel(k as f32 - bin);
+                    self.spectrum[k.rem_euclid(FRAME_SIZE as i64) as usize] += value;
+                    self.spectrum[(-k).rem_euclid(FRAME_SIZE as i64) as usize] += value.conj();
+                }
+            }
+            *phase =
+                (*phase + (partial_frequency as f64 * HOP as f64 / sample_rate as f64)).fract();
+        }
+
+        self.ifft
+            .process_with_scratch(&mut self.spectrum, &mut self.scratch);
+
+        // The frame's first half completes the block the last frame started
+        for (i, gain) in SYNTHESIS_GAIN.iter().enumerate() {
+            let n = (i as i64 - HOP as i64).rem_euclid(FRAME_SIZE as i64) as usize;
+            let sample = self.spectrum[n].re * gain;
+            if i < HOP {
+                self.block[i] = self.pending[i] + sample;
+            } else {
+                self.pending[i - HOP] = sample;
+            }
+        }
+    }
+}
+
+impl AudioNode for AdditiveOscillator {
+    fn process(&mut self, context: &AudioContext, current_sample: u64) -> f32 {
+        if !self.started {
+            // The first frame only contributes to the first block's second half
+            self.render_frame(context.sample_rate(), current_sample);
+            self.started = true;
+        }
+        if self.block_position == HOP {
+            self.render_frame(context.sample_rate(), current_sample);
+            self.block_position = 0;
+        }
+
+        let output = self.block[self.block_position];
+        self.block_position += 1;
+        output * self.gain.get_value(current_sample)
+    }
+
+    // Partial parameters are addressed as "partial<n>.<name>", e.g. "partial0.amplitude"
+    fn set_parameter(&self, name: &str, value: f32) {
+        match name {
+            "frequency" => self.frequency.set_value(value),
+            "gain" => self.gain.set_value(value),
+            _ => {
+                if let Some(param) = self.partial_parameter(name) {
+                    param.set_value(value);
+                }
+            }
+        }
+    }
+
+    fn set_parameter_at(&self, name: &str, value: f32, at_sample: u64) {
+        match name {
+            "frequency" => self.frequency.set_value_at_time(value, at_sample),
+            "gain" => self.gain.set_value_at_time(value, at_sample),
+            _ => {
+                if let Some(param) = self.partial_parameter(name) {
+                    param.set_value_at_time(value, at_sample);
+                }
+            }
+        }
+    }
+
+    fn connect_input(&mut self, _name: &str, _node: Box<dyn AudioNode + Send>) {
+        // Oscillators don't have inputs
+    }
+
+    fn clear_input(&mut self, _input_name: &str) {
+        // No-op for oscillators
+    }
+
+    fn prepare(&mut self, sample_rate: f32, _max_block: usize) {
+        self.frequency.prepare(sample_rate);
+        self.gain.prepare(sample_rate);
+        for partial in &self.partials {
+            partial.amplitude.prepare(sample_rate);
+            partial.ratio.prepare(sample_rate);
+            partial.phase.prepare(sample_rate);
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn AudioNode + Send> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for AdditiveOscillator {
+    fn clone(&self) -> Self {
+        Self {
+            frequency: self.frequency.clone(),
+            gain: self.gain.clone(),
+            partials: self.partials.clone(),
+            phases: self.phases.clone(),
+            ifft: self.ifft.clone(),
+            spectrum: self.spectrum.clone(),
+            scratch: self.scratch.clone(),
+            block: self.block.clone(),
+            pending: self.pending.clone(),
+            block_position: self.block_position,
+            started: self.started,
+        }
+    }
+}
@@ -0,0 +1,287 @@
+// src/synth/analyser.rs
+
+use crate::synth::audio_context::AudioContext;
+use crate::synth::audio_node::AudioNode;
+use crate::synth::ring_buffer::{ring_buffer, RingConsumer, RingProducer};
+use anyhow::anyhow;
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+pub const MIN_FFT_SIZE: usize = 32;
+pub const MAX_FFT_SIZE: usize = 32768;
+
+/// Window applied to each frame before the FFT.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AnalyserWindow {
+    // The Web Audio window: Blackman with alpha 0.16
+    Blackman,
+    BlackmanHarris,
+    Hann,
+    Rectangular,
+}
+
+impl AnalyserWindow {
+    // Window gain at sample `n` of a frame of `size`
+    pub fn gain(&self, n: usize, size: usize) -> f32 {
+        let x = 2.0 * PI * n as f32 / size as f32;
+        match self {
+            AnalyserWindow::Blackman => 0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos(),
+            AnalyserWindow::BlackmanHarris => {
+                0.35875 - 0.48829 * x.cos() + 0.14128 * (2.0 * x).cos() - 0.01168 * (3.0 * x).cos()
+            }
+            AnalyserWindow::Hann => 0.5 - 0.5 * x.cos(),
+            AnalyserWindow::Rectangular => 1.0,
+        }
+    }
+}
+
+/// Passes its "input" through unchanged while recording it for spectrum and
+/// scope displays, following the Web Audio `AnalyserNode`.
+///
+/// The audio thread only writes samples into a lock-free ring buffer. The FFT,
+/// window and smoothing all run on whichever thread calls the `get_*` methods,
+/// which is why they take `&mut self` and why the settings live on the reading
+/// side. Clones share the same recording, so keep one to read from and add
+/// another to the graph; only one of them should be processed. Stereo input is
+/// analysed as the mono mix.
+pub struct AnalyserNode {
+    input: Option<Box<dyn AudioNode + Send>>,
+    producer: RingProducer,
+    consumer: RingConsumer,
+    fft_size: usize,
+    window: AnalyserWindow,
+    smoothing_time_constant: f32,
+    min_decibels: f32,
+    max_decibels: f32,
+    fft: Arc<dyn Fft<f32>>,
+    // Latest frame, its spectrum and the smoothed magnitudes per bin
+    frame: Vec<f32>,
+    spectrum: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    magnitudes: Vec<f32>,
+}
+
+impl AnalyserNode {
+    pub fn new() -> Self {
+        let (producer, consumer) = ring_buffer(MAX_FFT_SIZE);
+        let mut analyser = Self {
+            input: None,
+            producer,
+            consumer,
+            fft_size: 2048,
+            window: AnalyserWindow::Blackman,
+            smoothing_time_constant: 0.8,
+            min_decibels: -100.0,
+            max_decibels: -30.0,
+            fft: FftPlanner::new().plan_fft_forward(2048),
+            frame: Vec::new(),
+            spectrum: Vec::new(),
+            scratch: Vec::new(),
+            magnitudes: Vec::new(),
+        };
+        analyser.plan();
+        analyser
+    }
+
+    pub fn fft_size(&self) -> usize {
+        self.fft_size
+    }
+
+    // Frame length for the FFT: a power of two from 32 to 32768
+    pub fn set_fft_size(&mut self, fft_size: usize) -> anyhow::Result<()> {
+        if !fft_size.is_power_of_two() || !(MIN_FFT_SIZE..=MAX_FFT_SIZE).contains(&fft_size) {
+            return Err(anyhow!(
+                "FFT size must be a power of two from {} to {}, got {}",
+                MIN_FFT_SIZE,
+                MAX_FFT_SIZE,
+                fft_size
+            ));
+        }
+        self.fft_size = fft_size;
+        self.plan();
+        Ok(())
+    }
+
+    pub fn frequency_bin_count(&self) -> usize {
+        self.fft_size / 2
+    }
+
+    pub fn window(&self) -> AnalyserWindow {
+        self.window
+    }
+
+    pub fn set_window(&mut self, window: AnalyserWindow) {
+        self.window = window;
+    }
+
+    pub fn smoothing_time_constant(&self) -> f32 {
+        self.smoothing_time_constant
+    }
+
+    // How much of the previous spectrum each new one keeps, from 0 to 1
+    pub fn set_smoothing_time_constant(&mut self, value: f32) -> anyhow::Result<()> {
+        if !(0.0..=1.0).contains(&value) {
+            return Err(anyhow!(
+                "Smoothing time constant must be from 0 to 1, got {}",
+                value
+            ));
+        }
+        self.smoothing_time_constant = value;
+        Ok(())
+    }
+
+    pub fn min_decibels(&self) -> f32 {
+        self.min_decibels
+    }
+
+    pub fn max_decibels(&self) -> f32 {
+        self.max_decibels
+    }
+
+    // The range mapped onto 0 to 255 by `get_byte_frequency_data`
+    pub fn set_decibel_range(&mut self, min: f32, max: f32) -> anyhow::Result<()> {
+        if min >= max {
+            return Err(anyhow!(
+                "Minimum decibels ({}) must be below maximum ({})",
+                min,
+                max
+            ));
+        }
+        self.min_decibels = min;
+        self.max_decibels = max;
+        Ok(())
+    }
+
+    // Smoothed magnitude of each bin in decibels, for the first `out.len()` bins
+    pub fn get_float_frequency_data(&mut self, out: &mut [f32]) {
+        self.analyse();
+        for (value, &magnitude) in out.iter_mut().zip(self.magnitudes.iter()) {
+            *value = 20.0 * magnitude.log10();
+        }
+    }
+
+    // As `get_float_frequency_data`, scaled from the decibel range onto 0 to 255
+    pub fn get_byte_frequency_data(&mut self, out: &mut [u8]) {
+        self.analyse();
+        let scale = 255.0 / (self.max_decibels - self.min_decibels);
+        for (value, &magnitude) in out.iter_mut().zip(self.magnitudes.iter()) {
+            let decibels = 20.0 * magnitude.log10();
+            *value = ((decibels - self.min_decibels) * scale).clamp(0.0, 255.0) as u8;
+        }
+    }
+
+    // The latest `fft_size` samples, oldest first, for as many as fit in `out`
+    pub fn get_float_time_domain_data(&mut self, out: &mut [f32]) {
+        self.read_frame();
+        for (value, &sample) in out.iter_mut().zip(self.frame.iter()) {
+            *value = sample;
+        }
+    }
+
+    fn plan(&mut self) {
+        self.fft = FftPlanner::new().plan_fft_forward(self.fft_size);
+        self.frame = vec![0.0; self.fft_size];
+        self.spectrum = vec![Complex::new(0.0, 0.0); self.fft_size];
+        self.scratch = vec![Complex::new(0.0, 0.0); self.fft.get_inplace_scratch_len()];
+        self.magnitudes = vec![0.0; self.fft_size / 2];
+    }
+
+    fn read_frame(&mut self) {
+        self.frame.iter_mut().for_each(|sample| *sample = 0.0);
+        self.consumer.peek_latest(&mut self.frame);
+    }
+
+    // Windows the latest frame, transforms it and folds the magnitudes, scaled
+    // by 1/N, into the smoothed spectrum
+    fn analyse(&mut self) {
+        self.read_frame();
+        for (n, (bin, &sample)) in self.spectrum.iter_mut().zip(&self.frame).enumerate() {
+            *bin = Complex::new(sample * self.window.gain(n, self.fft_size), 0.0);
+        }
+        self.fft
+            .process_with_scratch(&mut self.spectrum, &mut self.scratch);
+
+        let scale = 1.0 / self.fft_size as f32;
+        let smoothing = self.smoothing_time_constant;
+        for (magnitude, bin) in self.magnitudes.iter_mut().zip(&self.spectrum) {
+            let value = bin.norm() * scale;
+            *magnitude = smoothing * *magnitude + (1.0 - smoothing) * value;
+            if !magnitude.is_finite() {
+                *magnitude = 0.0;
+            }
+        }
+    }
+}
+
+impl Default for AnalyserNode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioNode for AnalyserNode {
+    fn process(&mut self, context: &AudioContext, current_sample: u64) -> f32 {
+        let sample = match self.input.as_mut() {
+            Some(input) => input.process(context, current_sample),
+            None => 0.0,
+        };
+        self.producer.push_overwrite(&[sample]);
+        sample
+    }
+
+    fn process_stereo(&mut self, context: &AudioContext, current_sample: u64) -> (f32, f32) {
+        let (left, right) = match self.input.as_mut() {
+            Some(input) => input.process_stereo(context, current_sample),
+            None => (0.0, 0.0),
+        };
+        self.producer.push_overwrite(&[(left + right) * 0.5]);
+        (left, right)
+    }
+
+    fn set_parameter(&self, _name: &str, _value: f32) {
+        // Settings belong to the reading side; see the setters
+    }
+
+    fn connect_input(&mut self, name: &str, node: Box<dyn AudioNode + Send>) {
+        if name == "input" {
+            self.input = Some(node);
+        }
+    }
+
+    fn clear_input(&mut self, input_name: &str) {
+        if input_name == "input" {
+            self.input = None;
+        }
+    }
+
+    fn prepare(&mut self, sample_rate: f32, max_block: usize) {
+        if let Some(input) = self.input.as_mut() {
+            input.prepare(sample_rate, max_block);
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn AudioNode + Send> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for AnalyserNode {
+    fn clone(&self) -> Self {
+        Self {
+            input: self.input.as_ref().map(|input| input.clone_box()),
+            producer: self.producer.share(),
+            consumer: self.consumer.share(),
+            fft_size: self.fft_size,
+            window: self.window,
+            smoothing_time_constant: self.smoothing_time_constant,
+            min_decibels: self.min_decibels,
+            max_decibels: self.max_decibels,
+            fft: self.fft.clone(),
+            frame: self.frame.clone(),
+            spectrum: self.spectrum.clone(),
+            scratch: self.scratch.clone(),
+            magnitudes: self.magnitudes.clone(),
+        }
+    }
+}
@@ -0,0 +1,63 @@
+// src/synth/audio_buffer.rs
+
+/// In-memory multichannel sample data, stored as one `f32` vector per channel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudioBuffer {
+    channels: Vec<Vec<f32>>,
+    sample_rate: f32,
+}
+
+impl AudioBuffer {
+    pub fn new(number_of_channels: usize, length: usize, sample_rate: f32) -> Self {
+        Self {
+            channels: vec![vec![0.0; length]; number_of_channels.max(1)],
+            sample_rate,
+        }
+    }
+
+    pub fn from_channels(channels: Vec<Vec<f32>>, sample_rate: f32) -> anyhow::Result<Self> {
+        if channels.is_empty() {
+            return Err(anyhow::anyhow!("Audio buffer needs at least one channel"));
+        }
+        let length = channels[0].len();
+        if channels.iter().any(|channel| channel.len() != length) {
+            return Err(anyhow::anyhow!("Audio buffer channels differ in length"));
+        }
+        if sample_rate <= 0.0 {
+            return Err(anyhow::anyhow!("Invalid sample rate: {}", sample_rate));
+        }
+        Ok(Self {
+            channels,
+            sample_rate,
+        })
+    }
+
+    pub fn number_of_channels(&self) -> usize {
+        self.channels.len()
+    }
+
+    // Length in frames
+    pub fn length(&self) -> usize {
+        self.channels[0].len()
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.length() as f64 / self.sample_rate as f64
+    }
+
+    pub fn channel(&self, index: usize) -> &[f32] {
+        &self.channels[index]
+    }
+
+    pub fn channel_mut(&mut self, index: usize) -> &mut [f32] {
+        &mut self.channels[index]
+    }
+
+    pub fn channels(&self) -> &[Vec<f32>] {
+        &self.channels
+    }
+}
@@ -1,20 +1,51 @@
+use crate::synth::audio_buffer::AudioBuffer;
+use crate::synth::audio_decoder;
+use crate::synth::periodic_wave::{self, PeriodicWave};
+use crate::synth::resampler::resample_buffer;
+use crate::synth::wave_bank::WaveBankRegistry;
+use crossbeam::atomic::AtomicCell;
+use std::path::Path;
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 
 pub struct AudioContext {
-    sample_rate: f32,
+    sample_rate: AtomicCell<f32>,
     current_sample: AtomicU64,
+    wave_banks: Arc<WaveBankRegistry>,
 }
 
 impl AudioContext {
     pub fn new(sample_rate: f32) -> Self {
+        Self::with_wave_banks(sample_rate, Arc::new(WaveBankRegistry::new()))
+    }
+
+    // A context reading wave banks from a registry shared with other contexts
+    pub fn with_wave_banks(sample_rate: f32, wave_banks: Arc<WaveBankRegistry>) -> Self {
         Self {
-            sample_rate,
+            sample_rate: AtomicCell::new(sample_rate),
             current_sample: AtomicU64::new(0),
+            wave_banks,
         }
     }
 
+    pub fn wave_banks(&self) -> &Arc<WaveBankRegistry> {
+        &self.wave_banks
+    }
+
     pub fn sample_rate(&self) -> f32 {
-        self.sample_rate
+        self.sample_rate.load()
+    }
+
+    // Moves the context to a new rate, rescaling the sample clock so `current_time`
+    // carries on. Nodes catch up in `AudioNode::prepare`, which the graph calls.
+    pub fn set_sample_rate(&self, sample_rate: f32) {
+        let previous = self.sample_rate.swap(sample_rate);
+        if previous != sample_rate && previous > 0.0 {
+            let ratio = sample_rate as f64 / previous as f64;
+            let current = self.current_sample.load(Ordering::Relaxed);
+            self.current_sample
+                .store(rescale_sample(current, ratio), Ordering::Relaxed);
+        }
     }
 
     pub fn increment_samples(&self, n: u64) {
@@ -26,6 +57,54 @@ impl AudioContext {
     }
 
     pub fn current_time(&self) -> f64 {
-        self.current_sample() as f64 / self.sample_rate as f64
+        self.current_sample() as f64 / self.sample_rate() as f64
     }
+
+    // Decode a WAV, FLAC or Ogg Vorbis file and resample it to the context rate
+    pub fn decode_audio_data(&self, bytes: &[u8]) -> anyhow::Result<AudioBuffer> {
+        let buffer = audio_decoder::decode_audio_data(bytes)?;
+        resample_buffer(&buffer, self.sample_rate())
+    }
+
+    pub fn decode_audio_file<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<AudioBuffer> {
+        let buffer = audio_decoder::decode_audio_file(path)?;
+        resample_buffer(&buffer, self.sample_rate())
+    }
+
+    // Like Web Audio's createPeriodicWave. The wave is registered with the
+    // context, so it plays through `OscillatorType::Custom` while it is held.
+    pub fn create_periodic_wave(
+        &self,
+        real: &[f32],
+        imag: &[f32],
+        normalize: bool,
+    ) -> anyhow::Result<Arc<PeriodicWave>> {
+        let wave = PeriodicWave::new(real, imag, normalize)?;
+        self.wave_banks.register_periodic_wave(&wave);
+        Ok(wave)
+    }
+
+    // Loads a wavetable file of `frame_size`-sample frames like the free
+    // `load_wavetable`, registering the wave as `create_periodic_wave` does
+    pub fn load_wavetable(
+        &self,
+        bytes: &[u8],
+        frame_size: usize,
+    ) -> anyhow::Result<Arc<PeriodicWave>> {
+        let wave = periodic_wave::load_wavetable(bytes, frame_size)?;
+        self.wave_banks.register_periodic_wave(&wave);
+        Ok(wave)
+    }
+}
+
+// A sample position at a rate `ratio` times the old one, at the same time in seconds
+pub(crate) fn rescale_sample(sample: u64, ratio: f64) -> u64 {
+    (sample as f64 * ratio).round() as u64
+}
+
+// For nodes keeping sample positions of their own: records `sample_rate` in
+// `prepared` and returns the factor to rescale by if it was prepared at another rate
+pub(crate) fn rate_change(prepared: &mut f32, sample_rate: f32) -> Option<f64> {
+    let previous = std::mem::replace(prepared, sample_rate);
+    (previous > 0.0 && previous != sample_rate).then(|| sample_rate as f64 / previous as f64)
 }
@@ -0,0 +1,568 @@
+// src/synth/audio_decoder.rs
+
+use crate::synth::audio_buffer::AudioBuffer;
+use crate::synth::wav::{
+    convert_wav_samples, decode_wav, deinterleave, parse_wav_header, WavHeader,
+};
+use claxon::frame::FrameReader;
+use claxon::input::{BufferedReader, ReadBytes};
+use claxon::metadata::StreamInfo;
+use lewton::inside_ogg::OggStreamReader;
+use lewton::samples::InterleavedSamples;
+use std::fs::File;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AudioFileFormat {
+    Wav,
+    Flac,
+    OggVorbis,
+}
+
+impl AudioFileFormat {
+    // Sniff the container from the first bytes of the file
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WAVE" {
+            Some(Self::Wav)
+        } else if bytes.starts_with(b"fLaC") {
+            Some(Self::Flac)
+        } else if bytes.starts_with(b"OggS") {
+            Some(Self::OggVorbis)
+        } else {
+            None
+        }
+    }
+}
+
+/// Decodes a WAV, FLAC or Ogg Vorbis file held in memory, at its own sample rate.
+/// Use `AudioContext::decode_audio_data` to get a buffer at the context rate.
+pub fn decode_audio_data(bytes: &[u8]) -> anyhow::Result<AudioBuffer> {
+    match AudioFileFormat::detect(bytes) {
+        Some(AudioFileFormat::Wav) => decode_wav(bytes),
+        Some(AudioFileFormat::Flac) => decode_flac(bytes),
+        Some(AudioFileFormat::OggVorbis) => decode_vorbis(bytes),
+        None => Err(anyhow::anyhow!("Unrecognized audio file format")),
+    }
+}
+
+pub fn decode_audio_file<P: AsRef<Path>>(path: P) -> anyhow::Result<AudioBuffer> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+    decode_audio_data(&bytes)
+}
+
+fn decode_flac(bytes: &[u8]) -> anyhow::Result<AudioBuffer> {
+    let mut reader = claxon::FlacReader::new(Cursor::new(bytes))
+        .map_err(|e| anyhow::anyhow!("Failed to read FLAC stream: {}", e))?;
+    let info = reader.streaminfo();
+    let scale = 1.0 / (1u64 << (info.bits_per_sample - 1)) as f32;
+
+    let mut interleaved = Vec::new();
+    for sample in reader.samples() {
+        let sample = sample.map_err(|e| anyhow::anyhow!("Failed to decode FLAC: {}", e))?;
+        interleaved.push(sample as f32 * scale);
+    }
+
+    let channels = deinterleave(&interleaved, info.channels as usize);
+    AudioBuffer::from_channels(channels, info.sample_rate as f32)
+}
+
+fn decode_vorbis(bytes: &[u8]) -> anyhow::Result<AudioBuffer> {
+    let mut reader = OggStreamReader::new(Cursor::new(bytes))
+        .map_err(|e| anyhow::anyhow!("Failed to read Ogg Vorbis stream: {}", e))?;
+    let channel_count = reader.ident_hdr.audio_channels as usize;
+    let sample_rate = reader.ident_hdr.audio_sample_rate as f32;
+
+    let mut channels = vec![Vec::new(); channel_count];
+    while let Some(packet) = reader
+        .read_dec_packet_generic::<Vec<Vec<f32>>>()
+        .map_err(|e| anyhow::anyhow!("Failed to decode Ogg Vorbis: {}", e))?
+    {
+        for (channel, samples) in channels.iter_mut().zip(packet) {
+            channel.extend(samples);
+        }
+    }
+
+    AudioBuffer::from_channels(channels, sample_rate)
+}
+
+// Chunk size for streaming reads, in frames
+const STREAM_CHUNK_FRAMES: usize = 4096;
+
+/// Incremental decoder used by `StreamingPlayerNode` to read a file a chunk at a time.
+pub(crate) trait StreamDecoder: Send {
+    fn channels(&self) -> usize;
+    fn sample_rate(&self) -> f32;
+    // Length in frames, when the container declares it
+    fn length(&self) -> Option<u64>;
+    // Append the next chunk of interleaved samples to `out`. Returns false at the end.
+    fn read(&mut self, out: &mut Vec<f32>) -> anyhow::Result<bool>;
+    fn seek(&mut self, frame: u64) -> anyhow::Result<()>;
+}
+
+pub(crate) fn open_stream_decoder<P: AsRef<Path>>(
+    path: P,
+) -> anyhow::Result<Box<dyn StreamDecoder>> {
+    let path = path.as_ref();
+    let mut file = File::open(path)
+        .map_err(|e| anyhow::anyhow!("Failed to open {}: {}", path.display(), e))?;
+    let mut magic = [0u8; 12];
+    let read = file.read(&mut magic)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    match AudioFileFormat::detect(&magic[..read]) {
+        Some(AudioFileFormat::Wav) => Ok(Box::new(WavStreamDecoder::new(file)?)),
+        Some(AudioFileFormat::Flac) => Ok(Box::new(FlacStreamDecoder::new(path)?)),
+        Some(AudioFileFormat::OggVorbis) => Ok(Box::new(VorbisStreamDecoder::new(path)?)),
+        None => Err(anyhow::anyhow!(
+            "Unrecognized audio file format: {}",
+            path.display()
+        )),
+    }
+}
+
+struct WavStreamDecoder {
+    file: File,
+    header: WavHeader,
+    // Data bytes left in the chunk from the current position
+    remaining: usize,
+    bytes: Vec<u8>,
+}
+
+impl WavStreamDecoder {
+    fn new(mut file: File) -> anyhow::Result<Self> {
+        let file_len = file.metadata()?.len() as usize;
+
+        // Grow the prefix until it reaches the data chunk
+        let mut prefix = Vec::new();
+        let mut size = 4096;
+        let header = loop {
+            prefix.resize(size.min(file_len), 0);
+            file.seek(SeekFrom::Start(0))?;
+            file.read_exact(&mut prefix)?;
+            match parse_wav_header(&prefix) {
+                Ok(header) => break header,
+                Err(_) if size < file_len => size *= 4,
+                Err(e) => return Err(e),
+            }
+        };
+
+        let remaining = header
+            .data_len
+            .min(file_len.saturating_sub(header.data_offset));
+        file.seek(SeekFrom::Start(header.data_offset as u64))?;
+        Ok(Self {
+            file,
+            header,
+            remaining,
+            bytes: Vec::new(),
+        })
+    }
+
+    fn data_len(&self) -> usize {
+        let file_len = self.file.metadata().map_or(0, |m| m.len() as usize);
+        self.header
+            .data_len
+            .min(file_len.saturating_sub(self.header.data_offset))
+    }
+}
+
+impl StreamDecoder for WavStreamDecoder {
+    fn channels(&self) -> usize {
+        self.header.spec.channels as usize
+    }
+
+    fn sample_rate(&self) -> f32 {
+        self.header.spec.sample_rate as f32
+    }
+
+    fn length(&self) -> Option<u64> {
+        Some((self.data_len() / self.header.spec.bytes_per_frame()) as u64)
+    }
+
+    fn read(&mut self, out: &mut Vec<f32>) -> anyhow::Result<bool> {
+        let frame_bytes = self.header.spec.bytes_per_frame();
+        let wanted = (STREAM_CHUNK_FRAMES * frame_bytes).min(self.remaining);
+        let wanted = wanted - wanted % frame_bytes;
+        if wanted == 0 {
+            return Ok(false);
+        }
+
+        self.bytes.resize(wanted, 0);
+        let mut filled = 0;
+        while filled < wanted {
+            match self.file.read(&mut self.bytes[filled..])? {
+                0 => break,
+                n => filled += n,
+            }
+        }
+        filled -= filled % frame_bytes;
+        self.remaining = if filled < wanted {
+            0
+        } else {
+            self.remaining - filled
+        };
+        convert_wav_samples(&self.header.spec, &self.bytes[..filled], out);
+        Ok(filled > 0)
+    }
+
+    fn seek(&mut self, frame: u64) -> anyhow::Result<()> {
+        let frame_bytes = self.header.spec.bytes_per_frame();
+        let data_len = self.data_len();
+        let offset = (frame as usize).saturating_mul(frame_bytes).min(data_len);
+        self.file
+            .seek(SeekFrom::Start((self.header.data_offset + offset) as u64))?;
+        self.remaining = data_len - offset;
+        Ok(())
+    }
+}
+
+// Where a FLAC frame starts: its first sample and its byte offset in the file
+#[derive(Clone, Copy)]
+struct FlacSeekPoint {
+    sample: u64,
+    offset: u64,
+}
+
+// Counts the bytes claxon takes, so the decoder knows where each frame starts
+struct CountingReader<R> {
+    inner: R,
+    position: u64,
+}
+
+impl<R: ReadBytes> ReadBytes for CountingReader<R> {
+    fn read_u8(&mut self) -> io::Result<u8> {
+        let byte = self.inner.read_u8()?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn read_u8_or_eof(&mut self) -> io::Result<Option<u8>> {
+        let byte = self.inner.read_u8_or_eof()?;
+        self.position += byte.is_some() as u64;
+        Ok(byte)
+    }
+
+    fn read_into(&mut self, buffer: &mut [u8]) -> io::Result<()> {
+        self.inner.read_into(buffer)?;
+        self.position += buffer.len() as u64;
+        Ok(())
+    }
+
+    fn skip(&mut self, amount: u32) -> io::Result<()> {
+        self.inner.skip(amount)?;
+        self.position += amount as u64;
+        Ok(())
+    }
+}
+
+// FLAC frames can only be decoded from their start, so a seek jumps to the
+// nearest known frame before the target and decodes forward from there. Frames
+// are known from the file's seek table and from reading past them.
+struct FlacStreamDecoder {
+    path: PathBuf,
+    info: StreamInfo,
+    input: CountingReader<BufferedReader<File>>,
+    // First sample of the next frame
+    sample: u64,
+    // Sorted by sample, and always starting with the first frame
+    seek_points: Vec<FlacSeekPoint>,
+    block_buffer: Vec<i32>,
+    skip: u64,
+}
+
+impl FlacStreamDecoder {
+    fn new(path: &Path) -> anyhow::Result<Self> {
+        let options = claxon::FlacReaderOptions {
+            metadata_only: true,
+            read_vorbis_comment: false,
+        };
+        let info = claxon::FlacReader::open_ext(path, options)
+            .map_err(|e| anyhow::anyhow!("Failed to read FLAC stream: {}", e))?
+            .streaminfo();
+
+        let mut file = File::open(path)?;
+        let seek_points = Self::read_seek_points(&mut file)?;
+        let first_frame = seek_points[0].offset;
+        Ok(Self {
+            path: path.to_path_buf(),
+            info,
+            input: CountingReader {
+                inner: BufferedReader::new(file),
+                position: first_frame,
+            },
+            sample: 0,
+            seek_points,
+            block_buffer: Vec::new(),
+            skip: 0,
+        })
+    }
+
+    // Walks the metadata blocks to the first frame, collecting the seek table on
+    // the way. Its offsets count from the first frame.
+    fn read_seek_points(file: &mut File) -> anyhow::Result<Vec<FlacSeekPoint>> {
+        let mut table = Vec::new();
+        file.seek(SeekFrom::Start(4))?;
+        loop {
+            let mut header = [0u8; 4];
+            file.read_exact(&mut header)?;
+            let length = u32::from_be_bytes([0, header[1], header[2], header[3]]);
+            if header[0] & 0x7F == 3 {
+                let mut points = vec![0u8; length as usize];
+                file.read_exact(&mut points)?;
+                for point in points.chunks_exact(18) {
+                    let sample = u64::from_be_bytes(point[0..8].try_into().unwrap());
+                    let offset = u64::from_be_bytes(point[8..16].try_into().unwrap());
+                    // Placeholders hold the place of points added later
+                    if sample != u64::MAX {
+                        table.push(FlacSeekPoint { sample, offset });
+                    }
+                }
+            } else {
+                file.seek(SeekFrom::Current(length as i64))?;
+            }
+            if header[0] & 0x80 != 0 {
+                break;
+            }
+        }
+
+        let first_frame = file.stream_position()?;
+        let mut seek_points = vec![FlacSeekPoint {
+            sample: 0,
+            offset: first_frame,
+        }];
+        for point in table {
+            if point.sample > seek_points.last().unwrap().sample {
+                seek_points.push(FlacSeekPoint {
+                    sample: point.sample,
+                    offset: first_frame + point.offset,
+                });
+            }
+        }
+        Ok(seek_points)
+    }
+
+    // Keeps about one frame a second, which bounds what a seek has to decode
+    fn note_seek_point(&mut self, point: FlacSeekPoint) {
+        let index = self
+            .seek_points
+            .partition_point(|known| known.sample <= point.sample);
+        let previous = self.seek_points[index - 1].sample;
+        if point.sample - previous >= self.info.sample_rate as u64 {
+            self.seek_points.insert(index, point);
+        }
+    }
+}
+
+impl StreamDecoder for FlacStreamDecoder {
+    fn channels(&self) -> usize {
+        self.info.channels as usize
+    }
+
+    fn sample_rate(&self) -> f32 {
+        self.info.sample_rate as f32
+    }
+
+    fn length(&self) -> Option<u64> {
+        self.info.samples
+    }
+
+    fn read(&mut self, out: &mut Vec<f32>) -> anyhow::Result<bool> {
+        let scale = 1.0 / (1u64 << (self.info.bits_per_sample - 1)) as f32;
+
+        loop {
+            let point = FlacSeekPoint {
+                sample: self.sample,
+                offset: self.input.position,
+            };
+            let buffer = std::mem::take(&mut self.block_buffer);
+            let block = match FrameReader::new(&mut self.input)
+                .read_next_or_eof(buffer)
+                .map_err(|e| anyhow::anyhow!("Failed to decode FLAC: {}", e))?
+            {
+                Some(block) => block,
+                None => return Ok(false),
+            };
+            self.note_seek_point(point);
+
+            // Frame numbers in fixed-size streams don't give the start of a
+            // shorter last frame, so the samples are counted here instead
+            let duration = block.duration() as u64;
+            self.sample += duration;
+            let first = self.skip.min(duration);
+            self.skip -= first;
+            for frame in first as u32..block.duration() {
+                for channel in 0..block.channels() {
+                    out.push(block.sample(channel, frame) as f32 * scale);
+                }
+            }
+            self.block_buffer = block.into_buffer();
+            if first < duration {
+                return Ok(true);
+            }
+        }
+    }
+
+    fn seek(&mut self, frame: u64) -> anyhow::Result<()> {
+        let index = self
+            .seek_points
+            .partition_point(|point| point.sample <= frame);
+        let point = self.seek_points[index - 1];
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(point.offset))?;
+        self.input = CountingReader {
+            inner: BufferedReader::new(file),
+            position: point.offset,
+        };
+        self.sample = point.sample;
+        self.skip = frame - point.sample;
+        Ok(())
+    }
+}
+
+// Ogg seeks land on a page boundary, and the position is only known again once
+// a page has been read to its end. Until then the decoded audio is held back,
+// then trimmed to the target.
+struct VorbisStreamDecoder {
+    path: PathBuf,
+    reader: OggStreamReader<File>,
+    // End of the first page of audio. Seeking to a page before it can land on
+    // the headers, so those seeks decode from the top.
+    first_granule: u64,
+    // Where the last seek was headed while the position is unknown
+    seek_target: Option<u64>,
+    held: Vec<f32>,
+    // File frame of the first held sample, once a page has ended
+    held_start: Option<u64>,
+    skip: u64,
+}
+
+impl VorbisStreamDecoder {
+    fn new(path: &Path) -> anyhow::Result<Self> {
+        let mut reader = Self::open(path)?;
+        let mut first_granule = u64::MAX;
+        while reader
+            .read_dec_packet_generic::<InterleavedSamples<f32>>()
+            .map_err(|e| anyhow::anyhow!("Failed to decode Ogg Vorbis: {}", e))?
+            .is_some()
+        {
+            if let Some(granule) = reader.get_last_absgp().filter(|&granule| granule > 0) {
+                first_granule = granule;
+                break;
+            }
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            reader: Self::open(path)?,
+            first_granule,
+            seek_target: None,
+            held: Vec::new(),
+            held_start: None,
+            skip: 0,
+        })
+    }
+
+    fn open(path: &Path) -> anyhow::Result<OggStreamReader<File>> {
+        let file = File::open(path)?;
+        OggStreamReader::new(file)
+            .map_err(|e| anyhow::anyhow!("Failed to read Ogg Vorbis stream: {}", e))
+    }
+
+    // The slow way: decode from the top, dropping everything before `frame`
+    fn rewind(&mut self, frame: u64) -> anyhow::Result<()> {
+        self.reader = Self::open(&self.path)?;
+        self.seek_target = None;
+        self.held.clear();
+        self.held_start = None;
+        self.skip = frame;
+        Ok(())
+    }
+}
+
+impl StreamDecoder for VorbisStreamDecoder {
+    fn channels(&self) -> usize {
+        self.reader.ident_hdr.audio_channels as usize
+    }
+
+    fn sample_rate(&self) -> f32 {
+        self.reader.ident_hdr.audio_sample_rate as f32
+    }
+
+    fn length(&self) -> Option<u64> {
+        None
+    }
+
+    fn read(&mut self, out: &mut Vec<f32>) -> anyhow::Result<bool> {
+        let channels = self.channels();
+        loop {
+            let packet = match self
+                .reader
+                .read_dec_packet_generic::<InterleavedSamples<f32>>()
+                .map_err(|e| anyhow::anyhow!("Failed to decode Ogg Vorbis: {}", e))?
+            {
+                Some(packet) => packet,
+                // The last page trims its audio to its granule position, which
+                // lewton can only do with the position known, so a seek that
+                // settles there is redone from the top
+                None => match self.seek_target {
+                    Some(target) => {
+                        self.rewind(target)?;
+                        continue;
+                    }
+                    None => return Ok(false),
+                },
+            };
+
+            let mut samples = packet.samples;
+            if let Some(target) = self.seek_target {
+                self.held.extend_from_slice(&samples);
+                let Some(start) = self.held_start else {
+                    // The granule position of a finished page is the frame just past it
+                    if let Some(end) = self.reader.get_last_absgp() {
+                        let held_frames = (self.held.len() / channels) as u64;
+                        self.held_start = Some(end.saturating_sub(held_frames));
+                    }
+                    continue;
+                };
+                // Reading on past that page shows it wasn't the last, so the
+                // position holds. A page starting after the target is no use.
+                if start > target {
+                    self.rewind(target)?;
+                    continue;
+                }
+                samples = std::mem::take(&mut self.held);
+                self.seek_target = None;
+                self.held_start = None;
+                self.skip = target - start;
+            }
+
+            let frames = (samples.len() / channels) as u64;
+            let first = self.skip.min(frames);
+            self.skip -= first;
+            out.extend_from_slice(&samples[first as usize * channels..]);
+            if first < frames {
+                return Ok(true);
+            }
+        }
+    }
+
+    fn seek(&mut self, frame: u64) -> anyhow::Result<()> {
+        // Aim a long block early, as the first packet after a seek only primes
+        // the decoder
+        let granule = frame.saturating_sub(1 << self.reader.ident_hdr.blocksize_1);
+        if granule < self.first_granule {
+            return self.rewind(frame);
+        }
+        self.reader
+            .seek_absgp_pg(granule)
+            .map_err(|e| anyhow::anyhow!("Failed to seek Ogg Vorbis: {}", e))?;
+        self.seek_target = Some(frame);
+        self.held.clear();
+        self.held_start = None;
+        self.skip = 0;
+        Ok(())
+    }
+}
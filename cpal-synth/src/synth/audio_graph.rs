@@ -1,80 +1,110 @@
+use crate::synth::audio_buffer::AudioBuffer;
 use crate::synth::audio_context::AudioContext;
 use crate::synth::audio_node::AudioNode;
+use crate::synth::backend::{
+    default_backend, AudioBackend, AudioRenderer, BackendConfig, PullBackend,
+};
+use crate::synth::live_input::{AudioInput, LiveInputNode};
+use crate::synth::meter::{Meter, MeterNode};
 use crate::synth::processor::AudioProcessor;
+use crate::synth::stats::{now, EngineStats, NodeTimer, PerformanceMonitor, TimedNode};
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::Instant;
 
-#[cfg(feature = "cpal-output")]
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::BufferSize;
-#[cfg(feature = "cpal-output")]
-use cpal::{FromSample, Sample};
+// Block size nodes are prepared for when the backend leaves it to the device
+const DEFAULT_MAX_BLOCK: usize = 4096;
 
 pub struct AudioGraph {
     nodes: HashMap<String, Box<dyn AudioNode + Send>>,
     output_node: Box<dyn AudioNode + Send>,
     playing: Arc<AtomicBool>,
-    #[cfg(feature = "cpal-output")]
-    stream: Option<cpal::Stream>,
+    backend: Box<dyn AudioBackend>,
+    backend_config: BackendConfig,
+    // Capture devices feeding input nodes, kept running as long as the graph
+    inputs: Vec<Box<dyn AudioInput>>,
+    // Meter on the output node, kept across `set_output`
+    output_meter: Option<Meter>,
+    monitor: PerformanceMonitor,
+    // Timers for nodes added while node timing is on
+    node_timing: bool,
+    node_timers: Vec<(String, Arc<NodeTimer>)>,
     pub context: Arc<AudioContext>,
 }
 
 impl AudioGraph {
+    // Opens the default backend: the cpal output device, or a null backend
+    // without the `cpal-output` feature
     pub fn new() -> anyhow::Result<Self> {
-        println!("Creating new AudioGraph");
-        #[cfg(feature = "cpal-output")]
-        {
-            let host = cpal::default_host();
-            println!("Using audio host: {}", host.id().name());
-
-            let device = host
-                .default_output_device()
-                .ok_or_else(|| anyhow::anyhow!("No output device available"))?;
-            println!("Using output device: {}", device.name()?);
-
-            let config_format = device.default_output_config()?;
-            println!("Default config format: {:?}", config_format);
-
-            let sample_rate = config_format.sample_rate().0 as f32;
-            println!("Sample rate: {}", sample_rate);
-
-            let context = Arc::new(AudioContext::new(sample_rate));
-
-            let output_node = Box::new(AudioProcessor::new("gain"));
-
-            Ok(Self {
-                nodes: HashMap::new(),
-                output_node,
-                playing: Arc::new(AtomicBool::new(false)),
-                stream: None,
-                context,
-            })
-        }
+        Self::with_backend(default_backend())
+    }
 
-        #[cfg(not(feature = "cpal-output"))]
-        {
-            let sample_rate = 44100.0;
-            let context = Arc::new(AudioContext::new(sample_rate));
+    pub fn with_backend(backend: Box<dyn AudioBackend>) -> anyhow::Result<Self> {
+        Self::with_backend_config(backend, BackendConfig::default())
+    }
 
-            let output_node = Box::new(AudioProcessor::new("gain"));
+    pub fn with_backend_config(
+        mut backend: Box<dyn AudioBackend>,
+        config: BackendConfig,
+    ) -> anyhow::Result<Self> {
+        println!(
+            "Creating new AudioGraph with the {} backend",
+            backend.name()
+        );
+        backend.open(&config)?;
+
+        let sample_rate = backend.sample_rate();
+        println!("Sample rate: {}", sample_rate);
+
+        Ok(Self {
+            nodes: HashMap::new(),
+            output_node: Box::new(AudioProcessor::new("gain")),
+            playing: Arc::new(AtomicBool::new(false)),
+            backend,
+            backend_config: config,
+            inputs: Vec::new(),
+            output_meter: None,
+            monitor: PerformanceMonitor::new(),
+            node_timing: false,
+            node_timers: Vec::new(),
+            context: Arc::new(AudioContext::new(sample_rate)),
+        })
+    }
 
-            Ok(Self {
-                nodes: HashMap::new(),
-                output_node,
-                playing: Arc::new(AtomicBool::new(false)),
-                stream: None,
-                context,
-            })
-        }
+    // A graph with no output device, for `render_offline`
+    pub fn offline(sample_rate: f32) -> Self {
+        Self::with_backend(Box::new(PullBackend::new(sample_rate)))
+            .expect("the pull backend always opens")
     }
 
-    pub fn add_node(&mut self, name: &str, node: Box<dyn AudioNode + Send>) {
+    pub fn add_node(&mut self, name: &str, mut node: Box<dyn AudioNode + Send>) {
         println!("Adding node: {}", name);
+        node.prepare(self.context.sample_rate(), self.max_block());
+        let node: Box<dyn AudioNode + Send> = if self.node_timing {
+            let timer = Arc::new(NodeTimer::default());
+            self.node_timers.retain(|(timed, _)| timed != name);
+            self.node_timers.push((name.to_string(), timer.clone()));
+            Box::new(TimedNode::new(node, timer))
+        } else {
+            node
+        };
         self.nodes.insert(name.to_string(), node);
     }
 
+    // Starts `input` and adds a node playing it, returning a handle on the node
+    // for its channel selection, latency and status
+    pub fn add_input(
+        &mut self,
+        name: &str,
+        mut input: Box<dyn AudioInput>,
+    ) -> anyhow::Result<LiveInputNode> {
+        println!("Adding {} input: {}", input.name(), name);
+        let node = LiveInputNode::new(&mut *input, &self.context)?;
+        self.add_node(name, Box::new(node.clone()));
+        self.inputs.push(input);
+        Ok(node)
+    }
+
     pub fn connect(&mut self, from: &str, to: &str, input_name: &str) {
         println!("Connecting {} to {} at input {}", from, to, input_name);
         if let Some(from_node) = self.nodes.get(from) {
@@ -101,238 +131,175 @@ impl AudioGraph {
         println!("Setting output to node: {}", node_name);
         if let Some(node) = self.nodes.get(node_name) {
             self.output_node = node.clone_box();
+            if let Some(meter) = &self.output_meter {
+                self.output_node =
+                    Self::metered(&self.context, meter, self.output_node.clone_box());
+            }
             println!("Output node set successfully");
         } else {
             println!("Output node '{}' not found", node_name);
         }
     }
 
-    #[cfg(feature = "cpal-output")]
-    fn write_data<T>(
-        output: &mut [T],
-        channels: usize,
-        playing: &Arc<AtomicBool>,
-        output_node: &mut dyn AudioNode,
-        context: Arc<AudioContext>,
-    ) where
-        T: Sample + FromSample<f32> + Send,
-    {
-        let num_frames = output.len() / channels;
-        let sample_rate = context.sample_rate(); // Ensure this method provides sample rate in Hz
-        let buffer_duration = num_frames as f32 / sample_rate;
-
-        println!(
-            "Received buffer size: {} ({} frames)",
-            output.len(),
-            num_frames
-        );
-
-        if !playing.load(Ordering::SeqCst) {
-            for sample in output.iter_mut() {
-                *sample = T::EQUILIBRIUM;
-            }
-            return;
-        }
-        //let start_time = Instant::now();
-
-        let base_sample = context.current_sample();
-
-        for (frame_index, frame) in output.chunks_mut(channels).enumerate() {
-            let current_sample = base_sample + frame_index as u64;
-
-            let sample_value = output_node.process(&*context, current_sample);
-
-            let sample_value = T::from_sample(sample_value);
-            for sample in frame.iter_mut() {
-                *sample = sample_value;
-            }
+    // Meters the output node, returning the meter's readings. Call before `start`,
+    // which takes its own copy of the output.
+    pub fn meter_output(&mut self) -> Meter {
+        if let Some(meter) = &self.output_meter {
+            return meter.clone();
         }
-
-        context.increment_samples(num_frames as u64);
-        // Stop timing
-        /*
-        let processing_time = start_time.elapsed();
-        let processing_time_secs = processing_time.as_secs_f32();
-
-        // Compute CPU usage
-         
-        let cpu_usage = (processing_time_secs / buffer_duration) * 100.0;
-
-        // Log CPU usage
-        println!(
-            "Processed buffer of {} frames in {:.3} ms (CPU Usage: {:.2}%)",
-            num_frames,
-            processing_time_secs * 1000.0,
-            cpu_usage
-        );*/
-
+        let meter = MeterNode::new(&self.context).meter();
+        self.output_node = Self::metered(&self.context, &meter, self.output_node.clone_box());
+        self.output_meter = Some(meter.clone());
+        meter
     }
 
-    // #[cfg(feature = "cpal-output")]
-    // fn write_data<T>(
-    //     output: &mut [T],
-    //     channels: usize,
-    //     playing: &Arc<AtomicBool>,
-    //     output_node: &mut dyn AudioNode,
-    //     context: Arc<AudioContext>,
-    // ) where
-    //     T: Sample + FromSample<f32> + Send,
-    // {
-    //     use std::time::Instant;
+    fn metered(
+        context: &AudioContext,
+        meter: &Meter,
+        node: Box<dyn AudioNode + Send>,
+    ) -> Box<dyn AudioNode + Send> {
+        let mut tap = MeterNode::with_meter(context, meter.clone());
+        tap.connect_input("input", node);
+        Box::new(tap)
+    }
 
-    //     if !playing.load(Ordering::SeqCst) {
-    //         for sample in output.iter_mut() {
-    //             *sample = T::EQUILIBRIUM;
-    //         }
-    //         return;
-    //     }
+    // Times every call into nodes added from now on, inclusive of their inputs.
+    // This costs two clock reads per node per sample, so leave it off normally.
+    pub fn set_node_timing(&mut self, enabled: bool) {
+        self.node_timing = enabled;
+    }
 
-    //     let num_frames = output.len() / channels;
-    //     let sample_rate = context.sample_rate(); // Ensure this method provides sample rate in Hz
-    //     let buffer_duration = num_frames as f32 / sample_rate;
+    // The monitor the output callbacks report to, for backends of your own
+    pub fn performance_monitor(&self) -> PerformanceMonitor {
+        self.monitor.clone()
+    }
 
-    //     // Start timing
-    //     let start_time = Instant::now();
+    pub fn stats(&self) -> EngineStats {
+        let mut stats = self.monitor.snapshot();
+        stats.nodes = self
+            .node_timers
+            .iter()
+            .map(|(name, timer)| timer.stats(name))
+            .collect();
+        stats
+    }
 
-    //     let base_sample = context.current_sample();
+    pub fn reset_stats(&self) {
+        self.monitor.reset();
+        for (_, timer) in &self.node_timers {
+            timer.reset();
+        }
+    }
 
-    //     // Process audio data
-    //     for (frame_index, frame) in output.chunks_mut(channels).enumerate() {
-    //         let current_sample = base_sample + frame_index as u64;
+    // Renders `frames` of the output node as fast as possible into a stereo
+    // buffer, advancing the context. Measure the bounce with `integrated_loudness`.
+    pub fn render_offline(&mut self, frames: usize) -> AudioBuffer {
+        let mut buffer = AudioBuffer::new(2, frames, self.context.sample_rate());
+        self.output_node
+            .prepare(self.context.sample_rate(), frames.max(1));
+        let start = now();
+        let base_sample = self.context.current_sample();
+        for frame in 0..frames {
+            let (left, right) = self
+                .output_node
+                .process_stereo(&self.context, base_sample + frame as u64);
+            buffer.channel_mut(0)[frame] = left;
+            buffer.channel_mut(1)[frame] = right;
+        }
+        self.context.increment_samples(frames as u64);
+        if let Some(start) = start {
+            self.monitor
+                .record_callback(start.elapsed(), frames, self.context.sample_rate());
+        }
+        buffer
+    }
 
-    //         let sample_value = output_node.process(&*context, current_sample);
+    pub fn backend(&self) -> &dyn AudioBackend {
+        &*self.backend
+    }
 
-    //         let sample_value = T::from_sample(sample_value);
-    //         for sample in frame.iter_mut() {
-    //             *sample = sample_value;
-    //         }
-    //     }
+    // Swaps the backend at runtime, stopping the current one. A graph that was
+    // playing carries on through the new backend.
+    pub fn set_backend(&mut self, backend: Box<dyn AudioBackend>) -> anyhow::Result<()> {
+        println!("Switching to the {} backend", backend.name());
+        self.reopen(Some(backend))
+    }
 
-    //     context.increment_samples(num_frames as u64);
+    // Asks the backend for a new rate. Nodes are prepared for whatever rate it
+    // opens at, and automation keeps its timing in seconds.
+    pub fn set_sample_rate(&mut self, sample_rate: f32) -> anyhow::Result<()> {
+        println!("Requesting sample rate {}", sample_rate);
+        self.backend_config.sample_rate = Some(sample_rate);
+        self.reopen(None)
+    }
 
-    //     // Stop timing
-    //     let processing_time = start_time.elapsed();
-    //     let processing_time_secs = processing_time.as_secs_f32();
+    fn reopen(&mut self, backend: Option<Box<dyn AudioBackend>>) -> anyhow::Result<()> {
+        let was_playing = self.playing.load(Ordering::SeqCst);
+        self.stop();
+        if let Some(backend) = backend {
+            self.backend = backend;
+        }
+        self.backend.open(&self.backend_config)?;
+        self.update_context();
+        if was_playing {
+            self.start(None)?;
+        }
+        Ok(())
+    }
 
-    //     // Compute CPU usage
-    //     let cpu_usage = (processing_time_secs / buffer_duration) * 100.0;
+    // Moves the context to the backend's rate. The context is kept, so handles on
+    // it stay valid and its clock carries on at the same time in seconds.
+    fn update_context(&mut self) {
+        let sample_rate = self.backend.sample_rate();
+        if sample_rate != self.context.sample_rate() {
+            println!("Sample rate changed to {}", sample_rate);
+            self.context.set_sample_rate(sample_rate);
+            self.prepare_nodes();
+            let freed = self.context.wave_banks().evict_unused_rates(sample_rate);
+            if freed > 0 {
+                println!("Freed {} wave banks at the old rate", freed);
+            }
+        } else {
+            self.prepare_nodes();
+        }
+    }
 
-    //     // Log CPU usage
-    //     println!(
-    //         "Processed buffer of {} frames in {:.3} ms (CPU Usage: {:.2}%)",
-    //         num_frames,
-    //         processing_time_secs * 1000.0,
-    //         cpu_usage
-    //     );
+    fn max_block(&self) -> usize {
+        self.backend.buffer_size().unwrap_or(DEFAULT_MAX_BLOCK)
+    }
 
-    //     // Optional: Warn if close to underrun
-    //     if cpu_usage > 80.0 {
-    //         eprintln!(
-    //             "Warning: High CPU usage detected ({:.2}%). Risk of buffer underrun!",
-    //             cpu_usage
-    //         );
-    //     }
-    // }
+    fn prepare_nodes(&mut self) {
+        let sample_rate = self.context.sample_rate();
+        let max_block = self.max_block();
+        for node in self.nodes.values_mut() {
+            node.prepare(sample_rate, max_block);
+        }
+        self.output_node.prepare(sample_rate, max_block);
+    }
 
     pub fn start(&mut self, buffer_size: Option<usize>) -> anyhow::Result<()> {
         println!("Starting audio graph");
-        #[cfg(feature = "cpal-output")]
-        {
-            let host = cpal::default_host();
-            let device = host
-                .default_output_device()
-                .ok_or_else(|| anyhow::anyhow!("No output device available"))?;
-
-            let supported_configs = device.supported_output_configs()?;
-            for config in supported_configs {
-                println!("Supported config: {:?}", config);
-            }
-
-            let config_format = device.default_output_config()?;
-            println!("Using config format: {:?}", config_format);
-
-            // Start with default config and apply buffer size if specified
-            let mut config: cpal::StreamConfig = config_format.clone().into();
-            if let Some(size) = buffer_size {
-                println!("Setting buffer size to {} frames", size);
-                config.buffer_size = BufferSize::Fixed(size as u32);
-            } else {
-                println!("Using default buffer size");
-            }
-
-            let sample_rate = config.sample_rate.0 as f32;
-            self.context = Arc::new(AudioContext::new(sample_rate));
-
-            let playing = self.playing.clone();
-            let output_node = self.output_node.clone_box();
-            let context = self.context.clone();
-
-            let stream = match config_format.sample_format() {
-                cpal::SampleFormat::F32 => {
-                    println!("Using F32 sample format");
-                    Self::build_stream::<f32>(&device, &config, playing, output_node, context)?
-                }
-                cpal::SampleFormat::I16 => {
-                    println!("Using I16 sample format");
-                    Self::build_stream::<i16>(&device, &config, playing, output_node, context)?
-                }
-                cpal::SampleFormat::U16 => {
-                    println!("Using U16 sample format");
-                    Self::build_stream::<u16>(&device, &config, playing, output_node, context)?
-                }
-                _ => {
-                    return Err(anyhow::anyhow!(
-                        "Unsupported sample format: {:?}",
-                        config_format.sample_format()
-                    ));
-                }
-            };
-            println!("Effective buffer size: {:?}", config.buffer_size);
-
-            println!("Starting audio stream");
-            stream.play()?;
-            println!("Audio stream started successfully");
-            self.stream = Some(stream);
+        self.backend.stop();
+        if buffer_size.is_some() && buffer_size != self.backend_config.buffer_size {
+            self.backend_config.buffer_size = buffer_size;
+            self.backend.open(&self.backend_config)?;
         }
+        self.update_context();
 
+        let renderer = AudioRenderer::new(
+            self.output_node.clone_box(),
+            self.context.clone(),
+            self.playing.clone(),
+            self.monitor.clone(),
+        );
+        self.backend.start(renderer)?;
         self.playing.store(true, Ordering::SeqCst);
         Ok(())
     }
 
-    #[cfg(feature = "cpal-output")]
-    fn build_stream<T>(
-        device: &cpal::Device,
-        config: &cpal::StreamConfig,
-        playing: Arc<AtomicBool>,
-        mut output_node: Box<dyn AudioNode + Send>,
-        context: Arc<AudioContext>,
-    ) -> anyhow::Result<cpal::Stream>
-    where
-        T: Sample + FromSample<f32> + cpal::SizedSample + Send + 'static,
-    {
-        let channels = config.channels as usize;
-        println!("Building stream with {} channels", channels);
-
-        let stream = device.build_output_stream(
-            config,
-            move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
-                Self::write_data(data, channels, &playing, &mut *output_node, context.clone());
-            },
-            move |err| {
-                eprintln!("Audio stream error: {}", err);
-            },
-            None,
-        )?;
-
-        Ok(stream)
-    }
-
     pub fn stop(&mut self) {
         println!("Stopping audio graph");
         self.playing.store(false, Ordering::SeqCst);
-        #[cfg(feature = "cpal-output")]
-        {
-            self.stream = None;
-        }
+        self.backend.stop();
     }
 }
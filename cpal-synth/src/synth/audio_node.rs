@@ -1,14 +1,41 @@
 // src/synth/audio_node.rs
 
 use crate::synth::audio_context::AudioContext;
+use crate::synth::hard_sync::SyncOut;
 use std::sync::{Arc, Mutex};
 
 pub trait AudioNode: Send {
     fn process(&mut self, context: &AudioContext, current_sample: u64) -> f32;
+
+    // Stereo output as (left, right). Mono nodes play the same signal on both sides.
+    fn process_stereo(&mut self, context: &AudioContext, current_sample: u64) -> (f32, f32) {
+        let sample = self.process(context, current_sample);
+        (sample, sample)
+    }
+
     fn set_parameter(&self, name: &str, value: f32);
+
+    // Schedule a parameter change at an exact sample. Nodes without automation
+    // support apply the value immediately.
+    fn set_parameter_at(&self, name: &str, value: f32, _at_sample: u64) {
+        self.set_parameter(name, value);
+    }
+
     fn connect_input(&mut self, name: &str, node: Box<dyn AudioNode + Send>);
     fn clear_input(&mut self, input_name: &str);
 
+    // Where the node's cycle stands after the sample it last processed, for the
+    // oscillators hard-synced to it. Nodes with a phase report when it wraps.
+    fn sync_out(&self) -> SyncOut {
+        SyncOut::ZeroCrossing
+    }
+
+    // Called before rendering and whenever the sample rate changes, with the most
+    // frames a callback will ask for. Nodes rebuild rate-dependent state, prepare
+    // their params and pass the call on to their inputs. It is called again at
+    // the same rate, so that case should cost nothing.
+    fn prepare(&mut self, _sample_rate: f32, _max_block: usize) {}
+
     // Optional method to clone the node
     fn clone_box(&self) -> Box<dyn AudioNode + Send>;
 }
@@ -29,11 +56,21 @@ where
         node.process(context, current_sample)
     }
 
+    fn process_stereo(&mut self, context: &AudioContext, current_sample: u64) -> (f32, f32) {
+        let mut node = self.lock().unwrap();
+        node.process_stereo(context, current_sample)
+    }
+
     fn set_parameter(&self, name: &str, value: f32) {
         let node = self.lock().unwrap();
         node.set_parameter(name, value);
     }
 
+    fn set_parameter_at(&self, name: &str, value: f32, at_sample: u64) {
+        let node = self.lock().unwrap();
+        node.set_parameter_at(name, value, at_sample);
+    }
+
     fn connect_input(&mut self, name: &str, input: Box<dyn AudioNode + Send>) {
         let mut node = self.lock().unwrap();
         node.connect_input(name, input);
@@ -44,6 +81,15 @@ where
         node.clear_input(name);
     }
 
+    fn sync_out(&self) -> SyncOut {
+        self.lock().unwrap().sync_out()
+    }
+
+    fn prepare(&mut self, sample_rate: f32, max_block: usize) {
+        let mut node = self.lock().unwrap();
+        node.prepare(sample_rate, max_block);
+    }
+
     fn clone_box(&self) -> Box<dyn AudioNode + Send> {
         Box::new(self.clone())
     }
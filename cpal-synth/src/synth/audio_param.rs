@@ -1,12 +1,15 @@
 // src/synth/audio_param.rs
 
+use crate::synth::audio_context::rescale_sample;
 use crossbeam::atomic::AtomicCell;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, RwLock};
 
 #[derive(Debug, Clone, Copy)]
 pub enum RampType {
     Linear,
     Exponential,
+    Step,
 }
 
 #[derive(Debug, Clone)]
@@ -24,6 +27,11 @@ pub struct AudioParam {
     min_value: f32,
     max_value: f32,
     events: Arc<RwLock<Vec<RampEvent>>>,
+    // Last sample the param was read at, used to retire finished events
+    last_sample: AtomicU64,
+    // Rate the event samples are counted at, 0 until the param is prepared or a
+    // ramp is scheduled
+    sample_rate: AtomicCell<f32>,
 }
 
 impl Clone for AudioParam {
@@ -40,6 +48,8 @@ impl Clone for AudioParam {
             min_value: self.min_value,
             max_value: self.max_value,
             events: Arc::new(RwLock::new(events_clone)),
+            last_sample: AtomicU64::new(self.last_sample.load(Ordering::Relaxed)),
+            sample_rate: AtomicCell::new(self.sample_rate.load()),
         }
     }
 }
@@ -52,9 +62,31 @@ impl AudioParam {
             min_value,
             max_value,
             events: Arc::new(RwLock::new(Vec::new())),
+            last_sample: AtomicU64::new(0),
+            sample_rate: AtomicCell::new(0.0),
         }
     }
 
+    // Counts events at `sample_rate` from now on. Events scheduled at another rate
+    // are moved so they still happen at the same time in seconds.
+    pub fn prepare(&self, sample_rate: f32) {
+        let previous = self.sample_rate.swap(sample_rate);
+        if previous == sample_rate || previous <= 0.0 {
+            return;
+        }
+        let ratio = sample_rate as f64 / previous as f64;
+        let mut events = self.events.write().unwrap();
+        for event in events.iter_mut() {
+            event.start_sample = rescale_sample(event.start_sample, ratio);
+            if !matches!(event.ramp_type, RampType::Step) {
+                event.duration_samples = rescale_sample(event.duration_samples, ratio).max(1);
+            }
+        }
+        let last_sample = self.last_sample.load(Ordering::Relaxed);
+        self.last_sample
+            .store(rescale_sample(last_sample, ratio), Ordering::Relaxed);
+    }
+
     pub fn set_value(&self, value: f32) {
         let value = self.clamp_value(value);
         self.current_value.store(value);
@@ -69,7 +101,10 @@ impl AudioParam {
     ) {
         let value = self.clamp_value(value);
         let duration_samples = ((duration_seconds * sample_rate) as u64).max(1);
+        self.note_sample_rate(sample_rate);
 
+        let mut events = self.events.write().unwrap();
+        self.retire_finished_events(&mut events);
         let start_value = self.current_value.load();
 
         let event = RampEvent {
@@ -80,8 +115,7 @@ impl AudioParam {
             ramp_type: RampType::Exponential,
         };
 
-        let mut events = self.events.write().unwrap();
-        events.push(event);
+        Self::insert_event(&mut events, event);
     }
 
     pub fn linear_ramp_to_value_at_time(
@@ -93,7 +127,10 @@ impl AudioParam {
     ) {
         let value = self.clamp_value(value);
         let duration_samples = ((duration_seconds * sample_rate) as u64).max(1);
+        self.note_sample_rate(sample_rate);
 
+        let mut events = self.events.write().unwrap();
+        self.retire_finished_events(&mut events);
         let start_value = self.current_value.load();
 
         let event = RampEvent {
@@ -104,19 +141,40 @@ impl AudioParam {
             ramp_type: RampType::Linear,
         };
 
+        Self::insert_event(&mut events, event);
+    }
+
+    // Jumps to `value` exactly at `start_sample`, like Web Audio's setValueAtTime
+    pub fn set_value_at_time(&self, value: f32, start_sample: u64) {
+        let value = self.clamp_value(value);
+
         let mut events = self.events.write().unwrap();
-        events.push(event);
+        self.retire_finished_events(&mut events);
+
+        let event = RampEvent {
+            start_value: value,
+            end_value: value,
+            start_sample,
+            duration_samples: 1,
+            ramp_type: RampType::Step,
+        };
+
+        Self::insert_event(&mut events, event);
     }
 
     pub fn get_value(&self, current_sample: u64) -> f32 {
+        self.last_sample.store(current_sample, Ordering::Relaxed);
         let mut value = self.current_value.load();
 
         let events = self.events.read().unwrap();
 
+        // Events are ordered by start, so the last one to have started wins,
+        // including over others starting on the same sample
         for event in events.iter() {
-            if current_sample >= event.start_sample
-                && current_sample < event.start_sample + event.duration_samples
-            {
+            if current_sample < event.start_sample {
+                break;
+            }
+            if current_sample < event.start_sample + event.duration_samples {
                 let t =
                     (current_sample - event.start_sample) as f32 / event.duration_samples as f32;
 
@@ -130,9 +188,9 @@ impl AudioParam {
                         let end = event.end_value.max(0.00001);
                         start * (end / start).powf(t)
                     }
+                    RampType::Step => event.end_value,
                 };
-                break;
-            } else if current_sample >= event.start_sample + event.duration_samples {
+            } else {
                 // Event has completed; set to end_value
                 value = event.end_value;
             }
@@ -152,6 +210,35 @@ impl AudioParam {
         events.clear();
     }
 
+    // Keep events ordered by start, after any starting on the same sample, so
+    // later automation always wins
+    fn insert_event(events: &mut Vec<RampEvent>, event: RampEvent) {
+        let index = events.partition_point(|e| e.start_sample <= event.start_sample);
+        events.insert(index, event);
+    }
+
+    // Fold events that ended before the last rendered sample into the current value,
+    // so long-running automation (e.g. a sequencer) does not grow the event list forever.
+    // Only the finished events at the front go: one that finished behind a ramp
+    // still running has to stay, or the ramp would win over it again.
+    fn retire_finished_events(&self, events: &mut Vec<RampEvent>) {
+        let last_sample = self.last_sample.load(Ordering::Relaxed);
+        let finished = events
+            .iter()
+            .take_while(|event| event.start_sample + event.duration_samples <= last_sample)
+            .count();
+        if finished > 0 {
+            self.current_value.store(events[finished - 1].end_value);
+            events.drain(..finished);
+        }
+    }
+
+    fn note_sample_rate(&self, sample_rate: f32) {
+        if self.sample_rate.load() <= 0.0 {
+            self.sample_rate.store(sample_rate);
+        }
+    }
+
     fn clamp_value(&self, value: f32) -> f32 {
         value.clamp(self.min_value, self.max_value)
     }
@@ -0,0 +1,526 @@
+// src/synth/backend.rs
+
+use crate::synth::audio_context::AudioContext;
+use crate::synth::audio_node::AudioNode;
+use crate::synth::dither::Dither;
+use crate::synth::stats::{now, PerformanceMonitor};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "cpal-output")]
+use crate::synth::devices::{
+    find_host, find_output_device, sample_format_from_cpal, select_output_config,
+};
+#[cfg(feature = "cpal-output")]
+use crate::synth::dither::Quantizer;
+#[cfg(feature = "cpal-output")]
+use cpal::traits::{DeviceTrait, StreamTrait};
+#[cfg(feature = "cpal-output")]
+use cpal::{FromSample, Sample};
+
+// Frames a callback is assumed to ask for at most when the device picks its own
+// buffer size, so the scratch buffer is sized before the stream starts
+#[cfg(feature = "cpal-output")]
+pub(crate) const MAX_CALLBACK_FRAMES: usize = 8192;
+
+/// How a device stores samples, as cpal 0.15 names them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SampleFormat {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64,
+}
+
+impl SampleFormat {
+    pub fn bits(&self) -> u32 {
+        match self {
+            SampleFormat::I8 | SampleFormat::U8 => 8,
+            SampleFormat::I16 | SampleFormat::U16 => 16,
+            SampleFormat::I32 | SampleFormat::U32 | SampleFormat::F32 => 32,
+            SampleFormat::I64 | SampleFormat::U64 | SampleFormat::F64 => 64,
+        }
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, SampleFormat::F32 | SampleFormat::F64)
+    }
+}
+
+/// What to ask a backend for when opening it. `None` leaves the choice to the
+/// backend. Backends for real devices fail to open when the device can't do
+/// what was asked.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BackendConfig {
+    pub sample_rate: Option<f32>,
+    pub buffer_size: Option<usize>,
+    pub channels: Option<usize>,
+    pub sample_format: Option<SampleFormat>,
+    // Applied when the stream's format is an integer one
+    pub dither: Dither,
+}
+
+/// Something that pulls audio out of a graph: a sound card, a timer or a test.
+///
+/// `open` settles the stream format, which can be read back once it returns.
+/// `start` hands over the renderer that fills the backend's buffers, and `stop`
+/// drops it again; the backend may be reopened and restarted afterwards.
+pub trait AudioBackend {
+    fn name(&self) -> &str;
+    fn open(&mut self, config: &BackendConfig) -> anyhow::Result<()>;
+    fn start(&mut self, renderer: AudioRenderer) -> anyhow::Result<()>;
+    fn stop(&mut self);
+    fn sample_rate(&self) -> f32;
+    // Frames per callback, when the backend fixes it
+    fn buffer_size(&self) -> Option<usize>;
+    fn channels(&self) -> usize;
+}
+
+/// Renders the graph's output node into interleaved buffers for a backend.
+pub struct AudioRenderer {
+    output_node: Box<dyn AudioNode + Send>,
+    context: Arc<AudioContext>,
+    playing: Arc<AtomicBool>,
+    monitor: PerformanceMonitor,
+}
+
+impl AudioRenderer {
+    pub fn new(
+        output_node: Box<dyn AudioNode + Send>,
+        context: Arc<AudioContext>,
+        playing: Arc<AtomicBool>,
+        monitor: PerformanceMonitor,
+    ) -> Self {
+        Self {
+            output_node,
+            context,
+            playing,
+            monitor,
+        }
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.context.sample_rate()
+    }
+
+    pub fn monitor(&self) -> &PerformanceMonitor {
+        &self.monitor
+    }
+
+    // Fills `output` with interleaved frames of `channels` channels, or silence
+    // while the graph is stopped
+    pub fn render(&mut self, output: &mut [f32], channels: usize) {
+        if !self.playing.load(Ordering::SeqCst) || channels == 0 {
+            output.fill(0.0);
+            return;
+        }
+
+        let start = now();
+        let num_frames = output.len() / channels;
+        let base_sample = self.context.current_sample();
+
+        for (frame_index, frame) in output.chunks_mut(channels).enumerate() {
+            let current_sample = base_sample + frame_index as u64;
+
+            if channels == 1 {
+                frame[0] = self.output_node.process(&self.context, current_sample);
+                continue;
+            }
+
+            // Left and right go to the first two channels, any others get the mono mix
+            let (left, right) = self
+                .output_node
+                .process_stereo(&self.context, current_sample);
+            let mono = (left + right) * 0.5;
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                *sample = match channel {
+                    0 => left,
+                    1 => right,
+                    _ => mono,
+                };
+            }
+        }
+
+        self.context.increment_samples(num_frames as u64);
+        if let Some(start) = start {
+            self.monitor
+                .record_callback(start.elapsed(), num_frames, self.context.sample_rate());
+        }
+    }
+}
+
+/// Plays through a cpal output device, the default one of the default host
+/// unless named. See `available_hosts` and `output_devices` for the names.
+#[cfg(feature = "cpal-output")]
+pub struct CpalBackend {
+    host_name: Option<String>,
+    device_name: Option<String>,
+    device: Option<cpal::Device>,
+    config: Option<cpal::StreamConfig>,
+    sample_format: SampleFormat,
+    dither: Dither,
+    stream: Option<cpal::Stream>,
+}
+
+#[cfg(feature = "cpal-output")]
+impl CpalBackend {
+    pub fn new() -> Self {
+        Self::with_device(None, None)
+    }
+
+    pub fn with_device(host: Option<&str>, device: Option<&str>) -> Self {
+        Self {
+            host_name: host.map(str::to_string),
+            device_name: device.map(str::to_string),
+            device: None,
+            config: None,
+            sample_format: SampleFormat::F32,
+            dither: Dither::default(),
+            stream: None,
+        }
+    }
+
+    fn build_stream<T>(
+        device: &cpal::Device,
+        config: &cpal::StreamConfig,
+        mut renderer: AudioRenderer,
+        mut quantizer: Option<Quantizer>,
+    ) -> anyhow::Result<cpal::Stream>
+    where
+        T: Sample + FromSample<f32> + cpal::SizedSample + Send + 'static,
+    {
+        let channels = config.channels as usize;
+        println!("Building stream with {} channels", channels);
+
+        let sample_rate = config.sample_rate.0 as f32;
+        let frames = match config.buffer_size {
+            cpal::BufferSize::Fixed(size) => (size as usize).max(MAX_CALLBACK_FRAMES),
+            cpal::BufferSize::Default => MAX_CALLBACK_FRAMES,
+        };
+        let mut scratch = vec![0.0f32; frames * channels];
+
+        // Device timestamps are measured from the first callback
+        let mut origin: Option<cpal::StreamInstant> = None;
+        let stream = device.build_output_stream(
+            config,
+            move |data: &mut [T], info: &cpal::OutputCallbackInfo| {
+                let timestamp = info.timestamp();
+                let origin = *origin.get_or_insert(timestamp.callback);
+                if let (Some(callback), Some(playback)) = (
+                    timestamp.callback.duration_since(&origin),
+                    timestamp.playback.duration_since(&origin),
+                ) {
+                    renderer.monitor().record_timestamps(
+                        callback,
+                        playback,
+                        data.len() / channels,
+                        sample_rate,
+                    );
+                }
+                // Only a device breaking its buffer size gets here
+                if scratch.len() < data.len() {
+                    scratch.resize(data.len(), 0.0);
+                }
+                let scratch = &mut scratch[..data.len()];
+                renderer.render(scratch, channels);
+                if let Some(quantizer) = &mut quantizer {
+                    quantizer.process(scratch);
+                }
+                for (sample, &value) in data.iter_mut().zip(scratch.iter()) {
+                    *sample = T::from_sample(value);
+                }
+            },
+            move |err| {
+                eprintln!("Audio stream error: {}", err);
+            },
+            None,
+        )?;
+
+        Ok(stream)
+    }
+}
+
+#[cfg(feature = "cpal-output")]
+impl Default for CpalBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "cpal-output")]
+impl AudioBackend for CpalBackend {
+    fn name(&self) -> &str {
+        "cpal"
+    }
+
+    fn open(&mut self, config: &BackendConfig) -> anyhow::Result<()> {
+        self.stop();
+
+        let host = find_host(self.host_name.as_deref())?;
+        println!("Using audio host: {}", host.id().name());
+
+        let device = find_output_device(&host, self.device_name.as_deref())?;
+        println!("Using output device: {}", device.name()?);
+
+        let (stream_config, sample_format) = select_output_config(&device, config)?;
+        let sample_format = sample_format_from_cpal(sample_format)
+            .ok_or_else(|| anyhow::anyhow!("Unsupported sample format: {:?}", sample_format))?;
+        println!("Using config: {:?} as {:?}", stream_config, sample_format);
+
+        self.sample_format = sample_format;
+        self.dither = config.dither;
+        self.config = Some(stream_config);
+        self.device = Some(device);
+        Ok(())
+    }
+
+    fn start(&mut self, renderer: AudioRenderer) -> anyhow::Result<()> {
+        self.stop();
+        let (device, config) = match (&self.device, &self.config) {
+            (Some(device), Some(config)) => (device, config),
+            _ => return Err(anyhow::anyhow!("The cpal backend has not been opened")),
+        };
+
+        // Integer formats are dithered down from the float mix
+        let format = self.sample_format;
+        println!("Using {:?} sample format", format);
+        let quantizer = (!format.is_float())
+            .then(|| Quantizer::new(self.dither, format.bits(), config.channels as usize));
+        let stream = match format {
+            SampleFormat::I8 => Self::build_stream::<i8>(device, config, renderer, quantizer)?,
+            SampleFormat::I16 => Self::build_stream::<i16>(device, config, renderer, quantizer)?,
+            SampleFormat::I32 => Self::build_stream::<i32>(device, config, renderer, quantizer)?,
+            SampleFormat::I64 => Self::build_stream::<i64>(device, config, renderer, quantizer)?,
+            SampleFormat::U8 => Self::build_stream::<u8>(device, config, renderer, quantizer)?,
+            SampleFormat::U16 => Self::build_stream::<u16>(device, config, renderer, quantizer)?,
+            SampleFormat::U32 => Self::build_stream::<u32>(device, config, renderer, quantizer)?,
+            SampleFormat::U64 => Self::build_stream::<u64>(device, config, renderer, quantizer)?,
+            SampleFormat::F32 => Self::build_stream::<f32>(device, config, renderer, quantizer)?,
+            SampleFormat::F64 => Self::build_stream::<f64>(device, config, renderer, quantizer)?,
+        };
+        println!("Effective buffer size: {:?}", config.buffer_size);
+
+        println!("Starting audio stream");
+        stream.play()?;
+        println!("Audio stream started successfully");
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        self.stream = None;
+    }
+
+    fn sample_rate(&self) -> f32 {
+        self.config
+            .as_ref()
+            .map_or(44100.0, |config| config.sample_rate.0 as f32)
+    }
+
+    fn buffer_size(&self) -> Option<usize> {
+        match self.config.as_ref()?.buffer_size {
+            cpal::BufferSize::Fixed(size) => Some(size as usize),
+            cpal::BufferSize::Default => None,
+        }
+    }
+
+    fn channels(&self) -> usize {
+        self.config
+            .as_ref()
+            .map_or(2, |config| config.channels as usize)
+    }
+}
+
+/// Renders on a timer thread at the pace of real time and throws the audio
+/// away. Useful without a sound card, where the graph must still advance.
+pub struct NullBackend {
+    sample_rate: f32,
+    buffer_size: usize,
+    channels: usize,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl NullBackend {
+    pub fn new() -> Self {
+        Self {
+            sample_rate: 44100.0,
+            buffer_size: 512,
+            channels: 2,
+            running: Arc::new(AtomicBool::new(false)),
+            thread: None,
+        }
+    }
+}
+
+impl Default for NullBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioBackend for NullBackend {
+    fn name(&self) -> &str {
+        "null"
+    }
+
+    fn open(&mut self, config: &BackendConfig) -> anyhow::Result<()> {
+        self.stop();
+        self.sample_rate = config.sample_rate.unwrap_or(44100.0);
+        self.buffer_size = config.buffer_size.unwrap_or(512).max(1);
+        self.channels = config.channels.unwrap_or(2).max(1);
+        Ok(())
+    }
+
+    fn start(&mut self, mut renderer: AudioRenderer) -> anyhow::Result<()> {
+        self.stop();
+        let running = Arc::new(AtomicBool::new(true));
+        self.running = running.clone();
+
+        let channels = self.channels;
+        let mut buffer = vec![0.0; self.buffer_size * channels];
+        let period = Duration::from_secs_f64(self.buffer_size as f64 / self.sample_rate as f64);
+        let thread = std::thread::Builder::new()
+            .name("null-audio".to_string())
+            .spawn(move || {
+                // Deadlines are kept from the start so sleep overshoot doesn't add up
+                let mut deadline = Instant::now();
+                while running.load(Ordering::SeqCst) {
+                    renderer.render(&mut buffer, channels);
+                    deadline += period;
+                    let now = Instant::now();
+                    if deadline > now {
+                        std::thread::sleep(deadline - now);
+                    } else {
+                        deadline = now;
+                    }
+                }
+            })?;
+        self.thread = Some(thread);
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+
+    fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    fn buffer_size(&self) -> Option<usize> {
+        Some(self.buffer_size)
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+}
+
+impl Drop for NullBackend {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Renders only when asked to with `pull`, for tests and offline use.
+///
+/// Clones share the renderer and the channel count it was started with, so
+/// keep one to pull from after handing another to `AudioGraph::with_backend`.
+#[derive(Clone)]
+pub struct PullBackend {
+    sample_rate: f32,
+    buffer_size: Option<usize>,
+    channels: usize,
+    renderer: Arc<Mutex<Option<(AudioRenderer, usize)>>>,
+}
+
+impl PullBackend {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            sample_rate,
+            buffer_size: None,
+            channels: 2,
+            renderer: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    // Fills `output` with interleaved frames, returning false (and silence)
+    // when the backend isn't started
+    pub fn pull(&self, output: &mut [f32]) -> bool {
+        match self.renderer.lock().unwrap().as_mut() {
+            Some((renderer, channels)) => {
+                renderer.render(output, *channels);
+                true
+            }
+            None => {
+                output.fill(0.0);
+                false
+            }
+        }
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.renderer.lock().unwrap().is_some()
+    }
+}
+
+impl AudioBackend for PullBackend {
+    fn name(&self) -> &str {
+        "pull"
+    }
+
+    fn open(&mut self, config: &BackendConfig) -> anyhow::Result<()> {
+        self.stop();
+        if let Some(sample_rate) = config.sample_rate {
+            self.sample_rate = sample_rate;
+        }
+        self.buffer_size = config.buffer_size;
+        self.channels = config.channels.unwrap_or(2).max(1);
+        Ok(())
+    }
+
+    fn start(&mut self, renderer: AudioRenderer) -> anyhow::Result<()> {
+        *self.renderer.lock().unwrap() = Some((renderer, self.channels));
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        *self.renderer.lock().unwrap() = None;
+    }
+
+    fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    fn buffer_size(&self) -> Option<usize> {
+        self.buffer_size
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+}
+
+// The device backend when there is one, otherwise a timer
+pub(crate) fn default_backend() -> Box<dyn AudioBackend> {
+    #[cfg(feature = "cpal-output")]
+    {
+        Box::new(CpalBackend::new())
+    }
+    #[cfg(not(feature = "cpal-output"))]
+    {
+        Box::new(NullBackend::new())
+    }
+}
@@ -1,11 +1,14 @@
 use crate::synth::audio_context::AudioContext;
 use crate::synth::audio_node::AudioNode;
 use crate::synth::audio_param::AudioParam;
+use crate::synth::hard_sync::{wrap_delay, HardSync, SyncOut};
+use crate::synth::interpolation::cubic_interpolate;
 use crate::synth::oscillator::OscillatorType;
-use lazy_static::lazy_static;
+use crate::synth::periodic_wave::PeriodicWave;
+use crate::synth::wave_bank::WaveBankRegistry;
 use rustfft::{num_complex::Complex, FftPlanner};
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::f32::consts::PI;
+use std::sync::Arc;
 
 #[cfg(target_arch = "x86_64")]
 use std::arch::x86_64::*;
@@ -15,50 +18,145 @@ const OVERSAMPLE: usize = 2;
 const BASE_FREQ: f32 = 20.0;
 const MIN_TABLE_SIZE: usize = 64;
 
+// Smallest table for the upper mip levels, so interpolation stays accurate
+const MIN_MIP_TABLE_SIZE: usize = 512;
+
+// One mip level: a table per frame, all of the same size
 #[derive(Debug)]
 struct WaveTable {
-    wave_table: Arc<Vec<f32>>,
+    frames: Vec<Arc<Vec<f32>>>,
+    #[allow(dead_code)]
     top_freq: f32,
     table_mask: usize, // For power-of-2 size tables
     table_size: usize,
 }
 
 #[derive(Debug)]
-struct WaveTableBank {
+pub(crate) struct WaveTableBank {
     tables: Vec<WaveTable>,
+    // The custom wave the bank was built from, kept alive so it can be rebuilt
+    // at another rate
+    periodic_wave: Option<Arc<PeriodicWave>>,
     sample_rate: f32,
     frequency_bounds: Vec<f32>, // Pre-computed frequency boundaries
 }
 
-lazy_static! {
-    static ref WAVETABLE_BANKS: Mutex<HashMap<(OscillatorType, u32), Arc<WaveTableBank>>> = {
-        let m = HashMap::new();
-        Mutex::new(m)
-    };
-}
-
 impl WaveTableBank {
-    fn new(waveform: OscillatorType, sample_rate: f32) -> Self {
+    // `periodic_wave` is the wave behind a custom `waveform`
+    pub(crate) fn new(
+        waveform: OscillatorType,
+        periodic_wave: Option<Arc<PeriodicWave>>,
+        sample_rate: f32,
+    ) -> anyhow::Result<Self> {
         let max_harmonics = (sample_rate / (3.0 * BASE_FREQ)) as usize;
 
-        // Find next power of 2
-        let mut table_len = MIN_TABLE_SIZE;
-        while table_len < max_harmonics * 2 * OVERSAMPLE {
-            table_len *= 2;
+        if let OscillatorType::Custom(id) = waveform {
+            if periodic_wave.is_none() {
+                return Err(anyhow::anyhow!("No periodic wave with id {}", id));
+            }
         }
+        let (frames, normalize) = match &periodic_wave {
+            Some(wave) => {
+                // Custom waves carry their own normalization
+                let frames = (0..wave.frame_count())
+                    .map(|frame| {
+                        let scale = |c: &f32| c * wave.scale();
+                        (
+                            wave.frame_real(frame).iter().map(scale).collect(),
+                            wave.frame_imag(frame).iter().map(scale).collect(),
+                        )
+                    })
+                    .collect();
+                (frames, false)
+            }
+            // The pulse is built from two saws, so their ramp is kept at exactly ±1
+            _ => (
+                vec![Self::builtin_coefficients(waveform, max_harmonics)],
+                waveform != OscillatorType::Pulse,
+            ),
+        };
 
-        let mut planner = FftPlanner::new();
-        let fft = planner.plan_fft_forward(table_len);
+        let mut bank = Self::from_coefficients(&frames, normalize, max_harmonics, sample_rate);
+        bank.periodic_wave = periodic_wave;
+        Ok(bank)
+    }
 
+    // Fourier coefficients of the built-in shapes, in `PeriodicWave` form
+    fn builtin_coefficients(waveform: OscillatorType, harmonics: usize) -> (Vec<f32>, Vec<f32>) {
+        let real = vec![0.0; harmonics + 1];
+        let mut imag = vec![0.0; harmonics + 1];
+
+        match waveform {
+            OscillatorType::Sawtooth => {
+                for (idx, value) in imag.iter_mut().enumerate().skip(1) {
+                    *value = -1.0 / idx as f32;
+                }
+            }
+            OscillatorType::Square => {
+                for idx in (1..=harmonics).step_by(2) {
+                    imag[idx] = -1.0 / idx as f32;
+                }
+            }
+            OscillatorType::Triangle => {
+                let mut sign = 1.0f32;
+                for idx in (1..=harmonics).step_by(2) {
+                    imag[idx] = -sign / (idx * idx) as f32;
+                    sign = -sign;
+                }
+            }
+            OscillatorType::Sine => {
+                imag[1] = -1.0;
+            }
+            // A sawtooth rising from -1 to 1
+            OscillatorType::Pulse => {
+                for (idx, value) in imag.iter_mut().enumerate().skip(1) {
+                    *value = -2.0 / (PI * idx as f32);
+                }
+            }
+            OscillatorType::Custom(_) => {}
+        }
+
+        (real, imag)
+    }
+
+    // Build one mip level per octave, halving the harmonic count each time. Each
+    // level is only as large as its harmonics need.
+    fn from_coefficients(
+        frames: &[(Vec<f32>, Vec<f32>)],
+        normalize: bool,
+        max_harmonics: usize,
+        sample_rate: f32,
+    ) -> Self {
+        let mut planner = FftPlanner::new();
         let mut tables = Vec::new();
         let mut frequency_bounds = Vec::new();
         let mut harmonics = max_harmonics;
         let mut top_freq = BASE_FREQ * 2.0 / sample_rate;
 
         while harmonics >= 1 {
-            let table = Self::create_wavetable(table_len, harmonics, waveform, top_freq, &fft);
+            // Find next power of 2
+            let mut table_len = MIN_TABLE_SIZE;
+            while table_len < harmonics * 2 * OVERSAMPLE {
+                table_len *= 2;
+            }
+            let table_len = table_len.max(MIN_MIP_TABLE_SIZE);
+            let fft = planner.plan_fft_inverse(table_len);
+
+            let level = frames
+                .iter()
+                .map(|(real, imag)| {
+                    Arc::new(Self::create_wavetable(
+                        table_len, harmonics, real, imag, &fft,
+                    ))
+                })
+                .collect();
+            tables.push(WaveTable {
+                frames: level,
+                top_freq,
+                table_mask: table_len - 1,
+                table_size: table_len,
+            });
             frequency_bounds.push(top_freq * sample_rate);
-            tables.push(table);
             harmonics >>= 1;
             top_freq *= 2.0;
         }
@@ -66,22 +164,23 @@ impl WaveTableBank {
         // Normalize all tables
         let global_max = tables
             .iter()
-            .flat_map(|table| table.wave_table.iter())
+            .flat_map(|table| table.frames.iter())
+            .flat_map(|frame| frame.iter())
             .fold(0.0f32, |max, &x| max.max(x.abs()));
 
-        if global_max > 0.0 {
+        if normalize && global_max > 0.0 {
             for table in &mut tables {
-                let normalized: Vec<f32> = table
-                    .wave_table
-                    .iter()
-                    .map(|&sample| sample / global_max)
-                    .collect();
-                table.wave_table = Arc::new(normalized);
+                for frame in &mut table.frames {
+                    let normalized: Vec<f32> =
+                        frame.iter().map(|&sample| sample / global_max).collect();
+                    *frame = Arc::new(normalized);
+                }
             }
         }
 
         Self {
             tables,
+            periodic_wave: None,
             sample_rate,
             frequency_bounds,
         }
@@ -90,54 +189,51 @@ impl WaveTableBank {
     fn create_wavetable(
         len: usize,
         num_harmonics: usize,
-        waveform: OscillatorType,
-        top_freq: f32,
-        fft: &Arc<dyn rustfft::Fft<f32>>,
-    ) -> WaveTable {
+        real: &[f32],
+        imag: &[f32],
+        ifft: &Arc<dyn rustfft::Fft<f32>>,
+    ) -> Vec<f32> {
         let mut spectrum = vec![Complex::new(0.0f32, 0.0f32); len];
 
-        match waveform {
-            OscillatorType::Sawtooth => {
-                for idx in 1..=num_harmonics {
-                    let temp = -1.0 / idx as f32;
-                    spectrum[idx] = Complex::new(-temp, 0.0);
-                    spectrum[len - idx] = Complex::new(temp, 0.0);
-                }
-            }
-            OscillatorType::Square => {
-                for idx in (1..=num_harmonics).step_by(2) {
-                    let temp = 1.0 / idx as f32;
-                    spectrum[idx] = Complex::new(temp, 0.0);
-                    spectrum[len - idx] = Complex::new(-temp, 0.0);
-                }
-            }
-            OscillatorType::Triangle => {
-                let mut sign = 1.0f32;
-                for idx in (1..=num_harmonics).step_by(2) {
-                    let temp = sign / (idx * idx) as f32;
-                    spectrum[idx] = Complex::new(temp, 0.0);
-                    spectrum[len - idx] = Complex::new(-temp, 0.0);
-                    sign = -sign;
-                }
-            }
-            OscillatorType::Sine => {
-                spectrum[1] = Complex::new(1.0, 0.0);
-                spectrum[len - 1] = Complex::new(-1.0, 0.0);
-            }
+        // a*cos + b*sin splits into (a - ib)/2 at +k and (a + ib)/2 at -k
+        let last = num_harmonics.min(real.len() - 1).min(len / 2 - 1);
+        for idx in 1..=last {
+            spectrum[idx] = Complex::new(real[idx] * 0.5, -imag[idx] * 0.5);
+            spectrum[len - idx] = Complex::new(real[idx] * 0.5, imag[idx] * 0.5);
         }
 
-        fft.process(&mut spectrum);
+        ifft.process(&mut spectrum);
 
         // Create table with padding for interpolation
-        let mut wave_table: Vec<f32> = spectrum.iter().map(|c| c.im).collect();
-        wave_table.push(wave_table[0]); // Add padding for interpolation
-
-        WaveTable {
-            wave_table: Arc::new(wave_table),
-            top_freq,
-            table_mask: len - 1,
-            table_size: len,
+        let mut wave_table: Vec<f32> = spectrum.iter().map(|c| c.re).collect();
+        // Add padding for interpolation (cubic reads two samples ahead)
+        wave_table.push(wave_table[0]);
+        wave_table.push(wave_table[1]);
+        wave_table
+    }
+
+    // Bytes of table data
+    pub(crate) fn memory_usage(&self) -> usize {
+        self.tables
+            .iter()
+            .map(|table| table.frames.len() * table.table_size * std::mem::size_of::<f32>())
+            .sum()
+    }
+
+    // Weight of the next mip level at `freq` in `table`: 0 at the bottom of the
+    // table's octave, 1 at its top bound where the next level takes over
+    #[inline]
+    fn mip_blend(&self, table: usize, freq: f32) -> f32 {
+        if table + 1 >= self.tables.len() {
+            return 0.0;
         }
+        let lower = if table == 0 {
+            BASE_FREQ
+        } else {
+            self.frequency_bounds[table - 1]
+        };
+        let upper = self.frequency_bounds[table];
+        ((freq / lower).log2() / (upper / lower).log2()).clamp(0.0, 1.0)
     }
 
     #[inline]
@@ -152,73 +248,66 @@ impl WaveTableBank {
     }
 }
 
-pub fn initialize_wave_banks(context: &AudioContext) -> anyhow::Result<()> {
-    let sample_rate = context.sample_rate();
-    let sample_rate_key = sample_rate as u32;
+const BUILTIN_WAVEFORMS: [OscillatorType; 5] = [
+    OscillatorType::Sine,
+    OscillatorType::Square,
+    OscillatorType::Sawtooth,
+    OscillatorType::Triangle,
+    OscillatorType::Pulse,
+];
 
-    let oscillator_types = [
-        OscillatorType::Sine,
-        OscillatorType::Square,
-        OscillatorType::Sawtooth,
-        OscillatorType::Triangle,
-    ];
-
-    // Single lock acquisition for the entire initialization
-    let mut banks = WAVETABLE_BANKS
-        .lock()
-        .map_err(|_| anyhow::anyhow!("Failed to acquire wavetable banks lock"))?;
-
-    // Pre-allocate with capacity
-    if banks.is_empty() {
-        banks.reserve(oscillator_types.len());
-    }
-
-    for &osc_type in &oscillator_types {
-        let key = (osc_type, sample_rate_key);
-        if !banks.contains_key(&key) {
-            println!(
-                "Initializing wave bank for {:?} at {}Hz",
-                osc_type, sample_rate_key
-            );
-            let bank = Arc::new(WaveTableBank::new(osc_type, sample_rate));
-            banks.insert(key, bank);
-        } else {
+// Builds the built-in banks at the context rate, so oscillators made later don't
+// have to
+pub fn initialize_wave_banks(context: &AudioContext) -> anyhow::Result<()> {
+    let registry = context.wave_banks();
+    for waveform in BUILTIN_WAVEFORMS {
+        if registry.is_loaded(waveform, context.sample_rate()) {
             println!(
                 "Wave bank for {:?} at {}Hz already initialized",
-                osc_type, sample_rate_key
+                waveform,
+                context.sample_rate()
             );
+        } else {
+            registry.load(waveform, context.sample_rate())?;
         }
     }
-
     Ok(())
 }
 
-// New helper function to check if banks are initialized
-pub fn are_wave_banks_initialized(sample_rate: f32) -> bool {
-    if let Ok(banks) = WAVETABLE_BANKS.lock() {
-        let sample_rate_key = sample_rate as u32;
-        [
-            OscillatorType::Sine,
-            OscillatorType::Square,
-            OscillatorType::Sawtooth,
-            OscillatorType::Triangle,
-        ]
-        .iter()
-        .all(|&osc_type| banks.contains_key(&(osc_type, sample_rate_key)))
-    } else {
-        false
-    }
+pub fn are_wave_banks_initialized(context: &AudioContext) -> bool {
+    BUILTIN_WAVEFORMS.iter().all(|&waveform| {
+        context
+            .wave_banks()
+            .is_loaded(waveform, context.sample_rate())
+    })
 }
 
 pub struct BandlimitedWavetableOscillator {
+    waveform: OscillatorType,
+    registry: Arc<WaveBankRegistry>,
     bank: Arc<WaveTableBank>,
     frequency: AudioParam,
     gain: AudioParam,
+    // Where to read in a multi-frame table, from the first frame (0) to the last (1)
+    position: AudioParam,
+    // Duty cycle for `OscillatorType::Pulse`, plus an optional audio-rate input
+    pulse_width: AudioParam,
+    pulse_width_input: Option<Box<dyn AudioNode + Send>>,
+    pulse: bool,
+    width: f32,
     phase: f32,
     phase_increment: f32,
+    // Where the phase wrapped on the way into the sample just processed, and into
+    // the next one, as samples before it
+    wrapped: Option<f32>,
+    next_wrap: Option<f32>,
     current_table: usize,
+    // Share of the next mip level when crossfading between levels
+    next_table_blend: f32,
+    mip_crossfade: bool,
     last_freq: f32,
     interpolation_mode: InterpolationType,
+    sync: HardSync,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -231,34 +320,46 @@ pub enum InterpolationType {
 
 impl BandlimitedWavetableOscillator {
     pub fn new(waveform: OscillatorType, context: &AudioContext) -> anyhow::Result<Self> {
-        let sample_rate = context.sample_rate();
-        let sample_rate_key = sample_rate as u32;
-
-        let bank = {
-            let mut banks = WAVETABLE_BANKS
-                .lock()
-                .map_err(|_| anyhow::anyhow!("Failed to acquire wavetable banks lock"))?;
-
-            let key = (waveform, sample_rate_key);
-            if let Some(bank) = banks.get(&key) {
-                bank.clone()
-            } else {
-                let bank = Arc::new(WaveTableBank::new(waveform, sample_rate));
-                banks.insert(key, bank.clone());
-                bank
-            }
-        };
+        let bank = context.wave_banks().get(waveform, context.sample_rate())?;
+        Ok(Self::with_bank(waveform, context, bank))
+    }
 
-        Ok(Self {
+    // For the audio thread: never blocks, and returns None until the bank is
+    // ready, having queued it for the context's background builder
+    pub fn try_new(waveform: OscillatorType, context: &AudioContext) -> Option<Self> {
+        let bank = context
+            .wave_banks()
+            .try_get(waveform, context.sample_rate())?;
+        Some(Self::with_bank(waveform, context, bank))
+    }
+
+    fn with_bank(
+        waveform: OscillatorType,
+        context: &AudioContext,
+        bank: Arc<WaveTableBank>,
+    ) -> Self {
+        Self {
+            waveform,
+            registry: context.wave_banks().clone(),
             bank,
             frequency: AudioParam::new(440.0, 0.01, 22050.0),
             gain: AudioParam::new(1.0, 0.0, 1.0),
+            position: AudioParam::new(0.0, 0.0, 1.0),
+            pulse_width: AudioParam::new(0.5, 0.0, 1.0),
+            pulse_width_input: None,
+            pulse: waveform == OscillatorType::Pulse,
+            width: 0.5,
             phase: 0.0,
             phase_increment: 0.0,
+            wrapped: None,
+            next_wrap: None,
             current_table: 0,
+            next_table_blend: 0.0,
+            mip_crossfade: false,
             last_freq: 0.0,
             interpolation_mode: InterpolationType::Linear,
-        })
+            sync: HardSync::new(),
+        }
     }
 
     pub fn frequency(&self) -> &AudioParam {
@@ -269,10 +370,46 @@ impl BandlimitedWavetableOscillator {
         &self.gain
     }
 
+    pub fn position(&self) -> &AudioParam {
+        &self.position
+    }
+
+    // Duty cycle of `OscillatorType::Pulse`, from 0 to 1
+    pub fn pulse_width(&self) -> &AudioParam {
+        &self.pulse_width
+    }
+
+    // Frames in the current table; 1 for the built-in shapes
+    pub fn frame_count(&self) -> usize {
+        self.bank.tables[0].frames.len()
+    }
+
+    // Restarts the waveform at `phase` (0 to 1) at the given sample
+    pub fn reset_phase(&self, at_sample: u64, phase: f32) {
+        self.sync.schedule(at_sample, phase);
+    }
+
     pub fn set_interpolation_mode(&mut self, mode: InterpolationType) {
         self.interpolation_mode = mode;
     }
 
+    // Blend into the next mip level across each octave instead of switching at the
+    // bounds, which removes timbre steps in sweeps at the cost of a second lookup
+    pub fn set_mip_crossfade(&mut self, enabled: bool) {
+        self.mip_crossfade = enabled;
+        self.last_freq = 0.0;
+    }
+
+    // Rate the current bank was built for
+    pub fn sample_rate(&self) -> f32 {
+        self.bank.sample_rate
+    }
+
+    // Upper frequency of each mip level, in Hz
+    pub fn mip_frequency_bounds(&self) -> &[f32] {
+        &self.bank.frequency_bounds
+    }
+
     #[inline(always)]
     fn linear_interpolate(&self, table: &[f32], idx: usize, frac: f32) -> f32 {
         let sample0 = table[idx];
@@ -281,19 +418,111 @@ impl BandlimitedWavetableOscillator {
     }
 
     #[inline(always)]
-    fn cubic_interpolate(&self, table: &[f32], idx: usize, frac: f32) -> f32 {
-        let y0 = table[idx.wrapping_sub(1) & self.bank.tables[self.current_table].table_mask];
+    fn cubic_interpolate(&self, table: &[f32], mask: usize, idx: usize, frac: f32) -> f32 {
+        let y0 = table[idx.wrapping_sub(1) & mask];
         let y1 = table[idx];
         let y2 = table[idx + 1];
         let y3 = table[idx + 2];
 
-        let mu2 = frac * frac;
-        let a0 = y3 - y2 - y0 + y1;
-        let a1 = y0 - y1 - a0;
-        let a2 = y2 - y0;
-        let a3 = y1;
+        cubic_interpolate(y0, y1, y2, y3, frac)
+    }
+
+    #[inline(always)]
+    fn read_table(&self, table: &[f32], mask: usize, idx: usize, frac: f32) -> f32 {
+        match self.interpolation_mode {
+            InterpolationType::Linear => self.linear_interpolate(table, idx, frac),
+            InterpolationType::Cubic => self.cubic_interpolate(table, mask, idx, frac),
+            #[cfg(target_arch = "x86_64")]
+            InterpolationType::Simd => unsafe { self.simd_interpolate(table, idx, frac) },
+        }
+    }
+
+    // Read one mip level at `phase`, blending `frame` into the next frame
+    #[inline(always)]
+    fn read_level(&self, phase: f32, level: usize, frame: usize, frame_blend: f32) -> f32 {
+        let table = &self.bank.tables[level];
+
+        // Calculate table indices
+        let temp = phase * table.table_size as f32;
+        let int_part = temp as usize;
+        let frac_part = temp - int_part as f32;
+        let idx = int_part & table.table_mask;
+
+        let a = self.read_table(&table.frames[frame], table.table_mask, idx, frac_part);
+        if frame_blend > 0.0 {
+            let b = self.read_table(&table.frames[frame + 1], table.table_mask, idx, frac_part);
+            a + (b - a) * frame_blend
+        } else {
+            a
+        }
+    }
+
+    // Mip level for `freq` and the share of the level above it
+    #[inline]
+    pub(crate) fn select_table(&self, freq: f32) -> (usize, f32) {
+        let table = self.bank.find_table_index(freq);
+        let blend = if self.mip_crossfade {
+            self.bank.mip_blend(table, freq)
+        } else {
+            0.0
+        };
+        (table, blend)
+    }
+
+    // Frame to read at the current position and the share of the frame after it
+    #[inline]
+    pub(crate) fn select_frame(&self, current_sample: u64) -> (usize, f32) {
+        let frame_count = self.bank.tables[0].frames.len();
+        if frame_count == 1 {
+            return (0, 0.0);
+        }
+        let position =
+            self.position.get_value(current_sample).clamp(0.0, 1.0) * (frame_count - 1) as f32;
+        let frame = (position as usize).min(frame_count - 2);
+        (frame, position - frame as f32)
+    }
+
+    #[inline(always)]
+    fn read_wave(
+        &self,
+        phase: f32,
+        (table, table_blend): (usize, f32),
+        (frame, frame_blend): (usize, f32),
+    ) -> f32 {
+        let output = self.read_level(phase, table, frame, frame_blend);
+        if table_blend > 0.0 {
+            let next = self.read_level(phase, table + 1, frame, frame_blend);
+            output + (next - output) * table_blend
+        } else {
+            output
+        }
+    }
+
+    // Updates the pulse width for this sample, running the modulation input
+    pub(crate) fn update_pulse_width(&mut self, context: &AudioContext, current_sample: u64) {
+        if !self.pulse {
+            return;
+        }
+        let modulation = match self.pulse_width_input.as_mut() {
+            Some(input) => input.process(context, current_sample),
+            None => 0.0,
+        };
+        self.width = (self.pulse_width.get_value(current_sample) + modulation).clamp(0.0, 1.0);
+    }
+
+    // One sample at `phase` from a table chosen by `select_table` and `select_frame`.
+    // Lets other nodes play several phases from this oscillator's bank.
+    #[inline(always)]
+    pub(crate) fn read(&self, phase: f32, table: (usize, f32), frame: (usize, f32)) -> f32 {
+        if !self.pulse {
+            return self.read_wave(phase, table, frame);
+        }
 
-        a0 * frac * mu2 + a1 * mu2 + a2 * frac + a3
+        // The difference of two saws a pulse width apart steps up at phase 0 and
+        // down at the width, and each saw is band-limited. Table lookups wrap, so
+        // the shifted phase doesn't need to.
+        let shifted = self.read_wave(phase + 1.0 - self.width, table, frame);
+        shifted - self.read_wave(phase, table, frame) + 2.0 * self.width - 1.0
     }
 
     #[cfg(target_arch = "x86_64")]
@@ -309,34 +538,37 @@ impl BandlimitedWavetableOscillator {
 impl AudioNode for BandlimitedWavetableOscillator {
     fn process(&mut self, context: &AudioContext, current_sample: u64) -> f32 {
         let freq = self.frequency.get_value(current_sample);
+        self.wrapped = self.next_wrap.take();
 
         // Update phase increment and table selection only if frequency changed
         if freq != self.last_freq {
             self.phase_increment = freq / context.sample_rate();
             self.last_freq = freq;
-            self.current_table = self.bank.find_table_index(freq);
+            (self.current_table, self.next_table_blend) = self.select_table(freq);
         }
 
-        let table = &self.bank.tables[self.current_table].wave_table;
-        let table_size = self.bank.tables[self.current_table].table_size as f32;
-        let table_mask = self.bank.tables[self.current_table].table_mask;
-
-        // Calculate table indices
-        let temp = self.phase * table_size;
-        let int_part = temp as usize;
-        let frac_part = temp - int_part as f32;
-        let idx = int_part & table_mask;
+        // Crossfade the two frames either side of the position. Every frame has its
+        // own mip levels, so the blend is as band-limited as its parts.
+        let frame = self.select_frame(current_sample);
+        let table = (self.current_table, self.next_table_blend);
+        self.update_pulse_width(context, current_sample);
+
+        // Jump to the reset phase, as if it happened `delay` samples ago. The tables
+        // are band-limited but the jump isn't, so its step is corrected separately.
+        let reset = self.sync.poll(context, current_sample);
+        let mut step = 0.0;
+        if let Some(reset) = reset {
+            let before = (self.phase - reset.delay * self.phase_increment).rem_euclid(1.0);
+            step = self.read(reset.phase, table, frame) - self.read(before, table, frame);
+            self.phase = (reset.phase + reset.delay * self.phase_increment).rem_euclid(1.0);
+        }
 
-        // Interpolate based on selected mode
-        let output = match self.interpolation_mode {
-            InterpolationType::Linear => self.linear_interpolate(table, idx, frac_part),
-            InterpolationType::Cubic => self.cubic_interpolate(table, idx, frac_part),
-            #[cfg(target_arch = "x86_64")]
-            InterpolationType::Simd => unsafe { self.simd_interpolate(table, idx, frac_part) },
-        };
+        let output = self.read(self.phase, table, frame);
+        let output = self.sync.finish(output, reset, step);
 
         // Update phase
         self.phase += self.phase_increment;
+        self.next_wrap = wrap_delay(self.phase, self.phase_increment);
         if self.phase >= 1.0 {
             self.phase -= 1.0;
         }
@@ -348,16 +580,75 @@ impl AudioNode for BandlimitedWavetableOscillator {
         match name {
             "frequency" => self.frequency.set_value(value),
             "gain" => self.gain.set_value(value),
+            "position" => self.position.set_value(value),
+            "pulse_width" => self.pulse_width.set_value(value),
+            "phase" => self.reset_phase(0, value),
             _ => {}
         }
     }
 
-    fn connect_input(&mut self, _name: &str, _node: Box<dyn AudioNode + Send>) {
-        // Oscillators don't have inputs
+    fn set_parameter_at(&self, name: &str, value: f32, at_sample: u64) {
+        match name {
+            "frequency" => self.frequency.set_value_at_time(value, at_sample),
+            "gain" => self.gain.set_value_at_time(value, at_sample),
+            "position" => self.position.set_value_at_time(value, at_sample),
+            "pulse_width" => self.pulse_width.set_value_at_time(value, at_sample),
+            "phase" => self.reset_phase(at_sample, value),
+            _ => {}
+        }
+    }
+
+    // "sync" hard-syncs this oscillator to another, restarting it each time the
+    // other's phase wraps, or for nodes without a phase, each time they cross zero
+    // going up. "pulse_width" adds an audio-rate signal to the pulse width.
+    fn connect_input(&mut self, name: &str, node: Box<dyn AudioNode + Send>) {
+        match name {
+            "sync" => self.sync.connect(node),
+            "pulse_width" => self.pulse_width_input = Some(node),
+            _ => {}
+        }
     }
 
-    fn clear_input(&mut self, _input_name: &str) {
-        // No-op for oscillators
+    fn clear_input(&mut self, input_name: &str) {
+        match input_name {
+            "sync" => self.sync.disconnect(),
+            "pulse_width" => self.pulse_width_input = None,
+            _ => {}
+        }
+    }
+
+    fn sync_out(&self) -> SyncOut {
+        self.wrapped.map_or(SyncOut::Running, SyncOut::Wrapped)
+    }
+
+    // Swaps in the bank for the new rate; mip bounds and the phase increment
+    // depend on it
+    fn prepare(&mut self, sample_rate: f32, max_block: usize) {
+        for param in [
+            &self.frequency,
+            &self.gain,
+            &self.position,
+            &self.pulse_width,
+        ] {
+            param.prepare(sample_rate);
+        }
+        if let Some(input) = self.pulse_width_input.as_mut() {
+            input.prepare(sample_rate, max_block);
+        }
+        self.sync.prepare(sample_rate, max_block);
+
+        if self.bank.sample_rate != sample_rate {
+            match self.registry.get(self.waveform, sample_rate) {
+                Ok(bank) => {
+                    self.bank = bank;
+                    self.last_freq = 0.0;
+                }
+                Err(e) => eprintln!(
+                    "No wave bank for {:?} at {}Hz: {}",
+                    self.waveform, sample_rate, e
+                ),
+            }
+        }
     }
 
     fn clone_box(&self) -> Box<dyn AudioNode + Send> {
@@ -368,14 +659,29 @@ impl AudioNode for BandlimitedWavetableOscillator {
 impl Clone for BandlimitedWavetableOscillator {
     fn clone(&self) -> Self {
         Self {
+            waveform: self.waveform,
+            registry: self.registry.clone(),
             bank: self.bank.clone(),
             frequency: self.frequency.clone(),
             gain: self.gain.clone(),
+            position: self.position.clone(),
+            pulse_width: self.pulse_width.clone(),
+            pulse_width_input: self
+                .pulse_width_input
+                .as_ref()
+                .map(|input| input.clone_box()),
+            pulse: self.pulse,
+            width: self.width,
             phase: self.phase,
             phase_increment: self.phase_increment,
+            wrapped: self.wrapped,
+            next_wrap: self.next_wrap,
             current_table: self.current_table,
+            next_table_blend: self.next_table_blend,
+            mip_crossfade: self.mip_crossfade,
             last_freq: self.last_freq,
             interpolation_mode: self.interpolation_mode,
+            sync: self.sync.clone(),
         }
     }
 }
@@ -0,0 +1,299 @@
+// src/synth/buffer_source.rs
+
+use crate::synth::audio_buffer::AudioBuffer;
+use crate::synth::audio_context::{rate_change, rescale_sample, AudioContext};
+use crate::synth::audio_node::AudioNode;
+use crate::synth::audio_param::AudioParam;
+use crate::synth::interpolation::cubic_interpolate;
+use std::sync::Arc;
+
+/// Plays an in-memory `AudioBuffer`, like Web Audio's `AudioBufferSourceNode`.
+///
+/// Playback starts either from `start` or on a rising edge of the `gate` parameter,
+/// in which case the gate value acts as velocity. That makes the node usable as a
+/// drum voice behind a `StepSequencer`.
+pub struct BufferSourceNode {
+    buffer: Option<Arc<AudioBuffer>>,
+    playback_rate: AudioParam,
+    detune: AudioParam,
+    gain: AudioParam,
+    gate: AudioParam,
+    looping: bool,
+    // Loop points in seconds; an end of 0.0 means the end of the buffer
+    loop_start: f64,
+    loop_end: f64,
+    scheduled_start: Option<(u64, f64)>,
+    scheduled_stop: Option<u64>,
+    // Rate the scheduled samples are counted at, 0 until prepared
+    sample_rate: f32,
+    // Read position in buffer frames
+    position: f64,
+    playing: bool,
+    velocity: f32,
+    last_gate: f32,
+}
+
+impl BufferSourceNode {
+    pub fn new(buffer: Option<Arc<AudioBuffer>>) -> Self {
+        Self {
+            buffer,
+            playback_rate: AudioParam::new(1.0, 0.0, 32.0),
+            detune: AudioParam::new(0.0, -4800.0, 4800.0),
+            gain: AudioParam::new(1.0, 0.0, 1.0),
+            gate: AudioParam::new(0.0, 0.0, 1.0),
+            looping: false,
+            loop_start: 0.0,
+            loop_end: 0.0,
+            scheduled_start: None,
+            scheduled_stop: None,
+            sample_rate: 0.0,
+            position: 0.0,
+            playing: false,
+            velocity: 1.0,
+            last_gate: 0.0,
+        }
+    }
+
+    pub fn playback_rate(&self) -> &AudioParam {
+        &self.playback_rate
+    }
+
+    // Pitch offset in cents, combined with the playback rate
+    pub fn detune(&self) -> &AudioParam {
+        &self.detune
+    }
+
+    pub fn gain(&self) -> &AudioParam {
+        &self.gain
+    }
+
+    pub fn gate(&self) -> &AudioParam {
+        &self.gate
+    }
+
+    pub fn buffer(&self) -> Option<&Arc<AudioBuffer>> {
+        self.buffer.as_ref()
+    }
+
+    pub fn set_buffer(&mut self, buffer: Option<Arc<AudioBuffer>>) {
+        self.buffer = buffer;
+        self.playing = false;
+        self.position = 0.0;
+    }
+
+    pub fn set_loop(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    pub fn set_loop_points(&mut self, loop_start: f64, loop_end: f64) {
+        self.loop_start = loop_start.max(0.0);
+        self.loop_end = loop_end.max(0.0);
+    }
+
+    // Start playing at `at_sample`, `offset` seconds into the buffer
+    pub fn start(&mut self, at_sample: u64, offset: f64) {
+        self.scheduled_start = Some((at_sample, offset.max(0.0)));
+        self.scheduled_stop = None;
+    }
+
+    pub fn stop(&mut self, at_sample: u64) {
+        self.scheduled_stop = Some(at_sample);
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    // Loop region in frames, if looping over a non-empty region
+    fn loop_frames(&self, buffer: &AudioBuffer) -> Option<(f64, f64)> {
+        if !self.looping {
+            return None;
+        }
+        let length = buffer.length() as f64;
+        let rate = buffer.sample_rate() as f64;
+        let start = (self.loop_start * rate).min(length);
+        let end = if self.loop_end > 0.0 {
+            (self.loop_end * rate).min(length)
+        } else {
+            length
+        };
+        (end > start).then_some((start, end))
+    }
+
+    #[inline]
+    fn read_frame(
+        buffer: &AudioBuffer,
+        channel: usize,
+        index: i64,
+        loop_frames: Option<(f64, f64)>,
+    ) -> f32 {
+        let data = buffer.channel(channel);
+        let mut index = index;
+        if let Some((start, end)) = loop_frames {
+            let (start, end) = (start as i64, end as i64);
+            if index >= end && end > start {
+                index = start + (index - end) % (end - start);
+            }
+        }
+        if index < 0 || index as usize >= data.len() {
+            0.0
+        } else {
+            data[index as usize]
+        }
+    }
+
+    fn interpolate(&self, buffer: &AudioBuffer, channel: usize) -> f32 {
+        let loop_frames = self.loop_frames(buffer);
+        let index = self.position.floor() as i64;
+        let frac = (self.position - index as f64) as f32;
+
+        let y0 = Self::read_frame(buffer, channel, index - 1, loop_frames);
+        let y1 = Self::read_frame(buffer, channel, index, loop_frames);
+        let y2 = Self::read_frame(buffer, channel, index + 1, loop_frames);
+        let y3 = Self::read_frame(buffer, channel, index + 2, loop_frames);
+        cubic_interpolate(y0, y1, y2, y3, frac)
+    }
+
+    fn update_transport(&mut self, current_sample: u64) {
+        let gate = self.gate.get_value(current_sample);
+        if gate > 0.0 && self.last_gate <= 0.0 {
+            self.position = 0.0;
+            self.velocity = gate;
+            self.playing = true;
+        }
+        self.last_gate = gate;
+
+        if let Some((at_sample, offset)) = self.scheduled_start {
+            if current_sample >= at_sample {
+                let rate = self.buffer.as_ref().map_or(0.0, |b| b.sample_rate() as f64);
+                self.position = offset * rate;
+                self.velocity = 1.0;
+                self.playing = true;
+                self.scheduled_start = None;
+            }
+        }
+
+        if let Some(at_sample) = self.scheduled_stop {
+            if current_sample >= at_sample {
+                self.playing = false;
+                self.scheduled_stop = None;
+            }
+        }
+    }
+}
+
+impl AudioNode for BufferSourceNode {
+    fn process(&mut self, context: &AudioContext, current_sample: u64) -> f32 {
+        let (left, right) = self.process_stereo(context, current_sample);
+        let channels = self.buffer.as_ref().map_or(1, |b| b.number_of_channels());
+        if channels == 1 {
+            left
+        } else {
+            (left + right) * 0.5
+        }
+    }
+
+    fn process_stereo(&mut self, context: &AudioContext, current_sample: u64) -> (f32, f32) {
+        self.update_transport(current_sample);
+        if !self.playing {
+            return (0.0, 0.0);
+        }
+        let Some(buffer) = self.buffer.clone() else {
+            self.playing = false;
+            return (0.0, 0.0);
+        };
+
+        let left = self.interpolate(&buffer, 0);
+        let right = if buffer.number_of_channels() > 1 {
+            self.interpolate(&buffer, 1)
+        } else {
+            left
+        };
+        let gain = self.gain.get_value(current_sample) * self.velocity;
+
+        // Advance, folding the rate conversion between buffer and context in
+        let rate = self.playback_rate.get_value(current_sample) as f64
+            * 2.0f64.powf(self.detune.get_value(current_sample) as f64 / 1200.0)
+            * buffer.sample_rate() as f64
+            / context.sample_rate() as f64;
+        self.position += rate;
+
+        match self.loop_frames(&buffer) {
+            Some((start, end)) if self.position >= end => {
+                self.position = start + (self.position - end) % (end - start);
+            }
+            None if self.position >= buffer.length() as f64 => {
+                self.playing = false;
+            }
+            _ => {}
+        }
+
+        (left * gain, right * gain)
+    }
+
+    fn set_parameter(&self, name: &str, value: f32) {
+        match name {
+            "playback_rate" => self.playback_rate.set_value(value),
+            "detune" => self.detune.set_value(value),
+            "gain" => self.gain.set_value(value),
+            "gate" => self.gate.set_value(value),
+            _ => {}
+        }
+    }
+
+    fn set_parameter_at(&self, name: &str, value: f32, at_sample: u64) {
+        match name {
+            "playback_rate" => self.playback_rate.set_value_at_time(value, at_sample),
+            "detune" => self.detune.set_value_at_time(value, at_sample),
+            "gain" => self.gain.set_value_at_time(value, at_sample),
+            "gate" => self.gate.set_value_at_time(value, at_sample),
+            _ => {}
+        }
+    }
+
+    fn connect_input(&mut self, _name: &str, _node: Box<dyn AudioNode + Send>) {
+        // Sources don't have inputs
+    }
+
+    fn clear_input(&mut self, _input_name: &str) {
+        // No-op for sources
+    }
+
+    fn prepare(&mut self, sample_rate: f32, _max_block: usize) {
+        for param in [&self.playback_rate, &self.detune, &self.gain, &self.gate] {
+            param.prepare(sample_rate);
+        }
+        if let Some(ratio) = rate_change(&mut self.sample_rate, sample_rate) {
+            self.scheduled_start = self
+                .scheduled_start
+                .map(|(at, offset)| (rescale_sample(at, ratio), offset));
+            self.scheduled_stop = self.scheduled_stop.map(|at| rescale_sample(at, ratio));
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn AudioNode + Send> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for BufferSourceNode {
+    fn clone(&self) -> Self {
+        Self {
+            buffer: self.buffer.clone(),
+            playback_rate: self.playback_rate.clone(),
+            detune: self.detune.clone(),
+            gain: self.gain.clone(),
+            gate: self.gate.clone(),
+            looping: self.looping,
+            loop_start: self.loop_start,
+            loop_end: self.loop_end,
+            scheduled_start: self.scheduled_start,
+            scheduled_stop: self.scheduled_stop,
+            sample_rate: self.sample_rate,
+            position: self.position,
+            playing: self.playing,
+            velocity: self.velocity,
+            last_gate: self.last_gate,
+        }
+    }
+}
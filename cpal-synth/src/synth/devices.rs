@@ -0,0 +1,382 @@
+// src/synth/devices.rs
+
+use crate::synth::backend::{BackendConfig, SampleFormat};
+use cpal::traits::{DeviceTrait, HostTrait};
+use std::fmt;
+
+/// An audio host (ALSA, JACK, WASAPI, CoreAudio...) available on this machine.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HostInfo {
+    pub name: String,
+    pub is_default: bool,
+}
+
+/// One range of stream formats a device supports.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SupportedConfig {
+    pub channels: usize,
+    pub min_sample_rate: f32,
+    pub max_sample_rate: f32,
+    pub sample_format: SampleFormat,
+    // Frames per buffer; `None` when the host doesn't say
+    pub buffer_size_range: Option<(usize, usize)>,
+}
+
+impl SupportedConfig {
+    // Whether a stream opened with `config` fits this range. Fields left as
+    // `None` match anything.
+    pub fn supports(&self, config: &BackendConfig) -> bool {
+        config
+            .channels
+            .is_none_or(|channels| channels == self.channels)
+            && config
+                .sample_rate
+                .is_none_or(|rate| self.min_sample_rate <= rate && rate <= self.max_sample_rate)
+            && config
+                .sample_format
+                .is_none_or(|format| format == self.sample_format)
+            && match (config.buffer_size, self.buffer_size_range) {
+                (Some(size), Some((min, max))) => min <= size && size <= max,
+                _ => true,
+            }
+    }
+}
+
+impl fmt::Display for SupportedConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ch, ", self.channels)?;
+        if self.min_sample_rate == self.max_sample_rate {
+            write!(f, "{} Hz", self.min_sample_rate)?;
+        } else {
+            write!(f, "{}-{} Hz", self.min_sample_rate, self.max_sample_rate)?;
+        }
+        write!(f, ", {:?}", self.sample_format)?;
+        if let Some((min, max)) = self.buffer_size_range {
+            write!(f, ", {}-{} frames", min, max)?;
+        }
+        Ok(())
+    }
+}
+
+/// An input or output device and the formats it supports.
+#[derive(Clone, Debug)]
+pub struct DeviceInfo {
+    pub name: String,
+    pub host: String,
+    pub is_default: bool,
+    // The device's preferred format, at a single rate
+    pub default_config: Option<SupportedConfig>,
+    pub supported_configs: Vec<SupportedConfig>,
+}
+
+pub fn available_hosts() -> Vec<HostInfo> {
+    let default = cpal::default_host().id();
+    cpal::available_hosts()
+        .into_iter()
+        .map(|id| HostInfo {
+            name: id.name().to_string(),
+            is_default: id == default,
+        })
+        .collect()
+}
+
+// Lists the output devices of a host, or of the default host for `None`
+pub fn output_devices(host: Option<&str>) -> anyhow::Result<Vec<DeviceInfo>> {
+    list_devices(host, Direction::Output)
+}
+
+// Lists the capture devices of a host, or of the default host for `None`
+pub fn input_devices(host: Option<&str>) -> anyhow::Result<Vec<DeviceInfo>> {
+    list_devices(host, Direction::Input)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Direction {
+    Input,
+    Output,
+}
+
+impl Direction {
+    fn label(self) -> &'static str {
+        match self {
+            Direction::Input => "input",
+            Direction::Output => "output",
+        }
+    }
+
+    fn default_device(self, host: &cpal::Host) -> Option<cpal::Device> {
+        match self {
+            Direction::Input => host.default_input_device(),
+            Direction::Output => host.default_output_device(),
+        }
+    }
+
+    fn devices(self, host: &cpal::Host) -> anyhow::Result<Vec<cpal::Device>> {
+        Ok(match self {
+            Direction::Input => host.input_devices()?.collect(),
+            Direction::Output => host.output_devices()?.collect(),
+        })
+    }
+
+    fn default_config(self, device: &cpal::Device) -> anyhow::Result<cpal::SupportedStreamConfig> {
+        Ok(match self {
+            Direction::Input => device.default_input_config()?,
+            Direction::Output => device.default_output_config()?,
+        })
+    }
+
+    fn ranges(
+        self,
+        device: &cpal::Device,
+    ) -> anyhow::Result<Vec<cpal::SupportedStreamConfigRange>> {
+        Ok(match self {
+            Direction::Input => device.supported_input_configs()?.collect(),
+            Direction::Output => device.supported_output_configs()?.collect(),
+        })
+    }
+}
+
+fn list_devices(host: Option<&str>, direction: Direction) -> anyhow::Result<Vec<DeviceInfo>> {
+    let host = find_host(host)?;
+    let default_name = direction
+        .default_device(&host)
+        .and_then(|device| device.name().ok());
+
+    let mut devices = Vec::new();
+    for device in direction.devices(&host)? {
+        let name = match device.name() {
+            Ok(name) => name,
+            Err(_) => continue,
+        };
+        // Devices that are busy or unplugged can fail to report; list them anyway
+        let supported_configs = direction
+            .ranges(&device)
+            .map(|ranges| ranges.iter().filter_map(supported_config).collect())
+            .unwrap_or_default();
+        let default_config = direction
+            .default_config(&device)
+            .ok()
+            .and_then(|config| default_supported_config(&config));
+        devices.push(DeviceInfo {
+            is_default: default_name.as_deref() == Some(name.as_str()),
+            name,
+            host: host.id().name().to_string(),
+            default_config,
+            supported_configs,
+        });
+    }
+    Ok(devices)
+}
+
+pub(crate) fn find_host(name: Option<&str>) -> anyhow::Result<cpal::Host> {
+    let name = match name {
+        Some(name) => name,
+        None => return Ok(cpal::default_host()),
+    };
+    let id = cpal::available_hosts()
+        .into_iter()
+        .find(|id| id.name().eq_ignore_ascii_case(name))
+        .ok_or_else(|| {
+            let hosts: Vec<String> = available_hosts()
+                .into_iter()
+                .map(|host| host.name)
+                .collect();
+            anyhow::anyhow!(
+                "Audio host '{}' is not available, try one of: {}",
+                name,
+                hosts.join(", ")
+            )
+        })?;
+    Ok(cpal::host_from_id(id)?)
+}
+
+pub(crate) fn find_output_device(
+    host: &cpal::Host,
+    name: Option<&str>,
+) -> anyhow::Result<cpal::Device> {
+    find_device(host, name, Direction::Output)
+}
+
+pub(crate) fn find_input_device(
+    host: &cpal::Host,
+    name: Option<&str>,
+) -> anyhow::Result<cpal::Device> {
+    find_device(host, name, Direction::Input)
+}
+
+fn find_device(
+    host: &cpal::Host,
+    name: Option<&str>,
+    direction: Direction,
+) -> anyhow::Result<cpal::Device> {
+    let name = match name {
+        Some(name) => name,
+        None => {
+            return direction
+                .default_device(host)
+                .ok_or_else(|| anyhow::anyhow!("No {} device available", direction.label()))
+        }
+    };
+    let mut names = Vec::new();
+    for device in direction.devices(host)? {
+        if let Ok(device_name) = device.name() {
+            if device_name == name {
+                return Ok(device);
+            }
+            names.push(device_name);
+        }
+    }
+    Err(anyhow::anyhow!(
+        "No {} device named '{}' on {}, found: {}",
+        direction.label(),
+        name,
+        host.id().name(),
+        if names.is_empty() {
+            "none".to_string()
+        } else {
+            names.join(", ")
+        }
+    ))
+}
+
+// The stream config for a request: the device default when nothing is asked
+// for, otherwise the first supported range that fits, defaulting the unasked
+// fields to the device's preference
+pub(crate) fn select_output_config(
+    device: &cpal::Device,
+    request: &BackendConfig,
+) -> anyhow::Result<(cpal::StreamConfig, cpal::SampleFormat)> {
+    select_config(device, request, Direction::Output)
+}
+
+pub(crate) fn select_input_config(
+    device: &cpal::Device,
+    request: &BackendConfig,
+) -> anyhow::Result<(cpal::StreamConfig, cpal::SampleFormat)> {
+    select_config(device, request, Direction::Input)
+}
+
+fn select_config(
+    device: &cpal::Device,
+    request: &BackendConfig,
+    direction: Direction,
+) -> anyhow::Result<(cpal::StreamConfig, cpal::SampleFormat)> {
+    let default = direction.default_config(device)?;
+    let buffer_fits = |range: Option<(usize, usize)>| match (request.buffer_size, range) {
+        (Some(size), Some((min, max))) => min <= size && size <= max,
+        _ => true,
+    };
+    if request.sample_rate.is_none()
+        && request.channels.is_none()
+        && request.sample_format.is_none()
+        && buffer_fits(buffer_size_range(default.buffer_size()))
+    {
+        let mut config = default.config();
+        if let Some(size) = request.buffer_size {
+            config.buffer_size = cpal::BufferSize::Fixed(size as u32);
+        }
+        return Ok((config, default.sample_format()));
+    }
+
+    let ranges = direction.ranges(device)?;
+
+    let wanted = BackendConfig {
+        sample_rate: Some(
+            request
+                .sample_rate
+                .unwrap_or(default.sample_rate().0 as f32),
+        ),
+        buffer_size: request.buffer_size,
+        channels: Some(request.channels.unwrap_or(default.channels() as usize)),
+        sample_format: request.sample_format,
+        dither: request.dither,
+    };
+    let default_format = sample_format_from_cpal(default.sample_format());
+
+    // The default format is tried first when none was asked for
+    let mut candidates: Vec<&cpal::SupportedStreamConfigRange> = ranges
+        .iter()
+        .filter(|range| supported_config(range).is_some_and(|config| config.supports(&wanted)))
+        .collect();
+    candidates
+        .sort_by_key(|range| sample_format_from_cpal(range.sample_format()) != default_format);
+
+    let range = match candidates.first() {
+        Some(range) => **range,
+        None => {
+            let supported: Vec<String> = ranges
+                .iter()
+                .filter_map(supported_config)
+                .map(|config| format!("  {}", config))
+                .collect();
+            return Err(anyhow::anyhow!(
+                "{} doesn't support {} {} channels at {} Hz{}{}, supported configs:\n{}",
+                device.name().unwrap_or_default(),
+                direction.label(),
+                wanted.channels.unwrap_or_default(),
+                wanted.sample_rate.unwrap_or_default(),
+                request
+                    .sample_format
+                    .map(|format| format!(" as {:?}", format))
+                    .unwrap_or_default(),
+                request
+                    .buffer_size
+                    .map(|size| format!(" with {}-frame buffers", size))
+                    .unwrap_or_default(),
+                supported.join("\n")
+            ));
+        }
+    };
+
+    let rate = cpal::SampleRate(wanted.sample_rate.unwrap_or_default().round() as u32);
+    let supported = range.with_sample_rate(rate);
+    let mut config = supported.config();
+    if let Some(size) = request.buffer_size {
+        config.buffer_size = cpal::BufferSize::Fixed(size as u32);
+    }
+    Ok((config, supported.sample_format()))
+}
+
+pub(crate) fn sample_format_from_cpal(format: cpal::SampleFormat) -> Option<SampleFormat> {
+    Some(match format {
+        cpal::SampleFormat::I8 => SampleFormat::I8,
+        cpal::SampleFormat::I16 => SampleFormat::I16,
+        cpal::SampleFormat::I32 => SampleFormat::I32,
+        cpal::SampleFormat::I64 => SampleFormat::I64,
+        cpal::SampleFormat::U8 => SampleFormat::U8,
+        cpal::SampleFormat::U16 => SampleFormat::U16,
+        cpal::SampleFormat::U32 => SampleFormat::U32,
+        cpal::SampleFormat::U64 => SampleFormat::U64,
+        cpal::SampleFormat::F32 => SampleFormat::F32,
+        cpal::SampleFormat::F64 => SampleFormat::F64,
+        _ => return None,
+    })
+}
+
+fn buffer_size_range(size: &cpal::SupportedBufferSize) -> Option<(usize, usize)> {
+    match size {
+        cpal::SupportedBufferSize::Range { min, max } => Some((*min as usize, *max as usize)),
+        cpal::SupportedBufferSize::Unknown => None,
+    }
+}
+
+// `None` for formats newer than this crate
+fn supported_config(range: &cpal::SupportedStreamConfigRange) -> Option<SupportedConfig> {
+    Some(SupportedConfig {
+        channels: range.channels() as usize,
+        min_sample_rate: range.min_sample_rate().0 as f32,
+        max_sample_rate: range.max_sample_rate().0 as f32,
+        sample_format: sample_format_from_cpal(range.sample_format())?,
+        buffer_size_range: buffer_size_range(range.buffer_size()),
+    })
+}
+
+fn default_supported_config(config: &cpal::SupportedStreamConfig) -> Option<SupportedConfig> {
+    Some(SupportedConfig {
+        channels: config.channels() as usize,
+        min_sample_rate: config.sample_rate().0 as f32,
+        max_sample_rate: config.sample_rate().0 as f32,
+        sample_format: sample_format_from_cpal(config.sample_format())?,
+        buffer_size_range: buffer_size_range(config.buffer_size()),
+    })
+}
@@ -0,0 +1,138 @@
+// src/synth/dither.rs
+
+use crate::synth::random::XorShiftRng;
+
+// Error feedback filters, applied to past quantisation errors. First order
+// tilts the noise up by 6dB per octave; Lipshitz's five-tap E-weighted filter
+// pushes it out of the ear's most sensitive band at 44.1kHz.
+const FIRST_ORDER: [f32; 1] = [1.0];
+const LIPSHITZ: [f32; 5] = [2.033, -2.165, 1.959, -1.590, 0.6149];
+const MAX_TAPS: usize = 5;
+
+// Above this many bits an f32 has no precision left to dither
+const MAX_DITHER_BITS: u32 = 24;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NoiseShaping {
+    #[default]
+    None,
+    FirstOrder,
+    Lipshitz,
+}
+
+impl NoiseShaping {
+    fn coefficients(&self) -> &'static [f32] {
+        match self {
+            NoiseShaping::None => &[],
+            NoiseShaping::FirstOrder => &FIRST_ORDER,
+            NoiseShaping::Lipshitz => &LIPSHITZ,
+        }
+    }
+}
+
+/// How to reduce float audio to an integer format. TPDF dither adds two least
+/// significant bits of triangular noise, turning truncation distortion into a
+/// steady noise floor; noise shaping then moves that floor towards the top of
+/// the spectrum. The default is plain TPDF.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Dither {
+    pub tpdf: bool,
+    pub noise_shaping: NoiseShaping,
+}
+
+impl Dither {
+    // Plain rounding
+    pub fn off() -> Self {
+        Self {
+            tpdf: false,
+            noise_shaping: NoiseShaping::None,
+        }
+    }
+
+    pub fn shaped(noise_shaping: NoiseShaping) -> Self {
+        Self {
+            tpdf: true,
+            noise_shaping,
+        }
+    }
+}
+
+impl Default for Dither {
+    fn default() -> Self {
+        Self::shaped(NoiseShaping::None)
+    }
+}
+
+/// Rounds interleaved float samples to the steps of a `bits`-bit integer format,
+/// keeping them as floats in [-1, 1). Multiplying by 2^(bits - 1) then gives the
+/// integer exactly. Formats wider than 24 bits are passed through.
+pub struct Quantizer {
+    dither: Dither,
+    channels: usize,
+    scale: f32,
+    min: f32,
+    max: f32,
+    // Past errors per channel, most recent first
+    errors: Vec<[f32; MAX_TAPS]>,
+    rng: XorShiftRng,
+}
+
+impl Quantizer {
+    pub fn new(dither: Dither, bits: u32, channels: usize) -> Self {
+        let bits = bits.clamp(2, 64);
+        let scale = 2f32.powi(bits as i32 - 1);
+        Self {
+            dither,
+            channels: channels.max(1),
+            scale,
+            min: -1.0,
+            max: 1.0 - 1.0 / scale,
+            errors: vec![[0.0; MAX_TAPS]; channels.max(1)],
+            rng: XorShiftRng::new(0x5EED_D17E),
+        }
+    }
+
+    // Whether `process` changes anything at this width
+    pub fn is_active(&self) -> bool {
+        self.scale <= 2f32.powi(MAX_DITHER_BITS as i32 - 1)
+    }
+
+    pub fn set_seed(&mut self, seed: u32) {
+        self.rng = XorShiftRng::new(seed);
+    }
+
+    pub fn reset(&mut self) {
+        for errors in &mut self.errors {
+            *errors = [0.0; MAX_TAPS];
+        }
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        if !self.is_active() {
+            return;
+        }
+        let coefficients = self.dither.noise_shaping.coefficients();
+        for frame in samples.chunks_mut(self.channels) {
+            for (sample, errors) in frame.iter_mut().zip(self.errors.iter_mut()) {
+                let feedback: f32 = coefficients
+                    .iter()
+                    .zip(errors.iter())
+                    .map(|(c, e)| c * e)
+                    .sum();
+                let target = *sample - feedback;
+
+                let noise = if self.dither.tpdf {
+                    self.rng.next_f32() - self.rng.next_f32()
+                } else {
+                    0.0
+                };
+                let quantized = ((target * self.scale + noise).round()) / self.scale;
+
+                // The error is taken before clipping so overloads don't feed back
+                errors.copy_within(0..MAX_TAPS - 1, 1);
+                errors[0] = quantized - target;
+                *sample = quantized.clamp(self.min, self.max);
+            }
+        }
+    }
+}
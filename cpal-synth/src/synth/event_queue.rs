@@ -0,0 +1,66 @@
+// src/synth/event_queue.rs
+
+use crate::synth::audio_context::rescale_sample;
+use std::sync::Mutex;
+
+// Events a node can hold at once; past this the latest ones are dropped
+pub const EVENT_QUEUE_CAPACITY: usize = 32;
+
+// Note events scheduled from any thread and taken on the audio thread, kept
+// sorted by sample. The storage is reserved up front so neither side allocates.
+pub struct EventQueue<T> {
+    events: Mutex<Vec<(u64, T)>>,
+}
+
+impl<T: Copy> EventQueue<T> {
+    pub fn new() -> Self {
+        Self {
+            events: Mutex::new(Vec::with_capacity(EVENT_QUEUE_CAPACITY)),
+        }
+    }
+
+    // Events on the same sample come out in the order they were pushed
+    pub fn push(&self, at_sample: u64, event: T) {
+        let mut events = self.events.lock().unwrap();
+        let index = events.partition_point(|&(sample, _)| sample <= at_sample);
+        if index == EVENT_QUEUE_CAPACITY {
+            return;
+        }
+        if events.len() == EVENT_QUEUE_CAPACITY {
+            events.pop();
+        }
+        events.insert(index, (at_sample, event));
+    }
+
+    // The earliest event due by `current_sample`, if any
+    pub fn pop_due(&self, current_sample: u64) -> Option<T> {
+        let mut events = self.events.lock().unwrap();
+        match events.first() {
+            Some(&(at_sample, _)) if at_sample <= current_sample => Some(events.remove(0).1),
+            _ => None,
+        }
+    }
+
+    // Moves every event by `ratio`, for a change of sample rate
+    pub fn rescale(&self, ratio: f64) {
+        for (at_sample, _) in self.events.lock().unwrap().iter_mut() {
+            *at_sample = rescale_sample(*at_sample, ratio);
+        }
+    }
+}
+
+impl<T: Copy> Clone for EventQueue<T> {
+    fn clone(&self) -> Self {
+        let mut events = Vec::with_capacity(EVENT_QUEUE_CAPACITY);
+        events.extend_from_slice(&self.events.lock().unwrap());
+        Self {
+            events: Mutex::new(events),
+        }
+    }
+}
+
+impl<T: Copy> Default for EventQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
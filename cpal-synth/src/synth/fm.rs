@@ -0,0 +1,431 @@
+// src/synth/fm.rs
+
+use crate::synth::audio_context::AudioContext;
+use crate::synth::audio_node::AudioNode;
+use crate::synth::audio_param::AudioParam;
+use crate::synth::bandlimited_wavetableoscillator::BandlimitedWavetableOscillator;
+use crate::synth::oscillator::OscillatorType;
+use std::f32::consts::PI;
+
+/// How an operator's frequency is set.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OperatorMode {
+    // The note frequency times `ratio`
+    Ratio,
+    // `frequency` in Hz, whatever the note
+    Fixed,
+}
+
+/// A DX7-style FM operator: a sine oscillator whose phase can be modulated.
+///
+/// Its "pm" input offsets the phase by `index` radians per unit, and its "fm"
+/// input scales the frequency by `1 + index * input`, running the phase backwards
+/// when that goes negative (through-zero FM). `feedback` modulates the operator by
+/// its own output, averaged over two samples as the DX7 does to keep it stable.
+/// In `Ratio` mode a standalone operator plays `frequency * ratio`; inside an
+/// `FmVoice` it follows the voice frequency instead.
+pub struct FmOperator {
+    // Only the sine bank is read
+    sine: BandlimitedWavetableOscillator,
+    frequency: AudioParam,
+    ratio: AudioParam,
+    index: AudioParam,
+    feedback: AudioParam,
+    gain: AudioParam,
+    mode: OperatorMode,
+    phase: f32,
+    // Last two outputs, before gain, for feedback
+    history: [f32; 2],
+    pm_input: Option<Box<dyn AudioNode + Send>>,
+    fm_input: Option<Box<dyn AudioNode + Send>>,
+}
+
+impl FmOperator {
+    pub fn new(context: &AudioContext) -> anyhow::Result<Self> {
+        Ok(Self {
+            sine: BandlimitedWavetableOscillator::new(OscillatorType::Sine, context)?,
+            frequency: AudioParam::new(440.0, 0.0, 22050.0),
+            ratio: AudioParam::new(1.0, 0.0, 64.0),
+            index: AudioParam::new(1.0, 0.0, 100.0),
+            feedback: AudioParam::new(0.0, 0.0, 1.0),
+            gain: AudioParam::new(1.0, 0.0, 1.0),
+            mode: OperatorMode::Ratio,
+            phase: 0.0,
+            history: [0.0; 2],
+            pm_input: None,
+            fm_input: None,
+        })
+    }
+
+    pub fn frequency(&self) -> &AudioParam {
+        &self.frequency
+    }
+
+    pub fn ratio(&self) -> &AudioParam {
+        &self.ratio
+    }
+
+    pub fn index(&self) -> &AudioParam {
+        &self.index
+    }
+
+    pub fn feedback(&self) -> &AudioParam {
+        &self.feedback
+    }
+
+    pub fn gain(&self) -> &AudioParam {
+        &self.gain
+    }
+
+    pub fn set_mode(&mut self, mode: OperatorMode) {
+        self.mode = mode;
+    }
+
+    pub fn mode(&self) -> OperatorMode {
+        self.mode
+    }
+
+    pub fn reset(&mut self) {
+        self.phase = 0.0;
+        self.history = [0.0; 2];
+    }
+
+    // One sample with the given note frequency and modulation, advancing the phase
+    fn render(
+        &mut self,
+        note_frequency: f32,
+        pm: f32,
+        fm: f32,
+        sample_rate: f32,
+        current_sample: u64,
+    ) -> f32 {
+        let frequency = match self.mode {
+            OperatorMode::Ratio => note_frequency * self.ratio.get_value(current_sample),
+            OperatorMode::Fixed => self.frequency.get_value(current_sample),
+        };
+        let index = self.index.get_value(current_sample);
+        let feedback = self.feedback.get_value(current_sample) * PI;
+
+        // Phase offset in cycles
+        let offset =
+            (index * pm + feedback * 0.5 * (self.history[0] + self.history[1])) / (2.0 * PI);
+        // The sine table is inverted, so read half a cycle on
+        let read_phase = (self.phase + offset + 0.5).rem_euclid(1.0);
+        let output = self.sine.read(read_phase, (0, 0.0), (0, 0.0));
+        self.history = [output, self.history[0]];
+
+        self.phase += frequency * (1.0 + index * fm) / sample_rate;
+        self.phase -= self.phase.floor();
+
+        output * self.gain.get_value(current_sample)
+    }
+}
+
+impl AudioNode for FmOperator {
+    fn process(&mut self, context: &AudioContext, current_sample: u64) -> f32 {
+        let pm = match self.pm_input.as_mut() {
+            Some(input) => input.process(context, current_sample),
+            None => 0.0,
+        };
+        let fm = match self.fm_input.as_mut() {
+            Some(input) => input.process(context, current_sample),
+            None => 0.0,
+        };
+        let frequency = self.frequency.get_value(current_sample);
+        self.render(frequency, pm, fm, context.sample_rate(), current_sample)
+    }
+
+    fn set_parameter(&self, name: &str, value: f32) {
+        match name {
+            "frequency" => self.frequency.set_value(value),
+            "ratio" => self.ratio.set_value(value),
+            "index" => self.index.set_value(value),
+            "feedback" => self.feedback.set_value(value),
+            "gain" => self.gain.set_value(value),
+            _ => {}
+        }
+    }
+
+    fn set_parameter_at(&self, name: &str, value: f32, at_sample: u64) {
+        match name {
+            "frequency" => self.frequency.set_value_at_time(value, at_sample),
+            "ratio" => self.ratio.set_value_at_time(value, at_sample),
+            "index" => self.index.set_value_at_time(value, at_sample),
+            "feedback" => self.feedback.set_value_at_time(value, at_sample),
+            "gain" => self.gain.set_value_at_time(value, at_sample),
+            _ => {}
+        }
+    }
+
+    // "pm" modulates the phase and "fm" the frequency
+    fn connect_input(&mut self, name: &str, node: Box<dyn AudioNode + Send>) {
+        match name {
+            "pm" => self.pm_input = Some(node),
+            "fm" => self.fm_input = Some(node),
+            _ => {}
+        }
+    }
+
+    fn clear_input(&mut self, input_name: &str) {
+        match input_name {
+            "pm" => self.pm_input = None,
+            "fm" => self.fm_input = None,
+            _ => {}
+        }
+    }
+
+    fn prepare(&mut self, sample_rate: f32, max_block: usize) {
+        self.sine.prepare(sample_rate, max_block);
+        for param in [
+            &self.frequency,
+            &self.ratio,
+            &self.index,
+            &self.feedback,
+            &self.gain,
+        ] {
+            param.prepare(sample_rate);
+        }
+        for input in [self.pm_input.as_mut(), self.fm_input.as_mut()]
+            .into_iter()
+            .flatten()
+        {
+            input.prepare(sample_rate, max_block);
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn AudioNode + Send> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for FmOperator {
+    fn clone(&self) -> Self {
+        Self {
+            sine: self.sine.clone(),
+            frequency: self.frequency.clone(),
+            ratio: self.ratio.clone(),
+            index: self.index.clone(),
+            feedback: self.feedback.clone(),
+            gain: self.gain.clone(),
+            mode: self.mode,
+            phase: self.phase,
+            history: self.history,
+            pm_input: self.pm_input.as_ref().map(|input| input.clone_box()),
+            fm_input: self.fm_input.as_ref().map(|input| input.clone_box()),
+        }
+    }
+}
+
+/// Which operators modulate which, and which are heard.
+///
+/// Operators are numbered from 0 and may only be modulated by higher-numbered
+/// ones, so a voice can run them from the top down in a single pass.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FmAlgorithm {
+    modulators: Vec<Vec<usize>>,
+    carriers: Vec<usize>,
+}
+
+impl FmAlgorithm {
+    pub fn new(modulators: Vec<Vec<usize>>, carriers: Vec<usize>) -> anyhow::Result<Self> {
+        let operators = modulators.len();
+        if operators == 0 {
+            return Err(anyhow::anyhow!("FM algorithm needs at least one operator"));
+        }
+        for (op, sources) in modulators.iter().enumerate() {
+            if let Some(&source) = sources.iter().find(|&&s| s <= op || s >= operators) {
+                return Err(anyhow::anyhow!(
+                    "Operator {} can't be modulated by operator {}",
+                    op,
+                    source
+                ));
+            }
+        }
+        if carriers.is_empty() || carriers.iter().any(|&c| c >= operators) {
+            return Err(anyhow::anyhow!(
+                "FM algorithm carriers must be among its {} operators",
+                operators
+            ));
+        }
+        Ok(Self {
+            modulators,
+            carriers,
+        })
+    }
+
+    /// The eight four-operator algorithms of the DX21/TX81Z family, numbered 1 to 8.
+    /// Their operator 1 is operator 0 here, and operator 4, the top of the stack,
+    /// is operator 3.
+    pub fn four_operator(number: u8) -> anyhow::Result<Self> {
+        let (modulators, carriers): (Vec<Vec<usize>>, Vec<usize>) = match number {
+            1 => (vec![vec![1], vec![2], vec![3], vec![]], vec![0]),
+            2 => (vec![vec![1], vec![2, 3], vec![], vec![]], vec![0]),
+            3 => (vec![vec![1, 3], vec![2], vec![], vec![]], vec![0]),
+            4 => (vec![vec![1, 2], vec![], vec![3], vec![]], vec![0]),
+            5 => (vec![vec![1], vec![], vec![3], vec![]], vec![0, 2]),
+            6 => (vec![vec![3], vec![3], vec![3], vec![]], vec![0, 1, 2]),
+            7 => (vec![vec![], vec![], vec![3], vec![]], vec![0, 1, 2]),
+            8 => (vec![vec![]; 4], vec![0, 1, 2, 3]),
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Four-operator algorithms are numbered 1 to 8, got {}",
+                    number
+                ))
+            }
+        };
+        Self::new(modulators, carriers)
+    }
+
+    pub fn operator_count(&self) -> usize {
+        self.modulators.len()
+    }
+
+    pub fn modulators(&self, operator: usize) -> &[usize] {
+        &self.modulators[operator]
+    }
+
+    pub fn carriers(&self) -> &[usize] {
+        &self.carriers
+    }
+}
+
+/// Several `FmOperator`s wired by an `FmAlgorithm`, playing one note.
+///
+/// Each operator runs once per sample, however many others it modulates, and
+/// the carriers are mixed at equal level.
+pub struct FmVoice {
+    operators: Vec<FmOperator>,
+    algorithm: FmAlgorithm,
+    frequency: AudioParam,
+    gain: AudioParam,
+    outputs: Vec<f32>,
+}
+
+impl FmVoice {
+    pub fn new(algorithm: FmAlgorithm, context: &AudioContext) -> anyhow::Result<Self> {
+        let operators = (0..algorithm.operator_count())
+            .map(|_| FmOperator::new(context))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self {
+            outputs: vec![0.0; operators.len()],
+            operators,
+            algorithm,
+            frequency: AudioParam::new(440.0, 0.01, 22050.0),
+            gain: AudioParam::new(1.0, 0.0, 1.0),
+        })
+    }
+
+    pub fn frequency(&self) -> &AudioParam {
+        &self.frequency
+    }
+
+    pub fn gain(&self) -> &AudioParam {
+        &self.gain
+    }
+
+    pub fn operator(&self, index: usize) -> &FmOperator {
+        &self.operators[index]
+    }
+
+    pub fn operator_mut(&mut self, index: usize) -> &mut FmOperator {
+        &mut self.operators[index]
+    }
+
+    pub fn operator_count(&self) -> usize {
+        self.operators.len()
+    }
+
+    pub fn algorithm(&self) -> &FmAlgorithm {
+        &self.algorithm
+    }
+
+    pub fn reset(&mut self) {
+        for operator in &mut self.operators {
+            operator.reset();
+        }
+    }
+
+    // Splits "op<n>.<name>" into the operator and its parameter name
+    fn operator_parameter<'a>(&self, name: &'a str) -> Option<(&FmOperator, &'a str)> {
+        let (operator, param) = name.strip_prefix("op")?.split_once('.')?;
+        let operator = self.operators.get(operator.parse::<usize>().ok()?)?;
+        Some((operator, param))
+    }
+}
+
+impl AudioNode for FmVoice {
+    fn process(&mut self, context: &AudioContext, current_sample: u64) -> f32 {
+        let frequency = self.frequency.get_value(current_sample);
+        let sample_rate = context.sample_rate();
+
+        for op in (0..self.operators.len()).rev() {
+            let pm: f32 = self.algorithm.modulators[op]
+                .iter()
+                .map(|&source| self.outputs[source])
+                .sum();
+            self.outputs[op] =
+                self.operators[op].render(frequency, pm, 0.0, sample_rate, current_sample);
+        }
+
+        let carriers = self.algorithm.carriers();
+        let mix: f32 = carriers.iter().map(|&op| self.outputs[op]).sum();
+        mix / carriers.len() as f32 * self.gain.get_value(current_sample)
+    }
+
+    // Operator parameters are addressed as "op<n>.<name>", e.g. "op1.ratio"
+    fn set_parameter(&self, name: &str, value: f32) {
+        match name {
+            "frequency" => self.frequency.set_value(value),
+            "gain" => self.gain.set_value(value),
+            _ => {
+                if let Some((operator, param)) = self.operator_parameter(name) {
+                    operator.set_parameter(param, value);
+                }
+            }
+        }
+    }
+
+    fn set_parameter_at(&self, name: &str, value: f32, at_sample: u64) {
+        match name {
+            "frequency" => self.frequency.set_value_at_time(value, at_sample),
+            "gain" => self.gain.set_value_at_time(value, at_sample),
+            _ => {
+                if let Some((operator, param)) = self.operator_parameter(name) {
+                    operator.set_parameter_at(param, value, at_sample);
+                }
+            }
+        }
+    }
+
+    fn connect_input(&mut self, _name: &str, _node: Box<dyn AudioNode + Send>) {
+        // Voices are self-contained
+    }
+
+    fn clear_input(&mut self, _input_name: &str) {
+        // No-op for voices
+    }
+
+    fn prepare(&mut self, sample_rate: f32, max_block: usize) {
+        self.frequency.prepare(sample_rate);
+        self.gain.prepare(sample_rate);
+        for operator in &mut self.operators {
+            operator.prepare(sample_rate, max_block);
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn AudioNode + Send> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for FmVoice {
+    fn clone(&self) -> Self {
+        Self {
+            operators: self.operators.clone(),
+            algorithm: self.algorithm.clone(),
+            frequency: self.frequency.clone(),
+            gain: self.gain.clone(),
+            outputs: self.outputs.clone(),
+        }
+    }
+}
@@ -0,0 +1,371 @@
+// src/synth/granular.rs
+
+use crate::synth::audio_buffer::AudioBuffer;
+use crate::synth::audio_context::{rate_change, AudioContext};
+use crate::synth::audio_node::AudioNode;
+use crate::synth::audio_param::AudioParam;
+use crate::synth::interpolation::cubic_interpolate;
+use crate::synth::random::XorShiftRng;
+use std::f32::consts::{FRAC_PI_2, PI};
+use std::sync::Arc;
+
+/// Amplitude envelope applied over the life of each grain.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GrainWindow {
+    Hann,
+    // Flat top with cosine tapers; the value is the fraction of the grain spent tapering
+    Tukey(f32),
+    // Flat top with linear ramps; the value is the fraction of the grain spent ramping
+    Trapezoid(f32),
+}
+
+impl GrainWindow {
+    // Window gain at `x` in [0, 1] through the grain
+    #[inline]
+    pub fn gain(&self, x: f32) -> f32 {
+        match *self {
+            GrainWindow::Hann => 0.5 - 0.5 * (2.0 * PI * x).cos(),
+            GrainWindow::Tukey(taper) => {
+                let edge = taper.clamp(0.0, 1.0) * 0.5;
+                let distance = x.min(1.0 - x);
+                if distance >= edge {
+                    1.0
+                } else {
+                    0.5 - 0.5 * (PI * distance / edge).cos()
+                }
+            }
+            GrainWindow::Trapezoid(ramp) => {
+                let edge = ramp.clamp(0.0, 1.0) * 0.5;
+                let distance = x.min(1.0 - x);
+                if distance >= edge {
+                    1.0
+                } else {
+                    distance / edge
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Grain {
+    active: bool,
+    // Read position and step in source frames
+    position: f64,
+    increment: f64,
+    age: u32,
+    length: u32,
+    pan: f32,
+}
+
+/// Granular synthesis from a source buffer.
+///
+/// Grains start at `position` (a fraction of the buffer), offset by up to `spray`
+/// seconds either way, and last `grain_size` seconds. `density` sets grains per
+/// second and `jitter` randomizes the gaps between them. `pitch` is in semitones
+/// and `pan` runs from -1 (left) to 1 (right). Parameters are sampled when a grain
+/// starts. Grains come from a fixed pool, so nothing is allocated while processing;
+/// when the pool is exhausted new grains are skipped.
+pub struct GranularNode {
+    buffer: Option<Arc<AudioBuffer>>,
+    position: AudioParam,
+    spray: AudioParam,
+    jitter: AudioParam,
+    grain_size: AudioParam,
+    density: AudioParam,
+    pitch: AudioParam,
+    pan: AudioParam,
+    gain: AudioParam,
+    window: GrainWindow,
+    grains: Vec<Grain>,
+    // Samples until the next grain starts
+    countdown: f64,
+    // Rate the countdown and grains are counted at, 0 until prepared
+    sample_rate: f32,
+    rng: XorShiftRng,
+}
+
+impl GranularNode {
+    pub fn new(buffer: Option<Arc<AudioBuffer>>, max_grains: usize) -> Self {
+        Self {
+            buffer,
+            position: AudioParam::new(0.0, 0.0, 1.0),
+            spray: AudioParam::new(0.0, 0.0, 10.0),
+            jitter: AudioParam::new(0.0, 0.0, 1.0),
+            grain_size: AudioParam::new(0.1, 0.001, 2.0),
+            density: AudioParam::new(20.0, 0.0, 1000.0),
+            pitch: AudioParam::new(0.0, -48.0, 48.0),
+            pan: AudioParam::new(0.0, -1.0, 1.0),
+            gain: AudioParam::new(1.0, 0.0, 1.0),
+            window: GrainWindow::Hann,
+            grains: vec![Grain::default(); max_grains.max(1)],
+            countdown: 0.0,
+            sample_rate: 0.0,
+            rng: XorShiftRng::new(0x6EA1),
+        }
+    }
+
+    pub fn position(&self) -> &AudioParam {
+        &self.position
+    }
+
+    pub fn spray(&self) -> &AudioParam {
+        &self.spray
+    }
+
+    pub fn jitter(&self) -> &AudioParam {
+        &self.jitter
+    }
+
+    pub fn grain_size(&self) -> &AudioParam {
+        &self.grain_size
+    }
+
+    pub fn density(&self) -> &AudioParam {
+        &self.density
+    }
+
+    pub fn pitch(&self) -> &AudioParam {
+        &self.pitch
+    }
+
+    pub fn pan(&self) -> &AudioParam {
+        &self.pan
+    }
+
+    pub fn gain(&self) -> &AudioParam {
+        &self.gain
+    }
+
+    pub fn set_buffer(&mut self, buffer: Option<Arc<AudioBuffer>>) {
+        self.buffer = buffer;
+        for grain in &mut self.grains {
+            grain.active = false;
+        }
+    }
+
+    pub fn set_window(&mut self, window: GrainWindow) {
+        self.window = window;
+    }
+
+    pub fn window(&self) -> GrainWindow {
+        self.window
+    }
+
+    pub fn set_seed(&mut self, seed: u32) {
+        self.rng = XorShiftRng::new(seed);
+    }
+
+    pub fn max_grains(&self) -> usize {
+        self.grains.len()
+    }
+
+    pub fn active_grains(&self) -> usize {
+        self.grains.iter().filter(|g| g.active).count()
+    }
+
+    fn spawn_grain(&mut self, buffer: &AudioBuffer, sample_rate: f32, current_sample: u64) {
+        let Some(slot) = self.grains.iter().position(|g| !g.active) else {
+            return;
+        };
+
+        let source_rate = buffer.sample_rate() as f64;
+        let spray = self.spray.get_value(current_sample) as f64 * self.rng.next_bipolar() as f64;
+        let start = self.position.get_value(current_sample) as f64 * buffer.length() as f64
+            + spray * source_rate;
+        let length = (self.grain_size.get_value(current_sample) * sample_rate).max(1.0) as u32;
+        let increment = 2.0f64.powf(self.pitch.get_value(current_sample) as f64 / 12.0)
+            * source_rate
+            / sample_rate as f64;
+
+        self.grains[slot] = Grain {
+            active: true,
+            position: start.clamp(0.0, buffer.length() as f64),
+            increment,
+            age: 0,
+            length,
+            pan: self.pan.get_value(current_sample),
+        };
+    }
+
+    #[inline]
+    fn read_frame(data: &[f32], index: i64) -> f32 {
+        if index < 0 || index as usize >= data.len() {
+            0.0
+        } else {
+            data[index as usize]
+        }
+    }
+
+    #[inline]
+    fn interpolate(data: &[f32], position: f64) -> f32 {
+        let index = position.floor() as i64;
+        let frac = (position - index as f64) as f32;
+        cubic_interpolate(
+            Self::read_frame(data, index - 1),
+            Self::read_frame(data, index),
+            Self::read_frame(data, index + 1),
+            Self::read_frame(data, index + 2),
+            frac,
+        )
+    }
+}
+
+impl AudioNode for GranularNode {
+    fn process(&mut self, context: &AudioContext, current_sample: u64) -> f32 {
+        let (left, right) = self.process_stereo(context, current_sample);
+        (left + right) * 0.5
+    }
+
+    fn process_stereo(&mut self, context: &AudioContext, current_sample: u64) -> (f32, f32) {
+        let Some(buffer) = self.buffer.clone() else {
+            return (0.0, 0.0);
+        };
+        if buffer.length() == 0 {
+            return (0.0, 0.0);
+        }
+        let sample_rate = context.sample_rate();
+
+        let density = self.density.get_value(current_sample);
+        if density > 0.0 {
+            self.countdown -= 1.0;
+            if self.countdown <= 0.0 {
+                self.spawn_grain(&buffer, sample_rate, current_sample);
+                let jitter = self.jitter.get_value(current_sample) * self.rng.next_bipolar();
+                let interval = sample_rate as f64 / density as f64 * (1.0 + jitter as f64);
+                self.countdown += interval.max(1.0);
+            }
+        }
+
+        let stereo = buffer.number_of_channels() > 1;
+        let left_data = buffer.channel(0);
+        let right_data = buffer.channel(if stereo { 1 } else { 0 });
+
+        let mut out_left = 0.0;
+        let mut out_right = 0.0;
+        for grain in self.grains.iter_mut().filter(|g| g.active) {
+            let envelope = self.window.gain(grain.age as f32 / grain.length as f32);
+            let left = Self::interpolate(left_data, grain.position) * envelope;
+            let right = if stereo {
+                Self::interpolate(right_data, grain.position) * envelope
+            } else {
+                left
+            };
+
+            // Equal-power panning as in Web Audio's StereoPannerNode
+            if stereo {
+                let (x, towards_left) = if grain.pan <= 0.0 {
+                    (grain.pan + 1.0, true)
+                } else {
+                    (grain.pan, false)
+                };
+                let (gain_left, gain_right) = ((x * FRAC_PI_2).cos(), (x * FRAC_PI_2).sin());
+                if towards_left {
+                    out_left += left + right * gain_left;
+                    out_right += right * gain_right;
+                } else {
+                    out_left += left * gain_left;
+                    out_right += right + left * gain_right;
+                }
+            } else {
+                let x = (grain.pan + 1.0) * 0.5;
+                out_left += left * (x * FRAC_PI_2).cos();
+                out_right += left * (x * FRAC_PI_2).sin();
+            }
+
+            grain.position += grain.increment;
+            grain.age += 1;
+            if grain.age >= grain.length || grain.position >= buffer.length() as f64 + 2.0 {
+                grain.active = false;
+            }
+        }
+
+        let gain = self.gain.get_value(current_sample);
+        (out_left * gain, out_right * gain)
+    }
+
+    fn set_parameter(&self, name: &str, value: f32) {
+        match name {
+            "position" => self.position.set_value(value),
+            "spray" => self.spray.set_value(value),
+            "jitter" => self.jitter.set_value(value),
+            "grain_size" => self.grain_size.set_value(value),
+            "density" => self.density.set_value(value),
+            "pitch" => self.pitch.set_value(value),
+            "pan" => self.pan.set_value(value),
+            "gain" => self.gain.set_value(value),
+            _ => {}
+        }
+    }
+
+    fn set_parameter_at(&self, name: &str, value: f32, at_sample: u64) {
+        match name {
+            "position" => self.position.set_value_at_time(value, at_sample),
+            "spray" => self.spray.set_value_at_time(value, at_sample),
+            "jitter" => self.jitter.set_value_at_time(value, at_sample),
+            "grain_size" => self.grain_size.set_value_at_time(value, at_sample),
+            "density" => self.density.set_value_at_time(value, at_sample),
+            "pitch" => self.pitch.set_value_at_time(value, at_sample),
+            "pan" => self.pan.set_value_at_time(value, at_sample),
+            "gain" => self.gain.set_value_at_time(value, at_sample),
+            _ => {}
+        }
+    }
+
+    fn connect_input(&mut self, _name: &str, _node: Box<dyn AudioNode + Send>) {
+        // Sources don't have inputs
+    }
+
+    fn clear_input(&mut self, _input_name: &str) {
+        // No-op for sources
+    }
+
+    // Grains in flight keep their length in seconds and their pitch
+    fn prepare(&mut self, sample_rate: f32, _max_block: usize) {
+        for param in [
+            &self.position,
+            &self.spray,
+            &self.jitter,
+            &self.grain_size,
+            &self.density,
+            &self.pitch,
+            &self.pan,
+            &self.gain,
+        ] {
+            param.prepare(sample_rate);
+        }
+        if let Some(ratio) = rate_change(&mut self.sample_rate, sample_rate) {
+            self.countdown *= ratio;
+            for grain in self.grains.iter_mut().filter(|g| g.active) {
+                grain.age = (grain.age as f64 * ratio) as u32;
+                grain.length = ((grain.length as f64 * ratio) as u32).max(grain.age + 1);
+                grain.increment /= ratio;
+            }
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn AudioNode + Send> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for GranularNode {
+    fn clone(&self) -> Self {
+        Self {
+            buffer: self.buffer.clone(),
+            position: self.position.clone(),
+            spray: self.spray.clone(),
+            jitter: self.jitter.clone(),
+            grain_size: self.grain_size.clone(),
+            density: self.density.clone(),
+            pitch: self.pitch.clone(),
+            pan: self.pan.clone(),
+            gain: self.gain.clone(),
+            window: self.window,
+            grains: self.grains.clone(),
+            countdown: self.countdown,
+            sample_rate: self.sample_rate,
+            rng: self.rng.clone(),
+        }
+    }
+}
@@ -0,0 +1,138 @@
+// src/synth/hard_sync.rs
+
+use crate::synth::audio_context::{rate_change, AudioContext};
+use crate::synth::audio_node::AudioNode;
+use crate::synth::event_queue::EventQueue;
+
+/// What a node reports to the oscillators hard-synced to it, from
+/// `AudioNode::sync_out`, after each sample it processes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SyncOut {
+    // The node has no phase, so its output crossing zero going up stands in for
+    // the start of a cycle
+    ZeroCrossing,
+    // The node's cycle carried on through the last sample
+    Running,
+    // The node's cycle restarted this many samples (0 to 1) before the last sample
+    Wrapped(f32),
+}
+
+// How many samples before the next one a phase advanced by `dt` to `phase`
+// crossed a whole cycle, or None if it stayed inside this one
+pub(crate) fn wrap_delay(phase: f32, dt: f32) -> Option<f32> {
+    let past = if phase >= 1.0 {
+        phase - 1.0
+    } else if phase < 0.0 {
+        phase
+    } else {
+        return None;
+    };
+    Some((past / dt).clamp(0.0, 1.0))
+}
+
+// A phase reset, `delay` samples (0 to 1) before the current sample
+#[derive(Clone, Copy, Debug)]
+pub struct PhaseReset {
+    pub delay: f32,
+    pub phase: f32,
+}
+
+// Phase resets for oscillators: a sync input that restarts the oscillator each time
+// the input's phase wraps, and resets scheduled with `schedule`. Inputs without a
+// phase restart it each time they cross zero going up. The step a reset leaves in
+// the waveform is smoothed with a polyBLEP. Half of that lands on the sample before
+// the reset, so while a sync input is connected the output runs one sample late.
+pub struct HardSync {
+    input: Option<Box<dyn AudioNode + Send>>,
+    last_input: f32,
+    // Output waiting for its share of a reset in the next sample
+    held: f32,
+    // Phases to reset to, by sample
+    scheduled: EventQueue<f32>,
+    // Rate the scheduled resets are counted at, 0 until prepared
+    sample_rate: f32,
+}
+
+impl HardSync {
+    pub fn new() -> Self {
+        Self {
+            input: None,
+            last_input: 0.0,
+            held: 0.0,
+            scheduled: EventQueue::new(),
+            sample_rate: 0.0,
+        }
+    }
+
+    pub fn connect(&mut self, node: Box<dyn AudioNode + Send>) {
+        self.input = Some(node);
+        self.last_input = 0.0;
+        self.held = 0.0;
+    }
+
+    pub fn disconnect(&mut self) {
+        self.input = None;
+    }
+
+    pub fn schedule(&self, at_sample: u64, phase: f32) {
+        self.scheduled.push(at_sample, phase.rem_euclid(1.0));
+    }
+
+    pub fn prepare(&mut self, sample_rate: f32, max_block: usize) {
+        if let Some(input) = self.input.as_mut() {
+            input.prepare(sample_rate, max_block);
+        }
+        if let Some(ratio) = rate_change(&mut self.sample_rate, sample_rate) {
+            self.scheduled.rescale(ratio);
+        }
+    }
+
+    // Runs the sync input and returns the reset due at this sample, if any
+    pub fn poll(&mut self, context: &AudioContext, current_sample: u64) -> Option<PhaseReset> {
+        let mut reset = None;
+        if let Some(input) = self.input.as_mut() {
+            let value = input.process(context, current_sample);
+            let delay = match input.sync_out() {
+                SyncOut::Wrapped(delay) => Some(delay),
+                SyncOut::Running => None,
+                SyncOut::ZeroCrossing => (self.last_input <= 0.0 && value > 0.0)
+                    .then(|| value / (value - self.last_input)),
+            };
+            reset = delay.map(|delay| PhaseReset { delay, phase: 0.0 });
+            self.last_input = value;
+        }
+
+        // Resets due together collapse into the last of them
+        while let Some(phase) = self.scheduled.pop_due(current_sample) {
+            reset = Some(PhaseReset { delay: 0.0, phase });
+        }
+        reset
+    }
+
+    // Adds the polyBLEP residuals for a step of `height` made by `reset`, and
+    // returns the output, delayed by a sample when synced
+    pub fn finish(&mut self, sample: f32, reset: Option<PhaseReset>, height: f32) -> f32 {
+        let mut sample = sample;
+        if let Some(PhaseReset { delay, .. }) = reset {
+            self.held += 0.5 * height * delay * delay;
+            sample += 0.5 * height * (2.0 * delay - delay * delay - 1.0);
+        }
+
+        if self.input.is_none() {
+            return sample;
+        }
+        std::mem::replace(&mut self.held, sample)
+    }
+}
+
+impl Clone for HardSync {
+    fn clone(&self) -> Self {
+        Self {
+            input: self.input.as_ref().map(|input| input.clone_box()),
+            last_input: self.last_input,
+            held: self.held,
+            scheduled: self.scheduled.clone(),
+            sample_rate: self.sample_rate,
+        }
+    }
+}
@@ -0,0 +1,13 @@
+// src/synth/interpolation.rs
+
+// Four-point cubic through y0..y3, evaluated between y1 and y2 at `frac`
+#[inline(always)]
+pub(crate) fn cubic_interpolate(y0: f32, y1: f32, y2: f32, y3: f32, frac: f32) -> f32 {
+    let mu2 = frac * frac;
+    let a0 = y3 - y2 - y0 + y1;
+    let a1 = y0 - y1 - a0;
+    let a2 = y2 - y0;
+    let a3 = y1;
+
+    a0 * frac * mu2 + a1 * mu2 + a2 * frac + a3
+}
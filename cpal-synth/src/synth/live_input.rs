@@ -0,0 +1,557 @@
+// src/synth/live_input.rs
+
+use crate::synth::audio_buffer::AudioBuffer;
+use crate::synth::audio_context::AudioContext;
+use crate::synth::audio_decoder::decode_audio_file;
+use crate::synth::audio_node::AudioNode;
+use crate::synth::audio_param::AudioParam;
+use crate::synth::resampler::StreamResampler;
+use crate::synth::ring_buffer::{ring_buffer, RingConsumer, RingProducer};
+use crossbeam::atomic::AtomicCell;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "cpal-output")]
+use crate::synth::backend::{BackendConfig, SampleFormat, MAX_CALLBACK_FRAMES};
+#[cfg(feature = "cpal-output")]
+use crate::synth::devices::{
+    find_host, find_input_device, sample_format_from_cpal, select_input_config,
+};
+#[cfg(feature = "cpal-output")]
+use cpal::traits::{DeviceTrait, StreamTrait};
+#[cfg(feature = "cpal-output")]
+use cpal::{FromSample, Sample};
+
+const BUFFER_SECONDS: f64 = 1.0;
+const DEFAULT_LATENCY_SECONDS: f64 = 0.05;
+// Input frames taken from the ring per resampler call
+const BLOCK_FRAMES: usize = 64;
+// Drift correction: how quickly the fill estimate follows the ring, how hard the
+// ratio is bent per unit of fill error, and the most it may be bent
+const FILL_SMOOTHING: f64 = 0.01;
+const CORRECTION_GAIN: f64 = 0.02;
+const MAX_CORRECTION: f64 = 0.002;
+
+// Room for one resampled block at the most the ratio can be bent to, so the
+// audio thread never grows `pending`
+fn pending_capacity(channels: usize, nominal_ratio: f64) -> usize {
+    let frames = (BLOCK_FRAMES as f64 * nominal_ratio / (1.0 - MAX_CORRECTION)).ceil() as usize;
+    (frames + 2) * channels
+}
+
+/// A source of captured audio: a sound card, a file, or anything else that
+/// produces interleaved frames on its own clock.
+///
+/// `start` hands over the writer to push frames into; they're consumed by the
+/// `LiveInputNode` the writer belongs to. `stop` stops pushing.
+pub trait AudioInput {
+    fn name(&self) -> &str;
+    fn sample_rate(&self) -> f32;
+    fn channels(&self) -> usize;
+    fn start(&mut self, writer: InputWriter) -> anyhow::Result<()>;
+    fn stop(&mut self);
+}
+
+// State shared between the writer, the audio thread and the controlling thread.
+// Frame counts are in input frames.
+struct InputShared {
+    channels: usize,
+    sample_rate: f32,
+    left: AtomicUsize,
+    right: AtomicUsize,
+    latency_frames: AtomicU64,
+    // True once the latency target has filled; cleared by underruns
+    rolling: AtomicBool,
+    underruns: AtomicU64,
+    dropped_frames: AtomicU64,
+    ratio: AtomicCell<f64>,
+}
+
+/// The capture side of a `LiveInputNode`. Lock-free, so it can be written to
+/// from a device callback.
+pub struct InputWriter {
+    producer: RingProducer,
+    shared: Arc<InputShared>,
+}
+
+impl InputWriter {
+    pub fn channels(&self) -> usize {
+        self.shared.channels
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.shared.sample_rate
+    }
+
+    // Queue interleaved frames, returning how many fit. The rest are dropped
+    // and counted, which only happens when the graph isn't pulling.
+    pub fn write(&mut self, interleaved: &[f32]) -> usize {
+        let channels = self.shared.channels;
+        let frames = interleaved.len() / channels;
+        let fits = frames.min(self.producer.free_space() / channels);
+        self.producer.push_slice(&interleaved[..fits * channels]);
+        if fits < frames {
+            self.shared
+                .dropped_frames
+                .fetch_add((frames - fits) as u64, Ordering::Relaxed);
+        }
+        fits
+    }
+}
+
+/// Snapshot of an input's state, from `LiveInputNode::status`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LiveInputStatus {
+    // Seconds of input queued
+    pub buffered: f64,
+    // Output frames per input frame, including the drift correction
+    pub ratio: f64,
+    pub rolling: bool,
+    pub underruns: u64,
+    pub dropped_frames: u64,
+}
+
+/// Plays captured audio into the graph.
+///
+/// Frames arrive through an `InputWriter` on the input's clock and are
+/// resampled to the context rate. The input and output clocks never quite
+/// agree, so the resampling ratio is nudged to hold the queue at the latency
+/// target. Playback waits for the target to fill before starting, and again
+/// after an underrun. Clones share the same input; only one of them should be
+/// part of the processed graph.
+pub struct LiveInputNode {
+    consumer: RingConsumer,
+    shared: Arc<InputShared>,
+    gain: AudioParam,
+    nominal_ratio: f64,
+    // Audio thread state
+    resampler: StreamResampler,
+    fill: f64,
+    block: Vec<f32>,
+    // Resampled frames and how many have been played
+    pending: Vec<f32>,
+    read: usize,
+}
+
+impl LiveInputNode {
+    // Starts `input` writing into a new node
+    pub fn new(input: &mut dyn AudioInput, context: &AudioContext) -> anyhow::Result<Self> {
+        let (node, writer) = Self::with_writer(input.channels(), input.sample_rate(), context);
+        input.start(writer)?;
+        Ok(node)
+    }
+
+    // A node fed by hand through the returned writer, for inputs of your own
+    pub fn with_writer(
+        channels: usize,
+        sample_rate: f32,
+        context: &AudioContext,
+    ) -> (Self, InputWriter) {
+        let channels = channels.max(1);
+        let capacity = ((BUFFER_SECONDS * sample_rate as f64) as usize).max(BLOCK_FRAMES * 4);
+        let (producer, consumer) = ring_buffer(capacity * channels);
+        let nominal_ratio = context.sample_rate() as f64 / sample_rate as f64;
+
+        let shared = Arc::new(InputShared {
+            channels,
+            sample_rate,
+            left: AtomicUsize::new(0),
+            right: AtomicUsize::new(1.min(channels - 1)),
+            latency_frames: AtomicU64::new(0),
+            rolling: AtomicBool::new(false),
+            underruns: AtomicU64::new(0),
+            dropped_frames: AtomicU64::new(0),
+            ratio: AtomicCell::new(nominal_ratio),
+        });
+        let node = Self {
+            consumer,
+            shared: shared.clone(),
+            gain: AudioParam::new(1.0, 0.0, 4.0),
+            nominal_ratio,
+            resampler: StreamResampler::new(channels, nominal_ratio),
+            fill: 0.0,
+            block: vec![0.0; BLOCK_FRAMES * channels],
+            pending: Vec::with_capacity(pending_capacity(channels, nominal_ratio)),
+            read: 0,
+        };
+        node.set_latency(DEFAULT_LATENCY_SECONDS);
+        (node, InputWriter { producer, shared })
+    }
+
+    pub fn gain(&self) -> &AudioParam {
+        &self.gain
+    }
+
+    pub fn channels(&self) -> usize {
+        self.shared.channels
+    }
+
+    // Which input channels play on the left and right. Out of range channels
+    // play the last one.
+    pub fn set_channels(&self, left: usize, right: usize) {
+        self.shared.left.store(left, Ordering::Release);
+        self.shared.right.store(right, Ordering::Release);
+    }
+
+    // Plays one input channel on both sides
+    pub fn set_channel(&self, channel: usize) {
+        self.set_channels(channel, channel);
+    }
+
+    // How much input to hold back against jitter in the two clocks, limited to
+    // half the queue
+    pub fn set_latency(&self, seconds: f64) {
+        let frames = (seconds.max(0.0) * self.shared.sample_rate as f64) as u64;
+        let max = (self.consumer.capacity() / self.shared.channels / 2) as u64;
+        self.shared
+            .latency_frames
+            .store(frames.clamp(BLOCK_FRAMES as u64, max), Ordering::Release);
+    }
+
+    pub fn status(&self) -> LiveInputStatus {
+        let shared = &self.shared;
+        LiveInputStatus {
+            buffered: self.available_frames() as f64 / shared.sample_rate as f64,
+            ratio: shared.ratio.load(),
+            rolling: shared.rolling.load(Ordering::Acquire),
+            underruns: shared.underruns.load(Ordering::Acquire),
+            dropped_frames: shared.dropped_frames.load(Ordering::Acquire),
+        }
+    }
+
+    fn available_frames(&self) -> usize {
+        self.consumer.available() / self.shared.channels
+    }
+
+    // Resample the next block of input into `pending`. False when the queue ran dry.
+    fn refill(&mut self) -> bool {
+        let channels = self.shared.channels;
+        self.pending.clear();
+        self.read = 0;
+        while self.pending.is_empty() {
+            let frames = self.available_frames().min(BLOCK_FRAMES);
+            if frames == 0 {
+                return false;
+            }
+            self.consumer
+                .pop_slice(&mut self.block[..frames * channels]);
+
+            // Bend the ratio towards holding the queue at the latency target
+            let target = self.shared.latency_frames.load(Ordering::Acquire) as f64;
+            self.fill += FILL_SMOOTHING * (self.available_frames() as f64 - self.fill);
+            let correction = (CORRECTION_GAIN * (self.fill - target) / target)
+                .clamp(-MAX_CORRECTION, MAX_CORRECTION);
+            let ratio = self.nominal_ratio / (1.0 + correction);
+            self.resampler.set_ratio(ratio);
+            self.shared.ratio.store(ratio);
+
+            self.resampler
+                .process(&self.block[..frames * channels], &mut self.pending);
+        }
+        true
+    }
+}
+
+impl AudioNode for LiveInputNode {
+    fn process(&mut self, context: &AudioContext, current_sample: u64) -> f32 {
+        let (left, right) = self.process_stereo(context, current_sample);
+        (left + right) * 0.5
+    }
+
+    fn process_stereo(&mut self, _context: &AudioContext, current_sample: u64) -> (f32, f32) {
+        let channels = self.shared.channels;
+        if !self.shared.rolling.load(Ordering::Acquire) {
+            let target = self.shared.latency_frames.load(Ordering::Acquire);
+            if (self.available_frames() as u64) < target {
+                return (0.0, 0.0);
+            }
+            self.fill = target as f64;
+            self.shared.rolling.store(true, Ordering::Release);
+        }
+
+        if self.read * channels >= self.pending.len() && !self.refill() {
+            // The input fell behind: count it and wait for the latency target again
+            self.shared.underruns.fetch_add(1, Ordering::AcqRel);
+            self.shared.rolling.store(false, Ordering::Release);
+            self.resampler.reset();
+            return (0.0, 0.0);
+        }
+
+        let frame = &self.pending[self.read * channels..(self.read + 1) * channels];
+        self.read += 1;
+        let left = frame[self.shared.left.load(Ordering::Relaxed).min(channels - 1)];
+        let right = frame[self.shared.right.load(Ordering::Relaxed).min(channels - 1)];
+        let gain = self.gain.get_value(current_sample);
+        (left * gain, right * gain)
+    }
+
+    fn set_parameter(&self, name: &str, value: f32) {
+        if name == "gain" {
+            self.gain.set_value(value);
+        }
+    }
+
+    fn set_parameter_at(&self, name: &str, value: f32, at_sample: u64) {
+        if name == "gain" {
+            self.gain.set_value_at_time(value, at_sample);
+        }
+    }
+
+    fn connect_input(&mut self, _name: &str, _node: Box<dyn AudioNode + Send>) {
+        // Sources don't have inputs
+    }
+
+    fn clear_input(&mut self, _input_name: &str) {
+        // No-op for sources
+    }
+
+    // The queue is counted at the input's rate, so only the ratio changes
+    fn prepare(&mut self, sample_rate: f32, _max_block: usize) {
+        self.gain.prepare(sample_rate);
+        self.nominal_ratio = sample_rate as f64 / self.shared.sample_rate as f64;
+        let capacity = pending_capacity(self.shared.channels, self.nominal_ratio);
+        self.pending
+            .reserve(capacity.saturating_sub(self.pending.len()));
+    }
+
+    fn clone_box(&self) -> Box<dyn AudioNode + Send> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for LiveInputNode {
+    fn clone(&self) -> Self {
+        let channels = self.shared.channels;
+        Self {
+            consumer: self.consumer.share(),
+            shared: self.shared.clone(),
+            gain: self.gain.clone(),
+            nominal_ratio: self.nominal_ratio,
+            resampler: StreamResampler::new(channels, self.nominal_ratio),
+            fill: 0.0,
+            block: vec![0.0; BLOCK_FRAMES * channels],
+            pending: Vec::with_capacity(pending_capacity(channels, self.nominal_ratio)),
+            read: 0,
+        }
+    }
+}
+
+/// Stands in for an input device by playing a buffer in real time from a
+/// timer thread, once or looped.
+pub struct BufferInput {
+    buffer: Arc<AudioBuffer>,
+    looping: bool,
+    block_frames: usize,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl BufferInput {
+    pub fn new(buffer: AudioBuffer) -> Self {
+        Self {
+            buffer: Arc::new(buffer),
+            looping: false,
+            block_frames: 256,
+            running: Arc::new(AtomicBool::new(false)),
+            thread: None,
+        }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        Ok(Self::new(decode_audio_file(path)?))
+    }
+
+    // Takes effect on the next `start`
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    // Frames pushed per tick of the timer
+    pub fn set_block_size(&mut self, frames: usize) {
+        self.block_frames = frames.max(1);
+    }
+}
+
+impl AudioInput for BufferInput {
+    fn name(&self) -> &str {
+        "buffer"
+    }
+
+    fn sample_rate(&self) -> f32 {
+        self.buffer.sample_rate()
+    }
+
+    fn channels(&self) -> usize {
+        self.buffer.number_of_channels()
+    }
+
+    fn start(&mut self, mut writer: InputWriter) -> anyhow::Result<()> {
+        self.stop();
+        let running = Arc::new(AtomicBool::new(true));
+        self.running = running.clone();
+
+        let buffer = self.buffer.clone();
+        let looping = self.looping;
+        let block_frames = self.block_frames;
+        let channels = buffer.number_of_channels();
+        let period = Duration::from_secs_f64(block_frames as f64 / buffer.sample_rate() as f64);
+        let thread = std::thread::Builder::new()
+            .name("buffer-input".to_string())
+            .spawn(move || {
+                let mut block = Vec::with_capacity(block_frames * channels);
+                let mut position = 0;
+                let mut deadline = Instant::now();
+                while running.load(Ordering::SeqCst) {
+                    block.clear();
+                    while block.len() < block_frames * channels {
+                        if position >= buffer.length() {
+                            if !looping || buffer.length() == 0 {
+                                break;
+                            }
+                            position = 0;
+                        }
+                        block.extend(buffer.channels().iter().map(|channel| channel[position]));
+                        position += 1;
+                    }
+                    if block.is_empty() {
+                        break;
+                    }
+                    writer.write(&block);
+
+                    deadline += period;
+                    let now = Instant::now();
+                    if deadline > now {
+                        std::thread::sleep(deadline - now);
+                    } else {
+                        deadline = now;
+                    }
+                }
+            })?;
+        self.thread = Some(thread);
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for BufferInput {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Captures from a cpal input device, the default one of the default host
+/// unless named. See `input_devices` for the names.
+#[cfg(feature = "cpal-output")]
+pub struct CpalInput {
+    name: String,
+    device: cpal::Device,
+    config: cpal::StreamConfig,
+    sample_format: SampleFormat,
+    stream: Option<cpal::Stream>,
+}
+
+#[cfg(feature = "cpal-output")]
+impl CpalInput {
+    pub fn open(
+        host: Option<&str>,
+        device: Option<&str>,
+        config: &BackendConfig,
+    ) -> anyhow::Result<Self> {
+        let host = find_host(host)?;
+        let device = find_input_device(&host, device)?;
+        let name = device.name()?;
+        println!("Using input device: {}", name);
+
+        let (stream_config, sample_format) = select_input_config(&device, config)?;
+        let sample_format = sample_format_from_cpal(sample_format)
+            .ok_or_else(|| anyhow::anyhow!("Unsupported sample format: {:?}", sample_format))?;
+        println!(
+            "Using input config: {:?} as {:?}",
+            stream_config, sample_format
+        );
+
+        Ok(Self {
+            name,
+            device,
+            config: stream_config,
+            sample_format,
+            stream: None,
+        })
+    }
+
+    pub fn device_name(&self) -> &str {
+        &self.name
+    }
+
+    fn build_stream<T>(&self, mut writer: InputWriter) -> anyhow::Result<cpal::Stream>
+    where
+        T: Sample + cpal::SizedSample + Send + 'static,
+        f32: FromSample<T>,
+    {
+        let frames = match self.config.buffer_size {
+            cpal::BufferSize::Fixed(size) => (size as usize).max(MAX_CALLBACK_FRAMES),
+            cpal::BufferSize::Default => MAX_CALLBACK_FRAMES,
+        };
+        let mut scratch: Vec<f32> = Vec::with_capacity(frames * self.config.channels as usize);
+        let stream = self.device.build_input_stream(
+            &self.config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                scratch.clear();
+                scratch.extend(data.iter().map(|&sample| f32::from_sample(sample)));
+                writer.write(&scratch);
+            },
+            move |err| {
+                eprintln!("Audio input stream error: {}", err);
+            },
+            None,
+        )?;
+        Ok(stream)
+    }
+}
+
+#[cfg(feature = "cpal-output")]
+impl AudioInput for CpalInput {
+    fn name(&self) -> &str {
+        "cpal"
+    }
+
+    fn sample_rate(&self) -> f32 {
+        self.config.sample_rate.0 as f32
+    }
+
+    fn channels(&self) -> usize {
+        self.config.channels as usize
+    }
+
+    fn start(&mut self, writer: InputWriter) -> anyhow::Result<()> {
+        self.stop();
+        let stream = match self.sample_format {
+            SampleFormat::I8 => self.build_stream::<i8>(writer)?,
+            SampleFormat::I16 => self.build_stream::<i16>(writer)?,
+            SampleFormat::I32 => self.build_stream::<i32>(writer)?,
+            SampleFormat::I64 => self.build_stream::<i64>(writer)?,
+            SampleFormat::U8 => self.build_stream::<u8>(writer)?,
+            SampleFormat::U16 => self.build_stream::<u16>(writer)?,
+            SampleFormat::U32 => self.build_stream::<u32>(writer)?,
+            SampleFormat::U64 => self.build_stream::<u64>(writer)?,
+            SampleFormat::F32 => self.build_stream::<f32>(writer)?,
+            SampleFormat::F64 => self.build_stream::<f64>(writer)?,
+        };
+        stream.play()?;
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        self.stream = None;
+    }
+}
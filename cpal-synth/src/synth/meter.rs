@@ -0,0 +1,570 @@
+// src/synth/meter.rs
+
+use crate::synth::audio_buffer::AudioBuffer;
+use crate::synth::audio_context::{rate_change, AudioContext};
+use crate::synth::audio_node::AudioNode;
+use crossbeam::atomic::AtomicCell;
+use lazy_static::lazy_static;
+use std::f64::consts::PI;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// Loudness is measured in 100ms steps: momentary over 4 of them, short-term over 30,
+// and integrated from 400ms gating blocks overlapping by 75% (BS.1770-4)
+const STEP_SECONDS: f32 = 0.1;
+const MOMENTARY_STEPS: usize = 4;
+const SHORT_TERM_STEPS: usize = 30;
+const ABSOLUTE_GATE: f64 = -70.0;
+const RELATIVE_GATE: f64 = -10.0;
+
+// Gating blocks are counted in a histogram of 0.01 LU bins from the absolute gate
+// up to +10 LUFS, so integrated loudness needs no memory that grows with time
+const HISTOGRAM_STEP: f64 = 0.01;
+const HISTOGRAM_BINS: usize = 8000;
+
+// True peak by 4x oversampling with a windowed-sinc interpolator, 12 taps a phase
+const OVERSAMPLING: usize = 4;
+const TRUE_PEAK_TAPS: usize = 12;
+
+const DEFAULT_RMS_WINDOW: f32 = 0.3;
+const DEFAULT_PEAK_HOLD: f32 = 2.0;
+
+lazy_static! {
+    // Interpolation filter for each phase between one sample and the next, each
+    // normalised to unity gain at DC
+    static ref TRUE_PEAK_FILTER: [[f32; TRUE_PEAK_TAPS]; OVERSAMPLING] = {
+        let mut filter = [[0.0; TRUE_PEAK_TAPS]; OVERSAMPLING];
+        let half = TRUE_PEAK_TAPS as f64 / 2.0;
+        for (phase, taps) in filter.iter_mut().enumerate() {
+            let offset = phase as f64 / OVERSAMPLING as f64;
+            for (k, tap) in taps.iter_mut().enumerate() {
+                // Distance from the interpolated point to the tap's sample
+                let t = k as f64 - (half - 1.0) - offset;
+                let sinc = if t == 0.0 { 1.0 } else { (PI * t).sin() / (PI * t) };
+                let window = 0.5 + 0.5 * (PI * t / half).cos();
+                *tap = (sinc * window) as f32;
+            }
+            let sum: f32 = taps.iter().sum();
+            taps.iter_mut().for_each(|tap| *tap /= sum);
+        }
+        filter
+    };
+}
+
+// Loudness in LUFS of a mean square power, or -inf for silence
+fn loudness(power: f64) -> f64 {
+    if power > 0.0 {
+        -0.691 + 10.0 * power.log10()
+    } else {
+        f64::NEG_INFINITY
+    }
+}
+
+fn power(loudness: f64) -> f64 {
+    10f64.powf((loudness + 0.691) / 10.0)
+}
+
+#[derive(Clone, Copy, Default)]
+struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    z1: f64,
+    z2: f64,
+}
+
+impl Biquad {
+    #[inline]
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b0 * x + self.z1;
+        self.z1 = self.b1 * x - self.a1 * y + self.z2;
+        self.z2 = self.b2 * x - self.a2 * y;
+        y
+    }
+
+    fn clear(&mut self) {
+        self.z1 = 0.0;
+        self.z2 = 0.0;
+    }
+}
+
+// The BS.1770 K-weighting: a high shelf for the head, then the RLB highpass,
+// designed for any sample rate (as in libebur128)
+#[derive(Clone, Copy, Default)]
+struct KWeighting {
+    shelf: Biquad,
+    highpass: Biquad,
+}
+
+impl KWeighting {
+    fn new(sample_rate: f32) -> Self {
+        let sample_rate = sample_rate as f64;
+
+        let f0 = 1681.974450955533;
+        let gain = 3.999843853973347;
+        let q = 0.7071752369554196;
+        let k = (PI * f0 / sample_rate).tan();
+        let vh = 10f64.powf(gain / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Biquad {
+            b0: (vh + vb * k / q + k * k) / a0,
+            b1: 2.0 * (k * k - vh) / a0,
+            b2: (vh - vb * k / q + k * k) / a0,
+            a1: 2.0 * (k * k - 1.0) / a0,
+            a2: (1.0 - k / q + k * k) / a0,
+            ..Default::default()
+        };
+
+        let f0 = 38.13547087602444;
+        let q = 0.5003270373238773;
+        let k = (PI * f0 / sample_rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let highpass = Biquad {
+            b0: 1.0,
+            b1: -2.0,
+            b2: 1.0,
+            a1: 2.0 * (k * k - 1.0) / a0,
+            a2: (1.0 - k / q + k * k) / a0,
+            ..Default::default()
+        };
+
+        Self { shelf, highpass }
+    }
+
+    #[inline]
+    fn process(&mut self, x: f32) -> f64 {
+        self.highpass.process(self.shelf.process(x as f64))
+    }
+}
+
+// EBU R128 loudness of one or two channels
+#[derive(Clone)]
+struct LoudnessMeter {
+    filters: [KWeighting; 2],
+    step_length: usize,
+    step_position: usize,
+    step_sum: f64,
+    // Mean power of the most recent steps, as a ring
+    steps: [f64; SHORT_TERM_STEPS],
+    step_count: usize,
+    histogram: Vec<u32>,
+    momentary: f64,
+    short_term: f64,
+}
+
+impl LoudnessMeter {
+    fn new(sample_rate: f32) -> Self {
+        Self {
+            filters: [KWeighting::new(sample_rate); 2],
+            step_length: ((sample_rate * STEP_SECONDS).round() as usize).max(1),
+            step_position: 0,
+            step_sum: 0.0,
+            steps: [0.0; SHORT_TERM_STEPS],
+            step_count: 0,
+            histogram: vec![0; HISTOGRAM_BINS],
+            momentary: f64::NEG_INFINITY,
+            short_term: f64::NEG_INFINITY,
+        }
+    }
+
+    fn reset(&mut self) {
+        for filter in &mut self.filters {
+            filter.shelf.clear();
+            filter.highpass.clear();
+        }
+        self.step_position = 0;
+        self.step_sum = 0.0;
+        self.steps = [0.0; SHORT_TERM_STEPS];
+        self.step_count = 0;
+        self.histogram.iter_mut().for_each(|count| *count = 0);
+        self.momentary = f64::NEG_INFINITY;
+        self.short_term = f64::NEG_INFINITY;
+    }
+
+    // Adds a frame of one or two channels and returns true when a step completes
+    fn push(&mut self, frame: &[f32]) -> bool {
+        for (filter, &sample) in self.filters.iter_mut().zip(frame) {
+            let weighted = filter.process(sample);
+            self.step_sum += weighted * weighted;
+        }
+        self.step_position += 1;
+        if self.step_position < self.step_length {
+            return false;
+        }
+
+        self.steps[self.step_count % SHORT_TERM_STEPS] = self.step_sum / self.step_length as f64;
+        self.step_count += 1;
+        self.step_position = 0;
+        self.step_sum = 0.0;
+
+        if self.step_count >= MOMENTARY_STEPS {
+            let block = self.mean_power(MOMENTARY_STEPS);
+            self.momentary = loudness(block);
+            if self.momentary >= ABSOLUTE_GATE {
+                let bin = ((self.momentary - ABSOLUTE_GATE) / HISTOGRAM_STEP) as usize;
+                self.histogram[bin.min(HISTOGRAM_BINS - 1)] += 1;
+            }
+        }
+        if self.step_count >= SHORT_TERM_STEPS {
+            self.short_term = loudness(self.mean_power(SHORT_TERM_STEPS));
+        }
+        true
+    }
+
+    // Mean power of the last `count` steps
+    fn mean_power(&self, count: usize) -> f64 {
+        let sum: f64 = (1..=count)
+            .map(|back| self.steps[(self.step_count - back) % SHORT_TERM_STEPS])
+            .sum();
+        sum / count as f64
+    }
+
+    // Gated mean over the blocks at or above `threshold`, as (power, blocks)
+    fn gated_power(&self, threshold: f64) -> (f64, u64) {
+        let first = ((threshold - ABSOLUTE_GATE) / HISTOGRAM_STEP)
+            .ceil()
+            .max(0.0) as usize;
+        let mut sum = 0.0;
+        let mut blocks = 0u64;
+        for (bin, &count) in self.histogram.iter().enumerate().skip(first) {
+            if count > 0 {
+                let centre = ABSOLUTE_GATE + (bin as f64 + 0.5) * HISTOGRAM_STEP;
+                sum += count as f64 * power(centre);
+                blocks += count as u64;
+            }
+        }
+        (sum, blocks)
+    }
+
+    fn integrated(&self) -> f64 {
+        let (sum, blocks) = self.gated_power(ABSOLUTE_GATE);
+        if blocks == 0 {
+            return f64::NEG_INFINITY;
+        }
+        let threshold = loudness(sum / blocks as f64) + RELATIVE_GATE;
+        let (sum, blocks) = self.gated_power(threshold);
+        if blocks == 0 {
+            return f64::NEG_INFINITY;
+        }
+        loudness(sum / blocks as f64)
+    }
+}
+
+// Readings published by the audio thread
+struct MeterShared {
+    peak: AtomicCell<f32>,
+    peak_hold: AtomicCell<f32>,
+    rms: AtomicCell<f32>,
+    true_peak: AtomicCell<f32>,
+    momentary: AtomicCell<f32>,
+    short_term: AtomicCell<f32>,
+    integrated: AtomicCell<f32>,
+    reset: AtomicBool,
+}
+
+/// Readings from a `MeterNode`, for the control thread.
+///
+/// The audio thread updates them every 100ms through atomics, so reading never
+/// blocks the callback. Peaks and RMS are linear; loudness is in LUFS, and -inf
+/// until enough audio has been measured.
+#[derive(Clone)]
+pub struct Meter {
+    shared: Arc<MeterShared>,
+}
+
+impl Meter {
+    fn new() -> Self {
+        Self {
+            shared: Arc::new(MeterShared {
+                peak: AtomicCell::new(0.0),
+                peak_hold: AtomicCell::new(0.0),
+                rms: AtomicCell::new(0.0),
+                true_peak: AtomicCell::new(0.0),
+                momentary: AtomicCell::new(f32::NEG_INFINITY),
+                short_term: AtomicCell::new(f32::NEG_INFINITY),
+                integrated: AtomicCell::new(f32::NEG_INFINITY),
+                reset: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    // Highest sample over the last 100ms
+    pub fn peak(&self) -> f32 {
+        self.shared.peak.load()
+    }
+
+    // Highest sample, held for the hold time before falling back
+    pub fn peak_hold(&self) -> f32 {
+        self.shared.peak_hold.load()
+    }
+
+    pub fn rms(&self) -> f32 {
+        self.shared.rms.load()
+    }
+
+    // Highest inter-sample peak since the last reset
+    pub fn true_peak(&self) -> f32 {
+        self.shared.true_peak.load()
+    }
+
+    pub fn momentary_loudness(&self) -> f32 {
+        self.shared.momentary.load()
+    }
+
+    pub fn short_term_loudness(&self) -> f32 {
+        self.shared.short_term.load()
+    }
+
+    pub fn integrated_loudness(&self) -> f32 {
+        self.shared.integrated.load()
+    }
+
+    // Starts the measurement over, from the next sample the audio thread processes
+    pub fn reset(&self) {
+        self.shared.reset.store(true, Ordering::Release);
+    }
+}
+
+/// A metering tap: passes its "input" through unchanged and measures it.
+///
+/// Sample peak with hold, RMS over a sliding window, true peak at 4x oversampling
+/// and EBU R128 loudness are all computed on the audio thread and published to
+/// the `Meter` from `meter()`, which clones share. Mono input is measured as one
+/// channel and stereo as two, as BS.1770 sums them. Set the RMS window and hold
+/// time before adding the node to a graph.
+pub struct MeterNode {
+    input: Option<Box<dyn AudioNode + Send>>,
+    meter: Meter,
+    loudness: LoudnessMeter,
+    sample_rate: f32,
+    // Squares of the mono mix over the RMS window, and their running sum
+    rms_window: Vec<f32>,
+    rms_position: usize,
+    rms_sum: f64,
+    // Recent samples of each channel for the true peak, newest first
+    history: [[f32; TRUE_PEAK_TAPS]; 2],
+    step_peak: f32,
+    held_peak: f32,
+    hold_samples: usize,
+    hold_remaining: usize,
+    true_peak: f32,
+}
+
+impl MeterNode {
+    pub fn new(context: &AudioContext) -> Self {
+        Self::with_meter(context, Meter::new())
+    }
+
+    // A tap that publishes to an existing meter
+    pub(crate) fn with_meter(context: &AudioContext, meter: Meter) -> Self {
+        let sample_rate = context.sample_rate();
+        Self {
+            input: None,
+            meter,
+            loudness: LoudnessMeter::new(sample_rate),
+            sample_rate,
+            rms_window: vec![0.0; ((sample_rate * DEFAULT_RMS_WINDOW) as usize).max(1)],
+            rms_position: 0,
+            rms_sum: 0.0,
+            history: [[0.0; TRUE_PEAK_TAPS]; 2],
+            step_peak: 0.0,
+            held_peak: 0.0,
+            hold_samples: (sample_rate * DEFAULT_PEAK_HOLD) as usize,
+            hold_remaining: 0,
+            true_peak: 0.0,
+        }
+    }
+
+    pub fn meter(&self) -> Meter {
+        self.meter.clone()
+    }
+
+    // Length of the RMS window in seconds
+    pub fn set_rms_window(&mut self, seconds: f32) {
+        self.rms_window = vec![0.0; ((self.sample_rate * seconds) as usize).max(1)];
+        self.rms_position = 0;
+        self.rms_sum = 0.0;
+    }
+
+    // How long `peak_hold` keeps a peak, in seconds
+    pub fn set_peak_hold(&mut self, seconds: f32) {
+        self.hold_samples = (self.sample_rate * seconds.max(0.0)) as usize;
+    }
+
+    fn reset(&mut self) {
+        self.loudness.reset();
+        self.rms_window.iter_mut().for_each(|square| *square = 0.0);
+        self.rms_sum = 0.0;
+        self.history = [[0.0; TRUE_PEAK_TAPS]; 2];
+        self.step_peak = 0.0;
+        self.held_peak = 0.0;
+        self.hold_remaining = 0;
+        self.true_peak = 0.0;
+        self.publish();
+    }
+
+    // Highest of the points between the newest sample and the one before it
+    fn inter_sample_peak(&mut self, channel: usize, sample: f32) -> f32 {
+        let history = &mut self.history[channel];
+        history.copy_within(..TRUE_PEAK_TAPS - 1, 1);
+        history[0] = sample;
+        TRUE_PEAK_FILTER
+            .iter()
+            .map(|taps| {
+                taps.iter()
+                    .zip(history.iter())
+                    .map(|(tap, x)| tap * x)
+                    .sum::<f32>()
+                    .abs()
+            })
+            .fold(0.0, f32::max)
+    }
+
+    fn measure(&mut self, frame: &[f32]) {
+        if self.meter.shared.reset.swap(false, Ordering::AcqRel) {
+            self.reset();
+        }
+
+        let mut peak = 0.0f32;
+        let mut square = 0.0;
+        for (channel, &sample) in frame.iter().enumerate() {
+            peak = peak.max(sample.abs());
+            square += sample * sample;
+            let true_peak = self.inter_sample_peak(channel, sample);
+            self.true_peak = self.true_peak.max(true_peak).max(sample.abs());
+        }
+        self.step_peak = self.step_peak.max(peak);
+
+        if peak >= self.held_peak {
+            self.held_peak = peak;
+            self.hold_remaining = self.hold_samples;
+        } else if self.hold_remaining > 0 {
+            self.hold_remaining -= 1;
+        } else {
+            self.held_peak = peak;
+        }
+
+        // Recompute the running sum on each pass through the window so rounding
+        // errors can't build up
+        let square = square / frame.len() as f32;
+        self.rms_sum += (square - self.rms_window[self.rms_position]) as f64;
+        self.rms_window[self.rms_position] = square;
+        self.rms_position += 1;
+        if self.rms_position == self.rms_window.len() {
+            self.rms_position = 0;
+            self.rms_sum = self.rms_window.iter().map(|&x| x as f64).sum();
+        }
+
+        if self.loudness.push(frame) {
+            self.publish();
+            self.step_peak = 0.0;
+        }
+    }
+
+    fn publish(&self) {
+        let shared = &self.meter.shared;
+        shared.peak.store(self.step_peak);
+        shared.peak_hold.store(self.held_peak);
+        shared
+            .rms
+            .store((self.rms_sum.max(0.0) / self.rms_window.len() as f64).sqrt() as f32);
+        shared.true_peak.store(self.true_peak);
+        shared.momentary.store(self.loudness.momentary as f32);
+        shared.short_term.store(self.loudness.short_term as f32);
+        shared.integrated.store(self.loudness.integrated() as f32);
+    }
+}
+
+impl AudioNode for MeterNode {
+    fn process(&mut self, context: &AudioContext, current_sample: u64) -> f32 {
+        let sample = match self.input.as_mut() {
+            Some(input) => input.process(context, current_sample),
+            None => 0.0,
+        };
+        self.measure(&[sample]);
+        sample
+    }
+
+    fn process_stereo(&mut self, context: &AudioContext, current_sample: u64) -> (f32, f32) {
+        let (left, right) = match self.input.as_mut() {
+            Some(input) => input.process_stereo(context, current_sample),
+            None => (0.0, 0.0),
+        };
+        self.measure(&[left, right]);
+        (left, right)
+    }
+
+    fn set_parameter(&self, _name: &str, _value: f32) {
+        // Nothing to automate
+    }
+
+    fn connect_input(&mut self, name: &str, node: Box<dyn AudioNode + Send>) {
+        if name == "input" {
+            self.input = Some(node);
+        }
+    }
+
+    fn clear_input(&mut self, input_name: &str) {
+        if input_name == "input" {
+            self.input = None;
+        }
+    }
+
+    // The K-weighting filters are rebuilt for the new rate, so loudness measurement
+    // starts over; the RMS window and peak hold keep their length in seconds
+    fn prepare(&mut self, sample_rate: f32, max_block: usize) {
+        if let Some(input) = self.input.as_mut() {
+            input.prepare(sample_rate, max_block);
+        }
+        if let Some(ratio) = rate_change(&mut self.sample_rate, sample_rate) {
+            self.loudness = LoudnessMeter::new(sample_rate);
+            let window = ((self.rms_window.len() as f64 * ratio) as usize).max(1);
+            self.rms_window = vec![0.0; window];
+            self.rms_position = 0;
+            self.rms_sum = 0.0;
+            self.hold_samples = (self.hold_samples as f64 * ratio) as usize;
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn AudioNode + Send> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for MeterNode {
+    fn clone(&self) -> Self {
+        Self {
+            input: self.input.as_ref().map(|input| input.clone_box()),
+            meter: self.meter.clone(),
+            loudness: self.loudness.clone(),
+            sample_rate: self.sample_rate,
+            rms_window: self.rms_window.clone(),
+            rms_position: self.rms_position,
+            rms_sum: self.rms_sum,
+            history: self.history,
+            step_peak: self.step_peak,
+            held_peak: self.held_peak,
+            hold_samples: self.hold_samples,
+            hold_remaining: self.hold_remaining,
+            true_peak: self.true_peak,
+        }
+    }
+}
+
+/// Integrated loudness of a whole buffer in LUFS, e.g. of an offline render.
+/// The first two channels are measured; -inf if it is too short or too quiet.
+pub fn integrated_loudness(buffer: &AudioBuffer) -> f32 {
+    let mut meter = LoudnessMeter::new(buffer.sample_rate());
+    let channels = buffer.number_of_channels().min(2);
+    let mut frame = [0.0; 2];
+    for i in 0..buffer.length() {
+        for (channel, sample) in frame[..channels].iter_mut().enumerate() {
+            *sample = buffer.channel(channel)[i];
+        }
+        meter.push(&frame[..channels]);
+    }
+    meter.integrated() as f32
+}
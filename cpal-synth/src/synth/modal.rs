@@ -0,0 +1,367 @@
+// src/synth/modal.rs
+
+use crate::synth::audio_context::{rate_change, AudioContext};
+use crate::synth::audio_node::AudioNode;
+use crate::synth::audio_param::AudioParam;
+use crate::synth::event_queue::EventQueue;
+use std::f32::consts::PI;
+
+pub const MAX_MODES: usize = 64;
+
+/// Preset mode tables, as (frequency ratio, gain, decay in seconds) for each mode.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ModeTable {
+    // Uniform bar, free at both ends
+    Bar,
+    // Church bell: hum, prime, tierce, quint, nominal and the partials above
+    Bell,
+    // Ideal circular membrane, the first ten Bessel modes
+    Membrane,
+    // Marimba bar, undercut so the overtones sit at 4 and 10 times the fundamental
+    Marimba,
+}
+
+impl ModeTable {
+    pub fn modes(&self) -> &'static [(f32, f32, f32)] {
+        match self {
+            ModeTable::Bar => &[
+                (1.0, 0.5, 1.2),
+                (2.756, 0.25, 0.8),
+                (5.404, 0.12, 0.5),
+                (8.933, 0.07, 0.3),
+                (13.345, 0.04, 0.2),
+                (18.638, 0.02, 0.12),
+            ],
+            ModeTable::Bell => &[
+                (0.5, 0.15, 6.0),
+                (1.0, 0.2, 4.0),
+                (1.183, 0.15, 3.0),
+                (1.506, 0.08, 2.5),
+                (2.0, 0.18, 2.0),
+                (2.514, 0.1, 1.5),
+                (2.662, 0.06, 1.2),
+                (3.011, 0.05, 1.0),
+                (4.166, 0.03, 0.7),
+            ],
+            ModeTable::Membrane => &[
+                (1.0, 0.3, 0.5),
+                (1.593, 0.2, 0.4),
+                (2.135, 0.14, 0.3),
+                (2.295, 0.1, 0.3),
+                (2.653, 0.08, 0.25),
+                (2.917, 0.06, 0.2),
+                (3.155, 0.04, 0.2),
+                (3.5, 0.03, 0.15),
+                (3.598, 0.03, 0.15),
+                (3.647, 0.02, 0.12),
+            ],
+            ModeTable::Marimba => &[
+                (1.0, 0.6, 0.8),
+                (3.99, 0.25, 0.25),
+                (10.65, 0.1, 0.08),
+                (18.0, 0.05, 0.04),
+            ],
+        }
+    }
+}
+
+/// One resonant mode: `ratio` times the base frequency, ringing at `gain` for an
+/// impulse of 1, and falling by 60dB over `decay` seconds.
+pub struct Mode {
+    ratio: AudioParam,
+    gain: AudioParam,
+    decay: AudioParam,
+}
+
+impl Mode {
+    fn new(ratio: f32, gain: f32, decay: f32) -> Self {
+        Self {
+            ratio: AudioParam::new(ratio, 0.0, 1024.0),
+            gain: AudioParam::new(gain, 0.0, 1.0),
+            decay: AudioParam::new(decay, 0.001, 60.0),
+        }
+    }
+
+    pub fn ratio(&self) -> &AudioParam {
+        &self.ratio
+    }
+
+    pub fn gain(&self) -> &AudioParam {
+        &self.gain
+    }
+
+    pub fn decay(&self) -> &AudioParam {
+        &self.decay
+    }
+}
+
+impl Clone for Mode {
+    fn clone(&self) -> Self {
+        Self {
+            ratio: self.ratio.clone(),
+            gain: self.gain.clone(),
+            decay: self.decay.clone(),
+        }
+    }
+}
+
+// Filter state for one mode, with the settings its coefficients were made for
+#[derive(Clone, Copy, Default)]
+struct Resonator {
+    frequency: f32,
+    decay: f32,
+    a1: f32,
+    a2: f32,
+    b0: f32,
+    y1: f32,
+    y2: f32,
+}
+
+impl Resonator {
+    // Two poles at radius r and angle θ, scaled so an impulse of 1 rings as
+    // r^n sin(θ(n + 1)), with a peak of about 1
+    fn tune(&mut self, frequency: f32, decay: f32, sample_rate: f32) {
+        self.frequency = frequency;
+        self.decay = decay;
+        let theta = 2.0 * PI * frequency / sample_rate;
+        let r = 0.001f32.powf(1.0 / (decay * sample_rate));
+        self.a1 = 2.0 * r * theta.cos();
+        self.a2 = r * r;
+        self.b0 = theta.sin();
+    }
+
+    #[inline]
+    fn process(&mut self, input: f32) -> f32 {
+        let y = self.b0 * input + self.a1 * self.y1 - self.a2 * self.y2;
+        self.y2 = self.y1;
+        self.y1 = y;
+        y
+    }
+}
+
+/// A bank of tuned, decaying two-pole resonators for modal percussion.
+///
+/// The "input" connected with `connect_input` drives every mode, so the resonator
+/// can colour noise, a mallet click or another voice. It can also be struck
+/// directly: a rising edge of `gate` (or `strike`) puts an impulse of the gate
+/// value into the bank, so it plays from note events like `BufferSourceNode`.
+/// `decay` scales every mode's decay time together. Modes at or above Nyquist
+/// are left out.
+pub struct ModalResonator {
+    frequency: AudioParam,
+    gain: AudioParam,
+    gate: AudioParam,
+    decay: AudioParam,
+    modes: Vec<Mode>,
+    resonators: Vec<Resonator>,
+    input: Option<Box<dyn AudioNode + Send>>,
+    // Strike velocities, by sample
+    scheduled: EventQueue<f32>,
+    last_gate: f32,
+    // Rate the resonators are tuned for, 0 until prepared
+    sample_rate: f32,
+}
+
+impl ModalResonator {
+    pub fn new(table: ModeTable) -> Self {
+        let mut resonator = Self {
+            frequency: AudioParam::new(440.0, 0.01, 22050.0),
+            gain: AudioParam::new(1.0, 0.0, 1.0),
+            gate: AudioParam::new(0.0, 0.0, 1.0),
+            decay: AudioParam::new(1.0, 0.01, 100.0),
+            modes: Vec::new(),
+            resonators: Vec::new(),
+            input: None,
+            scheduled: EventQueue::new(),
+            last_gate: 0.0,
+            sample_rate: 0.0,
+        };
+        resonator.set_modes(table.modes());
+        resonator
+    }
+
+    pub fn frequency(&self) -> &AudioParam {
+        &self.frequency
+    }
+
+    pub fn gain(&self) -> &AudioParam {
+        &self.gain
+    }
+
+    pub fn gate(&self) -> &AudioParam {
+        &self.gate
+    }
+
+    // Multiplier on every mode's decay time
+    pub fn decay(&self) -> &AudioParam {
+        &self.decay
+    }
+
+    pub fn mode(&self, index: usize) -> &Mode {
+        &self.modes[index]
+    }
+
+    pub fn mode_count(&self) -> usize {
+        self.modes.len()
+    }
+
+    pub fn set_mode_table(&mut self, table: ModeTable) {
+        self.set_modes(table.modes());
+    }
+
+    // Replaces the modes with (ratio, gain, decay) triples, up to `MAX_MODES`
+    pub fn set_modes(&mut self, modes: &[(f32, f32, f32)]) {
+        self.modes = modes
+            .iter()
+            .take(MAX_MODES)
+            .map(|&(ratio, gain, decay)| Mode::new(ratio, gain, decay))
+            .collect();
+        self.resonators = vec![Resonator::default(); self.modes.len()];
+    }
+
+    // Strikes the bank with an impulse of `velocity` at the given sample
+    pub fn strike(&self, at_sample: u64, velocity: f32) {
+        self.scheduled.push(at_sample, velocity);
+    }
+
+    // Silences every mode
+    pub fn reset(&mut self) {
+        for resonator in &mut self.resonators {
+            resonator.y1 = 0.0;
+            resonator.y2 = 0.0;
+        }
+    }
+
+    // Splits "mode<n>.<name>" into the mode and its parameter
+    fn mode_parameter(&self, name: &str) -> Option<&AudioParam> {
+        let (mode, param) = name.strip_prefix("mode")?.split_once('.')?;
+        let mode = self.modes.get(mode.parse::<usize>().ok()?)?;
+        match param {
+            "ratio" => Some(&mode.ratio),
+            "gain" => Some(&mode.gain),
+            "decay" => Some(&mode.decay),
+            _ => None,
+        }
+    }
+}
+
+impl AudioNode for ModalResonator {
+    fn process(&mut self, context: &AudioContext, current_sample: u64) -> f32 {
+        let sample_rate = context.sample_rate();
+
+        let mut excitation = match self.input.as_mut() {
+            Some(input) => input.process(context, current_sample),
+            None => 0.0,
+        };
+        let gate = self.gate.get_value(current_sample);
+        if gate > 0.0 && self.last_gate <= 0.0 {
+            excitation += gate;
+        }
+        self.last_gate = gate;
+        while let Some(velocity) = self.scheduled.pop_due(current_sample) {
+            excitation += velocity;
+        }
+
+        let frequency = self.frequency.get_value(current_sample);
+        let decay_scale = self.decay.get_value(current_sample);
+        let nyquist = sample_rate * 0.5;
+        let mut output = 0.0;
+        for (mode, resonator) in self.modes.iter().zip(self.resonators.iter_mut()) {
+            let mode_frequency = frequency * mode.ratio.get_value(current_sample);
+            if mode_frequency >= nyquist {
+                continue;
+            }
+            let decay = mode.decay.get_value(current_sample) * decay_scale;
+            if mode_frequency != resonator.frequency || decay != resonator.decay {
+                resonator.tune(mode_frequency, decay, sample_rate);
+            }
+            output += resonator.process(excitation) * mode.gain.get_value(current_sample);
+        }
+
+        output * self.gain.get_value(current_sample)
+    }
+
+    // Mode parameters are addressed as "mode<n>.<name>", e.g. "mode0.decay"
+    fn set_parameter(&self, name: &str, value: f32) {
+        match name {
+            "frequency" => self.frequency.set_value(value),
+            "gain" => self.gain.set_value(value),
+            "gate" => self.gate.set_value(value),
+            "decay" => self.decay.set_value(value),
+            _ => {
+                if let Some(param) = self.mode_parameter(name) {
+                    param.set_value(value);
+                }
+            }
+        }
+    }
+
+    fn set_parameter_at(&self, name: &str, value: f32, at_sample: u64) {
+        match name {
+            "frequency" => self.frequency.set_value_at_time(value, at_sample),
+            "gain" => self.gain.set_value_at_time(value, at_sample),
+            "gate" => self.gate.set_value_at_time(value, at_sample),
+            "decay" => self.decay.set_value_at_time(value, at_sample),
+            _ => {
+                if let Some(param) = self.mode_parameter(name) {
+                    param.set_value_at_time(value, at_sample);
+                }
+            }
+        }
+    }
+
+    // "input" is the excitation signal
+    fn connect_input(&mut self, name: &str, node: Box<dyn AudioNode + Send>) {
+        if name == "input" {
+            self.input = Some(node);
+        }
+    }
+
+    fn clear_input(&mut self, input_name: &str) {
+        if input_name == "input" {
+            self.input = None;
+        }
+    }
+
+    fn prepare(&mut self, sample_rate: f32, max_block: usize) {
+        for param in [&self.frequency, &self.gain, &self.gate, &self.decay] {
+            param.prepare(sample_rate);
+        }
+        for mode in &self.modes {
+            mode.ratio.prepare(sample_rate);
+            mode.gain.prepare(sample_rate);
+            mode.decay.prepare(sample_rate);
+        }
+        if let Some(input) = self.input.as_mut() {
+            input.prepare(sample_rate, max_block);
+        }
+        if let Some(ratio) = rate_change(&mut self.sample_rate, sample_rate) {
+            // Retune on the next sample; the ringing carries on
+            for resonator in &mut self.resonators {
+                resonator.frequency = 0.0;
+            }
+            self.scheduled.rescale(ratio);
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn AudioNode + Send> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for ModalResonator {
+    fn clone(&self) -> Self {
+        Self {
+            frequency: self.frequency.clone(),
+            gain: self.gain.clone(),
+            gate: self.gate.clone(),
+            decay: self.decay.clone(),
+            modes: self.modes.clone(),
+            resonators: self.resonators.clone(),
+            input: self.input.as_ref().map(|input| input.clone_box()),
+            scheduled: self.scheduled.clone(),
+            last_gate: self.last_gate,
+            sample_rate: self.sample_rate,
+        }
+    }
+}
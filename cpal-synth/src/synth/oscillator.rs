@@ -3,7 +3,10 @@
 use crate::synth::audio_context::AudioContext;
 use crate::synth::audio_node::AudioNode;
 use crate::synth::audio_param::AudioParam;
+use crate::synth::hard_sync::{wrap_delay, HardSync, SyncOut};
+use crate::synth::periodic_wave::PeriodicWave;
 use std::f32::consts::PI;
+use std::sync::Arc;
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum OscillatorType {
@@ -11,24 +14,70 @@ pub enum OscillatorType {
     Square,
     Sawtooth,
     Triangle,
+    // High for the first `pulse_width` of each cycle, low for the rest
+    Pulse,
+    // A registered `PeriodicWave`, by id
+    Custom(u32),
 }
 
 pub struct Oscillator {
     osc_type: OscillatorType,
     frequency: AudioParam,
     gain: AudioParam,
+    pulse_width: AudioParam,
+    // Audio-rate modulation added to `pulse_width`
+    pulse_width_input: Option<Box<dyn AudioNode + Send>>,
+    width: f32,
     phase: f32,
+    // Where the phase wrapped on the way into the sample just processed, and into
+    // the next one, as samples before it
+    wrapped: Option<f32>,
+    next_wrap: Option<f32>,
     triangle_state: f32,
+    periodic_wave: Option<Arc<PeriodicWave>>,
+    sync: HardSync,
 }
 
 impl Oscillator {
-    pub fn new(osc_type: OscillatorType) -> Self {
+    // A built-in shape. Custom waves need their `PeriodicWave`, so they play
+    // through `custom` or `with_periodic_wave` and are an error here.
+    pub fn new(osc_type: OscillatorType) -> anyhow::Result<Self> {
+        if let OscillatorType::Custom(id) = osc_type {
+            anyhow::bail!(
+                "Custom wave {} needs Oscillator::custom or Oscillator::with_periodic_wave",
+                id
+            );
+        }
+        Ok(Self::with_wave(osc_type, None))
+    }
+
+    // Plays the wave registered with the context under the given id
+    pub fn custom(id: u32, context: &AudioContext) -> anyhow::Result<Self> {
+        let wave = context
+            .wave_banks()
+            .periodic_wave(id)
+            .ok_or_else(|| anyhow::anyhow!("No periodic wave with id {}", id))?;
+        Ok(Self::with_periodic_wave(wave))
+    }
+
+    pub fn with_periodic_wave(wave: Arc<PeriodicWave>) -> Self {
+        Self::with_wave(wave.oscillator_type(), Some(wave))
+    }
+
+    fn with_wave(osc_type: OscillatorType, periodic_wave: Option<Arc<PeriodicWave>>) -> Self {
         Self {
             osc_type,
             frequency: AudioParam::new(440.0, 0.01, 22050.0),
             gain: AudioParam::new(1.0, 0.0, 1.0),
+            pulse_width: AudioParam::new(0.5, 0.0, 1.0),
+            pulse_width_input: None,
+            width: 0.5,
             phase: 0.0,
+            wrapped: None,
+            next_wrap: None,
             triangle_state: 0.0,
+            periodic_wave,
+            sync: HardSync::new(),
         }
     }
 
@@ -40,6 +89,16 @@ impl Oscillator {
         &self.gain
     }
 
+    // Duty cycle of `OscillatorType::Pulse`, from 0 to 1
+    pub fn pulse_width(&self) -> &AudioParam {
+        &self.pulse_width
+    }
+
+    // Restarts the waveform at `phase` (0 to 1) at the given sample
+    pub fn reset_phase(&self, at_sample: u64, phase: f32) {
+        self.sync.schedule(at_sample, phase);
+    }
+
     fn poly_blep(&self, t: f32, dt: f32) -> f32 {
         if t < dt {
             let t = t / dt;
@@ -52,21 +111,51 @@ impl Oscillator {
         }
     }
 
-    fn process_bandlimited(&mut self, sample_rate: f32, current_sample: u64) -> f32 {
-        let freq = self.frequency.get_value(current_sample);
+    // The waveform at `phase` without band-limiting, used to size the step a
+    // phase reset makes. The triangle is integrated, so a reset only bends it.
+    fn naive(&self, phase: f32, dt: f32) -> f32 {
+        match self.osc_type {
+            OscillatorType::Sine => (phase * 2.0 * PI).sin(),
+            OscillatorType::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            OscillatorType::Sawtooth => 2.0 * phase - 1.0,
+            OscillatorType::Triangle => 0.0,
+            OscillatorType::Pulse => {
+                if phase < self.width {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            OscillatorType::Custom(_) => match &self.periodic_wave {
+                Some(wave) => wave.evaluate(phase, (0.5 / dt.abs()).ceil() as usize - 1),
+                None => 0.0,
+            },
+        }
+    }
+
+    // With `blep` false the corrections for the waveform's own edges are left out,
+    // for the sample after a phase reset, whose step is corrected separately
+    fn process_bandlimited(&mut self, freq: f32, sample_rate: f32, blep: bool) -> f32 {
         let dt = freq / sample_rate;
+        let poly_blep = |t: f32| if blep { self.poly_blep(t, dt) } else { 0.0 };
 
         let output = match self.osc_type {
             OscillatorType::Sine => (self.phase * 2.0 * PI).sin(),
             OscillatorType::Square => {
                 let mut out = if self.phase < 0.5 { 1.0 } else { -1.0 };
-                out += self.poly_blep(self.phase, dt);
-                out -= self.poly_blep(fmod(self.phase + 0.5, 1.0), dt);
+                out += poly_blep(self.phase);
+                out -= poly_blep(fmod(self.phase + 0.5, 1.0));
                 out
             }
             OscillatorType::Sawtooth => {
                 let mut out = 2.0 * self.phase - 1.0;
-                out -= self.poly_blep(self.phase, dt);
+                out -= poly_blep(self.phase);
                 out
             }
             OscillatorType::Triangle => {
@@ -82,9 +171,18 @@ impl Oscillator {
                 // Scale the output
                 self.triangle_state
             }
+            OscillatorType::Custom(_) => self.naive(self.phase, dt),
+            OscillatorType::Pulse => {
+                // Rising edge at phase 0, falling edge at the pulse width
+                let mut out = self.naive(self.phase, dt);
+                out += poly_blep(self.phase);
+                out -= poly_blep(fmod(self.phase + 1.0 - self.width, 1.0));
+                out
+            }
         };
 
         self.phase += dt;
+        self.next_wrap = wrap_delay(self.phase, dt);
         self.phase = fmod(self.phase, 1.0);
 
         output
@@ -98,7 +196,29 @@ fn fmod(x: f32, y: f32) -> f32 {
 impl AudioNode for Oscillator {
     fn process(&mut self, context: &AudioContext, current_sample: u64) -> f32 {
         let sample_rate = context.sample_rate();
-        let output = self.process_bandlimited(sample_rate, current_sample);
+        let freq = self.frequency.get_value(current_sample);
+        self.wrapped = self.next_wrap.take();
+        if self.osc_type == OscillatorType::Pulse {
+            let modulation = match self.pulse_width_input.as_mut() {
+                Some(input) => input.process(context, current_sample),
+                None => 0.0,
+            };
+            self.width = (self.pulse_width.get_value(current_sample) + modulation).clamp(0.0, 1.0);
+        }
+
+        // Jump to the reset phase, as if it happened `delay` samples ago, and
+        // measure the step that leaves in the waveform
+        let reset = self.sync.poll(context, current_sample);
+        let mut step = 0.0;
+        if let Some(reset) = reset {
+            let dt = freq / sample_rate;
+            let before = fmod(self.phase - reset.delay * dt, 1.0);
+            step = self.naive(reset.phase, dt) - self.naive(before, dt);
+            self.phase = fmod(reset.phase + reset.delay * dt, 1.0);
+        }
+
+        let output = self.process_bandlimited(freq, sample_rate, reset.is_none());
+        let output = self.sync.finish(output, reset, step);
         let final_output = output * self.gain.get_value(current_sample);
 
         // Debug output every second
@@ -120,16 +240,53 @@ impl AudioNode for Oscillator {
         match name {
             "frequency" => self.frequency.set_value(value),
             "gain" => self.gain.set_value(value),
+            "pulse_width" => self.pulse_width.set_value(value),
+            "phase" => self.reset_phase(0, value),
+            _ => {}
+        }
+    }
+
+    fn set_parameter_at(&self, name: &str, value: f32, at_sample: u64) {
+        match name {
+            "frequency" => self.frequency.set_value_at_time(value, at_sample),
+            "gain" => self.gain.set_value_at_time(value, at_sample),
+            "pulse_width" => self.pulse_width.set_value_at_time(value, at_sample),
+            "phase" => self.reset_phase(at_sample, value),
+            _ => {}
+        }
+    }
+
+    // "sync" hard-syncs this oscillator to another, restarting it each time the
+    // other's phase wraps, or for nodes without a phase, each time they cross zero
+    // going up. "pulse_width" adds an audio-rate signal to the pulse width.
+    fn connect_input(&mut self, name: &str, node: Box<dyn AudioNode + Send>) {
+        match name {
+            "sync" => self.sync.connect(node),
+            "pulse_width" => self.pulse_width_input = Some(node),
+            _ => {}
+        }
+    }
+
+    fn clear_input(&mut self, input_name: &str) {
+        match input_name {
+            "sync" => self.sync.disconnect(),
+            "pulse_width" => self.pulse_width_input = None,
             _ => {}
         }
     }
 
-    fn connect_input(&mut self, _name: &str, _node: Box<dyn AudioNode + Send>) {
-        // Oscillators don't have inputs
+    fn sync_out(&self) -> SyncOut {
+        self.wrapped.map_or(SyncOut::Running, SyncOut::Wrapped)
     }
 
-    fn clear_input(&mut self, _input_name: &str) {
-        // No-op implementation for oscillators as they do not store inputs
+    fn prepare(&mut self, sample_rate: f32, max_block: usize) {
+        for param in [&self.frequency, &self.gain, &self.pulse_width] {
+            param.prepare(sample_rate);
+        }
+        if let Some(input) = self.pulse_width_input.as_mut() {
+            input.prepare(sample_rate, max_block);
+        }
+        self.sync.prepare(sample_rate, max_block);
     }
 
     fn clone_box(&self) -> Box<dyn AudioNode + Send> {
@@ -144,8 +301,18 @@ impl Clone for Oscillator {
             osc_type: self.osc_type,
             frequency: self.frequency.clone(), // Use clone() instead of accessing private fields
             gain: self.gain.clone(),           // Use clone() instead of accessing private fields
+            pulse_width: self.pulse_width.clone(),
+            pulse_width_input: self
+                .pulse_width_input
+                .as_ref()
+                .map(|input| input.clone_box()),
+            width: self.width,
             phase: self.phase,
+            wrapped: self.wrapped,
+            next_wrap: self.next_wrap,
             triangle_state: self.triangle_state,
+            periodic_wave: self.periodic_wave.clone(),
+            sync: self.sync.clone(),
         }
     }
 }
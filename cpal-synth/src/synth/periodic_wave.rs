@@ -0,0 +1,232 @@
+// src/synth/periodic_wave.rs
+
+use crate::synth::audio_decoder::decode_audio_data;
+use crate::synth::oscillator::OscillatorType;
+use rustfft::{num_complex::Complex, FftPlanner};
+use std::f32::consts::PI;
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+// Points used to find the peak of a waveform for normalization
+const NORMALIZE_POINTS: usize = 4096;
+
+/// Frame length used by common wavetable editors.
+pub const WAVETABLE_FRAME_SIZE: usize = 2048;
+
+static NEXT_WAVE_ID: AtomicU32 = AtomicU32::new(1);
+
+#[derive(Debug)]
+struct Harmonics {
+    real: Vec<f32>,
+    imag: Vec<f32>,
+}
+
+/// A waveform defined by Fourier coefficients, like Web Audio's `PeriodicWave`.
+///
+/// One period is `sum(real[k] * cos(2πkt) + imag[k] * sin(2πkt))` for k >= 1; the
+/// DC terms at index 0 are ignored. A wave can hold several frames, as imported
+/// wavetables do; `real`, `imag` and `evaluate` refer to the first. Hand a wave
+/// straight to `Oscillator::with_periodic_wave`, or register it with a context's
+/// `WaveBankRegistry` (`AudioContext::create_periodic_wave` does) to play it
+/// through `OscillatorType::Custom(wave.id())`.
+#[derive(Debug)]
+pub struct PeriodicWave {
+    id: u32,
+    frames: Vec<Harmonics>,
+    normalize: bool,
+    // Gain that brings the peak of the full-bandwidth waveform to 1.0
+    scale: f32,
+}
+
+impl PeriodicWave {
+    /// Creates a wave. With `normalize` the waveform is scaled to a peak of
+    /// 1.0; otherwise the coefficients are used as given.
+    pub fn new(real: &[f32], imag: &[f32], normalize: bool) -> anyhow::Result<Arc<Self>> {
+        if real.len() != imag.len() {
+            return Err(anyhow::anyhow!(
+                "PeriodicWave real and imag lengths differ ({} and {})",
+                real.len(),
+                imag.len()
+            ));
+        }
+        if real.len() < 2 {
+            return Err(anyhow::anyhow!(
+                "PeriodicWave needs at least 2 coefficients"
+            ));
+        }
+
+        let mut wave = Self {
+            id: NEXT_WAVE_ID.fetch_add(1, Ordering::Relaxed),
+            frames: vec![Harmonics {
+                real: real.to_vec(),
+                imag: imag.to_vec(),
+            }],
+            normalize,
+            scale: 1.0,
+        };
+        if normalize {
+            let peak = (0..NORMALIZE_POINTS)
+                .map(|i| {
+                    wave.evaluate(i as f32 / NORMALIZE_POINTS as f32, usize::MAX)
+                        .abs()
+                })
+                .fold(0.0f32, f32::max);
+            if peak > 0.0 {
+                wave.scale = 1.0 / peak;
+            }
+        }
+
+        Ok(Arc::new(wave))
+    }
+
+    /// Creates a wave from single-cycle frames of audio. Each frame is analysed
+    /// with an FFT, so frames can be any length but should all describe one cycle.
+    /// With `normalize` the loudest frame peaks at 1.0 and the others keep their
+    /// level relative to it.
+    pub fn from_frames<F: AsRef<[f32]>>(
+        frames: &[F],
+        normalize: bool,
+    ) -> anyhow::Result<Arc<Self>> {
+        if frames.is_empty() {
+            return Err(anyhow::anyhow!("Wavetable has no frames"));
+        }
+
+        let mut planner = FftPlanner::new();
+        let mut harmonics = Vec::with_capacity(frames.len());
+        let mut peak = 0.0f32;
+        for frame in frames {
+            let frame = frame.as_ref();
+            if frame.len() < 4 {
+                return Err(anyhow::anyhow!(
+                    "Wavetable frames need at least 4 samples, got {}",
+                    frame.len()
+                ));
+            }
+
+            let len = frame.len();
+            let mut spectrum: Vec<Complex<f32>> =
+                frame.iter().map(|&x| Complex::new(x, 0.0)).collect();
+            planner.plan_fft_forward(len).process(&mut spectrum);
+
+            // Bin k of the FFT is (a - ib) * N/2 for a*cos + b*sin
+            let count = len.div_ceil(2);
+            let scale = 2.0 / len as f32;
+            let mut real = vec![0.0; count];
+            let mut imag = vec![0.0; count];
+            for k in 1..count {
+                real[k] = spectrum[k].re * scale;
+                imag[k] = -spectrum[k].im * scale;
+            }
+
+            // The peak ignores DC, which the tables drop
+            let dc = spectrum[0].re / len as f32;
+            peak = frame.iter().fold(peak, |max, &x| max.max((x - dc).abs()));
+            harmonics.push(Harmonics { real, imag });
+        }
+
+        let scale = if normalize && peak > 0.0 {
+            1.0 / peak
+        } else {
+            1.0
+        };
+        Ok(Arc::new(Self {
+            id: NEXT_WAVE_ID.fetch_add(1, Ordering::Relaxed),
+            frames: harmonics,
+            normalize,
+            scale,
+        }))
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn oscillator_type(&self) -> OscillatorType {
+        OscillatorType::Custom(self.id)
+    }
+
+    pub fn real(&self) -> &[f32] {
+        &self.frames[0].real
+    }
+
+    pub fn imag(&self) -> &[f32] {
+        &self.frames[0].imag
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn frame_real(&self, frame: usize) -> &[f32] {
+        &self.frames[frame].real
+    }
+
+    pub fn frame_imag(&self, frame: usize) -> &[f32] {
+        &self.frames[frame].imag
+    }
+
+    pub fn is_normalized(&self) -> bool {
+        self.normalize
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    // Number of harmonics in the first frame, not counting DC
+    pub fn harmonics(&self) -> usize {
+        self.frames[0].real.len() - 1
+    }
+
+    /// Value at `phase` in [0, 1), summing harmonics up to `max_harmonic`.
+    /// Sine and cosine are stepped by rotation, so the cost is one `sin_cos` per call.
+    pub fn evaluate(&self, phase: f32, max_harmonic: usize) -> f32 {
+        let frame = &self.frames[0];
+        let (s1, c1) = (2.0 * PI * phase).sin_cos();
+        let (mut s, mut c) = (s1, c1);
+        let mut output = 0.0;
+        for k in 1..=self.harmonics().min(max_harmonic) {
+            output += frame.real[k] * c + frame.imag[k] * s;
+            (s, c) = (s * c1 + c * s1, c * c1 - s * s1);
+        }
+        output * self.scale
+    }
+}
+
+/// Loads a wavetable from a WAV (or FLAC/Ogg Vorbis) file of consecutive
+/// `frame_size`-sample frames. A file shorter than one frame is
+/// taken as a single cycle of its own length. Only the first channel is used.
+pub fn load_wavetable(bytes: &[u8], frame_size: usize) -> anyhow::Result<Arc<PeriodicWave>> {
+    let buffer = decode_audio_data(bytes)?;
+    let samples = buffer.channel(0);
+    if samples.is_empty() {
+        return Err(anyhow::anyhow!("Wavetable file has no samples"));
+    }
+    if frame_size == 0 {
+        return Err(anyhow::anyhow!("Wavetable frame size must be positive"));
+    }
+
+    if samples.len() < frame_size {
+        return PeriodicWave::from_frames(&[samples], true);
+    }
+    if samples.len() % frame_size != 0 {
+        return Err(anyhow::anyhow!(
+            "Wavetable length {} is not a multiple of the {}-sample frame size",
+            samples.len(),
+            frame_size
+        ));
+    }
+    let frames: Vec<&[f32]> = samples.chunks_exact(frame_size).collect();
+    PeriodicWave::from_frames(&frames, true)
+}
+
+pub fn load_wavetable_file<P: AsRef<Path>>(
+    path: P,
+    frame_size: usize,
+) -> anyhow::Result<Arc<PeriodicWave>> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+    load_wavetable(&bytes, frame_size)
+}
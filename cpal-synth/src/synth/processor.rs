@@ -54,6 +54,17 @@ impl AudioNode for AudioProcessor {
         output
     }
 
+    fn process_stereo(&mut self, context: &AudioContext, current_sample: u64) -> (f32, f32) {
+        let (left, right) = self
+            .inputs
+            .values_mut()
+            .map(|node| node.process_stereo(context, current_sample))
+            .fold((0.0, 0.0), |(l, r), (il, ir)| (l + il, r + ir));
+
+        let gain = self.gain.get_value(current_sample);
+        (left * gain, right * gain)
+    }
+
     fn set_parameter(&self, name: &str, value: f32) {
         match name {
             "gain" => self.gain.set_value(value),
@@ -61,6 +72,13 @@ impl AudioNode for AudioProcessor {
         }
     }
 
+    fn set_parameter_at(&self, name: &str, value: f32, at_sample: u64) {
+        match name {
+            "gain" => self.gain.set_value_at_time(value, at_sample),
+            _ => println!("Unknown parameter: {}", name),
+        }
+    }
+
     fn connect_input(&mut self, name: &str, node: Box<dyn AudioNode + Send>) {
         println!(
             "AudioProcessor: Connecting input '{}' (total inputs: {})",
@@ -75,6 +93,13 @@ impl AudioNode for AudioProcessor {
         self.inputs.remove(input_name);
     }
 
+    fn prepare(&mut self, sample_rate: f32, max_block: usize) {
+        self.gain.prepare(sample_rate);
+        for input in self.inputs.values_mut() {
+            input.prepare(sample_rate, max_block);
+        }
+    }
+
     fn clone_box(&self) -> Box<dyn AudioNode + Send> {
         Box::new(self.clone())
     }
@@ -0,0 +1,39 @@
+// src/synth/random.rs
+
+// Small xorshift generator for the audio thread: no allocation, no locking,
+// and deterministic for a given seed so renders are reproducible.
+#[derive(Clone, Debug)]
+pub struct XorShiftRng {
+    state: u32,
+}
+
+impl XorShiftRng {
+    pub fn new(seed: u32) -> Self {
+        // Zero is a fixed point of xorshift
+        Self {
+            state: if seed == 0 { 0x9E37_79B9 } else { seed },
+        }
+    }
+
+    #[inline]
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+
+    // Uniform in [0, 1)
+    #[inline]
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    // Uniform in [-1, 1)
+    #[inline]
+    pub fn next_bipolar(&mut self) -> f32 {
+        self.next_f32() * 2.0 - 1.0
+    }
+}
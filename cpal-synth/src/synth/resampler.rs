@@ -0,0 +1,188 @@
+// src/synth/resampler.rs
+
+use crate::synth::audio_buffer::AudioBuffer;
+use std::f64::consts::PI;
+
+// Kernel half-width in zero crossings, and table points per zero crossing
+const ZERO_CROSSINGS: usize = 16;
+const TABLE_OVERSAMPLE: usize = 256;
+// Keep the passband edge slightly below Nyquist so the transition band fits
+const PASSBAND: f64 = 0.95;
+
+/// Blackman-windowed sinc kernel, tabulated for one side.
+pub(crate) struct SincTable {
+    table: Vec<f32>,
+}
+
+impl SincTable {
+    pub(crate) fn new() -> Self {
+        let len = ZERO_CROSSINGS * TABLE_OVERSAMPLE;
+        let table = (0..=len + 1)
+            .map(|i| {
+                let x = i as f64 / TABLE_OVERSAMPLE as f64;
+                if x >= ZERO_CROSSINGS as f64 {
+                    return 0.0;
+                }
+                let sinc = if i == 0 {
+                    1.0
+                } else {
+                    (PI * x).sin() / (PI * x)
+                };
+                // Blackman window over [-ZERO_CROSSINGS, ZERO_CROSSINGS]
+                let w = 0.5 + 0.5 * x / ZERO_CROSSINGS as f64;
+                let window = 0.42 - 0.5 * (2.0 * PI * w).cos() + 0.08 * (4.0 * PI * w).cos();
+                (sinc * window) as f32
+            })
+            .collect();
+        Self { table }
+    }
+
+    // Kernel value at `x` zero crossings from the center
+    #[inline]
+    pub(crate) fn kernel(&self, x: f64) -> f32 {
+        let pos = x.abs() * TABLE_OVERSAMPLE as f64;
+        let index = pos as usize;
+        if index >= ZERO_CROSSINGS * TABLE_OVERSAMPLE {
+            return 0.0;
+        }
+        let frac = (pos - index as f64) as f32;
+        let a = self.table[index];
+        let b = self.table[index + 1];
+        a + (b - a) * frac
+    }
+
+    // Band-limited value of `input` at fractional index `position`, with `cutoff`
+    // as a fraction of the input Nyquist. Samples outside `input` count as silence.
+    #[inline]
+    pub(crate) fn interpolate(&self, input: &[f32], position: f64, cutoff: f64) -> f32 {
+        let half_width = (ZERO_CROSSINGS as f64 / cutoff).ceil() as i64;
+        let center = position.floor() as i64;
+        let first = (center - half_width + 1).max(0);
+        let last = (center + half_width).min(input.len() as i64 - 1);
+
+        let mut acc = 0.0f32;
+        for k in first..=last {
+            let distance = position - k as f64;
+            acc += input[k as usize] * self.kernel(distance * cutoff);
+        }
+        acc * cutoff as f32
+    }
+}
+
+/// Converts `input` from `from_rate` to `to_rate` with a windowed-sinc filter.
+pub fn resample(input: &[f32], from_rate: f32, to_rate: f32) -> Vec<f32> {
+    let ratio = to_rate as f64 / from_rate as f64;
+    if (ratio - 1.0).abs() < 1e-9 || input.is_empty() {
+        return input.to_vec();
+    }
+
+    // Downsampling moves the cutoff down to the new Nyquist frequency
+    let cutoff = ratio.min(1.0) * PASSBAND;
+    let table = SincTable::new();
+    let output_len = (input.len() as f64 * ratio).round() as usize;
+
+    (0..output_len)
+        .map(|n| table.interpolate(input, n as f64 / ratio, cutoff))
+        .collect()
+}
+
+pub fn resample_buffer(buffer: &AudioBuffer, to_rate: f32) -> anyhow::Result<AudioBuffer> {
+    if buffer.sample_rate() == to_rate {
+        return Ok(buffer.clone());
+    }
+    let channels = buffer
+        .channels()
+        .iter()
+        .map(|channel| resample(channel, buffer.sample_rate(), to_rate))
+        .collect();
+    AudioBuffer::from_channels(channels, to_rate)
+}
+
+/// Windowed-sinc resampler for interleaved audio that arrives in chunks.
+pub(crate) struct StreamResampler {
+    table: SincTable,
+    channels: usize,
+    ratio: f64,
+    // Input history per channel and the next read position within it
+    history: Vec<Vec<f32>>,
+    position: f64,
+}
+
+impl StreamResampler {
+    pub(crate) fn new(channels: usize, ratio: f64) -> Self {
+        Self {
+            table: SincTable::new(),
+            channels: channels.max(1),
+            ratio,
+            history: vec![Vec::new(); channels.max(1)],
+            position: 0.0,
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        for channel in &mut self.history {
+            channel.clear();
+        }
+        self.position = 0.0;
+    }
+
+    // Output frames per input frame. Can change between calls to follow a
+    // drifting clock; the read position carries over.
+    pub(crate) fn set_ratio(&mut self, ratio: f64) {
+        self.ratio = ratio;
+    }
+
+    fn cutoff(&self) -> f64 {
+        self.ratio.min(1.0) * PASSBAND
+    }
+
+    fn half_width(&self) -> usize {
+        (ZERO_CROSSINGS as f64 / self.cutoff()).ceil() as usize
+    }
+
+    // Resample `input` and append the result to `output`. Output lags the input
+    // by the kernel half-width until `flush` is called.
+    pub(crate) fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        for frame in input.chunks_exact(self.channels) {
+            for (channel, &sample) in self.history.iter_mut().zip(frame) {
+                channel.push(sample);
+            }
+        }
+        self.drain(output, self.half_width());
+    }
+
+    // Emit the remaining output, treating the input as ended
+    pub(crate) fn flush(&mut self, output: &mut Vec<f32>) {
+        self.drain(output, 0);
+        self.reset();
+    }
+
+    fn drain(&mut self, output: &mut Vec<f32>, lookahead: usize) {
+        let available = self.history[0].len();
+        let cutoff = self.cutoff();
+        let passthrough = (self.ratio - 1.0).abs() < 1e-9;
+
+        while self.position + (lookahead as f64) < available as f64 {
+            for channel in &self.history {
+                let sample = if passthrough && self.position.fract() == 0.0 {
+                    channel[self.position as usize]
+                } else {
+                    self.table.interpolate(channel, self.position, cutoff)
+                };
+                output.push(sample);
+            }
+            self.position += 1.0 / self.ratio;
+        }
+
+        // Keep only the history the kernel can still reach
+        let consumed = (self.position.floor() as usize)
+            .saturating_sub(self.half_width())
+            .min(available);
+        if consumed > 0 {
+            for channel in &mut self.history {
+                channel.drain(..consumed);
+            }
+            self.position -= consumed as f64;
+        }
+    }
+}
@@ -0,0 +1,156 @@
+// src/synth/ring_buffer.rs
+
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// Single-producer single-consumer queue of f32 samples. Samples are stored as
+// bits in atomics so the whole thing stays free of locks and `unsafe`.
+struct RingShared {
+    slots: Box<[AtomicU32]>,
+    // Monotonic counters; the slot is the counter modulo the capacity
+    read: AtomicUsize,
+    write: AtomicUsize,
+}
+
+pub(crate) struct RingProducer {
+    shared: Arc<RingShared>,
+}
+
+pub(crate) struct RingConsumer {
+    shared: Arc<RingShared>,
+}
+
+// The capacity is rounded up to a power of two so the counters can wrap freely
+pub(crate) fn ring_buffer(capacity: usize) -> (RingProducer, RingConsumer) {
+    let capacity = capacity.max(1).next_power_of_two();
+    let shared = Arc::new(RingShared {
+        slots: (0..capacity).map(|_| AtomicU32::new(0)).collect(),
+        read: AtomicUsize::new(0),
+        write: AtomicUsize::new(0),
+    });
+    (
+        RingProducer {
+            shared: shared.clone(),
+        },
+        RingConsumer { shared },
+    )
+}
+
+impl RingShared {
+    fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    #[inline]
+    fn slot(&self, counter: usize) -> &AtomicU32 {
+        &self.slots[counter & (self.slots.len() - 1)]
+    }
+
+    fn available(&self) -> usize {
+        let write = self.write.load(Ordering::Acquire);
+        let read = self.read.load(Ordering::Acquire);
+        write.wrapping_sub(read)
+    }
+}
+
+impl RingProducer {
+    pub(crate) fn free_space(&self) -> usize {
+        self.shared.capacity() - self.shared.available()
+    }
+
+    // Push as many samples as fit and return how many were written
+    pub(crate) fn push_slice(&mut self, samples: &[f32]) -> usize {
+        let shared = &*self.shared;
+        let write = shared.write.load(Ordering::Relaxed);
+        let read = shared.read.load(Ordering::Acquire);
+        let free = shared.capacity() - write.wrapping_sub(read);
+        let count = samples.len().min(free);
+
+        for (i, &sample) in samples[..count].iter().enumerate() {
+            shared
+                .slot(write.wrapping_add(i))
+                .store(sample.to_bits(), Ordering::Relaxed);
+        }
+        shared
+            .write
+            .store(write.wrapping_add(count), Ordering::Release);
+        count
+    }
+
+    // Push every sample, overwriting the oldest ones. Only for queues that are
+    // read with `peek_latest` rather than popped.
+    pub(crate) fn push_overwrite(&mut self, samples: &[f32]) {
+        let shared = &*self.shared;
+        let write = shared.write.load(Ordering::Relaxed);
+        for (i, &sample) in samples.iter().enumerate() {
+            shared
+                .slot(write.wrapping_add(i))
+                .store(sample.to_bits(), Ordering::Relaxed);
+        }
+        shared
+            .write
+            .store(write.wrapping_add(samples.len()), Ordering::Release);
+    }
+
+    // Another handle on the same queue. Only one of them may be written to at a time.
+    pub(crate) fn share(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl RingConsumer {
+    pub(crate) fn available(&self) -> usize {
+        self.shared.available()
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.shared.capacity()
+    }
+
+    // Pop up to `out.len()` samples and return how many were read
+    pub(crate) fn pop_slice(&self, out: &mut [f32]) -> usize {
+        let shared = &*self.shared;
+        let read = shared.read.load(Ordering::Relaxed);
+        let write = shared.write.load(Ordering::Acquire);
+        let count = out.len().min(write.wrapping_sub(read));
+
+        for (i, sample) in out[..count].iter_mut().enumerate() {
+            *sample = f32::from_bits(shared.slot(read.wrapping_add(i)).load(Ordering::Relaxed));
+        }
+        shared
+            .read
+            .store(read.wrapping_add(count), Ordering::Release);
+        count
+    }
+
+    // Copy the most recent samples into the end of `out` without consuming them,
+    // and return how many there were. Samples the producer overwrites during the
+    // copy may come from the newer pass.
+    pub(crate) fn peek_latest(&self, out: &mut [f32]) -> usize {
+        let shared = &*self.shared;
+        let write = shared.write.load(Ordering::Acquire);
+        let count = out.len().min(shared.capacity()).min(write);
+        let start = write.wrapping_sub(count);
+        let offset = out.len() - count;
+
+        for (i, sample) in out[offset..].iter_mut().enumerate() {
+            *sample = f32::from_bits(shared.slot(start.wrapping_add(i)).load(Ordering::Relaxed));
+        }
+        count
+    }
+
+    // Drop everything currently queued
+    pub(crate) fn clear(&self) {
+        let write = self.shared.write.load(Ordering::Acquire);
+        self.shared.read.store(write, Ordering::Release);
+    }
+
+    // Another handle on the same queue. Only one of them may be read from at a time.
+    pub(crate) fn share(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
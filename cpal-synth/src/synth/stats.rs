@@ -0,0 +1,285 @@
+// src/synth/stats.rs
+
+use crate::synth::audio_context::AudioContext;
+use crate::synth::audio_node::AudioNode;
+use crossbeam::atomic::AtomicCell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// `Instant` panics in the browser, so timing is skipped there
+#[inline]
+pub(crate) fn now() -> Option<Instant> {
+    if cfg!(target_arch = "wasm32") {
+        None
+    } else {
+        Some(Instant::now())
+    }
+}
+
+/// Time spent in one node, including the inputs it pulls from.
+#[derive(Clone, Debug)]
+pub struct NodeStats {
+    pub name: String,
+    pub calls: u64,
+    pub total: Duration,
+}
+
+impl NodeStats {
+    pub fn average(&self) -> Duration {
+        if self.calls == 0 {
+            Duration::ZERO
+        } else {
+            Duration::from_nanos((self.total.as_nanos() / self.calls as u128) as u64)
+        }
+    }
+}
+
+/// A snapshot of the audio engine's performance.
+///
+/// Load is the time spent processing a callback over the time its buffer lasts,
+/// so 1.0 is the limit. A callback is late when it took longer than that, and
+/// an underrun is counted when a callback starts after the previous buffer has
+/// finished playing, from the device's timestamps.
+#[derive(Clone, Debug, Default)]
+pub struct EngineStats {
+    pub callbacks: u64,
+    pub frames: u64,
+    pub load: f32,
+    pub average_load: f32,
+    pub peak_load: f32,
+    pub min_callback_time: Duration,
+    pub average_callback_time: Duration,
+    pub max_callback_time: Duration,
+    pub late_callbacks: u64,
+    pub underruns: u64,
+    pub nodes: Vec<NodeStats>,
+}
+
+struct MonitorShared {
+    callbacks: AtomicU64,
+    frames: AtomicU64,
+    // Totals in nanoseconds: time spent processing and audio produced
+    busy: AtomicU64,
+    audio: AtomicU64,
+    min_time: AtomicU64,
+    max_time: AtomicU64,
+    load: AtomicCell<f32>,
+    peak_load: AtomicCell<f32>,
+    late_callbacks: AtomicU64,
+    underruns: AtomicU64,
+    // When the last buffer finishes playing, in nanoseconds plus one; 0 for none
+    playback_end: AtomicU64,
+}
+
+/// Collects callback timings from the audio thread without locking.
+///
+/// `AudioGraph` records its own callbacks and offline renders; other backends
+/// can report theirs with `record_callback` and `record_timestamps`. Clones
+/// share the same counters.
+#[derive(Clone)]
+pub struct PerformanceMonitor {
+    shared: Arc<MonitorShared>,
+}
+
+impl PerformanceMonitor {
+    pub fn new() -> Self {
+        Self {
+            shared: Arc::new(MonitorShared {
+                callbacks: AtomicU64::new(0),
+                frames: AtomicU64::new(0),
+                busy: AtomicU64::new(0),
+                audio: AtomicU64::new(0),
+                min_time: AtomicU64::new(u64::MAX),
+                max_time: AtomicU64::new(0),
+                load: AtomicCell::new(0.0),
+                peak_load: AtomicCell::new(0.0),
+                late_callbacks: AtomicU64::new(0),
+                underruns: AtomicU64::new(0),
+                playback_end: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    // A callback that took `elapsed` to produce `frames` frames
+    pub fn record_callback(&self, elapsed: Duration, frames: usize, sample_rate: f32) {
+        let shared = &*self.shared;
+        let nanos = elapsed.as_nanos() as u64;
+        let budget = (frames as f64 / sample_rate as f64 * 1e9) as u64;
+        let load = if budget > 0 {
+            nanos as f32 / budget as f32
+        } else {
+            0.0
+        };
+
+        shared.callbacks.fetch_add(1, Ordering::Relaxed);
+        shared.frames.fetch_add(frames as u64, Ordering::Relaxed);
+        shared.busy.fetch_add(nanos, Ordering::Relaxed);
+        shared.audio.fetch_add(budget, Ordering::Relaxed);
+        shared.min_time.fetch_min(nanos, Ordering::Relaxed);
+        shared.max_time.fetch_max(nanos, Ordering::Relaxed);
+        shared.load.store(load);
+        if load > shared.peak_load.load() {
+            shared.peak_load.store(load);
+        }
+        if nanos > budget {
+            shared.late_callbacks.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // The device's timestamps for a callback, measured from any fixed point: when
+    // it was called and when its first frame will play
+    pub fn record_timestamps(
+        &self,
+        callback: Duration,
+        playback: Duration,
+        frames: usize,
+        sample_rate: f32,
+    ) {
+        let shared = &*self.shared;
+        let previous_end = shared.playback_end.load(Ordering::Relaxed);
+        if previous_end > 0 && callback.as_nanos() as u64 + 1 > previous_end {
+            shared.underruns.fetch_add(1, Ordering::Relaxed);
+        }
+        let length = Duration::from_secs_f64(frames as f64 / sample_rate as f64);
+        shared
+            .playback_end
+            .store((playback + length).as_nanos() as u64 + 1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> EngineStats {
+        let shared = &*self.shared;
+        let callbacks = shared.callbacks.load(Ordering::Relaxed);
+        let busy = shared.busy.load(Ordering::Relaxed);
+        let audio = shared.audio.load(Ordering::Relaxed);
+        let min_time = shared.min_time.load(Ordering::Relaxed);
+        EngineStats {
+            callbacks,
+            frames: shared.frames.load(Ordering::Relaxed),
+            load: shared.load.load(),
+            average_load: if audio > 0 {
+                busy as f32 / audio as f32
+            } else {
+                0.0
+            },
+            peak_load: shared.peak_load.load(),
+            min_callback_time: Duration::from_nanos(if callbacks > 0 { min_time } else { 0 }),
+            average_callback_time: Duration::from_nanos(busy.checked_div(callbacks).unwrap_or(0)),
+            max_callback_time: Duration::from_nanos(shared.max_time.load(Ordering::Relaxed)),
+            late_callbacks: shared.late_callbacks.load(Ordering::Relaxed),
+            underruns: shared.underruns.load(Ordering::Relaxed),
+            nodes: Vec::new(),
+        }
+    }
+
+    pub fn reset(&self) {
+        let shared = &*self.shared;
+        for counter in [
+            &shared.callbacks,
+            &shared.frames,
+            &shared.busy,
+            &shared.audio,
+            &shared.max_time,
+            &shared.late_callbacks,
+            &shared.underruns,
+            &shared.playback_end,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+        shared.min_time.store(u64::MAX, Ordering::Relaxed);
+        shared.load.store(0.0);
+        shared.peak_load.store(0.0);
+    }
+}
+
+impl Default for PerformanceMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Call count and total nanoseconds for a timed node, shared by its clones
+#[derive(Default)]
+pub(crate) struct NodeTimer {
+    calls: AtomicU64,
+    nanos: AtomicU64,
+}
+
+impl NodeTimer {
+    pub(crate) fn stats(&self, name: &str) -> NodeStats {
+        NodeStats {
+            name: name.to_string(),
+            calls: self.calls.load(Ordering::Relaxed),
+            total: Duration::from_nanos(self.nanos.load(Ordering::Relaxed)),
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        self.calls.store(0, Ordering::Relaxed);
+        self.nanos.store(0, Ordering::Relaxed);
+    }
+
+    #[inline]
+    fn record(&self, start: Option<Instant>) {
+        if let Some(start) = start {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            self.nanos
+                .fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+// Wraps a node to time every call to `process`
+pub(crate) struct TimedNode {
+    node: Box<dyn AudioNode + Send>,
+    timer: Arc<NodeTimer>,
+}
+
+impl TimedNode {
+    pub(crate) fn new(node: Box<dyn AudioNode + Send>, timer: Arc<NodeTimer>) -> Self {
+        Self { node, timer }
+    }
+}
+
+impl AudioNode for TimedNode {
+    fn process(&mut self, context: &AudioContext, current_sample: u64) -> f32 {
+        let start = now();
+        let output = self.node.process(context, current_sample);
+        self.timer.record(start);
+        output
+    }
+
+    fn process_stereo(&mut self, context: &AudioContext, current_sample: u64) -> (f32, f32) {
+        let start = now();
+        let output = self.node.process_stereo(context, current_sample);
+        self.timer.record(start);
+        output
+    }
+
+    fn set_parameter(&self, name: &str, value: f32) {
+        self.node.set_parameter(name, value);
+    }
+
+    fn set_parameter_at(&self, name: &str, value: f32, at_sample: u64) {
+        self.node.set_parameter_at(name, value, at_sample);
+    }
+
+    fn connect_input(&mut self, name: &str, node: Box<dyn AudioNode + Send>) {
+        self.node.connect_input(name, node);
+    }
+
+    fn clear_input(&mut self, input_name: &str) {
+        self.node.clear_input(input_name);
+    }
+
+    fn prepare(&mut self, sample_rate: f32, max_block: usize) {
+        self.node.prepare(sample_rate, max_block);
+    }
+
+    fn clone_box(&self) -> Box<dyn AudioNode + Send> {
+        Box::new(TimedNode {
+            node: self.node.clone_box(),
+            timer: self.timer.clone(),
+        })
+    }
+}
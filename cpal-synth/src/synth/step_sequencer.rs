@@ -0,0 +1,449 @@
+// src/synth/step_sequencer.rs
+
+use crate::synth::audio_context::{rate_change, rescale_sample, AudioContext};
+use crate::synth::audio_node::AudioNode;
+use crate::synth::audio_param::AudioParam;
+use crate::synth::random::XorShiftRng;
+
+// Absorbs rounding in the accumulated step position
+const POSITION_EPSILON: f64 = 1e-9;
+
+// Sets `name` on target `target` (the index returned by `add_target`) for one step
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParameterLock {
+    pub target: usize,
+    pub name: String,
+    pub value: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Step {
+    pub note: u8,
+    pub velocity: f32,
+    // Gate length as a fraction of the step; 1.0 or more ties into the next step
+    pub gate: f32,
+    pub probability: f32,
+    pub locks: Vec<ParameterLock>,
+}
+
+impl Step {
+    pub fn new(note: u8, velocity: f32) -> Self {
+        Self {
+            note,
+            velocity,
+            gate: 0.5,
+            probability: 1.0,
+            locks: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Pattern {
+    // `None` is a rest
+    pub steps: Vec<Option<Step>>,
+}
+
+impl Pattern {
+    pub fn new(length: usize) -> Self {
+        Self {
+            steps: vec![None; length],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn set_step(&mut self, index: usize, step: Step) {
+        if let Some(slot) = self.steps.get_mut(index) {
+            *slot = Some(step);
+        }
+    }
+
+    pub fn clear_step(&mut self, index: usize) {
+        if let Some(slot) = self.steps.get_mut(index) {
+            *slot = None;
+        }
+    }
+}
+
+#[derive(Clone)]
+struct SequencerTarget {
+    node: Box<dyn AudioNode + Send>,
+    pitch_parameter: Option<String>,
+    gate_parameter: Option<String>,
+}
+
+/// Pattern sequencer clocked by the audio thread.
+///
+/// Every event is scheduled one sample ahead through `AudioNode::set_parameter_at`,
+/// so targets with automation support (oscillators, gain nodes) switch on the exact
+/// sample regardless of the order the graph evaluates them in. Pitch is sent in Hz
+/// and the gate as the step velocity, dropping to 0.0 when the gate ends.
+///
+/// The sequencer produces no audio; connect it to the output mixer so it is clocked,
+/// but never as an input of one of its own targets (the target's lock is held while
+/// its inputs are processed).
+pub struct StepSequencer {
+    patterns: Vec<Pattern>,
+    chain: Vec<usize>,
+    targets: Vec<SequencerTarget>,
+    base_values: Vec<ParameterLock>,
+    tempo: AudioParam,
+    swing: AudioParam,
+    steps_per_beat: u32,
+    start_sample: Option<u64>,
+    stop_sample: Option<u64>,
+    // Position in steps at `clock_sample`, the next sample to be evaluated
+    position: f64,
+    clock_sample: u64,
+    next_step: u64,
+    chain_index: usize,
+    step_index: usize,
+    gate_off_position: Option<f64>,
+    // (pattern, step) whose locks are currently applied
+    locked_step: Option<(usize, usize)>,
+    // Rate the clock samples are counted at, 0 until prepared
+    sample_rate: f32,
+    rng: XorShiftRng,
+}
+
+impl StepSequencer {
+    pub fn new(steps_per_beat: u32) -> Self {
+        Self {
+            patterns: vec![Pattern::new(16)],
+            chain: vec![0],
+            targets: Vec::new(),
+            base_values: Vec::new(),
+            tempo: AudioParam::new(120.0, 1.0, 999.0),
+            swing: AudioParam::new(0.0, 0.0, 0.75),
+            steps_per_beat: steps_per_beat.max(1),
+            start_sample: None,
+            stop_sample: None,
+            position: 0.0,
+            clock_sample: 0,
+            next_step: 0,
+            chain_index: 0,
+            step_index: 0,
+            gate_off_position: None,
+            locked_step: None,
+            sample_rate: 0.0,
+            rng: XorShiftRng::new(0x5EED),
+        }
+    }
+
+    // Tempo in beats per minute
+    pub fn tempo(&self) -> &AudioParam {
+        &self.tempo
+    }
+
+    // Delay of every off-beat step, as a fraction of a step
+    pub fn swing(&self) -> &AudioParam {
+        &self.swing
+    }
+
+    /// Registers a node driven by the sequencer and returns its index for parameter
+    /// locks. `pitch_parameter` receives the step note in Hz and `gate_parameter`
+    /// the velocity while the gate is open; pass `None` to skip either.
+    pub fn add_target(
+        &mut self,
+        node: Box<dyn AudioNode + Send>,
+        pitch_parameter: Option<&str>,
+        gate_parameter: Option<&str>,
+    ) -> usize {
+        self.targets.push(SequencerTarget {
+            node,
+            pitch_parameter: pitch_parameter.map(str::to_string),
+            gate_parameter: gate_parameter.map(str::to_string),
+        });
+        self.targets.len() - 1
+    }
+
+    // Value a locked parameter returns to on steps that do not lock it
+    pub fn set_base_value(&mut self, target: usize, name: &str, value: f32) {
+        match self
+            .base_values
+            .iter_mut()
+            .find(|lock| lock.target == target && lock.name == name)
+        {
+            Some(lock) => lock.value = value,
+            None => self.base_values.push(ParameterLock {
+                target,
+                name: name.to_string(),
+                value,
+            }),
+        }
+    }
+
+    pub fn set_pattern(&mut self, index: usize, pattern: Pattern) {
+        if index >= self.patterns.len() {
+            self.patterns.resize(index + 1, Pattern::default());
+        }
+        self.patterns[index] = pattern;
+    }
+
+    pub fn pattern(&self, index: usize) -> Option<&Pattern> {
+        self.patterns.get(index)
+    }
+
+    pub fn pattern_mut(&mut self, index: usize) -> Option<&mut Pattern> {
+        self.patterns.get_mut(index)
+    }
+
+    // Order in which patterns are played; the chain loops at its end
+    pub fn set_chain(&mut self, chain: Vec<usize>) {
+        self.chain = chain;
+        self.chain_index = 0;
+    }
+
+    pub fn set_seed(&mut self, seed: u32) {
+        self.rng = XorShiftRng::new(seed);
+    }
+
+    // Start the first step of the chain at `at_sample`
+    pub fn start(&mut self, at_sample: u64) {
+        self.start_sample = Some(at_sample);
+        self.stop_sample = None;
+        self.position = 0.0;
+        self.clock_sample = at_sample;
+        self.next_step = 0;
+        self.chain_index = 0;
+        self.step_index = 0;
+        self.gate_off_position = None;
+        self.locked_step = None;
+    }
+
+    // Stop at `at_sample`, closing any open gate on that sample
+    pub fn stop(&mut self, at_sample: u64) {
+        self.stop_sample = Some(at_sample);
+        self.send_gate(0.0, at_sample);
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.start_sample.is_some()
+    }
+
+    fn send(&self, target: usize, name: &str, value: f32, at_sample: u64) {
+        if let Some(target) = self.targets.get(target) {
+            target.node.set_parameter_at(name, value, at_sample);
+        }
+    }
+
+    fn send_gate(&self, value: f32, at_sample: u64) {
+        for target in &self.targets {
+            if let Some(gate) = &target.gate_parameter {
+                target.node.set_parameter_at(gate, value, at_sample);
+            }
+        }
+    }
+
+    // Pattern and step the next trigger will play, skipping empty patterns
+    fn current_slot(&self) -> Option<(usize, usize)> {
+        let pattern_index = *self.chain.get(self.chain_index)?;
+        let pattern = self.patterns.get(pattern_index)?;
+        if pattern.is_empty() {
+            return None;
+        }
+        Some((pattern_index, self.step_index % pattern.len()))
+    }
+
+    fn advance_slot(&mut self) {
+        self.step_index += 1;
+        let length = self
+            .chain
+            .get(self.chain_index)
+            .and_then(|&index| self.patterns.get(index))
+            .map_or(0, Pattern::len);
+        if self.step_index >= length {
+            self.step_index = 0;
+            self.chain_index += 1;
+            if self.chain_index >= self.chain.len() {
+                self.chain_index = 0;
+            }
+        }
+    }
+
+    // Returns whether a note was played
+    fn trigger_step(&mut self, trigger_position: f64, at_sample: u64) -> bool {
+        let Some((pattern_index, step_index)) = self.current_slot() else {
+            return false;
+        };
+        let Some(step) = &self.patterns[pattern_index].steps[step_index] else {
+            return false;
+        };
+        let (note, velocity, gate, probability) =
+            (step.note, step.velocity, step.gate, step.probability);
+        if probability < 1.0 && self.rng.next_f32() >= probability {
+            return false;
+        }
+        let step = self.patterns[pattern_index].steps[step_index]
+            .as_ref()
+            .expect("step checked above");
+
+        // Parameters locked by the previous step but not this one fall back to their base value
+        if let Some((prev_pattern, prev_step)) = self.locked_step {
+            if let Some(Some(previous)) = self
+                .patterns
+                .get(prev_pattern)
+                .and_then(|pattern| pattern.steps.get(prev_step))
+            {
+                for lock in &previous.locks {
+                    let still_locked = step
+                        .locks
+                        .iter()
+                        .any(|l| l.target == lock.target && l.name == lock.name);
+                    if still_locked {
+                        continue;
+                    }
+                    if let Some(base) = self
+                        .base_values
+                        .iter()
+                        .find(|b| b.target == lock.target && b.name == lock.name)
+                    {
+                        self.send(base.target, &base.name, base.value, at_sample);
+                    }
+                }
+            }
+        }
+
+        for lock in &step.locks {
+            self.send(lock.target, &lock.name, lock.value, at_sample);
+        }
+
+        let frequency = midi_to_frequency(note);
+        for target in &self.targets {
+            if let Some(pitch) = &target.pitch_parameter {
+                target.node.set_parameter_at(pitch, frequency, at_sample);
+            }
+        }
+        self.send_gate(velocity, at_sample);
+        self.locked_step = Some((pattern_index, step_index));
+        self.gate_off_position = Some(trigger_position + gate.max(0.0) as f64);
+        true
+    }
+}
+
+pub fn midi_to_frequency(note: u8) -> f32 {
+    440.0 * 2.0f32.powf((note as f32 - 69.0) / 12.0)
+}
+
+impl AudioNode for StepSequencer {
+    fn process(&mut self, context: &AudioContext, current_sample: u64) -> f32 {
+        if self.start_sample.is_none() {
+            return 0.0;
+        }
+
+        // Evaluate one sample ahead so targets see the events on time
+        let event_sample = current_sample + 1;
+        if event_sample < self.clock_sample {
+            return 0.0;
+        }
+        if self.stop_sample.is_some_and(|stop| event_sample >= stop) {
+            self.start_sample = None;
+            self.stop_sample = None;
+            self.gate_off_position = None;
+            return 0.0;
+        }
+
+        let tempo = self.tempo.get_value(event_sample) as f64;
+        let samples_per_step =
+            context.sample_rate() as f64 * 60.0 / (tempo * self.steps_per_beat as f64);
+
+        // Started in the past (e.g. at the current sample): catch up with the grid
+        if event_sample > self.clock_sample {
+            self.position += (event_sample - self.clock_sample) as f64 / samples_per_step;
+        }
+
+        let gate_closing = self
+            .gate_off_position
+            .is_some_and(|off| self.position + POSITION_EPSILON >= off);
+        if gate_closing {
+            self.gate_off_position = None;
+        }
+
+        let swing = if self.next_step % 2 == 1 {
+            self.swing.get_value(event_sample) as f64
+        } else {
+            0.0
+        };
+        let trigger_position = self.next_step as f64 + swing;
+        let mut retriggered = false;
+        if self.position + POSITION_EPSILON >= trigger_position {
+            retriggered = self.trigger_step(trigger_position, event_sample);
+            self.advance_slot();
+            self.next_step += 1;
+        }
+
+        // A gate that ends as the next note starts ties into it
+        if gate_closing && !retriggered {
+            self.send_gate(0.0, event_sample);
+        }
+
+        self.position += 1.0 / samples_per_step;
+        self.clock_sample = event_sample + 1;
+
+        0.0
+    }
+
+    fn set_parameter(&self, name: &str, value: f32) {
+        match name {
+            "tempo" => self.tempo.set_value(value),
+            "swing" => self.swing.set_value(value),
+            _ => {}
+        }
+    }
+
+    fn connect_input(&mut self, _name: &str, _node: Box<dyn AudioNode + Send>) {
+        // The sequencer is clocked by the graph and has no audio inputs
+    }
+
+    fn clear_input(&mut self, _input_name: &str) {
+        // No-op, see connect_input
+    }
+
+    // The position is kept in steps, so only the clock moves. Targets are
+    // prepared by the graph like any other node.
+    fn prepare(&mut self, sample_rate: f32, _max_block: usize) {
+        self.tempo.prepare(sample_rate);
+        self.swing.prepare(sample_rate);
+        if let Some(ratio) = rate_change(&mut self.sample_rate, sample_rate) {
+            self.start_sample = self.start_sample.map(|at| rescale_sample(at, ratio));
+            self.stop_sample = self.stop_sample.map(|at| rescale_sample(at, ratio));
+            self.clock_sample = rescale_sample(self.clock_sample, ratio);
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn AudioNode + Send> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for StepSequencer {
+    fn clone(&self) -> Self {
+        Self {
+            patterns: self.patterns.clone(),
+            chain: self.chain.clone(),
+            targets: self.targets.clone(),
+            base_values: self.base_values.clone(),
+            tempo: self.tempo.clone(),
+            swing: self.swing.clone(),
+            steps_per_beat: self.steps_per_beat,
+            start_sample: self.start_sample,
+            stop_sample: self.stop_sample,
+            position: self.position,
+            clock_sample: self.clock_sample,
+            next_step: self.next_step,
+            chain_index: self.chain_index,
+            step_index: self.step_index,
+            gate_off_position: self.gate_off_position,
+            locked_step: self.locked_step,
+            sample_rate: self.sample_rate,
+            rng: self.rng.clone(),
+        }
+    }
+}
@@ -0,0 +1,563 @@
+// src/synth/streaming_player.rs
+
+use crate::synth::audio_context::AudioContext;
+use crate::synth::audio_decoder::{open_stream_decoder, StreamDecoder};
+use crate::synth::audio_node::AudioNode;
+use crate::synth::audio_param::AudioParam;
+use crate::synth::resampler::StreamResampler;
+use crate::synth::ring_buffer::{ring_buffer, RingConsumer, RingProducer};
+use crossbeam::atomic::AtomicCell;
+use crossbeam::channel::{unbounded, RecvTimeoutError, Sender};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const DEFAULT_BUFFER_SECONDS: f64 = 2.0;
+const DEFAULT_PREROLL_SECONDS: f64 = 0.25;
+// How long the worker sleeps when the ring is full or the file has ended
+const WORKER_IDLE: Duration = Duration::from_millis(5);
+
+enum StreamCommand {
+    // Seek generation and target in seconds
+    Seek(u64, f64),
+    SetLoop(bool, f64, f64),
+    // Seek generation, new context rate and the position to carry on from
+    SetSampleRate(u64, f32, f64),
+}
+
+// State shared between the worker, the audio thread and the controlling thread.
+// Frame counts are in frames at the context rate.
+struct StreamShared {
+    playing: AtomicBool,
+    // True once pre-roll has completed; cleared by seeks and underruns
+    rolling: AtomicBool,
+    // The worker has pushed the last frame of a non-looping file
+    source_ended: AtomicBool,
+    finished: AtomicBool,
+    // Set by `close`; the worker stops reading and drops the file
+    closed: AtomicBool,
+    preroll_frames: AtomicU64,
+    position: AtomicU64,
+    underruns: AtomicU64,
+    // `seek` bumps `seek_generation`. Once the worker has moved it publishes the
+    // generation in `flush_request`, and the audio thread clears the ring and echoes
+    // it back in `flush_ack` before the worker pushes audio from the new position.
+    seek_generation: AtomicU64,
+    flush_request: AtomicU64,
+    flush_ack: AtomicU64,
+    flush_position: AtomicU64,
+    looping: AtomicBool,
+    loop_start: AtomicU64,
+    loop_end: AtomicU64,
+    // Length of the file, or 0 while unknown
+    length: AtomicU64,
+    // The context rate the frame counts are at
+    sample_rate: AtomicCell<f32>,
+}
+
+/// Snapshot of a stream's state, from `StreamingPlayerNode::status`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StreamStatus {
+    // Playback position in seconds
+    pub position: f64,
+    // Decoded audio waiting in the ring buffer, in seconds
+    pub buffered: f64,
+    pub underruns: u64,
+    pub playing: bool,
+    pub finished: bool,
+}
+
+/// Plays a long audio file from disk without loading it into memory.
+///
+/// A background thread decodes and resamples the file into a lock-free ring buffer
+/// that `process` drains, so the audio thread never touches the file or blocks.
+/// Playback waits for a pre-roll amount of audio before starting, and again after
+/// a seek or an underrun. Clones share the same stream; only one of them should be
+/// part of the processed graph.
+pub struct StreamingPlayerNode {
+    consumer: RingConsumer,
+    shared: Arc<StreamShared>,
+    commands: Sender<StreamCommand>,
+    gain: AudioParam,
+}
+
+impl StreamingPlayerNode {
+    pub fn open<P: AsRef<Path>>(path: P, context: &AudioContext) -> anyhow::Result<Self> {
+        Self::open_with_buffer(path, context, DEFAULT_BUFFER_SECONDS)
+    }
+
+    // Open with a ring buffer holding `buffer_seconds` of audio
+    pub fn open_with_buffer<P: AsRef<Path>>(
+        path: P,
+        context: &AudioContext,
+        buffer_seconds: f64,
+    ) -> anyhow::Result<Self> {
+        let decoder = open_stream_decoder(path)?;
+        let sample_rate = context.sample_rate();
+        let ratio = sample_rate as f64 / decoder.sample_rate() as f64;
+        let length = decoder.length().map_or(0, |l| (l as f64 * ratio) as u64);
+
+        let capacity = ((buffer_seconds * sample_rate as f64) as usize).max(64) * 2;
+        let (producer, consumer) = ring_buffer(capacity);
+        let preroll = (DEFAULT_PREROLL_SECONDS * sample_rate as f64) as u64;
+
+        let shared = Arc::new(StreamShared {
+            playing: AtomicBool::new(false),
+            rolling: AtomicBool::new(false),
+            source_ended: AtomicBool::new(false),
+            finished: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+            preroll_frames: AtomicU64::new(preroll.min(consumer.capacity() as u64 / 2)),
+            position: AtomicU64::new(0),
+            underruns: AtomicU64::new(0),
+            seek_generation: AtomicU64::new(0),
+            flush_request: AtomicU64::new(0),
+            flush_ack: AtomicU64::new(0),
+            flush_position: AtomicU64::new(0),
+            looping: AtomicBool::new(false),
+            loop_start: AtomicU64::new(0),
+            loop_end: AtomicU64::new(0),
+            length: AtomicU64::new(length),
+            sample_rate: AtomicCell::new(sample_rate),
+        });
+
+        let (commands, receiver) = unbounded();
+        let mut worker = StreamWorker {
+            decoder,
+            resampler: StreamResampler::new(2, ratio),
+            producer,
+            shared: shared.clone(),
+            ratio,
+            pending: Vec::new(),
+            pending_offset: 0,
+            decoded: Vec::new(),
+            end_of_file: false,
+            file_frame: 0,
+            looping: false,
+            loop_start: 0,
+            loop_end: 0,
+        };
+        std::thread::Builder::new()
+            .name("stream-reader".to_string())
+            .spawn(move || loop {
+                if worker.shared.closed.load(Ordering::Acquire) {
+                    break;
+                }
+                let timeout = if worker.fill() {
+                    Duration::ZERO
+                } else {
+                    WORKER_IDLE
+                };
+                match receiver.recv_timeout(timeout) {
+                    Ok(command) => worker.handle(command),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    // Every node handle is gone
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            })?;
+
+        Ok(Self {
+            consumer,
+            shared,
+            commands,
+            gain: AudioParam::new(1.0, 0.0, 1.0),
+        })
+    }
+
+    pub fn gain(&self) -> &AudioParam {
+        &self.gain
+    }
+
+    pub fn play(&self) {
+        self.shared.finished.store(false, Ordering::Release);
+        self.shared.playing.store(true, Ordering::Release);
+    }
+
+    pub fn pause(&self) {
+        self.shared.playing.store(false, Ordering::Release);
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.shared.playing.load(Ordering::Acquire)
+    }
+
+    // Playback stays silent from here until the new position has been pre-rolled.
+    // The seek completes on the audio thread, so the node has to be processed.
+    pub fn seek(&self, seconds: f64) {
+        let generation = self.shared.seek_generation.fetch_add(1, Ordering::AcqRel) + 1;
+        let _ = self
+            .commands
+            .send(StreamCommand::Seek(generation, seconds.max(0.0)));
+    }
+
+    // Loop between `loop_start` and `loop_end` seconds; an end of 0.0 means the
+    // end of the file. Audio that is already buffered is not affected, so set the
+    // loop before seeking or starting playback.
+    pub fn set_loop(&self, looping: bool, loop_start: f64, loop_end: f64) {
+        let _ = self.commands.send(StreamCommand::SetLoop(
+            looping,
+            loop_start.max(0.0),
+            loop_end.max(0.0),
+        ));
+    }
+
+    // Stops the reader and closes the file. What is already buffered still plays,
+    // then the stream underruns; seeks and loops have no effect after this.
+    pub fn close(&self) {
+        self.shared.closed.store(true, Ordering::Release);
+    }
+
+    // Audio that must be buffered before playback (re)starts
+    pub fn set_preroll(&self, seconds: f64) {
+        let frames = (seconds.max(0.0) * self.shared.sample_rate.load() as f64) as u64;
+        let max = self.consumer.capacity() as u64 / 2;
+        self.shared
+            .preroll_frames
+            .store(frames.min(max), Ordering::Release);
+    }
+
+    // Whether enough audio is buffered for playback to start
+    pub fn is_ready(&self) -> bool {
+        self.seek_settled()
+            && (self.shared.rolling.load(Ordering::Acquire) || self.preroll_complete())
+    }
+
+    pub fn status(&self) -> StreamStatus {
+        let shared = &self.shared;
+        let rate = shared.sample_rate.load() as f64;
+        StreamStatus {
+            position: shared.position.load(Ordering::Acquire) as f64 / rate,
+            buffered: (self.consumer.available() / 2) as f64 / rate,
+            underruns: shared.underruns.load(Ordering::Acquire),
+            playing: shared.playing.load(Ordering::Acquire),
+            finished: shared.finished.load(Ordering::Acquire),
+        }
+    }
+
+    fn seek_settled(&self) -> bool {
+        let shared = &self.shared;
+        shared.seek_generation.load(Ordering::Acquire) == shared.flush_ack.load(Ordering::Acquire)
+    }
+
+    fn preroll_complete(&self) -> bool {
+        let shared = &self.shared;
+        (self.consumer.available() / 2) as u64 >= shared.preroll_frames.load(Ordering::Acquire)
+            || shared.source_ended.load(Ordering::Acquire)
+    }
+
+    fn advance_position(&self) {
+        let shared = &self.shared;
+        let mut position = shared.position.load(Ordering::Relaxed) + 1;
+        if shared.looping.load(Ordering::Acquire) {
+            let start = shared.loop_start.load(Ordering::Acquire);
+            let end = match shared.loop_end.load(Ordering::Acquire) {
+                0 => shared.length.load(Ordering::Acquire),
+                end => end,
+            };
+            if end > start && position >= end {
+                position = start + (position - end);
+            }
+        }
+        shared.position.store(position, Ordering::Release);
+    }
+}
+
+impl AudioNode for StreamingPlayerNode {
+    fn process(&mut self, context: &AudioContext, current_sample: u64) -> f32 {
+        let (left, right) = self.process_stereo(context, current_sample);
+        (left + right) * 0.5
+    }
+
+    fn process_stereo(&mut self, _context: &AudioContext, current_sample: u64) -> (f32, f32) {
+        let shared = &self.shared;
+
+        // Acknowledge a seek by dropping the audio from before it
+        let request = shared.flush_request.load(Ordering::Acquire);
+        if request != shared.flush_ack.load(Ordering::Acquire) {
+            self.consumer.clear();
+            shared.position.store(
+                shared.flush_position.load(Ordering::Acquire),
+                Ordering::Release,
+            );
+            shared.rolling.store(false, Ordering::Release);
+            shared.flush_ack.store(request, Ordering::Release);
+        }
+
+        if !shared.playing.load(Ordering::Acquire) || !self.seek_settled() {
+            return (0.0, 0.0);
+        }
+        if !shared.rolling.load(Ordering::Acquire) {
+            if !self.preroll_complete() {
+                return (0.0, 0.0);
+            }
+            shared.rolling.store(true, Ordering::Release);
+        }
+
+        let mut frame = [0.0f32; 2];
+        if self.consumer.pop_slice(&mut frame) < 2 {
+            if shared.source_ended.load(Ordering::Acquire) {
+                shared.finished.store(true, Ordering::Release);
+                shared.playing.store(false, Ordering::Release);
+            } else {
+                // The reader fell behind: count it and wait for pre-roll again
+                shared.underruns.fetch_add(1, Ordering::AcqRel);
+                shared.rolling.store(false, Ordering::Release);
+            }
+            return (0.0, 0.0);
+        }
+        self.advance_position();
+
+        let gain = self.gain.get_value(current_sample);
+        (frame[0] * gain, frame[1] * gain)
+    }
+
+    fn set_parameter(&self, name: &str, value: f32) {
+        if name == "gain" {
+            self.gain.set_value(value);
+        }
+    }
+
+    fn set_parameter_at(&self, name: &str, value: f32, at_sample: u64) {
+        if name == "gain" {
+            self.gain.set_value_at_time(value, at_sample);
+        }
+    }
+
+    fn connect_input(&mut self, _name: &str, _node: Box<dyn AudioNode + Send>) {
+        // Sources don't have inputs
+    }
+
+    fn clear_input(&mut self, _input_name: &str) {
+        // No-op for sources
+    }
+
+    // The worker resamples for the new rate and refills from the current position,
+    // which plays like a seek. Clones share the stream, so only the first call acts.
+    fn prepare(&mut self, sample_rate: f32, _max_block: usize) {
+        self.gain.prepare(sample_rate);
+        let shared = &self.shared;
+        let previous = shared.sample_rate.swap(sample_rate);
+        if previous == sample_rate {
+            return;
+        }
+        let ratio = sample_rate as f64 / previous as f64;
+        let position = shared.position.load(Ordering::Acquire) as f64 / previous as f64;
+        let preroll = (shared.preroll_frames.load(Ordering::Acquire) as f64 * ratio) as u64;
+        shared.preroll_frames.store(
+            preroll.min(self.consumer.capacity() as u64 / 2),
+            Ordering::Release,
+        );
+        let generation = shared.seek_generation.fetch_add(1, Ordering::AcqRel) + 1;
+        let _ = self.commands.send(StreamCommand::SetSampleRate(
+            generation,
+            sample_rate,
+            position,
+        ));
+    }
+
+    fn clone_box(&self) -> Box<dyn AudioNode + Send> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for StreamingPlayerNode {
+    fn clone(&self) -> Self {
+        Self {
+            consumer: self.consumer.share(),
+            shared: self.shared.clone(),
+            commands: self.commands.clone(),
+            gain: self.gain.clone(),
+        }
+    }
+}
+
+// Runs on the background thread: decodes, converts to stereo at the context
+// rate and keeps the ring buffer full
+struct StreamWorker {
+    decoder: Box<dyn StreamDecoder>,
+    resampler: StreamResampler,
+    producer: RingProducer,
+    shared: Arc<StreamShared>,
+    ratio: f64,
+    // Resampled stereo frames not yet in the ring
+    pending: Vec<f32>,
+    pending_offset: usize,
+    decoded: Vec<f32>,
+    end_of_file: bool,
+    // Decoder read position and loop region, in file frames
+    file_frame: u64,
+    looping: bool,
+    loop_start: u64,
+    loop_end: u64,
+}
+
+impl StreamWorker {
+    fn handle(&mut self, command: StreamCommand) {
+        let file_rate = self.decoder.sample_rate() as f64;
+        match command {
+            StreamCommand::Seek(generation, seconds) => {
+                let frame = (seconds * file_rate) as u64;
+                self.seek(frame);
+                self.shared
+                    .flush_position
+                    .store((frame as f64 * self.ratio) as u64, Ordering::Release);
+                self.shared
+                    .flush_request
+                    .store(generation, Ordering::Release);
+            }
+            StreamCommand::SetSampleRate(generation, sample_rate, seconds) => {
+                self.ratio = sample_rate as f64 / file_rate;
+                self.resampler = StreamResampler::new(2, self.ratio);
+                let shared = &self.shared;
+                shared.loop_start.store(
+                    (self.loop_start as f64 * self.ratio) as u64,
+                    Ordering::Release,
+                );
+                shared.loop_end.store(
+                    (self.loop_end as f64 * self.ratio) as u64,
+                    Ordering::Release,
+                );
+                let length = self
+                    .decoder
+                    .length()
+                    .map_or(0, |l| (l as f64 * self.ratio) as u64);
+                shared.length.store(length, Ordering::Release);
+                self.handle(StreamCommand::Seek(generation, seconds));
+            }
+            StreamCommand::SetLoop(looping, start, end) => {
+                self.looping = looping;
+                self.loop_start = (start * file_rate) as u64;
+                self.loop_end = (end * file_rate) as u64;
+                // A loop past the end of the file plays out to the end
+                if let Some(length) = self.decoder.length() {
+                    self.loop_start = self.loop_start.min(length);
+                    self.loop_end = self.loop_end.min(length);
+                }
+
+                let shared = &self.shared;
+                shared.looping.store(looping, Ordering::Release);
+                shared.loop_start.store(
+                    (self.loop_start as f64 * self.ratio) as u64,
+                    Ordering::Release,
+                );
+                shared.loop_end.store(
+                    (self.loop_end as f64 * self.ratio) as u64,
+                    Ordering::Release,
+                );
+                // Turning the loop on revives a file that already ran out
+                if looping {
+                    self.end_of_file = false;
+                    shared.source_ended.store(false, Ordering::Release);
+                }
+            }
+        }
+    }
+
+    fn seek(&mut self, frame: u64) {
+        if let Err(e) = self.decoder.seek(frame) {
+            println!("Stream seek failed: {}", e);
+        }
+        self.file_frame = frame;
+        self.resampler.reset();
+        self.pending.clear();
+        self.pending_offset = 0;
+        self.end_of_file = false;
+        self.shared.source_ended.store(false, Ordering::Release);
+    }
+
+    // Push what fits into the ring. Returns true if there is more work to do right away.
+    fn fill(&mut self) -> bool {
+        // Hold off until the audio thread has dropped the audio from before a seek
+        if self.shared.flush_request.load(Ordering::Acquire)
+            != self.shared.flush_ack.load(Ordering::Acquire)
+        {
+            return false;
+        }
+
+        if self.pending_offset >= self.pending.len() {
+            self.pending.clear();
+            self.pending_offset = 0;
+            if self.end_of_file {
+                self.shared.source_ended.store(true, Ordering::Release);
+                return false;
+            }
+            self.decode_chunk();
+            return true;
+        }
+
+        let free = self.producer.free_space() & !1;
+        if free == 0 {
+            return false;
+        }
+        let end = (self.pending_offset + free).min(self.pending.len());
+        self.pending_offset += self
+            .producer
+            .push_slice(&self.pending[self.pending_offset..end]);
+        if self.end_of_file && self.pending_offset >= self.pending.len() {
+            self.shared.source_ended.store(true, Ordering::Release);
+        }
+        true
+    }
+
+    // Decode the next chunk into `pending`, wrapping around the loop region
+    fn decode_chunk(&mut self) {
+        let channels = self.decoder.channels();
+        self.decoded.clear();
+        let more = match self.decoder.read(&mut self.decoded) {
+            Ok(more) => more,
+            Err(e) => {
+                println!("Stream decode failed: {}", e);
+                self.resampler.flush(&mut self.pending);
+                self.end_of_file = true;
+                return;
+            }
+        };
+
+        let mut frames = (self.decoded.len() / channels) as u64;
+        let loop_end = match self.loop_end {
+            0 => self.decoder.length().unwrap_or(u64::MAX),
+            end => end,
+        };
+        // Nothing at all from the loop start means it is past the end of the file,
+        // and wrapping again would spin
+        let empty_loop = frames == 0 && self.file_frame == self.loop_start;
+        let wrap = self.looping && loop_end > self.loop_start && !empty_loop && {
+            if self.file_frame + frames >= loop_end {
+                frames = loop_end.saturating_sub(self.file_frame);
+                true
+            } else {
+                !more
+            }
+        };
+        self.file_frame += frames;
+
+        // Fold everything into stereo
+        let stereo: Vec<f32> = self
+            .decoded
+            .chunks_exact(channels)
+            .take(frames as usize)
+            .flat_map(|frame| {
+                let left = frame[0];
+                let right = if channels > 1 { frame[1] } else { left };
+                [left, right]
+            })
+            .collect();
+        self.resampler.process(&stereo, &mut self.pending);
+
+        if wrap {
+            if !more {
+                // Now the length is known for position wrapping
+                self.shared.length.store(
+                    (self.file_frame as f64 * self.ratio) as u64,
+                    Ordering::Release,
+                );
+            }
+            let _ = self.decoder.seek(self.loop_start);
+            self.file_frame = self.loop_start;
+        } else if !more {
+            self.resampler.flush(&mut self.pending);
+            self.end_of_file = true;
+        }
+    }
+}
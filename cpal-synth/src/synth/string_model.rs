@@ -0,0 +1,534 @@
+// src/synth/string_model.rs
+
+use crate::synth::audio_buffer::AudioBuffer;
+use crate::synth::audio_context::{rate_change, AudioContext};
+use crate::synth::audio_node::AudioNode;
+use crate::synth::audio_param::AudioParam;
+use crate::synth::event_queue::EventQueue;
+use crate::synth::random::XorShiftRng;
+use crate::synth::step_sequencer::midi_to_frequency;
+use std::sync::Arc;
+
+// Lowest note the delay lines are sized for
+const MIN_FREQUENCY: f32 = 20.0;
+// Pole of the loop lowpass at full damping. Kept low so the filter's delay stays
+// under a sample and the top of the keyboard can still be tuned.
+const MAX_DAMPING_POLE: f32 = 0.5;
+// Bow speed at zero and full velocity, and how quickly it follows the gate
+const MIN_BOW_SPEED: f32 = 0.03;
+const BOW_SPEED_RANGE: f32 = 0.2;
+const BOW_SMOOTHING: f32 = 0.005;
+
+/// What sets the string moving.
+#[derive(Clone)]
+pub enum Excitation {
+    // A zero-mean burst of white noise, one period long
+    Noise,
+    // The first channel of a buffer, played into the string at its own rate
+    Buffer(Arc<AudioBuffer>),
+    // Continuous bowing while the gate is open, at a speed set by velocity
+    Bow,
+}
+
+// Fixed-capacity delay with an adjustable length of at least one sample
+#[derive(Clone)]
+struct DelayLine {
+    buffer: Vec<f32>,
+    write: usize,
+    length: usize,
+}
+
+impl DelayLine {
+    fn new(capacity: usize) -> Self {
+        Self {
+            buffer: vec![0.0; capacity],
+            write: 0,
+            length: 1,
+        }
+    }
+
+    // The sample written `length` samples ago
+    #[inline]
+    fn read(&self) -> f32 {
+        let capacity = self.buffer.len();
+        self.buffer[(self.write + capacity - self.length) % capacity]
+    }
+
+    #[inline]
+    fn push(&mut self, value: f32) {
+        self.buffer[self.write] = value;
+        self.write = (self.write + 1) % self.buffer.len();
+    }
+
+    fn clear(&mut self) {
+        self.buffer.iter_mut().for_each(|sample| *sample = 0.0);
+    }
+}
+
+// Coefficients that only change with the parameters they're derived from
+#[derive(Clone, Copy, Default, PartialEq)]
+struct Tuning {
+    frequency: f32,
+    damping: f32,
+    pick_position: f32,
+    decay: f32,
+}
+
+/// A plucked or bowed string, as a digital waveguide.
+///
+/// The string is a loop of two delay lines that meet at the pick (or bow) point:
+/// one runs to the nut and back, the other to the bridge and back. The bridge end
+/// holds the losses: a one-pole lowpass set by `damping`, which makes the upper
+/// harmonics die away faster, and a gain that gives the fundamental a -60dB time
+/// of `decay` seconds. A first-order allpass makes up the fraction of a sample
+/// left over once the delays and the lowpass are counted, with its coefficient
+/// solved at the fundamental, so tuning holds from the bottom of the keyboard to
+/// the top.
+///
+/// Plucks are injected at `pick_position` (0 at the nut, 1 at the bridge), which
+/// notches out the harmonics that have a node there, after a lowpass set by
+/// `brightness` and velocity, so softer plucks are also duller. A rising edge of
+/// `gate` plucks with the gate value as velocity, like `BufferSourceNode`, and
+/// `note_on` and `note_off` schedule the same from note events. When the gate
+/// closes the string is damped to a decay of `release` seconds. With
+/// `Excitation::Bow` the open gate bows the string instead, using the friction
+/// curve from the STK bowed string, with `bow_pressure` shaping the stick-slip.
+pub struct StringNode {
+    excitation: Excitation,
+    frequency: AudioParam,
+    gain: AudioParam,
+    gate: AudioParam,
+    decay: AudioParam,
+    release: AudioParam,
+    damping: AudioParam,
+    brightness: AudioParam,
+    pick_position: AudioParam,
+    bow_pressure: AudioParam,
+    // Plucks scheduled by `pluck` and `note_on`, as (sample, velocity)
+    scheduled: EventQueue<f32>,
+    neck: DelayLine,
+    bridge: DelayLine,
+    tuning: Tuning,
+    loop_gain: f32,
+    damping_pole: f32,
+    allpass: f32,
+    lowpass_state: f32,
+    allpass_input: f32,
+    allpass_output: f32,
+    // Pending pluck: the noise burst or the position in the excitation buffer
+    burst: Vec<f32>,
+    burst_length: usize,
+    excitation_position: f64,
+    plucking: bool,
+    velocity: f32,
+    excitation_state: f32,
+    bow_speed: f32,
+    held: bool,
+    last_gate: f32,
+    // Rate the delay lines are sized and tuned for
+    sample_rate: f32,
+    rng: XorShiftRng,
+}
+
+impl StringNode {
+    pub fn new(excitation: Excitation, context: &AudioContext) -> Self {
+        let capacity = (context.sample_rate() / MIN_FREQUENCY).ceil() as usize + 4;
+        Self {
+            excitation,
+            frequency: AudioParam::new(220.0, MIN_FREQUENCY, 22050.0),
+            gain: AudioParam::new(1.0, 0.0, 1.0),
+            gate: AudioParam::new(0.0, 0.0, 1.0),
+            decay: AudioParam::new(3.0, 0.01, 60.0),
+            release: AudioParam::new(0.3, 0.01, 60.0),
+            damping: AudioParam::new(0.5, 0.0, 1.0),
+            brightness: AudioParam::new(0.7, 0.0, 1.0),
+            pick_position: AudioParam::new(0.2, 0.0, 1.0),
+            bow_pressure: AudioParam::new(0.5, 0.0, 1.0),
+            scheduled: EventQueue::new(),
+            neck: DelayLine::new(capacity),
+            bridge: DelayLine::new(capacity),
+            tuning: Tuning::default(),
+            loop_gain: 0.0,
+            damping_pole: 0.0,
+            allpass: 0.0,
+            lowpass_state: 0.0,
+            allpass_input: 0.0,
+            allpass_output: 0.0,
+            burst: vec![0.0; capacity],
+            burst_length: 0,
+            excitation_position: 0.0,
+            plucking: false,
+            velocity: 1.0,
+            excitation_state: 0.0,
+            bow_speed: 0.0,
+            held: false,
+            last_gate: 0.0,
+            sample_rate: context.sample_rate(),
+            rng: XorShiftRng::new(0x51A7),
+        }
+    }
+
+    pub fn frequency(&self) -> &AudioParam {
+        &self.frequency
+    }
+
+    pub fn gain(&self) -> &AudioParam {
+        &self.gain
+    }
+
+    pub fn gate(&self) -> &AudioParam {
+        &self.gate
+    }
+
+    // Time for the fundamental to fall by 60dB, in seconds
+    pub fn decay(&self) -> &AudioParam {
+        &self.decay
+    }
+
+    // Decay time once the gate closes
+    pub fn release(&self) -> &AudioParam {
+        &self.release
+    }
+
+    pub fn damping(&self) -> &AudioParam {
+        &self.damping
+    }
+
+    pub fn brightness(&self) -> &AudioParam {
+        &self.brightness
+    }
+
+    pub fn pick_position(&self) -> &AudioParam {
+        &self.pick_position
+    }
+
+    pub fn bow_pressure(&self) -> &AudioParam {
+        &self.bow_pressure
+    }
+
+    pub fn excitation(&self) -> &Excitation {
+        &self.excitation
+    }
+
+    pub fn set_excitation(&mut self, excitation: Excitation) {
+        self.excitation = excitation;
+        self.plucking = false;
+    }
+
+    pub fn set_seed(&mut self, seed: u32) {
+        self.rng = XorShiftRng::new(seed);
+    }
+
+    // Plucks the string at the given sample, whatever the gate is doing
+    pub fn pluck(&self, at_sample: u64, velocity: f32) {
+        self.scheduled.push(at_sample, velocity.clamp(0.0, 1.0));
+    }
+
+    // Tunes to the note and plucks (or starts bowing) at the given sample
+    pub fn note_on(&self, note: u8, velocity: f32, at_sample: u64) {
+        let velocity = velocity.clamp(0.0, 1.0);
+        self.frequency
+            .set_value_at_time(midi_to_frequency(note), at_sample);
+        self.gate.set_value_at_time(velocity, at_sample);
+        if !matches!(self.excitation, Excitation::Bow) {
+            self.pluck(at_sample, velocity);
+        }
+    }
+
+    // Damps the string (or lifts the bow) at the given sample
+    pub fn note_off(&self, at_sample: u64) {
+        self.gate.set_value_at_time(0.0, at_sample);
+    }
+
+    // Silences the string immediately
+    pub fn reset(&mut self) {
+        self.neck.clear();
+        self.bridge.clear();
+        self.lowpass_state = 0.0;
+        self.allpass_input = 0.0;
+        self.allpass_output = 0.0;
+        self.plucking = false;
+        self.bow_speed = 0.0;
+        self.held = false;
+    }
+
+    // Splits the period between the delay lines, the loop lowpass and the
+    // allpass, and sets the loop gain for the decay time
+    fn retune(&mut self, tuning: Tuning, sample_rate: f32) {
+        self.tuning = tuning;
+        let frequency = tuning.frequency.clamp(MIN_FREQUENCY, sample_rate / 4.0);
+        let period = sample_rate / frequency;
+        let omega = 2.0 * std::f32::consts::PI * frequency / sample_rate;
+
+        let pole = tuning.damping * MAX_DAMPING_POLE;
+        let (sin, cos) = omega.sin_cos();
+        let lowpass_delay = (pole * sin).atan2(1.0 - pole * cos) / omega;
+        let lowpass_magnitude = (1.0 - pole) / (1.0 - 2.0 * pole * cos + pole * pole).sqrt();
+
+        // Keep the allpass delay between 0.5 and 1.5 samples, where it's smooth
+        let remaining = (period - lowpass_delay).max(2.5);
+        let length = (remaining - 0.5).floor() as usize;
+        let fraction = remaining - length as f32;
+        self.allpass =
+            ((1.0 - fraction) * omega * 0.5).sin() / ((1.0 + fraction) * omega * 0.5).sin();
+
+        let capacity = self.neck.buffer.len() - 1;
+        let neck = ((tuning.pick_position * length as f32).round() as usize).clamp(1, length - 1);
+        self.neck.length = neck.min(capacity);
+        self.bridge.length = (length - neck).min(capacity);
+
+        // Loss per trip round the loop for the decay, made up at the fundamental
+        // for what the lowpass takes
+        let trip = 0.001f32.powf(1.0 / (frequency * tuning.decay));
+        self.damping_pole = pole;
+        self.loop_gain = (trip / lowpass_magnitude).min(0.9999);
+    }
+
+    fn start_pluck(&mut self, velocity: f32, period: usize) {
+        self.velocity = velocity;
+        self.held = true;
+        self.excitation_position = 0.0;
+        self.excitation_state = 0.0;
+        self.plucking = true;
+
+        match &self.excitation {
+            Excitation::Noise => {
+                let length = period.clamp(1, self.burst.len());
+                for sample in &mut self.burst[..length] {
+                    *sample = self.rng.next_bipolar();
+                }
+                let mean = self.burst[..length].iter().sum::<f32>() / length as f32;
+                self.burst[..length]
+                    .iter_mut()
+                    .for_each(|sample| *sample -= mean);
+                self.burst_length = length;
+            }
+            Excitation::Buffer(_) => {}
+            Excitation::Bow => self.plucking = false,
+        }
+    }
+
+    // Next sample of a pending pluck, before the brightness lowpass
+    fn next_excitation(&mut self, sample_rate: f32) -> f32 {
+        if !self.plucking {
+            return 0.0;
+        }
+        match &self.excitation {
+            Excitation::Noise => {
+                let index = self.excitation_position as usize;
+                self.excitation_position += 1.0;
+                if index + 1 >= self.burst_length {
+                    self.plucking = false;
+                }
+                self.burst.get(index).copied().unwrap_or(0.0)
+            }
+            Excitation::Buffer(buffer) => {
+                let data = buffer.channel(0);
+                let index = self.excitation_position as usize;
+                if index + 1 >= data.len() {
+                    self.plucking = false;
+                    return data.get(index).copied().unwrap_or(0.0);
+                }
+                let frac = (self.excitation_position - index as f64) as f32;
+                self.excitation_position += (buffer.sample_rate() / sample_rate) as f64;
+                data[index] + (data[index + 1] - data[index]) * frac
+            }
+            Excitation::Bow => 0.0,
+        }
+    }
+
+    // Friction of the bow against the string, from STK's BowTable
+    #[inline]
+    fn bow_table(difference: f32, pressure: f32) -> f32 {
+        let slope = 5.0 - 4.0 * pressure;
+        ((difference * slope).abs() + 0.75)
+            .powi(-4)
+            .clamp(0.01, 0.98)
+    }
+}
+
+impl AudioNode for StringNode {
+    fn process(&mut self, context: &AudioContext, current_sample: u64) -> f32 {
+        let sample_rate = context.sample_rate();
+
+        // Note events: scheduled plucks, then edges of the gate
+        let gate = self.gate.get_value(current_sample);
+        let period = (sample_rate / self.frequency.get_value(current_sample)).round() as usize;
+        // Plucks due together land as one, at the last velocity
+        let mut pluck = None;
+        while let Some(velocity) = self.scheduled.pop_due(current_sample) {
+            pluck = Some(velocity);
+        }
+        match pluck {
+            Some(velocity) => self.start_pluck(velocity, period),
+            None => {
+                if gate > 0.0 && self.last_gate <= 0.0 {
+                    self.start_pluck(gate, period);
+                }
+            }
+        }
+        if gate <= 0.0 && self.last_gate > 0.0 {
+            self.held = false;
+        }
+        self.last_gate = gate;
+
+        let decay = self.decay.get_value(current_sample);
+        let tuning = Tuning {
+            frequency: self.frequency.get_value(current_sample),
+            damping: self.damping.get_value(current_sample),
+            pick_position: self.pick_position.get_value(current_sample),
+            decay: if self.held {
+                decay
+            } else {
+                decay.min(self.release.get_value(current_sample))
+            },
+        };
+        if tuning != self.tuning {
+            self.retune(tuning, sample_rate);
+        }
+
+        // Reflections at each end: the bridge takes the losses and the tuning
+        let bridge_out = self.bridge.read();
+        self.lowpass_state =
+            (1.0 - self.damping_pole) * bridge_out + self.damping_pole * self.lowpass_state;
+        let lossy = self.lowpass_state * self.loop_gain;
+        let tuned = self.allpass * lossy + self.allpass_input - self.allpass * self.allpass_output;
+        self.allpass_input = lossy;
+        self.allpass_output = tuned;
+        let bridge_reflection = -tuned;
+        let nut_reflection = -self.neck.read();
+
+        // Force at the pick point: the pluck, scaled by velocity and softened by
+        // the brightness lowpass, plus the bow's friction
+        let excitation = self.next_excitation(sample_rate);
+        let brightness = self.brightness.get_value(current_sample);
+        let cutoff = (0.05 + 0.95 * brightness) * (0.5 + 0.5 * self.velocity);
+        self.excitation_state += cutoff * (excitation - self.excitation_state);
+        let mut force = self.excitation_state * self.velocity;
+
+        if let Excitation::Bow = self.excitation {
+            let target = if gate > 0.0 {
+                MIN_BOW_SPEED + BOW_SPEED_RANGE * gate
+            } else {
+                0.0
+            };
+            self.bow_speed += BOW_SMOOTHING * (target - self.bow_speed);
+            if self.bow_speed > 1e-4 {
+                let difference = self.bow_speed - (bridge_reflection + nut_reflection);
+                let pressure = self.bow_pressure.get_value(current_sample);
+                force += difference * Self::bow_table(difference, pressure);
+            }
+        }
+
+        self.neck.push(bridge_reflection + force);
+        self.bridge.push(nut_reflection + force);
+
+        bridge_out * self.gain.get_value(current_sample)
+    }
+
+    fn set_parameter(&self, name: &str, value: f32) {
+        match name {
+            "frequency" => self.frequency.set_value(value),
+            "gain" => self.gain.set_value(value),
+            "gate" => self.gate.set_value(value),
+            "decay" => self.decay.set_value(value),
+            "release" => self.release.set_value(value),
+            "damping" => self.damping.set_value(value),
+            "brightness" => self.brightness.set_value(value),
+            "pick_position" => self.pick_position.set_value(value),
+            "bow_pressure" => self.bow_pressure.set_value(value),
+            _ => {}
+        }
+    }
+
+    fn set_parameter_at(&self, name: &str, value: f32, at_sample: u64) {
+        match name {
+            "frequency" => self.frequency.set_value_at_time(value, at_sample),
+            "gain" => self.gain.set_value_at_time(value, at_sample),
+            "gate" => self.gate.set_value_at_time(value, at_sample),
+            "decay" => self.decay.set_value_at_time(value, at_sample),
+            "release" => self.release.set_value_at_time(value, at_sample),
+            "damping" => self.damping.set_value_at_time(value, at_sample),
+            "brightness" => self.brightness.set_value_at_time(value, at_sample),
+            "pick_position" => self.pick_position.set_value_at_time(value, at_sample),
+            "bow_pressure" => self.bow_pressure.set_value_at_time(value, at_sample),
+            _ => {}
+        }
+    }
+
+    fn connect_input(&mut self, _name: &str, _node: Box<dyn AudioNode + Send>) {
+        // Sources don't have inputs
+    }
+
+    fn clear_input(&mut self, _input_name: &str) {
+        // No-op for sources
+    }
+
+    // The delay lines are resized for the new rate, which silences the string
+    fn prepare(&mut self, sample_rate: f32, _max_block: usize) {
+        for param in [
+            &self.frequency,
+            &self.gain,
+            &self.gate,
+            &self.decay,
+            &self.release,
+            &self.damping,
+            &self.brightness,
+            &self.pick_position,
+            &self.bow_pressure,
+        ] {
+            param.prepare(sample_rate);
+        }
+        if let Some(ratio) = rate_change(&mut self.sample_rate, sample_rate) {
+            let capacity = (sample_rate / MIN_FREQUENCY).ceil() as usize + 4;
+            self.neck = DelayLine::new(capacity);
+            self.bridge = DelayLine::new(capacity);
+            self.burst = vec![0.0; capacity];
+            self.reset();
+            self.tuning = Tuning::default();
+            self.scheduled.rescale(ratio);
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn AudioNode + Send> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for StringNode {
+    fn clone(&self) -> Self {
+        Self {
+            excitation: self.excitation.clone(),
+            frequency: self.frequency.clone(),
+            gain: self.gain.clone(),
+            gate: self.gate.clone(),
+            decay: self.decay.clone(),
+            release: self.release.clone(),
+            damping: self.damping.clone(),
+            brightness: self.brightness.clone(),
+            pick_position: self.pick_position.clone(),
+            bow_pressure: self.bow_pressure.clone(),
+            scheduled: self.scheduled.clone(),
+            neck: self.neck.clone(),
+            bridge: self.bridge.clone(),
+            tuning: self.tuning,
+            loop_gain: self.loop_gain,
+            damping_pole: self.damping_pole,
+            allpass: self.allpass,
+            lowpass_state: self.lowpass_state,
+            allpass_input: self.allpass_input,
+            allpass_output: self.allpass_output,
+            burst: self.burst.clone(),
+            burst_length: self.burst_length,
+            excitation_position: self.excitation_position,
+            plucking: self.plucking,
+            velocity: self.velocity,
+            excitation_state: self.excitation_state,
+            bow_speed: self.bow_speed,
+            held: self.held,
+            last_gate: self.last_gate,
+            sample_rate: self.sample_rate,
+            rng: self.rng.clone(),
+        }
+    }
+}
@@ -0,0 +1,317 @@
+// src/synth/unison.rs
+
+use crate::synth::audio_context::AudioContext;
+use crate::synth::audio_node::AudioNode;
+use crate::synth::audio_param::AudioParam;
+use crate::synth::bandlimited_wavetableoscillator::{
+    BandlimitedWavetableOscillator, InterpolationType,
+};
+use crate::synth::oscillator::OscillatorType;
+use crate::synth::random::XorShiftRng;
+use std::f32::consts::{FRAC_PI_2, SQRT_2};
+
+pub const MAX_UNISON_VOICES: usize = 16;
+
+// Frequency offset of the outermost voices at full detune, as on the JP-8000
+const MAX_DETUNE: f32 = 0.11;
+
+/// How the `detune` control maps to the spread of voice frequencies.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DetuneCurve {
+    Linear,
+    // The JP-8000 supersaw response: fine control at low settings, rising steeply
+    // towards the top of the range
+    Supersaw,
+}
+
+impl DetuneCurve {
+    // Fraction of `MAX_DETUNE` used at `detune` in [0, 1]
+    pub fn amount(&self, detune: f32) -> f32 {
+        let x = detune.clamp(0.0, 1.0);
+        match self {
+            DetuneCurve::Linear => x,
+            // Polynomial fit of measurements from a JP-8000 (Adam Szabo, 2010)
+            DetuneCurve::Supersaw => {
+                const COEFFICIENTS: [f64; 12] = [
+                    10028.7312891634,
+                    -50818.8652045924,
+                    111363.4808729368,
+                    -138150.6761080548,
+                    106649.6679158292,
+                    -53046.9642751875,
+                    17019.951858008,
+                    -3425.0836591318,
+                    404.2703938388,
+                    -24.1878824391,
+                    0.6717417634,
+                    0.0030115596,
+                ];
+                let x = x as f64;
+                let y = COEFFICIENTS.iter().fold(0.0, |acc, &c| acc * x + c);
+                y.clamp(0.0, 1.0) as f32
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Voice {
+    phase: f32,
+    increment: f32,
+    last_freq: f32,
+    table: (usize, f32),
+    // Position in the stack, from -1 (lowest and leftmost) to 1
+    offset: f32,
+}
+
+/// Several detuned copies of a band-limited waveform, like a supersaw.
+///
+/// Voices are spaced evenly across `detune`, which runs from 0 (unison) to 1, where
+/// the outer voices are 11% off the base frequency. `spread` pans them from the
+/// centre (0) to the full stereo width (1). Every voice reads the same wave bank,
+/// so adding voices costs no memory, and each starts at a random phase.
+pub struct UnisonOscillator {
+    oscillator: BandlimitedWavetableOscillator,
+    frequency: AudioParam,
+    detune: AudioParam,
+    spread: AudioParam,
+    gain: AudioParam,
+    curve: DetuneCurve,
+    voices: [Voice; MAX_UNISON_VOICES],
+    voice_count: usize,
+    // Last output of each voice, before panning
+    outputs: [f32; MAX_UNISON_VOICES],
+    rng: XorShiftRng,
+}
+
+impl UnisonOscillator {
+    pub fn new(
+        waveform: OscillatorType,
+        voices: usize,
+        context: &AudioContext,
+    ) -> anyhow::Result<Self> {
+        let mut unison = Self {
+            oscillator: BandlimitedWavetableOscillator::new(waveform, context)?,
+            frequency: AudioParam::new(440.0, 0.01, 22050.0),
+            detune: AudioParam::new(0.25, 0.0, 1.0),
+            spread: AudioParam::new(0.5, 0.0, 1.0),
+            gain: AudioParam::new(1.0, 0.0, 1.0),
+            curve: DetuneCurve::Linear,
+            voices: [Voice::default(); MAX_UNISON_VOICES],
+            voice_count: 1,
+            outputs: [0.0; MAX_UNISON_VOICES],
+            rng: XorShiftRng::new(0x5A5A),
+        };
+        unison.set_voices(voices);
+        Ok(unison)
+    }
+
+    pub fn frequency(&self) -> &AudioParam {
+        &self.frequency
+    }
+
+    pub fn detune(&self) -> &AudioParam {
+        &self.detune
+    }
+
+    pub fn spread(&self) -> &AudioParam {
+        &self.spread
+    }
+
+    pub fn gain(&self) -> &AudioParam {
+        &self.gain
+    }
+
+    // Frame position for multi-frame wavetables, shared by all voices
+    pub fn position(&self) -> &AudioParam {
+        self.oscillator.position()
+    }
+
+    // Duty cycle when the waveform is `OscillatorType::Pulse`
+    pub fn pulse_width(&self) -> &AudioParam {
+        self.oscillator.pulse_width()
+    }
+
+    pub fn voices(&self) -> usize {
+        self.voice_count
+    }
+
+    // Sets the number of voices, from 1 to 16, and restarts them at random phases
+    pub fn set_voices(&mut self, voices: usize) {
+        self.voice_count = voices.clamp(1, MAX_UNISON_VOICES);
+        for (i, voice) in self.voices[..self.voice_count].iter_mut().enumerate() {
+            voice.offset = if self.voice_count == 1 {
+                0.0
+            } else {
+                2.0 * i as f32 / (self.voice_count - 1) as f32 - 1.0
+            };
+            voice.last_freq = 0.0;
+        }
+        self.randomize_phases();
+    }
+
+    pub fn set_detune_curve(&mut self, curve: DetuneCurve) {
+        self.curve = curve;
+    }
+
+    pub fn detune_curve(&self) -> DetuneCurve {
+        self.curve
+    }
+
+    pub fn set_seed(&mut self, seed: u32) {
+        self.rng = XorShiftRng::new(seed);
+        self.randomize_phases();
+    }
+
+    pub fn randomize_phases(&mut self) {
+        for voice in &mut self.voices[..self.voice_count] {
+            voice.phase = self.rng.next_f32();
+        }
+    }
+
+    pub fn set_interpolation_mode(&mut self, mode: InterpolationType) {
+        self.oscillator.set_interpolation_mode(mode);
+    }
+
+    pub fn set_mip_crossfade(&mut self, enabled: bool) {
+        self.oscillator.set_mip_crossfade(enabled);
+        for voice in &mut self.voices {
+            voice.last_freq = 0.0;
+        }
+    }
+
+    // Advances every voice one sample, leaving their outputs in `self.outputs`
+    fn render_voices(&mut self, context: &AudioContext, current_sample: u64) {
+        let freq = self.frequency.get_value(current_sample);
+        let detune = self.curve.amount(self.detune.get_value(current_sample)) * MAX_DETUNE;
+        let frame = self.oscillator.select_frame(current_sample);
+        self.oscillator.update_pulse_width(context, current_sample);
+        let nyquist = context.sample_rate() * 0.5;
+
+        for (voice, output) in self.voices[..self.voice_count]
+            .iter_mut()
+            .zip(self.outputs.iter_mut())
+        {
+            let voice_freq = (freq * (1.0 + detune * voice.offset)).min(nyquist);
+            if voice_freq != voice.last_freq {
+                voice.increment = voice_freq / context.sample_rate();
+                voice.last_freq = voice_freq;
+                voice.table = self.oscillator.select_table(voice_freq);
+            }
+
+            *output = self.oscillator.read(voice.phase, voice.table, frame);
+
+            voice.phase += voice.increment;
+            if voice.phase >= 1.0 {
+                voice.phase -= 1.0;
+            }
+        }
+    }
+
+    // Keeps the level roughly constant as voices are added, since their phases
+    // are uncorrelated
+    #[inline]
+    fn level(&self, current_sample: u64) -> f32 {
+        self.gain.get_value(current_sample) / (self.voice_count as f32).sqrt()
+    }
+}
+
+impl AudioNode for UnisonOscillator {
+    fn process(&mut self, context: &AudioContext, current_sample: u64) -> f32 {
+        self.render_voices(context, current_sample);
+        let sum: f32 = self.outputs[..self.voice_count].iter().sum();
+        sum * self.level(current_sample)
+    }
+
+    fn process_stereo(&mut self, context: &AudioContext, current_sample: u64) -> (f32, f32) {
+        self.render_voices(context, current_sample);
+        let spread = self.spread.get_value(current_sample);
+
+        // Equal-power panning, scaled so a centred voice has unity gain
+        let mut left = 0.0;
+        let mut right = 0.0;
+        for (voice, &output) in self.voices[..self.voice_count]
+            .iter()
+            .zip(self.outputs.iter())
+        {
+            let x = (voice.offset * spread + 1.0) * 0.5;
+            left += output * (x * FRAC_PI_2).cos();
+            right += output * (x * FRAC_PI_2).sin();
+        }
+
+        let level = self.level(current_sample) * SQRT_2;
+        (left * level, right * level)
+    }
+
+    fn set_parameter(&self, name: &str, value: f32) {
+        match name {
+            "frequency" => self.frequency.set_value(value),
+            "detune" => self.detune.set_value(value),
+            "spread" => self.spread.set_value(value),
+            "gain" => self.gain.set_value(value),
+            "position" => self.oscillator.position().set_value(value),
+            "pulse_width" => self.oscillator.pulse_width().set_value(value),
+            _ => {}
+        }
+    }
+
+    fn set_parameter_at(&self, name: &str, value: f32, at_sample: u64) {
+        match name {
+            "frequency" => self.frequency.set_value_at_time(value, at_sample),
+            "detune" => self.detune.set_value_at_time(value, at_sample),
+            "spread" => self.spread.set_value_at_time(value, at_sample),
+            "gain" => self.gain.set_value_at_time(value, at_sample),
+            "position" => self
+                .oscillator
+                .position()
+                .set_value_at_time(value, at_sample),
+            "pulse_width" => self
+                .oscillator
+                .pulse_width()
+                .set_value_at_time(value, at_sample),
+            _ => {}
+        }
+    }
+
+    fn connect_input(&mut self, _name: &str, _node: Box<dyn AudioNode + Send>) {
+        // Oscillators don't have inputs
+    }
+
+    fn clear_input(&mut self, _input_name: &str) {
+        // No-op for oscillators
+    }
+
+    fn prepare(&mut self, sample_rate: f32, max_block: usize) {
+        for param in [&self.frequency, &self.detune, &self.spread, &self.gain] {
+            param.prepare(sample_rate);
+        }
+        let previous = self.oscillator.sample_rate();
+        self.oscillator.prepare(sample_rate, max_block);
+        if self.oscillator.sample_rate() != previous {
+            for voice in &mut self.voices {
+                voice.last_freq = 0.0;
+            }
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn AudioNode + Send> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for UnisonOscillator {
+    fn clone(&self) -> Self {
+        Self {
+            oscillator: self.oscillator.clone(),
+            frequency: self.frequency.clone(),
+            detune: self.detune.clone(),
+            spread: self.spread.clone(),
+            gain: self.gain.clone(),
+            curve: self.curve,
+            voices: self.voices,
+            voice_count: self.voice_count,
+            outputs: self.outputs,
+            rng: self.rng.clone(),
+        }
+    }
+}
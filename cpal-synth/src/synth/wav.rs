@@ -0,0 +1,296 @@
+// src/synth/wav.rs
+
+use crate::synth::audio_buffer::AudioBuffer;
+use crate::synth::dither::{Dither, Quantizer};
+use std::path::Path;
+
+const WAVE_FORMAT_PCM: u16 = 0x0001;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x0003;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WavSampleFormat {
+    Int,
+    Float,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct WavSpec {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub bits_per_sample: u16,
+    pub sample_format: WavSampleFormat,
+}
+
+impl WavSpec {
+    pub fn bytes_per_sample(&self) -> usize {
+        (self.bits_per_sample as usize).div_ceil(8)
+    }
+
+    pub fn bytes_per_frame(&self) -> usize {
+        self.bytes_per_sample() * self.channels as usize
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.channels == 0 {
+            return Err(anyhow::anyhow!("WAV file has no channels"));
+        }
+        if self.sample_rate == 0 {
+            return Err(anyhow::anyhow!("WAV file has a sample rate of 0"));
+        }
+        match (self.sample_format, self.bits_per_sample) {
+            (WavSampleFormat::Int, 8 | 16 | 24 | 32) | (WavSampleFormat::Float, 32 | 64) => Ok(()),
+            (format, bits) => Err(anyhow::anyhow!(
+                "Unsupported WAV sample format: {:?} {} bit",
+                format,
+                bits
+            )),
+        }
+    }
+}
+
+// Layout of a WAV file: its format and where the sample data lives
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct WavHeader {
+    pub spec: WavSpec,
+    pub data_offset: usize,
+    // Declared size of the data chunk; may exceed the bytes actually present
+    pub data_len: usize,
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+/// Walks the RIFF chunks up to the `data` chunk. Only the header bytes are needed,
+/// so streaming readers can pass the start of a file.
+pub fn parse_wav_header(bytes: &[u8]) -> anyhow::Result<WavHeader> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(anyhow::anyhow!("Not a RIFF/WAVE file"));
+    }
+
+    let mut spec = None;
+    let mut offset = 12;
+    while bytes.len().saturating_sub(offset) >= 8 {
+        let id = &bytes[offset..offset + 4];
+        let size = read_u32(bytes, offset + 4) as usize;
+        let body = offset + 8;
+
+        match id {
+            b"fmt " => {
+                if size < 16 || body + 16 > bytes.len() {
+                    return Err(anyhow::anyhow!("Truncated WAV fmt chunk"));
+                }
+                let mut format_tag = read_u16(bytes, body);
+                let channels = read_u16(bytes, body + 2);
+                let sample_rate = read_u32(bytes, body + 4);
+                let bits_per_sample = read_u16(bytes, body + 14);
+
+                if format_tag == WAVE_FORMAT_EXTENSIBLE {
+                    // The sub-format GUID starts with the actual format tag
+                    if size < 40 || body + 26 > bytes.len() {
+                        return Err(anyhow::anyhow!("Truncated WAVE_FORMAT_EXTENSIBLE header"));
+                    }
+                    format_tag = read_u16(bytes, body + 24);
+                }
+
+                let sample_format = match format_tag {
+                    WAVE_FORMAT_PCM => WavSampleFormat::Int,
+                    WAVE_FORMAT_IEEE_FLOAT => WavSampleFormat::Float,
+                    other => {
+                        return Err(anyhow::anyhow!(
+                            "Unsupported WAV format tag: {:#06x}",
+                            other
+                        ))
+                    }
+                };
+
+                let parsed = WavSpec {
+                    channels,
+                    sample_rate,
+                    bits_per_sample,
+                    sample_format,
+                };
+                parsed.validate()?;
+                spec = Some(parsed);
+            }
+            b"data" => {
+                let spec =
+                    spec.ok_or_else(|| anyhow::anyhow!("WAV data chunk before fmt chunk"))?;
+                return Ok(WavHeader {
+                    spec,
+                    data_offset: body,
+                    data_len: size,
+                });
+            }
+            _ => {}
+        }
+
+        // Chunks are padded to an even size
+        offset = body.saturating_add(size).saturating_add(size & 1);
+    }
+
+    Err(anyhow::anyhow!("WAV file has no data chunk"))
+}
+
+/// Converts interleaved little-endian sample bytes to `f32` in [-1, 1], appending
+/// to `out`. Trailing bytes that do not form a whole sample are ignored.
+pub fn convert_wav_samples(spec: &WavSpec, data: &[u8], out: &mut Vec<f32>) {
+    let width = spec.bytes_per_sample();
+    let samples = data.chunks_exact(width);
+    out.reserve(samples.len());
+
+    match (spec.sample_format, spec.bits_per_sample) {
+        (WavSampleFormat::Int, 8) => {
+            // 8-bit WAV is unsigned
+            out.extend(samples.map(|s| (s[0] as f32 - 128.0) / 128.0));
+        }
+        (WavSampleFormat::Int, 16) => {
+            out.extend(samples.map(|s| i16::from_le_bytes([s[0], s[1]]) as f32 / 32768.0));
+        }
+        (WavSampleFormat::Int, 24) => {
+            out.extend(samples.map(|s| {
+                let value = i32::from_le_bytes([0, s[0], s[1], s[2]]) >> 8;
+                value as f32 / 8_388_608.0
+            }));
+        }
+        (WavSampleFormat::Int, 32) => {
+            out.extend(
+                samples
+                    .map(|s| i32::from_le_bytes([s[0], s[1], s[2], s[3]]) as f32 / 2_147_483_648.0),
+            );
+        }
+        (WavSampleFormat::Float, 32) => {
+            out.extend(samples.map(|s| f32::from_le_bytes([s[0], s[1], s[2], s[3]])));
+        }
+        (WavSampleFormat::Float, 64) => {
+            out.extend(samples.map(|s| {
+                f64::from_le_bytes([s[0], s[1], s[2], s[3], s[4], s[5], s[6], s[7]]) as f32
+            }));
+        }
+        _ => {}
+    }
+}
+
+pub(crate) fn deinterleave(interleaved: &[f32], channels: usize) -> Vec<Vec<f32>> {
+    let frames = interleaved.len() / channels;
+    let mut output = vec![Vec::with_capacity(frames); channels];
+    for frame in interleaved.chunks_exact(channels) {
+        for (channel, &sample) in output.iter_mut().zip(frame) {
+            channel.push(sample);
+        }
+    }
+    output
+}
+
+pub fn decode_wav(bytes: &[u8]) -> anyhow::Result<AudioBuffer> {
+    let header = parse_wav_header(bytes)?;
+    let end = header
+        .data_offset
+        .saturating_add(header.data_len)
+        .min(bytes.len());
+    let data = &bytes[header.data_offset..end];
+
+    let mut interleaved = Vec::new();
+    convert_wav_samples(&header.spec, data, &mut interleaved);
+
+    let channels = deinterleave(&interleaved, header.spec.channels as usize);
+    AudioBuffer::from_channels(channels, header.spec.sample_rate as f32)
+}
+
+/// Encodes a buffer as a WAV file. Integer formats are 8, 16, 24 or 32 bit and
+/// are reduced from float with `dither` (see `Quantizer`); float formats are 32
+/// or 64 bit.
+pub fn encode_wav(
+    buffer: &AudioBuffer,
+    sample_format: WavSampleFormat,
+    bits_per_sample: u16,
+    dither: Dither,
+) -> anyhow::Result<Vec<u8>> {
+    let spec = WavSpec {
+        channels: buffer.number_of_channels() as u16,
+        sample_rate: buffer.sample_rate().round() as u32,
+        bits_per_sample,
+        sample_format,
+    };
+    spec.validate()?;
+
+    let channels = spec.channels as usize;
+    let mut interleaved: Vec<f32> = (0..buffer.length())
+        .flat_map(|frame| buffer.channels().iter().map(move |channel| channel[frame]))
+        .collect();
+    if sample_format == WavSampleFormat::Int {
+        Quantizer::new(dither, bits_per_sample as u32, channels).process(&mut interleaved);
+    }
+
+    let data_len = interleaved.len() * spec.bytes_per_sample();
+    let format_tag = match sample_format {
+        WavSampleFormat::Int => WAVE_FORMAT_PCM,
+        WavSampleFormat::Float => WAVE_FORMAT_IEEE_FLOAT,
+    };
+    let mut bytes = Vec::with_capacity(44 + data_len + 1);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&((36 + data_len + (data_len & 1)) as u32).to_le_bytes());
+    bytes.extend_from_slice(b"WAVE");
+    bytes.extend_from_slice(b"fmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&format_tag.to_le_bytes());
+    bytes.extend_from_slice(&spec.channels.to_le_bytes());
+    bytes.extend_from_slice(&spec.sample_rate.to_le_bytes());
+    bytes.extend_from_slice(&(spec.sample_rate * spec.bytes_per_frame() as u32).to_le_bytes());
+    bytes.extend_from_slice(&(spec.bytes_per_frame() as u16).to_le_bytes());
+    bytes.extend_from_slice(&bits_per_sample.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&(data_len as u32).to_le_bytes());
+
+    // Quantized samples are exact multiples of the step, so scaling gives the integer
+    let scale = |bits: i32| 2f64.powi(bits - 1);
+    for &sample in &interleaved {
+        match (sample_format, bits_per_sample) {
+            (WavSampleFormat::Int, 8) => {
+                // 8-bit WAV is unsigned
+                bytes.push((sample as f64 * scale(8) + 128.0).clamp(0.0, 255.0) as u8);
+            }
+            (WavSampleFormat::Int, 16) => {
+                let value = (sample as f64 * scale(16)) as i16;
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            (WavSampleFormat::Int, 24) => {
+                let value = (sample as f64 * scale(24)) as i32;
+                bytes.extend_from_slice(&value.to_le_bytes()[..3]);
+            }
+            (WavSampleFormat::Int, _) => {
+                let value = (sample as f64 * scale(32)) as i32;
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            (WavSampleFormat::Float, 32) => bytes.extend_from_slice(&sample.to_le_bytes()),
+            (WavSampleFormat::Float, _) => bytes.extend_from_slice(&(sample as f64).to_le_bytes()),
+        }
+    }
+    if data_len & 1 == 1 {
+        bytes.push(0);
+    }
+    Ok(bytes)
+}
+
+pub fn write_wav_file<P: AsRef<Path>>(
+    path: P,
+    buffer: &AudioBuffer,
+    sample_format: WavSampleFormat,
+    bits_per_sample: u16,
+    dither: Dither,
+) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    let bytes = encode_wav(buffer, sample_format, bits_per_sample, dither)?;
+    std::fs::write(path, bytes)
+        .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))
+}
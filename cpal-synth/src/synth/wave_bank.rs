@@ -0,0 +1,229 @@
+// src/synth/wave_bank.rs
+
+use crate::synth::bandlimited_wavetableoscillator::WaveTableBank;
+use crate::synth::oscillator::OscillatorType;
+use crate::synth::periodic_wave::PeriodicWave;
+use crossbeam::channel::{bounded, Sender};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, Weak};
+
+// Banks that can wait on the worker at once. The queue and the pending set are
+// sized for this up front, so queueing from the audio thread never allocates.
+const QUEUE_CAPACITY: usize = 64;
+
+// Waveform and the exact bits of the rate, so 44100.0 and 44100.5 get tables
+// of their own
+type BankKey = (OscillatorType, u32);
+
+struct Banks {
+    ready: HashMap<BankKey, Arc<WaveTableBank>>,
+    // Queued on the worker and not built yet
+    pending: HashSet<BankKey>,
+    // Banks the worker couldn't build, so realtime lookups don't queue them again
+    failed: HashSet<BankKey>,
+    // Custom waves by id. Weak, so a wave goes once nothing plays or holds it;
+    // a bank keeps the wave it was built from.
+    waves: HashMap<u32, Weak<PeriodicWave>>,
+}
+
+impl Banks {
+    fn new() -> Self {
+        Self {
+            ready: HashMap::new(),
+            pending: HashSet::with_capacity(QUEUE_CAPACITY),
+            failed: HashSet::new(),
+            waves: HashMap::new(),
+        }
+    }
+
+    // The wave a custom bank is built from, if it is still around
+    fn periodic_wave(&self, waveform: OscillatorType) -> Option<Arc<PeriodicWave>> {
+        match waveform {
+            OscillatorType::Custom(id) => self.waves.get(&id)?.upgrade(),
+            _ => None,
+        }
+    }
+}
+
+/// The band-limited tables that `BandlimitedWavetableOscillator`s read, one bank
+/// per waveform and sample rate, and the `PeriodicWave`s behind
+/// `OscillatorType::Custom` ids.
+///
+/// Every `AudioContext` owns a registry, and contexts can share one with
+/// `AudioContext::with_wave_banks`. Banks are built on first use: `load` builds
+/// on the calling thread, while `request` and the realtime lookup behind
+/// `BandlimitedWavetableOscillator::try_new` hand the work to a background
+/// thread and never wait on the lock. Banks live until `evict_unused_rates` or
+/// the last context holding the registry is dropped.
+pub struct WaveBankRegistry {
+    banks: Arc<Mutex<Banks>>,
+    // Generation requests for the background thread, which runs until the
+    // registry is dropped. None where threads can't be spawned.
+    worker: Option<Sender<BankKey>>,
+}
+
+impl WaveBankRegistry {
+    pub fn new() -> Self {
+        let banks = Arc::new(Mutex::new(Banks::new()));
+        let worker = Self::spawn_worker(banks.clone());
+        Self { banks, worker }
+    }
+
+    // Builds the bank now unless it is already there
+    pub fn load(&self, waveform: OscillatorType, sample_rate: f32) -> anyhow::Result<()> {
+        self.get(waveform, sample_rate).map(|_| ())
+    }
+
+    // Queues the bank for the background thread and returns straight away
+    pub fn request(&self, waveform: OscillatorType, sample_rate: f32) {
+        let key = (waveform, sample_rate.to_bits());
+        let mut banks = self.banks.lock().unwrap();
+        if !banks.ready.contains_key(&key) {
+            self.queue(&mut banks, key);
+        }
+    }
+
+    // Makes `wave` playable as `OscillatorType::Custom(wave.id())` while it is held
+    pub fn register_periodic_wave(&self, wave: &Arc<PeriodicWave>) {
+        let mut banks = self.banks.lock().unwrap();
+        banks.waves.retain(|_, wave| wave.strong_count() > 0);
+        banks.waves.insert(wave.id(), Arc::downgrade(wave));
+        let waveform = wave.oscillator_type();
+        banks.failed.retain(|&(failed, _)| failed != waveform);
+    }
+
+    pub fn periodic_wave(&self, id: u32) -> Option<Arc<PeriodicWave>> {
+        self.banks.lock().unwrap().waves.get(&id)?.upgrade()
+    }
+
+    pub fn is_loaded(&self, waveform: OscillatorType, sample_rate: f32) -> bool {
+        let key = (waveform, sample_rate.to_bits());
+        self.banks.lock().unwrap().ready.contains_key(&key)
+    }
+
+    pub fn bank_count(&self) -> usize {
+        self.banks.lock().unwrap().ready.len()
+    }
+
+    // Bytes of table data held by the registry
+    pub fn memory_usage(&self) -> usize {
+        let banks = self.banks.lock().unwrap();
+        banks.ready.values().map(|bank| bank.memory_usage()).sum()
+    }
+
+    // Drops the banks at other rates that no oscillator is reading, returning how
+    // many were freed
+    pub fn evict_unused_rates(&self, sample_rate: f32) -> usize {
+        let keep = sample_rate.to_bits();
+        let mut banks = self.banks.lock().unwrap();
+        let before = banks.ready.len();
+        banks
+            .ready
+            .retain(|&(_, rate), bank| rate == keep || Arc::strong_count(bank) > 1);
+        before - banks.ready.len()
+    }
+
+    // The bank, built on this thread if need be. The lock isn't held while
+    // building, so realtime lookups don't wait on it.
+    pub(crate) fn get(
+        &self,
+        waveform: OscillatorType,
+        sample_rate: f32,
+    ) -> anyhow::Result<Arc<WaveTableBank>> {
+        let key = (waveform, sample_rate.to_bits());
+        let periodic_wave = {
+            let banks = self.banks.lock().unwrap();
+            if let Some(bank) = banks.ready.get(&key) {
+                return Ok(bank.clone());
+            }
+            banks.periodic_wave(waveform)
+        };
+
+        println!("Building wave bank for {:?} at {}Hz", waveform, sample_rate);
+        let built = WaveTableBank::new(waveform, periodic_wave, sample_rate);
+        let mut banks = self.banks.lock().unwrap();
+        banks.pending.remove(&key);
+        match built {
+            Ok(bank) => {
+                banks.failed.remove(&key);
+                Ok(banks.ready.entry(key).or_insert(Arc::new(bank)).clone())
+            }
+            Err(e) => {
+                banks.failed.insert(key);
+                Err(e)
+            }
+        }
+    }
+
+    // The bank if it is ready and the lock is free. Otherwise it is queued for
+    // the background thread and None is returned; nothing here blocks or
+    // allocates.
+    pub(crate) fn try_get(
+        &self,
+        waveform: OscillatorType,
+        sample_rate: f32,
+    ) -> Option<Arc<WaveTableBank>> {
+        let key = (waveform, sample_rate.to_bits());
+        let mut banks = self.banks.try_lock().ok()?;
+        if let Some(bank) = banks.ready.get(&key) {
+            return Some(bank.clone());
+        }
+        self.queue(&mut banks, key);
+        None
+    }
+
+    // Hands `key` to the worker unless it is already waiting or failed. With the
+    // queue full it is left for a later lookup to queue.
+    fn queue(&self, banks: &mut Banks, key: BankKey) {
+        let Some(sender) = &self.worker else {
+            return;
+        };
+        if banks.failed.contains(&key)
+            || banks.pending.contains(&key)
+            || banks.pending.len() >= QUEUE_CAPACITY
+        {
+            return;
+        }
+        if sender.try_send(key).is_ok() {
+            banks.pending.insert(key);
+        }
+    }
+
+    // Builds queued banks until the registry is dropped
+    fn spawn_worker(banks: Arc<Mutex<Banks>>) -> Option<Sender<BankKey>> {
+        let (sender, receiver) = bounded::<BankKey>(QUEUE_CAPACITY);
+        let spawned = std::thread::Builder::new()
+            .name("wave-banks".to_string())
+            .spawn(move || {
+                for key in receiver {
+                    let (waveform, rate) = key;
+                    let periodic_wave = banks.lock().unwrap().periodic_wave(waveform);
+                    let built = WaveTableBank::new(waveform, periodic_wave, f32::from_bits(rate));
+                    let mut banks = banks.lock().unwrap();
+                    banks.pending.remove(&key);
+                    match built {
+                        Ok(bank) => {
+                            banks.ready.entry(key).or_insert_with(|| Arc::new(bank));
+                        }
+                        Err(e) => {
+                            eprintln!("Failed to build wave bank for {:?}: {}", waveform, e);
+                            banks.failed.insert(key);
+                        }
+                    }
+                }
+            });
+        match spawned {
+            Ok(_) => Some(sender),
+            Err(e) => {
+                eprintln!("No wave bank worker, banks will be built on demand: {}", e);
+                None
+            }
+        }
+    }
+}
+
+impl Default for WaveBankRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
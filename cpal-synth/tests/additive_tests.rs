@@ -0,0 +1,90 @@
+mod common;
+
+use common::{render, SAMPLE_RATE};
+use cpal_synth::{AdditiveOscillator, AudioContext, AudioNode, MAX_PARTIALS};
+use std::f32::consts::PI;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_matches(samples: &[f32], expected: impl Fn(f32) -> f32, tolerance: f32) {
+        for (n, &sample) in samples.iter().enumerate() {
+            let expected = expected(n as f32 / SAMPLE_RATE);
+            assert!(
+                (sample - expected).abs() < tolerance,
+                "Sample {}: expected {}, got {}",
+                n,
+                expected,
+                sample
+            );
+        }
+    }
+
+    #[test]
+    fn test_single_partial() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let mut oscillator = AdditiveOscillator::new(4);
+        oscillator.frequency().set_value(300.0);
+        oscillator.set_parameter("partial2.amplitude", 0.5);
+        oscillator.set_parameter("partial2.ratio", 2.5);
+        oscillator.set_parameter("partial2.phase", 0.25);
+
+        let samples = render(&mut oscillator, &context, 4000);
+        assert_matches(
+            &samples,
+            |t| 0.5 * (2.0 * PI * 750.0 * t + PI / 2.0).sin(),
+            1e-3,
+        );
+    }
+
+    #[test]
+    fn test_many_partials_match_direct_sum() {
+        // A 100Hz sawtooth from 200 harmonics, all below Nyquist
+        let context = AudioContext::new(SAMPLE_RATE);
+        let mut oscillator = AdditiveOscillator::new(200);
+        oscillator.frequency().set_value(100.0);
+        let amplitudes: Vec<f32> = (1..=200).map(|k| 0.5 / k as f32).collect();
+        oscillator.set_amplitudes(&amplitudes);
+
+        let samples = render(&mut oscillator, &context, 2000);
+        assert_matches(
+            &samples,
+            |t| {
+                amplitudes
+                    .iter()
+                    .enumerate()
+                    .map(|(n, a)| a * (2.0 * PI * 100.0 * (n + 1) as f32 * t).sin())
+                    .sum()
+            },
+            2e-3,
+        );
+    }
+
+    #[test]
+    fn test_partials_above_nyquist_are_dropped() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let mut oscillator = AdditiveOscillator::new(2);
+        oscillator.frequency().set_value(15000.0);
+        oscillator.set_amplitudes(&[0.0, 1.0]);
+        let samples = render(&mut oscillator, &context, 1000);
+        assert!(samples.iter().all(|s| s.abs() < 1e-6));
+
+        // At 10kHz the second partial is below Nyquist again
+        oscillator.frequency().set_value(10000.0);
+        let peak = (1000..2000)
+            .map(|sample| oscillator.process(&context, sample).abs())
+            .fold(0.0f32, f32::max);
+        assert!(peak > 0.99, "Expected a full-scale partial, got {}", peak);
+    }
+
+    #[test]
+    fn test_partial_count() {
+        let mut oscillator = AdditiveOscillator::new(10_000);
+        assert_eq!(oscillator.partial_count(), MAX_PARTIALS);
+        oscillator.set_partial_count(3);
+        assert_eq!(oscillator.partial_count(), 3);
+        oscillator.set_partial_count(5);
+        assert_eq!(oscillator.partial(4).ratio().get_value(0), 5.0);
+    }
+}
@@ -0,0 +1,116 @@
+use cpal_synth::{
+    AnalyserNode, AnalyserWindow, AudioContext, AudioNode, Oscillator, OscillatorType,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    // An analyser fed by a sine at a bin centre, run for `length` samples. Returns
+    // the reading side and what came out of the graph copy.
+    fn analyse_sine(frequency: f32, length: usize) -> (AnalyserNode, Vec<f32>) {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let oscillator = Oscillator::new(OscillatorType::Sine).unwrap();
+        oscillator.frequency().set_value(frequency);
+        let analyser = AnalyserNode::new();
+        let mut processed = analyser.clone();
+        processed.connect_input("input", Box::new(oscillator));
+        let output = (0..length as u64)
+            .map(|sample| processed.process(&context, sample))
+            .collect();
+        (analyser, output)
+    }
+
+    #[test]
+    fn test_passes_audio_through_and_records_it() {
+        let (mut analyser, output) = analyse_sine(1000.0, 5000);
+
+        let mut reference = Oscillator::new(OscillatorType::Sine).unwrap();
+        reference.frequency().set_value(1000.0);
+        let context = AudioContext::new(SAMPLE_RATE);
+        for (sample, &value) in output.iter().enumerate() {
+            assert_eq!(value, reference.process(&context, sample as u64));
+        }
+
+        let mut scope = vec![0.0; 2048];
+        analyser.get_float_time_domain_data(&mut scope);
+        assert_eq!(&scope[..], &output[output.len() - 2048..]);
+
+        // Before enough audio has arrived the start of the frame is silent
+        let (mut analyser, output) = analyse_sine(1000.0, 100);
+        analyser.get_float_time_domain_data(&mut scope);
+        assert!(scope[..1948].iter().all(|&x| x == 0.0));
+        assert_eq!(&scope[1948..], &output[..]);
+    }
+
+    #[test]
+    fn test_frequency_data_peaks_at_the_tone() {
+        // 1500Hz is bin 64 of a 2048-point frame at 48kHz
+        let (mut analyser, _) = analyse_sine(1500.0, 4096);
+        analyser.set_smoothing_time_constant(0.0).unwrap();
+        let mut spectrum = vec![0.0; analyser.frequency_bin_count()];
+        analyser.get_float_frequency_data(&mut spectrum);
+
+        let peak = (0..spectrum.len())
+            .max_by(|&a, &b| spectrum[a].partial_cmp(&spectrum[b]).unwrap())
+            .unwrap();
+        assert_eq!(peak, 64);
+        // Half the amplitude per side, times the Blackman window's mean of 0.42
+        let expected = 20.0 * (0.5f32 * 0.42).log10();
+        assert!(
+            (spectrum[64] - expected).abs() < 0.1,
+            "peak {} dB",
+            spectrum[64]
+        );
+        assert!(spectrum[200] < expected - 80.0);
+
+        // A rectangular window gets the full half amplitude
+        analyser.set_window(AnalyserWindow::Rectangular);
+        analyser.get_float_frequency_data(&mut spectrum);
+        assert!((spectrum[64] - 20.0 * 0.5f32.log10()).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_smoothing_blends_successive_spectra() {
+        let (mut analyser, _) = analyse_sine(1500.0, 4096);
+        analyser.set_smoothing_time_constant(0.5).unwrap();
+        let mut spectrum = vec![0.0; 128];
+
+        // Starting from silence, each read closes half the remaining gap
+        let full = 0.5f32 * 0.42;
+        for reads in 1..=3 {
+            analyser.get_float_frequency_data(&mut spectrum);
+            let expected = 20.0 * (full * (1.0 - 0.5f32.powi(reads))).log10();
+            assert!((spectrum[64] - expected).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn test_byte_data_and_settings() {
+        let (mut analyser, _) = analyse_sine(1500.0, 4096);
+        analyser.set_smoothing_time_constant(0.0).unwrap();
+        analyser.set_decibel_range(-40.0, 0.0).unwrap();
+        let mut bytes = vec![0u8; 1024];
+        analyser.get_byte_frequency_data(&mut bytes);
+
+        // -13.6dB is two thirds of the way up a -40 to 0 range
+        let expected = (255.0 * (40.0 + 20.0 * (0.5f32 * 0.42).log10()) / 40.0) as i32;
+        assert!((bytes[64] as i32 - expected).abs() <= 1, "{}", bytes[64]);
+        assert_eq!(bytes[400], 0);
+
+        assert!(analyser.set_fft_size(1000).is_err());
+        assert!(analyser.set_fft_size(16).is_err());
+        assert!(analyser.set_fft_size(65536).is_err());
+        assert!(analyser.set_smoothing_time_constant(1.5).is_err());
+        assert!(analyser.set_decibel_range(-10.0, -20.0).is_err());
+
+        analyser.set_fft_size(256).unwrap();
+        assert_eq!(analyser.frequency_bin_count(), 128);
+        let mut spectrum = vec![0.0; 128];
+        analyser.get_float_frequency_data(&mut spectrum);
+        // 1500Hz is bin 8 at the smaller size
+        assert!(spectrum[8] > spectrum[20] + 40.0);
+    }
+}
@@ -0,0 +1,128 @@
+use cpal_synth::{
+    AudioBackend, AudioGraph, BackendConfig, NullBackend, Oscillator, OscillatorType, PullBackend,
+};
+use std::time::Duration;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    fn sine_graph(backend: Box<dyn AudioBackend>) -> AudioGraph {
+        let mut graph = AudioGraph::with_backend(backend).unwrap();
+        let oscillator = Oscillator::new(OscillatorType::Sine).unwrap();
+        oscillator.frequency().set_value(1000.0);
+        graph.add_node("osc", Box::new(oscillator));
+        graph.set_output("osc");
+        graph
+    }
+
+    #[test]
+    fn test_pull_backend_renders_the_output() {
+        let backend = PullBackend::new(SAMPLE_RATE);
+        let mut graph = sine_graph(Box::new(backend.clone()));
+        assert_eq!(graph.backend().name(), "pull");
+        assert_eq!(graph.context.sample_rate(), SAMPLE_RATE);
+
+        // Nothing to pull from until the graph starts
+        let mut buffer = vec![1.0; 256];
+        assert!(!backend.pull(&mut buffer));
+        assert!(buffer.iter().all(|&x| x == 0.0));
+
+        graph.start(None).unwrap();
+        assert!(backend.pull(&mut buffer));
+        assert_eq!(graph.context.current_sample(), 128);
+        // Both channels carry the mono oscillator
+        for frame in buffer.chunks(2) {
+            assert_eq!(frame[0], frame[1]);
+        }
+        assert!(buffer.iter().any(|&x| x.abs() > 0.5));
+        assert_eq!(graph.stats().callbacks, 1);
+
+        graph.stop();
+        assert!(!backend.pull(&mut buffer));
+        assert_eq!(graph.context.current_sample(), 128);
+    }
+
+    #[test]
+    fn test_backend_config_is_applied() {
+        let backend = PullBackend::new(SAMPLE_RATE);
+        let config = BackendConfig {
+            sample_rate: Some(22050.0),
+            buffer_size: Some(64),
+            channels: Some(1),
+            ..Default::default()
+        };
+        let mut graph = AudioGraph::with_backend_config(Box::new(backend.clone()), config).unwrap();
+        assert_eq!(graph.context.sample_rate(), 22050.0);
+        assert_eq!(graph.backend().buffer_size(), Some(64));
+        assert_eq!(graph.backend().channels(), 1);
+
+        graph.add_node(
+            "osc",
+            Box::new(Oscillator::new(OscillatorType::Square).unwrap()),
+        );
+        graph.set_output("osc");
+        graph.start(Some(128)).unwrap();
+        assert_eq!(graph.backend().buffer_size(), Some(128));
+        let mut buffer = vec![0.0; 100];
+        backend.pull(&mut buffer);
+        assert_eq!(graph.context.current_sample(), 100);
+    }
+
+    #[test]
+    fn test_null_backend_advances_in_real_time() {
+        let config = BackendConfig {
+            sample_rate: Some(SAMPLE_RATE),
+            buffer_size: Some(480),
+            ..Default::default()
+        };
+        let mut graph =
+            AudioGraph::with_backend_config(Box::new(NullBackend::new()), config).unwrap();
+        graph.add_node(
+            "osc",
+            Box::new(Oscillator::new(OscillatorType::Sine).unwrap()),
+        );
+        graph.set_output("osc");
+        graph.start(None).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        graph.stop();
+
+        // About ten 10ms buffers, allowing for a slow machine
+        let rendered = graph.context.current_sample();
+        assert!(rendered >= 480, "rendered {} samples", rendered);
+        assert!(rendered <= 480 * 20, "rendered {} samples", rendered);
+        assert_eq!(rendered % 480, 0);
+
+        // Nothing more is rendered once stopped
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(graph.context.current_sample(), rendered);
+    }
+
+    #[test]
+    fn test_switching_backends_while_playing() {
+        let first = PullBackend::new(SAMPLE_RATE);
+        let mut graph = sine_graph(Box::new(first.clone()));
+        graph.start(None).unwrap();
+        let mut buffer = vec![0.0; 64];
+        first.pull(&mut buffer);
+
+        let second = PullBackend::new(SAMPLE_RATE);
+        graph.set_backend(Box::new(second.clone())).unwrap();
+        assert!(!first.is_started());
+        assert!(second.is_started());
+
+        // The same context carries on, so the oscillator doesn't restart
+        let context = graph.context.clone();
+        second.pull(&mut buffer);
+        assert_eq!(context.current_sample(), 64);
+
+        // A different rate needs a new context
+        graph
+            .set_backend(Box::new(PullBackend::new(44100.0)))
+            .unwrap();
+        assert_eq!(graph.context.sample_rate(), 44100.0);
+        assert_eq!(graph.backend().name(), "pull");
+    }
+}
@@ -0,0 +1,87 @@
+use cpal_synth::{AudioBuffer, AudioContext, AudioNode, BufferSourceNode};
+use std::sync::Arc;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Arc<AudioContext> {
+        Arc::new(AudioContext::new(1000.0))
+    }
+
+    // Ramp 0, 1, 2, ... on the left channel, its negation on the right
+    fn ramp_buffer(length: usize) -> Arc<AudioBuffer> {
+        let left: Vec<f32> = (0..length).map(|i| i as f32).collect();
+        let right: Vec<f32> = left.iter().map(|x| -x).collect();
+        Arc::new(AudioBuffer::from_channels(vec![left, right], 1000.0).unwrap())
+    }
+
+    #[test]
+    fn test_scheduled_playback() {
+        let context = setup();
+        let mut source = BufferSourceNode::new(Some(ramp_buffer(8)));
+        source.start(10, 0.002);
+
+        let mut outputs = Vec::new();
+        for sample in 0..20 {
+            outputs.push(source.process_stereo(&context, sample));
+        }
+
+        for (sample, &(left, right)) in outputs.iter().enumerate() {
+            let expected = if (10..16).contains(&sample) {
+                (sample - 8) as f32
+            } else {
+                0.0
+            };
+            assert_eq!(left, expected, "Sample {}: wrong left output", sample);
+            assert_eq!(right, -expected, "Sample {}: wrong right output", sample);
+        }
+        assert!(!source.is_playing(), "Source should end with the buffer");
+    }
+
+    #[test]
+    fn test_rate_detune_and_loop() {
+        let context = setup();
+        let mut source = BufferSourceNode::new(Some(ramp_buffer(16)));
+        // One octave up from the detune, half speed from the rate: net rate 1.0
+        source.detune().set_value(1200.0);
+        source.playback_rate().set_value(0.5);
+        source.set_loop(true);
+        source.set_loop_points(0.004, 0.008);
+        source.start(0, 0.0);
+
+        let outputs: Vec<f32> = (0..12)
+            .map(|sample| source.process_stereo(&context, sample).0)
+            .collect();
+        let expected = [0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 4.0, 5.0, 6.0, 7.0];
+        for (sample, (&output, &expected)) in outputs.iter().zip(&expected).enumerate() {
+            assert!(
+                (output - expected).abs() < 1e-4,
+                "Sample {}: Expected {}, got {}",
+                sample,
+                expected,
+                output
+            );
+        }
+
+        source.stop(12);
+        assert_eq!(source.process(&context, 12), 0.0);
+        assert!(!source.is_playing());
+    }
+
+    #[test]
+    fn test_gate_retriggers_with_velocity() {
+        let context = setup();
+        let source = BufferSourceNode::new(Some(ramp_buffer(32)));
+        source.set_parameter_at("gate", 0.5, 3);
+        source.set_parameter_at("gate", 0.0, 6);
+        source.set_parameter_at("gate", 1.0, 8);
+
+        let mut source = source;
+        let outputs: Vec<f32> = (0..11)
+            .map(|sample| source.process_stereo(&context, sample).0)
+            .collect();
+        let expected = [0.0, 0.0, 0.0, 0.0, 0.5, 1.0, 1.5, 2.0, 0.0, 1.0, 2.0];
+        assert_eq!(outputs, expected);
+    }
+}
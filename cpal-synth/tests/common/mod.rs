@@ -0,0 +1,36 @@
+// Helpers shared by the integration tests; not every test file uses all of them
+#![allow(dead_code)]
+
+use cpal_synth::{AudioContext, AudioNode};
+use std::f32::consts::PI;
+
+pub const SAMPLE_RATE: f32 = 44100.0;
+
+// Processes `node` from sample 0 for `length` samples
+pub fn render(node: &mut dyn AudioNode, context: &AudioContext, length: usize) -> Vec<f32> {
+    (0..length as u64)
+        .map(|sample| node.process(context, sample))
+        .collect()
+}
+
+// Amplitude of the partial at `frequency` in audio at `SAMPLE_RATE`, from a
+// Hann-windowed DFT
+pub fn amplitude(samples: &[f32], frequency: f32) -> f32 {
+    partial(samples, frequency, SAMPLE_RATE).0
+}
+
+// Amplitude and phase of the partial at `frequency` in audio at `sample_rate`,
+// from a Hann-windowed DFT with time measured from the window start
+pub fn partial(samples: &[f32], frequency: f32, sample_rate: f32) -> (f32, f32) {
+    let step = 2.0 * PI as f64 * frequency as f64 / sample_rate as f64;
+    let (mut re, mut im, mut window_sum) = (0.0f64, 0.0f64, 0.0f64);
+    for (i, &x) in samples.iter().enumerate() {
+        let window = 0.5 - 0.5 * (2.0 * PI as f64 * i as f64 / samples.len() as f64).cos();
+        let (s, c) = (step * i as f64).sin_cos();
+        re += x as f64 * window * c;
+        im -= x as f64 * window * s;
+        window_sum += window;
+    }
+    let amplitude = 2.0 * (re * re + im * im).sqrt() / window_sum;
+    (amplitude as f32, im.atan2(re) as f32)
+}
@@ -0,0 +1,220 @@
+mod common;
+
+use common::partial;
+use cpal_synth::{decode_audio_data, resample, AudioContext, AudioFileFormat};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Builds a WAV file around raw sample bytes, optionally as WAVE_FORMAT_EXTENSIBLE
+    // and with an odd-sized chunk before the data to exercise padding
+    fn wav_bytes(
+        format_tag: u16,
+        channels: u16,
+        sample_rate: u32,
+        bits: u16,
+        data: &[u8],
+        extensible: bool,
+    ) -> Vec<u8> {
+        let block_align = channels * bits.div_ceil(8);
+        let mut fmt = Vec::new();
+        let tag = if extensible { 0xFFFE } else { format_tag };
+        fmt.extend_from_slice(&tag.to_le_bytes());
+        fmt.extend_from_slice(&channels.to_le_bytes());
+        fmt.extend_from_slice(&sample_rate.to_le_bytes());
+        fmt.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+        fmt.extend_from_slice(&block_align.to_le_bytes());
+        fmt.extend_from_slice(&bits.to_le_bytes());
+        if extensible {
+            fmt.extend_from_slice(&22u16.to_le_bytes());
+            fmt.extend_from_slice(&bits.to_le_bytes());
+            fmt.extend_from_slice(&0u32.to_le_bytes());
+            fmt.extend_from_slice(&format_tag.to_le_bytes());
+            fmt.extend_from_slice(&[
+                0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71,
+            ]);
+        }
+
+        let mut body = b"WAVE".to_vec();
+        body.extend_from_slice(b"fmt ");
+        body.extend_from_slice(&(fmt.len() as u32).to_le_bytes());
+        body.extend_from_slice(&fmt);
+        body.extend_from_slice(b"LIST");
+        body.extend_from_slice(&3u32.to_le_bytes());
+        body.extend_from_slice(&[1, 2, 3, 0]);
+        body.extend_from_slice(b"data");
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(data);
+
+        let mut file = b"RIFF".to_vec();
+        file.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        file.extend_from_slice(&body);
+        file
+    }
+
+    fn fixture(name: &str) -> Vec<u8> {
+        let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+        std::fs::read(path).unwrap()
+    }
+
+    #[test]
+    fn test_wav_sample_formats() {
+        let expected = [0.0f32, 0.5, -0.5, -1.0];
+
+        let pcm8: Vec<u8> = vec![128, 192, 64, 0];
+        let pcm16: Vec<u8> = [0i16, 16384, -16384, -32768]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let pcm24: Vec<u8> = [0i32, 0x40_0000, -0x40_0000, -0x80_0000]
+            .iter()
+            .flat_map(|s| s.to_le_bytes()[0..3].to_vec())
+            .collect();
+        let pcm32: Vec<u8> = [0i32, 1 << 30, -(1 << 30), i32::MIN]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let float32: Vec<u8> = expected.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let float64: Vec<u8> = expected
+            .iter()
+            .flat_map(|&s| (s as f64).to_le_bytes())
+            .collect();
+
+        let cases = [
+            ("8-bit", 1, 8, pcm8, false),
+            ("16-bit", 1, 16, pcm16, false),
+            ("24-bit", 1, 24, pcm24, true),
+            ("32-bit", 1, 32, pcm32, false),
+            ("float32", 3, 32, float32, true),
+            ("float64", 3, 64, float64, false),
+        ];
+
+        for (name, format_tag, bits, data, extensible) in cases {
+            let file = wav_bytes(format_tag, 1, 44100, bits, &data, extensible);
+            assert_eq!(AudioFileFormat::detect(&file), Some(AudioFileFormat::Wav));
+
+            let buffer = decode_audio_data(&file).unwrap();
+            assert_eq!(buffer.number_of_channels(), 1, "{}", name);
+            assert_eq!(buffer.sample_rate(), 44100.0, "{}", name);
+            assert_eq!(buffer.channel(0), &expected, "{}: wrong samples", name);
+        }
+    }
+
+    #[test]
+    fn test_wav_stereo_deinterleave() {
+        let data: Vec<u8> = [1000i16, -1000, 2000, -2000, 3000, -3000]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let file = wav_bytes(1, 2, 48000, 16, &data, false);
+
+        let buffer = decode_audio_data(&file).unwrap();
+        assert_eq!(buffer.number_of_channels(), 2);
+        assert_eq!(buffer.length(), 3);
+        for frame in 0..3 {
+            let expected = (frame + 1) as f32 * 1000.0 / 32768.0;
+            assert_eq!(buffer.channel(0)[frame], expected);
+            assert_eq!(buffer.channel(1)[frame], -expected);
+        }
+    }
+
+    #[test]
+    fn test_flac_fixtures() {
+        // 16-bit stereo: a sawtooth on the left and half of it inverted on the right
+        let buffer = decode_audio_data(&fixture("stereo_16bit.flac")).unwrap();
+        assert_eq!(buffer.sample_rate(), 8000.0);
+        assert_eq!(buffer.number_of_channels(), 2);
+        assert_eq!(buffer.length(), 4000);
+        for i in 0..4000 {
+            let left = ((i as i32 * 37) % 4096 - 2048) * 8;
+            assert_eq!(buffer.channel(0)[i], left as f32 / 32768.0, "Frame {}", i);
+            assert_eq!(buffer.channel(1)[i], -left as f32 / 65536.0, "Frame {}", i);
+        }
+
+        // 24-bit mono at 1kHz, scaled by its own bit depth
+        let buffer = decode_audio_data(&fixture("mono_24bit.flac")).unwrap();
+        assert_eq!(buffer.sample_rate(), 1000.0);
+        assert_eq!(buffer.number_of_channels(), 1);
+        assert_eq!(buffer.length(), 3000);
+        for i in 0..3000 {
+            let sample = ((i as i32 * 1543) % 65536 - 32768) * 200;
+            assert_eq!(
+                buffer.channel(0)[i],
+                sample as f32 / 8388608.0,
+                "Frame {}",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn test_vorbis_fixture() {
+        // Stereo at 8kHz in 256-sample blocks. The left channel holds a steady tone
+        // in MDCT bin 16 for 32 blocks, then a different bin every block; the right
+        // channel is silent. The last page trims the final block by 50 samples.
+        let buffer = decode_audio_data(&fixture("stereo.ogg")).unwrap();
+        assert_eq!(buffer.sample_rate(), 8000.0);
+        assert_eq!(buffer.number_of_channels(), 2);
+        assert_eq!(buffer.length(), 128 * 63 - 50);
+        assert!(buffer.channel(1).iter().all(|&s| s == 0.0));
+
+        // The tone comes out at 500Hz with its coefficient's amplitude of 0.25
+        let steady = &buffer.channel(0)[256..3584];
+        let (tone, _) = partial(steady, 500.0, 8000.0);
+        let (off, _) = partial(steady, 1000.0, 8000.0);
+        assert!((tone - 0.25).abs() < 0.01, "Tone amplitude {}", tone);
+        assert!(off < 0.01 * tone, "{} away from the tone", off);
+    }
+
+    #[test]
+    fn test_unsupported_input() {
+        assert!(decode_audio_data(b"definitely not audio").is_err());
+        assert_eq!(
+            AudioFileFormat::detect(b"fLaC\0\0\0\x22"),
+            Some(AudioFileFormat::Flac)
+        );
+        assert_eq!(
+            AudioFileFormat::detect(b"OggS\0\x02"),
+            Some(AudioFileFormat::OggVorbis)
+        );
+
+        // 12-bit packed PCM is not a supported layout
+        let file = wav_bytes(1, 1, 44100, 12, &[0, 0], false);
+        assert!(decode_audio_data(&file).is_err());
+    }
+
+    #[test]
+    fn test_resampling_to_context_rate() {
+        let frequency = 1000.0f32;
+        let data: Vec<u8> = (0..44100)
+            .map(|i| {
+                let phase = 2.0 * std::f32::consts::PI * frequency * i as f32 / 44100.0;
+                (phase.sin() * 0.5 * 32767.0) as i16
+            })
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let file = wav_bytes(1, 1, 44100, 16, &data, false);
+
+        let context = AudioContext::new(48000.0);
+        let buffer = context.decode_audio_data(&file).unwrap();
+        assert_eq!(buffer.sample_rate(), 48000.0);
+        assert_eq!(buffer.length(), 48000);
+
+        // Away from the edges the resampled sine must match the ideal one at 48kHz
+        let max_error = (1000..47000)
+            .map(|i| {
+                let phase = 2.0 * std::f32::consts::PI * frequency * i as f32 / 48000.0;
+                (buffer.channel(0)[i] - phase.sin() * 0.5).abs()
+            })
+            .fold(0.0f32, f32::max);
+        assert!(
+            max_error < 1e-3,
+            "Resampling error too large: {}",
+            max_error
+        );
+
+        let down = resample(buffer.channel(0), 48000.0, 24000.0);
+        assert_eq!(down.len(), 24000);
+    }
+}
@@ -0,0 +1,116 @@
+#![cfg(feature = "cpal-output")]
+
+use cpal_synth::{
+    available_hosts, output_devices, AudioBackend, BackendConfig, CpalBackend, SampleFormat,
+    SupportedConfig,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stereo_range() -> SupportedConfig {
+        SupportedConfig {
+            channels: 2,
+            min_sample_rate: 44100.0,
+            max_sample_rate: 96000.0,
+            sample_format: SampleFormat::I16,
+            buffer_size_range: Some((64, 4096)),
+        }
+    }
+
+    #[test]
+    fn test_supported_config_matching() {
+        let range = stereo_range();
+        assert!(range.supports(&BackendConfig::default()));
+        assert!(range.supports(&BackendConfig {
+            sample_rate: Some(48000.0),
+            buffer_size: Some(256),
+            channels: Some(2),
+            sample_format: Some(SampleFormat::I16),
+            ..Default::default()
+        }));
+
+        for config in [
+            BackendConfig {
+                sample_rate: Some(22050.0),
+                ..Default::default()
+            },
+            BackendConfig {
+                channels: Some(1),
+                ..Default::default()
+            },
+            BackendConfig {
+                sample_format: Some(SampleFormat::F32),
+                ..Default::default()
+            },
+            BackendConfig {
+                buffer_size: Some(8192),
+                ..Default::default()
+            },
+        ] {
+            assert!(!range.supports(&config), "{:?}", config);
+        }
+
+        // Hosts that don't report buffer sizes take any
+        let unknown = SupportedConfig {
+            buffer_size_range: None,
+            ..stereo_range()
+        };
+        assert!(unknown.supports(&BackendConfig {
+            buffer_size: Some(8192),
+            ..Default::default()
+        }));
+    }
+
+    #[test]
+    fn test_supported_config_display_and_formats() {
+        assert_eq!(
+            stereo_range().to_string(),
+            "2 ch, 44100-96000 Hz, I16, 64-4096 frames"
+        );
+        let fixed = SupportedConfig {
+            min_sample_rate: 48000.0,
+            max_sample_rate: 48000.0,
+            buffer_size_range: None,
+            ..stereo_range()
+        };
+        assert_eq!(fixed.to_string(), "2 ch, 48000 Hz, I16");
+
+        assert_eq!(SampleFormat::U8.bits(), 8);
+        assert_eq!(SampleFormat::I32.bits(), 32);
+        assert_eq!(SampleFormat::F64.bits(), 64);
+        assert!(SampleFormat::F32.is_float());
+        assert!(!SampleFormat::I16.is_float());
+    }
+
+    #[test]
+    fn test_hosts_are_listed() {
+        let hosts = available_hosts();
+        assert!(!hosts.is_empty());
+        assert_eq!(hosts.iter().filter(|host| host.is_default).count(), 1);
+
+        // Every listed host can be asked for its devices by name
+        for host in &hosts {
+            let name = host.name.to_uppercase();
+            if let Ok(devices) = output_devices(Some(&name)) {
+                assert!(devices.iter().all(|device| device.host == host.name));
+                assert!(devices.iter().filter(|device| device.is_default).count() <= 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_unknown_hosts_and_devices_are_errors() {
+        let error = output_devices(Some("No Such Host")).unwrap_err();
+        assert!(error.to_string().contains("'No Such Host'"), "{}", error);
+
+        let mut backend = CpalBackend::with_device(Some("No Such Host"), None);
+        assert!(backend.open(&BackendConfig::default()).is_err());
+
+        let mut backend = CpalBackend::with_device(None, Some("No Such Device"));
+        let error = backend.open(&BackendConfig::default()).unwrap_err();
+        assert!(error.to_string().contains("'No Such Device'"), "{}", error);
+        assert_eq!(backend.buffer_size(), None);
+    }
+}
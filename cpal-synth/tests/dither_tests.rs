@@ -0,0 +1,144 @@
+use cpal_synth::{
+    decode_audio_data, encode_wav, AudioBuffer, Dither, NoiseShaping, Quantizer, WavSampleFormat,
+};
+use std::f32::consts::PI;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(amplitude: f32, frequency: f32, length: usize) -> Vec<f32> {
+        (0..length)
+            .map(|n| amplitude * (2.0 * PI * frequency * n as f32 / 48000.0).sin())
+            .collect()
+    }
+
+    // Power of a signal summed over DFT bins every 50Hz across a band
+    fn band_power(signal: &[f32], low: usize, high: usize) -> f32 {
+        (low..high)
+            .step_by(50)
+            .map(|frequency| {
+                let w = 2.0 * PI * frequency as f32 / 48000.0;
+                let (re, im) = signal
+                    .iter()
+                    .enumerate()
+                    .fold((0.0, 0.0), |(re, im), (n, &x)| {
+                        (re + x * (w * n as f32).cos(), im + x * (w * n as f32).sin())
+                    });
+                re * re + im * im
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_samples_land_on_the_integer_grid() {
+        let input: Vec<f32> = sine(1.2, 997.0, 4800);
+        for dither in [
+            Dither::off(),
+            Dither::default(),
+            Dither::shaped(NoiseShaping::Lipshitz),
+        ] {
+            let mut samples = input.clone();
+            Quantizer::new(dither, 16, 2).process(&mut samples);
+            for (&x, &q) in input.iter().zip(&samples) {
+                let steps = q * 32768.0;
+                assert_eq!(steps, steps.round());
+                assert!((-32768.0..=32767.0).contains(&steps));
+                if dither == Dither::off() && x.abs() < 0.99 {
+                    assert!((q - x).abs() <= 0.5 / 32768.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_tpdf_keeps_signals_below_one_step() {
+        // Two fifths of an 8-bit step: rounding wipes it out, dither keeps it
+        // in the noise
+        let step = 1.0 / 128.0;
+        let input = sine(0.4 * step, 375.0, 48000);
+        let reference = sine(1.0, 375.0, 48000);
+        let correlate = |samples: &[f32]| {
+            samples
+                .iter()
+                .zip(&reference)
+                .map(|(q, r)| q * r)
+                .sum::<f32>()
+                * 2.0
+                / samples.len() as f32
+        };
+
+        let mut rounded = input.clone();
+        Quantizer::new(Dither::off(), 8, 1).process(&mut rounded);
+        assert!(rounded.iter().all(|&q| q == 0.0));
+
+        let mut dithered = input.clone();
+        Quantizer::new(Dither::default(), 8, 1).process(&mut dithered);
+        let amplitude = correlate(&dithered) / step;
+        assert!((amplitude - 0.4).abs() < 0.05, "{} steps", amplitude);
+    }
+
+    #[test]
+    fn test_noise_shaping_moves_noise_out_of_the_low_band() {
+        let input = sine(0.25, 440.0, 48000);
+        let error = |dither: Dither| {
+            let mut samples = input.clone();
+            Quantizer::new(dither, 8, 1).process(&mut samples);
+            samples
+                .iter()
+                .zip(&input)
+                .map(|(q, x)| q - x)
+                .collect::<Vec<f32>>()
+        };
+        let flat = error(Dither::default());
+        for shaping in [NoiseShaping::FirstOrder, NoiseShaping::Lipshitz] {
+            let shaped = error(Dither::shaped(shaping));
+            let total = |e: &[f32]| e.iter().map(|x| x * x).sum::<f32>();
+            // Lipshitz's filter is 15dB or more down up to 5kHz, and first order
+            // is 6dB down up to 3.8kHz
+            assert!(band_power(&shaped, 200, 4000) < 0.5 * band_power(&flat, 200, 4000));
+            assert!(total(&shaped) > total(&flat));
+        }
+    }
+
+    #[test]
+    fn test_wide_formats_pass_through() {
+        let input = sine(0.5, 1000.0, 480);
+        let mut quantizer = Quantizer::new(Dither::default(), 32, 1);
+        assert!(!quantizer.is_active());
+        let mut samples = input.clone();
+        quantizer.process(&mut samples);
+        assert_eq!(samples, input);
+        assert!(Quantizer::new(Dither::default(), 24, 1).is_active());
+    }
+
+    #[test]
+    fn test_wav_export_round_trips() {
+        let left = sine(0.8, 1000.0, 4801);
+        let right = sine(-0.3, 250.0, 4801);
+        let buffer = AudioBuffer::from_channels(vec![left, right], 48000.0).unwrap();
+
+        for (format, bits, tolerance) in [
+            (WavSampleFormat::Int, 8, 1.5 / 128.0),
+            (WavSampleFormat::Int, 16, 1.5 / 32768.0),
+            (WavSampleFormat::Int, 24, 1.5 / 8_388_608.0),
+            (WavSampleFormat::Int, 32, 1e-7),
+            (WavSampleFormat::Float, 32, 0.0),
+            (WavSampleFormat::Float, 64, 0.0),
+        ] {
+            let bytes = encode_wav(&buffer, format, bits, Dither::default()).unwrap();
+            let decoded = decode_audio_data(&bytes).unwrap();
+            assert_eq!(decoded.number_of_channels(), 2);
+            assert_eq!(decoded.length(), 4801);
+            assert_eq!(decoded.sample_rate(), 48000.0);
+            for (channel, original) in decoded.channels().iter().zip(buffer.channels()) {
+                for (a, b) in channel.iter().zip(original) {
+                    assert!((a - b).abs() <= tolerance, "{:?} {} bit", format, bits);
+                }
+            }
+        }
+
+        assert!(encode_wav(&buffer, WavSampleFormat::Int, 12, Dither::off()).is_err());
+        assert!(encode_wav(&buffer, WavSampleFormat::Float, 16, Dither::off()).is_err());
+    }
+}
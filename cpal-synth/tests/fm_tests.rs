@@ -0,0 +1,120 @@
+use cpal_synth::{AudioContext, AudioNode, FmAlgorithm, FmOperator, FmVoice, OperatorMode};
+use std::f32::consts::PI;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Outputs a fixed value, standing in for a modulation source
+    #[derive(Clone)]
+    struct Constant(f32);
+
+    impl AudioNode for Constant {
+        fn process(&mut self, _context: &AudioContext, _current_sample: u64) -> f32 {
+            self.0
+        }
+        fn set_parameter(&self, _name: &str, _value: f32) {}
+        fn connect_input(&mut self, _name: &str, _node: Box<dyn AudioNode + Send>) {}
+        fn clear_input(&mut self, _input_name: &str) {}
+        fn clone_box(&self) -> Box<dyn AudioNode + Send> {
+            Box::new(self.clone())
+        }
+    }
+
+    fn assert_close(node: &mut dyn AudioNode, expected: impl Fn(f32) -> f32) {
+        let context = AudioContext::new(44100.0);
+        for sample in 0..2000u64 {
+            let t = sample as f32 / 44100.0;
+            let output = node.process(&context, sample);
+            assert!(
+                (output - expected(t)).abs() < 2e-3,
+                "Sample {}: expected {}, got {}",
+                sample,
+                expected(t),
+                output
+            );
+        }
+    }
+
+    #[test]
+    fn test_operator_is_a_sine() {
+        let context = AudioContext::new(44100.0);
+        let mut operator = FmOperator::new(&context).unwrap();
+        operator.frequency().set_value(220.0);
+        operator.ratio().set_value(2.0);
+        assert_close(&mut operator, |t| (2.0 * PI * 440.0 * t).sin());
+
+        // Fixed mode ignores the ratio
+        let mut operator = FmOperator::new(&context).unwrap();
+        operator.set_mode(OperatorMode::Fixed);
+        operator.frequency().set_value(300.0);
+        operator.ratio().set_value(3.0);
+        assert_close(&mut operator, |t| (2.0 * PI * 300.0 * t).sin());
+    }
+
+    #[test]
+    fn test_phase_and_through_zero_modulation() {
+        let context = AudioContext::new(44100.0);
+        let modulator = FmOperator::new(&context).unwrap();
+        modulator.frequency().set_value(110.0);
+        modulator.gain().set_value(0.5);
+        let mut carrier = FmOperator::new(&context).unwrap();
+        carrier.frequency().set_value(440.0);
+        carrier.index().set_value(3.0);
+        carrier.connect_input("pm", Box::new(modulator));
+        assert_close(&mut carrier, |t| {
+            (2.0 * PI * 440.0 * t + 1.5 * (2.0 * PI * 110.0 * t).sin()).sin()
+        });
+
+        // An FM input of -2 takes the frequency through zero to -440Hz
+        let mut carrier = FmOperator::new(&context).unwrap();
+        carrier.frequency().set_value(440.0);
+        carrier.connect_input("fm", Box::new(Constant(-2.0)));
+        assert_close(&mut carrier, |t| -(2.0 * PI * 440.0 * t).sin());
+    }
+
+    #[test]
+    fn test_feedback_stays_bounded() {
+        let context = AudioContext::new(44100.0);
+        let mut operator = FmOperator::new(&context).unwrap();
+        operator.frequency().set_value(440.0);
+        operator.feedback().set_value(1.0);
+
+        let mut differs = false;
+        for sample in 0..44100u64 {
+            let output = operator.process(&context, sample);
+            assert!(output.is_finite() && output.abs() <= 1.001);
+            let sine = (2.0 * PI * 440.0 * sample as f32 / 44100.0).sin();
+            differs |= (output - sine).abs() > 0.1;
+        }
+        assert!(differs, "Feedback should change the waveform");
+    }
+
+    #[test]
+    fn test_algorithms() {
+        assert!(FmAlgorithm::four_operator(0).is_err());
+        assert!(FmAlgorithm::four_operator(9).is_err());
+        // Operators can only be modulated from above
+        assert!(FmAlgorithm::new(vec![vec![], vec![0]], vec![0]).is_err());
+        assert!(FmAlgorithm::new(vec![vec![1], vec![]], vec![2]).is_err());
+
+        let stack = FmAlgorithm::four_operator(1).unwrap();
+        assert_eq!(stack.operator_count(), 4);
+        assert_eq!(stack.carriers(), &[0]);
+        assert_eq!(stack.modulators(2), &[3]);
+        assert_eq!(FmAlgorithm::four_operator(8).unwrap().carriers().len(), 4);
+
+        // With the top two operators silent the stack is a single modulator pair
+        let context = AudioContext::new(44100.0);
+        let mut voice = FmVoice::new(stack, &context).unwrap();
+        voice.frequency().set_value(200.0);
+        voice.set_parameter("op1.ratio", 3.0);
+        voice.set_parameter("op1.gain", 0.8);
+        voice.set_parameter("op2.gain", 0.0);
+        voice.set_parameter("op3.gain", 0.0);
+        assert_eq!(voice.operator(1).ratio().get_value(0), 3.0);
+        assert_close(&mut voice, |t| {
+            (2.0 * PI * 200.0 * t + 0.8 * (2.0 * PI * 600.0 * t).sin()).sin()
+        });
+    }
+}
@@ -0,0 +1,120 @@
+use cpal_synth::{AudioBuffer, AudioContext, AudioNode, GrainWindow, GranularNode};
+use std::sync::Arc;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mono_buffer(samples: Vec<f32>) -> Arc<AudioBuffer> {
+        Arc::new(AudioBuffer::from_channels(vec![samples], 1000.0).unwrap())
+    }
+
+    #[test]
+    fn test_window_shapes() {
+        let hann = GrainWindow::Hann;
+        assert!(hann.gain(0.0).abs() < 1e-6);
+        assert!((hann.gain(0.5) - 1.0).abs() < 1e-6);
+        assert!((hann.gain(0.25) - 0.5).abs() < 1e-6);
+
+        // Tapers over the outer 25% on each side
+        let tukey = GrainWindow::Tukey(0.5);
+        assert!(tukey.gain(0.0).abs() < 1e-6);
+        assert!((tukey.gain(0.125) - 0.5).abs() < 1e-6);
+        assert_eq!(tukey.gain(0.3), 1.0);
+        assert_eq!(tukey.gain(0.7), 1.0);
+
+        let trapezoid = GrainWindow::Trapezoid(0.2);
+        assert!((trapezoid.gain(0.05) - 0.5).abs() < 1e-6);
+        assert!((trapezoid.gain(0.95) - 0.5).abs() < 1e-6);
+        assert_eq!(trapezoid.gain(0.5), 1.0);
+    }
+
+    #[test]
+    fn test_single_grain_window_and_pitch() {
+        let context = AudioContext::new(1000.0);
+        let ramp: Vec<f32> = (0..1000).map(|i| i as f32).collect();
+        let mut granular = GranularNode::new(Some(mono_buffer(ramp)), 8);
+        granular.set_window(GrainWindow::Trapezoid(0.0));
+        // One 20-sample grain per second, an octave up, hard left
+        granular.density().set_value(1.0);
+        granular.grain_size().set_value(0.02);
+        granular.pitch().set_value(12.0);
+        granular.position().set_value(0.1);
+        granular.pan().set_value(-1.0);
+
+        let outputs: Vec<(f32, f32)> = (0..40)
+            .map(|sample| granular.process_stereo(&context, sample))
+            .collect();
+        for (sample, &(left, right)) in outputs.iter().enumerate() {
+            let expected = if sample < 20 {
+                100.0 + 2.0 * sample as f32
+            } else {
+                0.0
+            };
+            assert!(
+                (left - expected).abs() < 1e-3,
+                "Sample {}: Expected {}, got {}",
+                sample,
+                expected,
+                left
+            );
+            assert!(
+                right.abs() < 1e-3,
+                "Sample {}: right should be silent",
+                sample
+            );
+        }
+        assert_eq!(granular.active_grains(), 0);
+    }
+
+    #[test]
+    fn test_grain_pool_is_bounded() {
+        let context = AudioContext::new(1000.0);
+        let noise: Vec<f32> = (0..1000)
+            .map(|i| ((i * 7919) % 200) as f32 / 100.0 - 1.0)
+            .collect();
+        let mut granular = GranularNode::new(Some(mono_buffer(noise)), 4);
+        granular.set_window(GrainWindow::Tukey(0.5));
+        granular.set_seed(7);
+        // Long, dense grains would need far more than four voices
+        granular.density().set_value(500.0);
+        granular.grain_size().set_value(0.5);
+        granular.spray().set_value(0.2);
+        granular.jitter().set_value(0.5);
+        granular.position().set_value(0.5);
+
+        for sample in 0..2000 {
+            let (left, right) = granular.process_stereo(&context, sample);
+            assert!(left.is_finite() && right.is_finite());
+            assert!(granular.active_grains() <= granular.max_grains());
+        }
+        assert_eq!(granular.active_grains(), 4);
+    }
+
+    #[test]
+    fn test_stereo_source_panning() {
+        let context = AudioContext::new(1000.0);
+        let buffer = Arc::new(
+            AudioBuffer::from_channels(vec![vec![1.0; 100], vec![0.5; 100]], 1000.0).unwrap(),
+        );
+        let mut granular = GranularNode::new(Some(buffer), 2);
+        granular.set_window(GrainWindow::Trapezoid(0.0));
+        granular.density().set_value(1.0);
+        granular.grain_size().set_value(0.05);
+        granular.position().set_value(0.2);
+
+        // Centered, the two channels pass through unchanged
+        let (left, right) = granular.process_stereo(&context, 0);
+        assert!((left - 1.0).abs() < 1e-6 && (right - 0.5).abs() < 1e-6);
+
+        // Pan is taken when a grain starts, so wait for the next one. Hard right
+        // folds the left channel into the right.
+        for sample in 1..999 {
+            granular.process_stereo(&context, sample);
+        }
+        granular.set_parameter("pan", 1.0);
+        let (left, right) = granular.process_stereo(&context, 999);
+        assert!(left.abs() < 1e-6, "Left should be silent, got {}", left);
+        assert!((right - 1.5).abs() < 1e-6, "Expected 1.5, got {}", right);
+    }
+}
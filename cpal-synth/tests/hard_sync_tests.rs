@@ -0,0 +1,220 @@
+mod common;
+
+use common::{amplitude, render, SAMPLE_RATE};
+use cpal_synth::{
+    AudioContext, AudioNode, BandlimitedWavetableOscillator, Oscillator, OscillatorType,
+};
+use std::f32::consts::PI;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MASTER: f32 = 440.0;
+    const SLAVE: f32 = 1234.5;
+
+    // Summed level of the partials between the harmonics of a 440Hz sync master.
+    // 44.1kHz is 100 above a multiple of 440, so that's where aliases fold to.
+    fn alias_level(samples: &[f32]) -> f32 {
+        (0..20)
+            .map(|m| amplitude(samples, 100.0 + MASTER * m as f32))
+            .sum()
+    }
+
+    fn master() -> Box<dyn AudioNode + Send> {
+        let master = Oscillator::new(OscillatorType::Sine).unwrap();
+        master.frequency().set_value(MASTER);
+        Box::new(master)
+    }
+
+    #[test]
+    fn test_reset_phase() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        for osc_type in [OscillatorType::Sine, OscillatorType::Sawtooth] {
+            let reset = Oscillator::new(osc_type).unwrap();
+            let reference = Oscillator::new(osc_type).unwrap();
+            let table = BandlimitedWavetableOscillator::new(osc_type, &context).unwrap();
+            let table_reference = BandlimitedWavetableOscillator::new(osc_type, &context).unwrap();
+            let mut nodes: Vec<Box<dyn AudioNode + Send>> = vec![
+                Box::new(reset),
+                Box::new(reference),
+                Box::new(table),
+                Box::new(table_reference),
+            ];
+            for node in &nodes {
+                node.set_parameter("frequency", 441.0);
+            }
+            nodes[0].set_parameter_at("phase", 0.25, 137);
+            nodes[2].set_parameter_at("phase", 0.25, 137);
+
+            // A quarter cycle is 25 samples, so after the reset each oscillator
+            // matches its reference 112 samples behind
+            let outputs: Vec<Vec<f32>> = nodes
+                .iter_mut()
+                .map(|node| {
+                    (0..400)
+                        .map(|sample| node.process(&context, sample))
+                        .collect()
+                })
+                .collect();
+            for n in 138..400 {
+                for (reset, reference) in [(0, 1), (2, 3)] {
+                    assert!(
+                        (outputs[reset][n] - outputs[reference][n - 112]).abs() < 1e-3,
+                        "{:?} sample {}: expected {}, got {}",
+                        osc_type,
+                        n,
+                        outputs[reference][n - 112],
+                        outputs[reset][n]
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_resets_queue_up() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let mut reset = Oscillator::new(OscillatorType::Sine).unwrap();
+        let mut reference = Oscillator::new(OscillatorType::Sine).unwrap();
+        reset.set_parameter("frequency", 441.0);
+        reference.set_parameter("frequency", 441.0);
+        // Scheduled out of order, and neither replaces the other
+        reset.set_parameter_at("phase", 0.5, 200);
+        reset.set_parameter_at("phase", 0.0, 100);
+
+        let output: Vec<f32> = (0..300).map(|n| reset.process(&context, n)).collect();
+        let expected: Vec<f32> = (0..300).map(|n| reference.process(&context, n)).collect();
+        for (n, &sample) in output.iter().enumerate().skip(101) {
+            // Half a cycle is 50 samples
+            let offset = if n < 200 { n - 100 } else { n - 200 + 50 };
+            assert!(
+                (sample - expected[offset]).abs() < 1e-3,
+                "Sample {}: expected {}, got {}",
+                n,
+                expected[offset],
+                sample
+            );
+        }
+    }
+
+    #[test]
+    fn test_sync_follows_master_period() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let mut slave = Oscillator::new(OscillatorType::Sawtooth).unwrap();
+        slave.frequency().set_value(SLAVE);
+        // A master at exactly 100 samples per cycle
+        let master = Oscillator::new(OscillatorType::Sine).unwrap();
+        master.frequency().set_value(441.0);
+        slave.connect_input("sync", Box::new(master));
+
+        let samples: Vec<f32> = (0..1000)
+            .map(|sample| slave.process(&context, sample))
+            .collect();
+        for n in 200..900 {
+            assert!(
+                (samples[n] - samples[n + 100]).abs() < 1e-2,
+                "Synced output isn't periodic at sample {}",
+                n
+            );
+        }
+
+        // Without sync it runs free again
+        slave.clear_input("sync");
+        let free: Vec<f32> = (1000..1200)
+            .map(|sample| slave.process(&context, sample))
+            .collect();
+        assert!((0..100).any(|n| (free[n] - free[n + 100]).abs() > 0.1));
+    }
+
+    #[test]
+    fn test_sync_follows_master_phase() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let length = 4410;
+
+        // A sawtooth master crosses zero going up halfway through its cycle, so
+        // resetting there would put the slave half a master cycle out. The slave
+        // is a sine, which has no edges of its own to band-limit.
+        let mut master_phase = 0.0f32;
+        let mut phase = 0.0f32;
+        let naive: Vec<f32> = (0..length)
+            .map(|_| {
+                let output = (2.0 * PI * phase).sin();
+                phase = (phase + SLAVE / SAMPLE_RATE).fract();
+                master_phase += MASTER / SAMPLE_RATE;
+                // Restart as far into the cycle as the master is into its own
+                if master_phase >= 1.0 {
+                    master_phase -= 1.0;
+                    phase = master_phase * SLAVE / MASTER;
+                }
+                output
+            })
+            .collect();
+
+        let slave = Oscillator::new(OscillatorType::Sine).unwrap();
+        slave.frequency().set_value(SLAVE);
+        let table_slave =
+            BandlimitedWavetableOscillator::new(OscillatorType::Sine, &context).unwrap();
+        table_slave.frequency().set_value(SLAVE);
+        // The table sine starts out going down
+        let slaves: [(Box<dyn AudioNode + Send>, f32); 2] =
+            [(Box::new(slave), 1.0), (Box::new(table_slave), -1.0)];
+        for (mut slave, sign) in slaves {
+            let master = Oscillator::new(OscillatorType::Sawtooth).unwrap();
+            master.frequency().set_value(MASTER);
+            slave.connect_input("sync", Box::new(master));
+
+            // The synced output runs a sample late
+            let output = render(slave.as_mut(), &context, length + 1);
+            let error = (0..length)
+                .map(|n| (output[n + 1] - sign * naive[n]).abs())
+                .sum::<f32>()
+                / length as f32;
+            println!("err {}", error);
+            assert!(error < 0.02, "Mean error {}", error);
+        }
+    }
+
+    #[test]
+    fn test_sync_is_band_limited() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let length = 16384;
+
+        // Naive hard sync for reference: a raw sawtooth restarted at each master cycle
+        let mut phase = 0.0f32;
+        let mut master_phase = 0.0f32;
+        let naive: Vec<f32> = (0..length)
+            .map(|_| {
+                let output = 2.0 * phase - 1.0;
+                phase = (phase + SLAVE / SAMPLE_RATE).fract();
+                master_phase += MASTER / SAMPLE_RATE;
+                if master_phase >= 1.0 {
+                    master_phase -= 1.0;
+                    phase = 0.0;
+                }
+                output
+            })
+            .collect();
+
+        let mut slave = Oscillator::new(OscillatorType::Sawtooth).unwrap();
+        slave.frequency().set_value(SLAVE);
+        slave.connect_input("sync", master());
+        let mut table_slave =
+            BandlimitedWavetableOscillator::new(OscillatorType::Sawtooth, &context).unwrap();
+        table_slave.frequency().set_value(SLAVE);
+        table_slave.connect_input("sync", master());
+
+        let (direct, table): (Vec<f32>, Vec<f32>) = (0..length as u64)
+            .map(|sample| {
+                (
+                    slave.process(&context, sample),
+                    table_slave.process(&context, sample),
+                )
+            })
+            .unzip();
+
+        let naive_aliases = alias_level(&naive);
+        assert!(alias_level(&direct) < naive_aliases * 0.25);
+        assert!(alias_level(&table) < naive_aliases * 0.25);
+    }
+}
@@ -0,0 +1,172 @@
+use cpal_synth::{
+    AudioBuffer, AudioContext, AudioGraph, AudioNode, BufferInput, InputWriter, LiveInputNode,
+};
+use std::f32::consts::PI;
+use std::time::Duration;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    fn sine(frequency: f32, sample_rate: f32, start: usize, frames: usize) -> Vec<f32> {
+        (start..start + frames)
+            .map(|n| (2.0 * PI * frequency * n as f32 / sample_rate).sin())
+            .collect()
+    }
+
+    // Frequency from rising zero crossings, to a fraction of a cycle
+    fn measure_frequency(signal: &[f32], sample_rate: f32) -> f32 {
+        let crossings: Vec<f32> = signal
+            .windows(2)
+            .enumerate()
+            .filter(|(_, pair)| pair[0] < 0.0 && pair[1] >= 0.0)
+            .map(|(n, pair)| n as f32 + pair[0] / (pair[0] - pair[1]))
+            .collect();
+        let cycles = (crossings.len() - 1) as f32;
+        cycles * sample_rate / (crossings[crossings.len() - 1] - crossings[0])
+    }
+
+    // Feeds a sine from a clock at `input_rate` while pulling `frames` of output,
+    // as a device would
+    fn play_sine(
+        node: &mut LiveInputNode,
+        writer: &mut InputWriter,
+        context: &AudioContext,
+        input_rate: f32,
+        frames: usize,
+    ) -> Vec<f32> {
+        let mut output = Vec::with_capacity(frames);
+        let mut written = 0;
+        while output.len() < frames {
+            let due =
+                ((output.len() + 128) as f64 * input_rate as f64 / SAMPLE_RATE as f64) as usize;
+            writer.write(&sine(1000.0, input_rate, written, due - written));
+            written = due;
+            for _ in 0..128 {
+                output.push(node.process(context, context.current_sample()));
+                context.increment_samples(1);
+            }
+        }
+        output
+    }
+
+    #[test]
+    fn test_waits_for_the_latency_then_plays() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let (mut node, mut writer) = LiveInputNode::with_writer(1, SAMPLE_RATE, &context);
+        node.set_latency(0.01);
+
+        // Short of the 480-frame target: silence
+        writer.write(&sine(1000.0, SAMPLE_RATE, 0, 400));
+        assert_eq!(node.process(&context, 0), 0.0);
+        assert!(!node.status().rolling);
+
+        writer.write(&sine(1000.0, SAMPLE_RATE, 400, 80));
+        let output = play_sine(&mut node, &mut writer, &context, SAMPLE_RATE, 9600);
+        let status = node.status();
+        assert!(status.rolling);
+        assert_eq!(status.underruns, 0);
+
+        let peak = output[1000..]
+            .iter()
+            .fold(0.0f32, |peak, x| peak.max(x.abs()));
+        assert!((peak - 1.0).abs() < 0.01, "peak {}", peak);
+        let frequency = measure_frequency(&output[1000..], SAMPLE_RATE);
+        assert!((frequency - 1000.0).abs() < 1.0, "{} Hz", frequency);
+    }
+
+    #[test]
+    fn test_resamples_to_the_context_rate() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let (mut node, mut writer) = LiveInputNode::with_writer(1, 44100.0, &context);
+        let output = play_sine(&mut node, &mut writer, &context, 44100.0, 48000);
+
+        let frequency = measure_frequency(&output[4000..], SAMPLE_RATE);
+        assert!((frequency - 1000.0).abs() < 1.0, "{} Hz", frequency);
+        assert_eq!(node.status().underruns, 0);
+    }
+
+    #[test]
+    fn test_channel_selection() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let (mut node, mut writer) = LiveInputNode::with_writer(3, SAMPLE_RATE, &context);
+        let frames: Vec<f32> = (0..9600).flat_map(|_| [0.25, -0.5, 0.75]).collect();
+        writer.write(&frames);
+
+        // Let the resampler settle on the constant levels
+        for n in 0..2400 {
+            node.process(&context, n);
+        }
+        let close =
+            |(a, b): (f32, f32), (x, y): (f32, f32)| (a - x).abs() < 1e-3 && (b - y).abs() < 1e-3;
+        assert!(close(node.process_stereo(&context, 2400), (0.25, -0.5)));
+        node.set_channels(2, 0);
+        assert!(close(node.process_stereo(&context, 2401), (0.75, 0.25)));
+        node.set_channel(1);
+        assert!(close(node.process_stereo(&context, 2402), (-0.5, -0.5)));
+        // Past the last channel reads the last one
+        node.set_channels(7, 0);
+        assert!((node.process(&context, 2403) - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_clock_drift_is_absorbed() {
+        // The input's clock runs 0.1% fast: 48048 frames arrive per second
+        let context = AudioContext::new(SAMPLE_RATE);
+        let (mut node, mut writer) = LiveInputNode::with_writer(1, SAMPLE_RATE, &context);
+        let drift = 1.001;
+        let mut written = 0;
+        let mut sample = 0u64;
+        for block in 0..(20 * 48000 / 128) {
+            let due = ((block + 1) as f64 * 128.0 * drift) as usize;
+            writer.write(&sine(440.0, SAMPLE_RATE, written, due - written));
+            written = due;
+            for _ in 0..128 {
+                node.process(&context, sample);
+                sample += 1;
+            }
+        }
+
+        let status = node.status();
+        assert_eq!(status.underruns, 0);
+        assert_eq!(status.dropped_frames, 0);
+        // Without correction 960 extra frames would have piled up
+        assert!(
+            status.buffered < 0.05 * 1.3,
+            "{} seconds queued",
+            status.buffered
+        );
+        assert!(
+            (status.ratio * drift - 1.0).abs() < 2e-4,
+            "ratio {}",
+            status.ratio
+        );
+    }
+
+    #[test]
+    fn test_underruns_and_buffer_input() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let (mut node, mut writer) = LiveInputNode::with_writer(1, SAMPLE_RATE, &context);
+        writer.write(&vec![0.5; 2400]);
+        let output: Vec<f32> = (0..4800).map(|n| node.process(&context, n)).collect();
+        assert_eq!(node.status().underruns, 1);
+        assert!(!node.status().rolling);
+        assert_eq!(output[4799], 0.0);
+
+        // A buffer looped in real time stands in for a device
+        let tone = AudioBuffer::from_channels(vec![vec![0.5; 4800]], SAMPLE_RATE).unwrap();
+        let mut input = BufferInput::new(tone);
+        input.set_looping(true);
+        let mut graph = AudioGraph::offline(SAMPLE_RATE);
+        let live = graph.add_input("mic", Box::new(input)).unwrap();
+        live.set_latency(0.02);
+        graph.set_output("mic");
+        std::thread::sleep(Duration::from_millis(100));
+
+        let bounce = graph.render_offline(480);
+        assert!(live.status().rolling);
+        assert!((bounce.channel(0)[479] - 0.5).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,168 @@
+use cpal_synth::{
+    integrated_loudness, AudioBuffer, AudioContext, AudioGraph, AudioNode, MeterNode,
+};
+use std::f32::consts::{FRAC_1_SQRT_2, PI};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    // A sine on one or both channels, with its level in dBFS
+    #[derive(Clone)]
+    struct Tone {
+        frequency: f32,
+        amplitude: f32,
+        phase: f32,
+        stereo: bool,
+    }
+
+    impl Tone {
+        fn new(frequency: f32, dbfs: f32, stereo: bool) -> Self {
+            Self {
+                frequency,
+                amplitude: 10f32.powf(dbfs / 20.0),
+                phase: 0.0,
+                stereo,
+            }
+        }
+
+        fn at(&self, current_sample: u64) -> f32 {
+            let t = current_sample as f64 * self.frequency as f64 / SAMPLE_RATE as f64;
+            self.amplitude * (2.0 * PI * t.fract() as f32 + self.phase).sin()
+        }
+    }
+
+    impl AudioNode for Tone {
+        fn process(&mut self, _context: &AudioContext, current_sample: u64) -> f32 {
+            self.at(current_sample)
+        }
+        fn process_stereo(&mut self, _context: &AudioContext, current_sample: u64) -> (f32, f32) {
+            let value = self.at(current_sample);
+            (value, if self.stereo { value } else { 0.0 })
+        }
+        fn set_parameter(&self, _name: &str, _value: f32) {}
+        fn connect_input(&mut self, _name: &str, _node: Box<dyn AudioNode + Send>) {}
+        fn clear_input(&mut self, _input_name: &str) {}
+        fn clone_box(&self) -> Box<dyn AudioNode + Send> {
+            Box::new(self.clone())
+        }
+    }
+
+    fn metered(tone: Tone, context: &AudioContext) -> MeterNode {
+        let mut node = MeterNode::new(context);
+        node.connect_input("input", Box::new(tone));
+        node
+    }
+
+    #[test]
+    fn test_sine_at_minus_23_dbfs_reads_minus_23_lufs() {
+        // EBU Tech 3341 case 1, and the same tone on one channel, 3dB quieter
+        let context = AudioContext::new(SAMPLE_RATE);
+        for (stereo, expected) in [(true, -23.0), (false, -26.0)] {
+            let mut node = metered(Tone::new(1000.0, -23.0, stereo), &context);
+            let meter = node.meter();
+            assert_eq!(meter.integrated_loudness(), f32::NEG_INFINITY);
+            for sample in 0..(4.0 * SAMPLE_RATE) as u64 {
+                node.process_stereo(&context, sample);
+            }
+            for reading in [
+                meter.momentary_loudness(),
+                meter.short_term_loudness(),
+                meter.integrated_loudness(),
+            ] {
+                assert!((reading - expected).abs() < 0.1, "read {} LUFS", reading);
+            }
+        }
+    }
+
+    #[test]
+    fn test_integrated_loudness_gates_quiet_passages() {
+        // Tech 3341 case 3, shortened: -36dBFS either side of -23dBFS
+        let tone = Tone::new(1000.0, 0.0, true);
+        let mut channel = Vec::new();
+        for (seconds, dbfs) in [(2.0, -36.0f32), (20.0, -23.0), (2.0, -36.0)] {
+            let gain = 10f32.powf(dbfs / 20.0);
+            let start = channel.len() as u64;
+            channel.extend((0..(seconds * SAMPLE_RATE) as u64).map(|n| tone.at(start + n) * gain));
+        }
+        let buffer =
+            AudioBuffer::from_channels(vec![channel.clone(), channel], SAMPLE_RATE).unwrap();
+        let loudness = integrated_loudness(&buffer);
+        assert!((loudness + 23.0).abs() < 0.1, "read {} LUFS", loudness);
+
+        let silence = AudioBuffer::new(2, SAMPLE_RATE as usize, SAMPLE_RATE);
+        assert_eq!(integrated_loudness(&silence), f32::NEG_INFINITY);
+    }
+
+    #[test]
+    fn test_peak_rms_and_true_peak() {
+        // At a quarter of the sample rate and 45 degrees in, every sample lands
+        // at 0.707 while the waveform peaks at 1 between them
+        let context = AudioContext::new(SAMPLE_RATE);
+        let mut tone = Tone::new(SAMPLE_RATE / 4.0, 0.0, false);
+        tone.phase = PI / 4.0;
+        let mut node = metered(tone, &context);
+        let meter = node.meter();
+        for sample in 0..SAMPLE_RATE as u64 {
+            node.process(&context, sample);
+        }
+
+        assert!((meter.peak() - FRAC_1_SQRT_2).abs() < 1e-3);
+        assert!((meter.rms() - FRAC_1_SQRT_2).abs() < 1e-3);
+        let true_peak = 20.0 * meter.true_peak().log10();
+        assert!(true_peak.abs() < 0.2, "true peak {} dBTP", true_peak);
+    }
+
+    #[test]
+    fn test_peak_hold_and_reset() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let mut node = MeterNode::new(&context);
+        node.set_peak_hold(1.0);
+        node.set_rms_window(0.1);
+        let meter = node.meter();
+
+        // Half a second of tone, then silence
+        node.connect_input("input", Box::new(Tone::new(1000.0, -6.0, false)));
+        for sample in 0..24000 {
+            node.process(&context, sample);
+        }
+        node.clear_input("input");
+        for sample in 24000..48000 {
+            node.process(&context, sample);
+        }
+        assert_eq!(meter.peak(), 0.0);
+        assert_eq!(meter.rms(), 0.0);
+        assert!((meter.peak_hold() - 0.501).abs() < 0.01);
+
+        // The hold runs out a second after the last peak
+        for sample in 48000..80000 {
+            node.process(&context, sample);
+        }
+        assert_eq!(meter.peak_hold(), 0.0);
+        assert!(meter.true_peak() > 0.5);
+
+        meter.reset();
+        node.process(&context, 80000);
+        assert_eq!(meter.true_peak(), 0.0);
+        assert_eq!(meter.integrated_loudness(), f32::NEG_INFINITY);
+    }
+
+    #[test]
+    fn test_offline_render_meters_the_output() {
+        let mut graph = AudioGraph::offline(SAMPLE_RATE);
+        graph.add_node("tone", Box::new(Tone::new(1000.0, -23.0, true)));
+        graph.set_output("tone");
+        let meter = graph.meter_output();
+
+        let bounce = graph.render_offline((3.0 * SAMPLE_RATE) as usize);
+        assert_eq!(bounce.number_of_channels(), 2);
+        assert_eq!(bounce.length(), 144000);
+        assert_eq!(graph.context.current_sample(), 144000);
+
+        let loudness = integrated_loudness(&bounce);
+        assert!((loudness + 23.0).abs() < 0.1, "read {} LUFS", loudness);
+        assert!((meter.integrated_loudness() - loudness).abs() < 0.01);
+    }
+}
@@ -0,0 +1,111 @@
+mod common;
+
+use common::{amplitude, SAMPLE_RATE};
+use cpal_synth::{AudioContext, BandlimitedWavetableOscillator, OscillatorType};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LENGTH: usize = 8192;
+
+    fn render(oscillator: &mut BandlimitedWavetableOscillator, frequency: f32) -> Vec<f32> {
+        let context = AudioContext::new(SAMPLE_RATE);
+        oscillator.frequency().set_value(frequency);
+        common::render(oscillator, &context, LENGTH)
+    }
+
+    // Largest change in any harmonic below 20kHz between a saw just under and just
+    // over `bound`, relative to the fundamental
+    fn discontinuity(crossfade: bool, bound: f32) -> f32 {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let mut below =
+            BandlimitedWavetableOscillator::new(OscillatorType::Sawtooth, &context).unwrap();
+        below.set_mip_crossfade(crossfade);
+        let mut above = below.clone();
+
+        let (f_below, f_above) = (bound * 0.998, bound * 1.002);
+        let below = render(&mut below, f_below);
+        let above = render(&mut above, f_above);
+
+        let fundamental = amplitude(&below, f_below);
+        (1..)
+            .take_while(|&k| k as f32 * f_above < 20000.0)
+            .map(|k| {
+                (amplitude(&below, k as f32 * f_below) - amplitude(&above, k as f32 * f_above))
+                    .abs()
+                    / fundamental
+            })
+            .fold(0.0f32, f32::max)
+    }
+
+    #[test]
+    fn test_mip_frequency_bounds() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let oscillator =
+            BandlimitedWavetableOscillator::new(OscillatorType::Sawtooth, &context).unwrap();
+        let bounds = oscillator.mip_frequency_bounds();
+        assert!(bounds.len() > 8);
+        for (i, &bound) in bounds.iter().enumerate() {
+            let expected = 40.0 * 2.0f32.powi(i as i32);
+            assert!(
+                (bound - expected).abs() < expected * 1e-4,
+                "Bound {}: expected {}, got {}",
+                i,
+                expected,
+                bound
+            );
+        }
+    }
+
+    #[test]
+    fn test_spectrum_is_continuous_across_bounds() {
+        for bound in [160.0, 320.0, 640.0, 1280.0, 2560.0, 5120.0] {
+            let hard = discontinuity(false, bound);
+            let blended = discontinuity(true, bound);
+            assert!(
+                hard > 0.008,
+                "Expected a step in the spectrum at {} Hz without crossfading, got {}",
+                bound,
+                hard
+            );
+            assert!(
+                blended < 0.002,
+                "Spectrum jumps by {} at {} Hz with crossfading",
+                blended,
+                bound
+            );
+        }
+    }
+
+    #[test]
+    fn test_upper_harmonics_fade_across_octave() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let mut oscillator =
+            BandlimitedWavetableOscillator::new(OscillatorType::Sawtooth, &context).unwrap();
+        oscillator.set_mip_crossfade(true);
+
+        // The 30th harmonic is in the 320-640 Hz level but not the next, so it fades
+        // out steadily over the octave instead of vanishing at 640 Hz
+        let levels: Vec<f32> = [340.0, 400.0, 460.0, 520.0, 580.0, 635.0]
+            .iter()
+            .map(|&frequency| {
+                let samples = render(&mut oscillator, frequency);
+                amplitude(&samples, 30.0 * frequency) / amplitude(&samples, frequency)
+            })
+            .collect();
+        for pair in levels.windows(2) {
+            assert!(
+                pair[1] < pair[0],
+                "Harmonic levels not decreasing: {:?}",
+                levels
+            );
+        }
+        assert!(levels[0] > 0.02, "Harmonic missing at 340 Hz: {:?}", levels);
+        assert!(
+            levels[5] < 0.002,
+            "Harmonic still present at 635 Hz: {:?}",
+            levels
+        );
+    }
+}
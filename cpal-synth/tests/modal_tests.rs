@@ -0,0 +1,143 @@
+mod common;
+
+use common::{amplitude, render, SAMPLE_RATE};
+use cpal_synth::{AudioContext, AudioNode, ModalResonator, ModeTable};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A unit impulse at the first sample
+    #[derive(Clone)]
+    struct Impulse;
+
+    impl AudioNode for Impulse {
+        fn process(&mut self, _context: &AudioContext, current_sample: u64) -> f32 {
+            if current_sample == 0 {
+                1.0
+            } else {
+                0.0
+            }
+        }
+        fn set_parameter(&self, _name: &str, _value: f32) {}
+        fn connect_input(&mut self, _name: &str, _node: Box<dyn AudioNode + Send>) {}
+        fn clear_input(&mut self, _input_name: &str) {}
+        fn clone_box(&self) -> Box<dyn AudioNode + Send> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[test]
+    fn test_modes_ring_at_their_ratios() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let mut bar = ModalResonator::new(ModeTable::Bar);
+        bar.frequency().set_value(200.0);
+        bar.strike(0, 1.0);
+        let samples = render(&mut bar, &context, 8192);
+
+        for &(ratio, _, _) in ModeTable::Bar.modes().iter().take(4) {
+            let mode = amplitude(&samples, 200.0 * ratio);
+            let between = amplitude(&samples, 200.0 * (ratio + 0.35));
+            assert!(mode > 10.0 * between, "mode at {} not resolved", ratio);
+        }
+        // No harmonic series: a free bar has nothing at twice the fundamental
+        assert!(amplitude(&samples, 400.0) < 0.1 * amplitude(&samples, 200.0));
+    }
+
+    #[test]
+    fn test_mode_gain_and_decay() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let mut resonator = ModalResonator::new(ModeTable::Marimba);
+        resonator.set_modes(&[(1.0, 0.5, 0.5)]);
+        resonator.frequency().set_value(441.0);
+        resonator.strike(0, 1.0);
+        let samples = render(&mut resonator, &context, SAMPLE_RATE as usize);
+
+        let peak = samples[..100].iter().fold(0.0f32, |m, x| m.max(x.abs()));
+        assert!((peak - 0.5).abs() < 0.01, "peak {}", peak);
+
+        // Half the decay time later the mode should be 30dB down
+        let early = amplitude(&samples[..4096], 441.0);
+        let late = amplitude(&samples[11025..11025 + 4096], 441.0);
+        let drop = 20.0 * (late / early).log10();
+        assert!((drop + 30.0).abs() < 1.0, "fell {} dB", drop);
+
+        // Doubling the mode's decay through its parameter halves the drop
+        let mut resonator = ModalResonator::new(ModeTable::Marimba);
+        resonator.set_modes(&[(1.0, 0.5, 0.5)]);
+        resonator.set_parameter("frequency", 441.0);
+        resonator.set_parameter("mode0.decay", 1.0);
+        resonator.strike(0, 1.0);
+        let samples = render(&mut resonator, &context, 16000);
+        let early = amplitude(&samples[..4096], 441.0);
+        let late = amplitude(&samples[11025..11025 + 4096], 441.0);
+        let drop = 20.0 * (late / early).log10();
+        assert!((drop + 15.0).abs() < 1.0, "fell {} dB", drop);
+    }
+
+    #[test]
+    fn test_input_excites_the_bank() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let mut struck = ModalResonator::new(ModeTable::Bell);
+        let mut driven = struck.clone();
+        struck.strike(0, 1.0);
+        driven.connect_input("input", Box::new(Impulse));
+
+        let struck = render(&mut struck, &context, 4096);
+        let driven_samples = render(&mut driven, &context, 4096);
+        assert!(struck.iter().any(|&x| x.abs() > 0.01));
+        for (a, b) in struck.iter().zip(driven_samples.iter()) {
+            assert!((a - b).abs() < 1e-6);
+        }
+
+        driven.clear_input("input");
+        driven.reset();
+        assert!(render(&mut driven, &context, 100).iter().all(|&x| x == 0.0));
+    }
+
+    #[test]
+    fn test_strikes_queue_up() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let mut first = ModalResonator::new(ModeTable::Bell);
+        let mut second = first.clone();
+        let mut both = first.clone();
+        first.strike(0, 1.0);
+        second.strike(2000, 0.5);
+        // Scheduled out of order, and neither replaces the other
+        both.strike(2000, 0.5);
+        both.strike(0, 1.0);
+
+        let first = render(&mut first, &context, 4096);
+        let second = render(&mut second, &context, 4096);
+        let both = render(&mut both, &context, 4096);
+        for ((a, b), sum) in first.iter().zip(second.iter()).zip(both.iter()) {
+            assert!((a + b - sum).abs() < 1e-4, "{} + {} != {}", a, b, sum);
+        }
+    }
+
+    #[test]
+    fn test_gate_strikes_with_velocity() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let peak = |samples: &[f32]| samples.iter().fold(0.0f32, |m, x| m.max(x.abs()));
+
+        let mut loud = ModalResonator::new(ModeTable::Membrane);
+        loud.set_parameter("frequency", 120.0);
+        let mut soft = loud.clone();
+        loud.set_parameter_at("gate", 1.0, 500);
+        soft.set_parameter_at("gate", 0.25, 500);
+        let loud = render(&mut loud, &context, 4000);
+        let soft = render(&mut soft, &context, 4000);
+
+        assert_eq!(peak(&loud[..500]), 0.0);
+        assert!(peak(&loud[500..]) > 0.1);
+        assert!((peak(&soft[500..]) / peak(&loud[500..]) - 0.25).abs() < 1e-3);
+
+        // Modes pushed past Nyquist drop out rather than alias
+        let mut high = ModalResonator::new(ModeTable::Bar);
+        high.frequency().set_value(12000.0);
+        high.strike(0, 1.0);
+        let samples = render(&mut high, &context, 4096);
+        assert!(samples.iter().all(|x| x.is_finite()));
+        assert!(amplitude(&samples, 12000.0) > 0.01);
+    }
+}
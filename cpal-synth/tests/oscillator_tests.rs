@@ -14,7 +14,7 @@ mod tests {
     #[test]
     fn test_linear_ramp() {
         let context = setup();
-        let mut osc = Oscillator::new(OscillatorType::Sine);
+        let osc = Oscillator::new(OscillatorType::Sine).unwrap();
         osc.frequency().set_value(440.0);
 
         println!("Initial frequency: {}", osc.frequency().get_value(0));
@@ -59,7 +59,7 @@ mod tests {
     #[test]
     fn test_gain_ramp() {
         let context = setup();
-        let mut osc = Oscillator::new(OscillatorType::Sine);
+        let osc = Oscillator::new(OscillatorType::Sine).unwrap();
         osc.gain().set_value(0.0);
         println!("Initial gain: {}", osc.gain().get_value(0));
 
@@ -95,7 +95,7 @@ mod tests {
     #[test]
     fn test_smooth_transitions() {
         let context = setup();
-        let mut osc = Oscillator::new(OscillatorType::Sine);
+        let osc = Oscillator::new(OscillatorType::Sine).unwrap();
         osc.frequency().set_value(440.0);
         osc.frequency()
             .linear_ramp_to_value_at_time(880.0, 0.1, 0, context.sample_rate());
@@ -127,7 +127,7 @@ mod tests {
     #[test]
     fn test_exponential_ramp() {
         let context = setup();
-        let mut osc = Oscillator::new(OscillatorType::Sine);
+        let osc = Oscillator::new(OscillatorType::Sine).unwrap();
         osc.frequency().set_value(440.0);
         osc.frequency()
             .exponential_ramp_to_value_at_time(880.0, 0.1, 0, context.sample_rate());
@@ -157,10 +157,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_later_automation_wins() {
+        let osc = Oscillator::new(OscillatorType::Sine).unwrap();
+        let gain = osc.gain();
+        gain.set_value_at_time(0.0, 100);
+        gain.set_value_at_time(0.8, 100);
+        assert_eq!(gain.get_value(100), 0.8);
+
+        // A step inside a running ramp takes over from it
+        gain.linear_ramp_to_value_at_time(0.0, 0.01, 200, 44100.0);
+        gain.set_value_at_time(0.3, 300);
+        assert_eq!(gain.get_value(300), 0.3);
+        assert_eq!(gain.get_value(400), 0.3);
+        assert_eq!(gain.get_value(1000), 0.3);
+
+        // Retiring the finished step doesn't hand the value back to the ramp it
+        // interrupted
+        let osc = Oscillator::new(OscillatorType::Sine).unwrap();
+        let gain = osc.gain();
+        gain.linear_ramp_to_value_at_time(1.0, 1000.0 / 44100.0, 0, 44100.0);
+        gain.set_value_at_time(0.8, 100);
+        assert_eq!(gain.get_value(300), 0.8);
+        gain.get_value(200);
+        gain.set_value_at_time(0.5, 5000);
+        assert_eq!(gain.get_value(300), 0.8);
+    }
+
     #[test]
     fn test_oscillator_output() {
         let context = setup();
-        let mut osc = Oscillator::new(OscillatorType::Sine);
+        let mut osc = Oscillator::new(OscillatorType::Sine).unwrap();
         osc.frequency().set_value(440.0);
         osc.gain().set_value(1.0);
 
@@ -0,0 +1,139 @@
+use cpal_synth::{
+    AudioContext, AudioNode, BandlimitedWavetableOscillator, Oscillator, OscillatorType,
+    PeriodicWave,
+};
+use std::f32::consts::PI;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_coefficients() {
+        assert!(PeriodicWave::new(&[0.0, 1.0], &[0.0], true).is_err());
+        assert!(PeriodicWave::new(&[0.0], &[0.0], true).is_err());
+
+        // An unknown id can't build an oscillator of either kind
+        let context = AudioContext::new(44100.0);
+        assert!(context.wave_banks().periodic_wave(u32::MAX).is_none());
+        assert!(
+            BandlimitedWavetableOscillator::new(OscillatorType::Custom(u32::MAX), &context)
+                .is_err()
+        );
+        assert!(Oscillator::custom(u32::MAX, &context).is_err());
+
+        // Custom waves need their wave, so Oscillator::new turns them away
+        let wave = context
+            .create_periodic_wave(&[0.0, 0.0], &[0.0, 1.0], true)
+            .unwrap();
+        assert!(Oscillator::new(wave.oscillator_type()).is_err());
+    }
+
+    #[test]
+    fn test_custom_wave_in_both_oscillators() {
+        let context = AudioContext::new(44100.0);
+        let wave = context
+            .create_periodic_wave(&[0.0, 0.0, 0.5], &[0.0, 1.0, 0.0], false)
+            .unwrap();
+        assert_eq!(wave.oscillator_type(), OscillatorType::Custom(wave.id()));
+
+        let frequency = 441.0;
+        let mut oscillator = Oscillator::custom(wave.id(), &context).unwrap();
+        oscillator.frequency().set_value(frequency);
+        let mut wavetable =
+            BandlimitedWavetableOscillator::new(wave.oscillator_type(), &context).unwrap();
+        wavetable.frequency().set_value(frequency);
+
+        for sample in 0..1000u64 {
+            let phase = (sample as f32 * frequency / 44100.0).fract();
+            let expected = (2.0 * PI * phase).sin() + 0.5 * (4.0 * PI * phase).cos();
+            let direct = oscillator.process(&context, sample);
+            let table = wavetable.process(&context, sample);
+            assert!(
+                (direct - expected).abs() < 1e-3,
+                "Sample {}: Oscillator expected {}, got {}",
+                sample,
+                expected,
+                direct
+            );
+            assert!(
+                (table - expected).abs() < 1e-2,
+                "Sample {}: wavetable expected {}, got {}",
+                sample,
+                expected,
+                table
+            );
+        }
+    }
+
+    #[test]
+    fn test_normalization() {
+        let real = [0.0, 0.0, 0.0, 0.0];
+        let imag = [0.0, 3.0, 0.0, 1.0];
+        let normalized = PeriodicWave::new(&real, &imag, true).unwrap();
+        let raw = PeriodicWave::new(&real, &imag, false).unwrap();
+        assert!(normalized.is_normalized() && !raw.is_normalized());
+
+        let peak = |wave: &PeriodicWave| {
+            (0..1000)
+                .map(|i| wave.evaluate(i as f32 / 1000.0, usize::MAX).abs())
+                .fold(0.0f32, f32::max)
+        };
+        assert!((peak(&normalized) - 1.0).abs() < 1e-3);
+        // 3 sin(x) + sin(3x) peaks at 2√2 at x = π/4
+        assert!((peak(&raw) - 2.0 * 2.0f32.sqrt()).abs() < 1e-2);
+    }
+
+    #[test]
+    fn test_harmonics_above_nyquist_are_dropped() {
+        let context = AudioContext::new(44100.0);
+        let mut imag = vec![0.0; 11];
+        imag[10] = 1.0;
+        let wave = PeriodicWave::new(&[0.0; 11], &imag, true).unwrap();
+        context.wave_banks().register_periodic_wave(&wave);
+
+        // The tenth harmonic of 3kHz is above Nyquist, so nothing is left
+        let mut oscillator = Oscillator::with_periodic_wave(wave.clone());
+        oscillator.frequency().set_value(3000.0);
+        let mut wavetable =
+            BandlimitedWavetableOscillator::new(wave.oscillator_type(), &context).unwrap();
+        wavetable.frequency().set_value(3000.0);
+        for sample in 0..500 {
+            assert_eq!(oscillator.process(&context, sample), 0.0);
+            assert!(wavetable.process(&context, sample).abs() < 1e-6);
+        }
+
+        // At 1kHz it plays at full level
+        oscillator.frequency().set_value(1000.0);
+        let peak = (500..1000)
+            .map(|sample| oscillator.process(&context, sample).abs())
+            .fold(0.0f32, f32::max);
+        assert!(peak > 0.99, "Expected a full-scale tone, got peak {}", peak);
+    }
+
+    #[test]
+    fn test_waves_belong_to_their_context() {
+        let context = AudioContext::new(44100.0);
+        let other = AudioContext::new(44100.0);
+        let wave = context
+            .create_periodic_wave(&[0.0, 0.0], &[0.0, 1.0], true)
+            .unwrap();
+        let id = wave.id();
+        assert!(other.wave_banks().periodic_wave(id).is_none());
+        assert!(BandlimitedWavetableOscillator::new(wave.oscillator_type(), &other).is_err());
+
+        let oscillator = Oscillator::custom(id, &context).unwrap();
+        let wavetable =
+            BandlimitedWavetableOscillator::new(wave.oscillator_type(), &context).unwrap();
+        drop(wave);
+
+        // Oscillators and their banks keep the wave alive
+        let registry = context.wave_banks();
+        assert!(registry.periodic_wave(id).is_some());
+        drop(oscillator);
+        drop(wavetable);
+        registry.evict_unused_rates(0.0);
+        assert!(registry.periodic_wave(id).is_none());
+        assert!(Oscillator::custom(id, &context).is_err());
+    }
+}
@@ -0,0 +1,132 @@
+mod common;
+
+use common::{amplitude, render, SAMPLE_RATE};
+use cpal_synth::{
+    AudioContext, AudioNode, BandlimitedWavetableOscillator, Oscillator, OscillatorType,
+};
+use std::f32::consts::PI;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Outputs a fixed value, standing in for a modulation source
+    #[derive(Clone)]
+    struct Constant(f32);
+
+    impl AudioNode for Constant {
+        fn process(&mut self, _context: &AudioContext, _current_sample: u64) -> f32 {
+            self.0
+        }
+        fn set_parameter(&self, _name: &str, _value: f32) {}
+        fn connect_input(&mut self, _name: &str, _node: Box<dyn AudioNode + Send>) {}
+        fn clear_input(&mut self, _input_name: &str) {}
+        fn clone_box(&self) -> Box<dyn AudioNode + Send> {
+            Box::new(self.clone())
+        }
+    }
+
+    fn pulse_oscillators(context: &AudioContext) -> Vec<Box<dyn AudioNode + Send>> {
+        vec![
+            Box::new(Oscillator::new(OscillatorType::Pulse).unwrap()),
+            Box::new(BandlimitedWavetableOscillator::new(OscillatorType::Pulse, context).unwrap()),
+        ]
+    }
+
+    #[test]
+    fn test_duty_cycle() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        for mut oscillator in pulse_oscillators(&context) {
+            // 441 samples per cycle
+            oscillator.set_parameter("frequency", 100.0);
+            for width in [0.1, 0.25, 0.5, 0.8] {
+                oscillator.set_parameter("pulse_width", width);
+                let cycle = &render(oscillator.as_mut(), &context, 4410)[441..882];
+                let mean = cycle.iter().sum::<f32>() / cycle.len() as f32;
+                let high = cycle.iter().filter(|&&x| x > 0.0).count() as f32 / 441.0;
+                assert!(
+                    (mean - (2.0 * width - 1.0)).abs() < 0.02,
+                    "Width {}: mean {}",
+                    width,
+                    mean
+                );
+                assert!(
+                    (high - width).abs() < 0.01,
+                    "Width {}: high {}",
+                    width,
+                    high
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_half_width_matches_square() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let mut pulse = Oscillator::new(OscillatorType::Pulse).unwrap();
+        let mut square = Oscillator::new(OscillatorType::Square).unwrap();
+        pulse.frequency().set_value(1234.0);
+        square.frequency().set_value(1234.0);
+        let pulse = render(&mut pulse, &context, 2000);
+        let square = render(&mut square, &context, 2000);
+        for (n, (a, b)) in pulse.iter().zip(square.iter()).enumerate() {
+            assert!((a - b).abs() < 1e-5, "Sample {}: {} vs {}", n, a, b);
+        }
+    }
+
+    #[test]
+    fn test_pulse_is_band_limited() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let (frequency, width, length) = (3000.0, 0.3, 16384);
+
+        // 44.1kHz is 2100 above a multiple of 3kHz, so aliases fold to 900 + 3000m
+        let alias_level = |samples: &[f32]| -> f32 {
+            (0..7)
+                .map(|m| amplitude(samples, 900.0 + 3000.0 * m as f32))
+                .sum()
+        };
+        let naive: Vec<f32> = (0..length)
+            .map(|n| {
+                if (n as f32 * frequency / SAMPLE_RATE).fract() < width {
+                    1.0
+                } else {
+                    -1.0
+                }
+            })
+            .collect();
+        let naive_aliases = alias_level(&naive);
+
+        for mut oscillator in pulse_oscillators(&context) {
+            oscillator.set_parameter("frequency", frequency);
+            oscillator.set_parameter("pulse_width", width);
+            let samples = render(oscillator.as_mut(), &context, length);
+            let aliases = alias_level(&samples);
+            assert!(
+                aliases < naive_aliases * 0.2,
+                "Alias level {} against {} for a naive pulse",
+                aliases,
+                naive_aliases
+            );
+            // The fundamental of a 30% pulse is (4 / π) sin(0.3π)
+            let fundamental = amplitude(&samples, frequency);
+            assert!((fundamental - 4.0 / PI * (0.3 * PI).sin()).abs() < 0.05);
+        }
+    }
+
+    #[test]
+    fn test_pulse_width_input() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        for mut oscillator in pulse_oscillators(&context) {
+            oscillator.set_parameter("frequency", 100.0);
+            oscillator.connect_input("pulse_width", Box::new(Constant(-0.3)));
+            let cycle = &render(oscillator.as_mut(), &context, 1000)[441..882];
+            let high = cycle.iter().filter(|&&x| x > 0.0).count() as f32 / 441.0;
+            assert!((high - 0.2).abs() < 0.01, "Expected 20% duty, got {}", high);
+
+            oscillator.clear_input("pulse_width");
+            let cycle = &render(oscillator.as_mut(), &context, 1000)[441..882];
+            let high = cycle.iter().filter(|&&x| x > 0.0).count() as f32 / 441.0;
+            assert!((high - 0.5).abs() < 0.01, "Expected 50% duty, got {}", high);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use cpal_synth::{
+    AudioContext, AudioGraph, AudioNode, AudioParam, BandlimitedWavetableOscillator, OscillatorType,
+};
+use std::sync::{Arc, Mutex};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Upward zero crossings, which a sine makes once per cycle
+    fn cycles(samples: &[f32]) -> usize {
+        samples
+            .windows(2)
+            .filter(|pair| pair[0] <= 0.0 && pair[1] > 0.0)
+            .count()
+    }
+
+    #[test]
+    fn test_context_keeps_time_across_rate_change() {
+        let context = AudioContext::new(44100.0);
+        context.increment_samples(22050);
+        assert_eq!(context.current_time(), 0.5);
+
+        context.set_sample_rate(48000.0);
+        assert_eq!(context.sample_rate(), 48000.0);
+        assert_eq!(context.current_sample(), 24000);
+        assert_eq!(context.current_time(), 0.5);
+    }
+
+    #[test]
+    fn test_param_events_keep_their_time_in_seconds() {
+        let step = AudioParam::new(0.0, 0.0, 1.0);
+        step.prepare(44100.0);
+        step.set_value_at_time(1.0, 44100);
+        let ramp = AudioParam::new(1.0, 0.0, 1.0);
+        ramp.linear_ramp_to_value_at_time(0.0, 0.5, 88200, 44100.0);
+
+        // Preparing again at the same rate changes nothing
+        step.prepare(44100.0);
+        assert_eq!(step.get_value(44099), 0.0);
+        assert_eq!(step.get_value(44100), 1.0);
+
+        step.prepare(48000.0);
+        assert_eq!(step.get_value(47999), 0.0);
+        assert_eq!(step.get_value(48000), 1.0);
+
+        // Scheduling the ramp told the param its rate
+        ramp.prepare(48000.0);
+        assert_eq!(ramp.get_value(95999), 1.0);
+        assert!((ramp.get_value(96000 + 12000) - 0.5).abs() < 1e-3);
+        assert_eq!(ramp.get_value(96000 + 24000), 0.0);
+    }
+
+    #[test]
+    fn test_wave_banks_are_keyed_on_the_exact_rate() {
+        let oscillator = BandlimitedWavetableOscillator::new(
+            OscillatorType::Sawtooth,
+            &AudioContext::new(44100.0),
+        )
+        .unwrap();
+        let nearby = BandlimitedWavetableOscillator::new(
+            OscillatorType::Sawtooth,
+            &AudioContext::new(44100.9),
+        )
+        .unwrap();
+        assert_eq!(oscillator.sample_rate(), 44100.0);
+        assert_eq!(nearby.sample_rate(), 44100.9);
+    }
+
+    #[test]
+    fn test_graph_rate_change_prepares_oscillators() {
+        let mut graph = AudioGraph::offline(44100.0);
+        let oscillator = Arc::new(Mutex::new(
+            BandlimitedWavetableOscillator::new(OscillatorType::Sine, &graph.context).unwrap(),
+        ));
+        oscillator.lock().unwrap().frequency().set_value(1000.0);
+        graph.add_node("osc", Box::new(oscillator.clone()));
+        graph.set_output("osc");
+        let context = graph.context.clone();
+        let bounds = oscillator.lock().unwrap().mip_frequency_bounds().to_vec();
+
+        graph.set_sample_rate(22050.0).unwrap();
+        assert!(Arc::ptr_eq(&context, &graph.context));
+        assert_eq!(context.sample_rate(), 22050.0);
+        assert_eq!(oscillator.lock().unwrap().sample_rate(), 22050.0);
+        assert_ne!(
+            oscillator.lock().unwrap().mip_frequency_bounds(),
+            &bounds[..]
+        );
+
+        // Still in tune at the new rate
+        let buffer = graph.render_offline(22050);
+        let count = cycles(buffer.channel(0));
+        assert!((999..=1001).contains(&count), "{} cycles", count);
+    }
+
+    #[test]
+    fn test_scheduled_automation_survives_rate_change() {
+        let mut graph = AudioGraph::offline(44100.0);
+        let oscillator =
+            BandlimitedWavetableOscillator::new(OscillatorType::Sine, &graph.context).unwrap();
+        oscillator.frequency().set_value(500.0);
+        // Silence the oscillator one second in
+        oscillator.set_parameter_at("gain", 0.0, 44100);
+        graph.add_node("osc", Box::new(oscillator));
+        graph.set_output("osc");
+
+        graph.render_offline(22050);
+        graph.set_sample_rate(22050.0).unwrap();
+        assert_eq!(graph.context.current_sample(), 11025);
+        assert_eq!(graph.context.current_time(), 0.5);
+
+        // The second half-second plays, then the step lands on time
+        let buffer = graph.render_offline(22050);
+        let samples = buffer.channel(0);
+        assert_eq!(cycles(&samples[..11025]), 250);
+        assert!(samples[11025..].iter().all(|&x| x == 0.0));
+    }
+}
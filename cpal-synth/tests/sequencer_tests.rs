@@ -0,0 +1,198 @@
+use cpal_synth::{
+    AudioContext, AudioNode, AudioProcessor, Oscillator, OscillatorType, ParameterLock, Pattern,
+    Step, StepSequencer,
+};
+use std::sync::{Arc, Mutex};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 120 BPM with 4 steps per beat at 48kHz gives exactly 6000 samples per step
+    const SAMPLES_PER_STEP: u64 = 6000;
+
+    fn setup() -> Arc<AudioContext> {
+        Arc::new(AudioContext::new(48000.0))
+    }
+
+    #[test]
+    fn test_gate_is_sample_accurate() {
+        let context = setup();
+        let vca = Arc::new(Mutex::new(AudioProcessor::new("gain")));
+        vca.lock().unwrap().gain().set_value(0.0);
+
+        let mut sequencer = StepSequencer::new(4);
+        sequencer.add_target(Box::new(vca.clone()), None, Some("gain"));
+        let mut pattern = Pattern::new(4);
+        pattern.set_step(0, Step::new(60, 1.0));
+        pattern.set_step(2, Step::new(60, 0.5));
+        sequencer.set_pattern(0, pattern);
+
+        let start = 100;
+        sequencer.start(start);
+
+        let check_points = [
+            (start - 1, 0.0),
+            (start, 1.0),
+            (start + SAMPLES_PER_STEP / 2 - 1, 1.0),
+            (start + SAMPLES_PER_STEP / 2, 0.0),
+            (start + 2 * SAMPLES_PER_STEP - 1, 0.0),
+            (start + 2 * SAMPLES_PER_STEP, 0.5),
+        ];
+
+        let mut checks = check_points.iter().peekable();
+        for sample in 0..start + 3 * SAMPLES_PER_STEP {
+            sequencer.process(&context, sample);
+            if let Some(&&(check_sample, expected)) = checks.peek() {
+                if check_sample == sample {
+                    let gain = vca.lock().unwrap().gain().get_value(sample);
+                    assert_eq!(
+                        gain, expected,
+                        "Sample {}: Expected gate {}, got {}",
+                        sample, expected, gain
+                    );
+                    checks.next();
+                }
+            }
+        }
+        assert!(checks.next().is_none(), "Not all check points reached");
+    }
+
+    #[test]
+    fn test_tied_gate_holds_into_next_step() {
+        let context = setup();
+        let vca = Arc::new(Mutex::new(AudioProcessor::new("gain")));
+        vca.lock().unwrap().gain().set_value(0.0);
+
+        let mut sequencer = StepSequencer::new(4);
+        sequencer.add_target(Box::new(vca.clone()), None, Some("gain"));
+        let mut pattern = Pattern::new(4);
+        let mut tied = Step::new(60, 1.0);
+        tied.gate = 1.0;
+        pattern.set_step(0, tied);
+        pattern.set_step(1, Step::new(62, 0.5));
+        sequencer.set_pattern(0, pattern);
+        let start = 100;
+        sequencer.start(start);
+
+        // The gate moves straight to the next velocity without closing
+        let gate = |sample| vca.lock().unwrap().gain().get_value(sample);
+        let release = start + 3 * SAMPLES_PER_STEP / 2;
+        for sample in 0..=release {
+            sequencer.process(&context, sample);
+            let expected = if sample < start {
+                0.0
+            } else if sample < start + SAMPLES_PER_STEP {
+                1.0
+            } else if sample < release {
+                0.5
+            } else {
+                0.0
+            };
+            assert_eq!(gate(sample), expected, "Sample {}", sample);
+        }
+    }
+
+    #[test]
+    fn test_pitch_and_parameter_locks() {
+        let context = setup();
+        let osc = Arc::new(Mutex::new(Oscillator::new(OscillatorType::Sine).unwrap()));
+
+        let mut sequencer = StepSequencer::new(4);
+        let target = sequencer.add_target(Box::new(osc.clone()), Some("frequency"), None);
+        sequencer.set_base_value(target, "gain", 1.0);
+
+        let mut pattern = Pattern::new(3);
+        pattern.set_step(0, Step::new(69, 1.0));
+        let mut locked = Step::new(81, 1.0);
+        locked.locks.push(ParameterLock {
+            target,
+            name: "gain".to_string(),
+            value: 0.25,
+        });
+        pattern.set_step(1, locked);
+        pattern.set_step(2, Step::new(57, 1.0));
+        sequencer.set_pattern(0, pattern);
+        sequencer.start(0);
+
+        let mut observed = Vec::new();
+        for sample in 0..3 * SAMPLES_PER_STEP {
+            sequencer.process(&context, sample);
+            if sample % SAMPLES_PER_STEP == 0 {
+                let osc = osc.lock().unwrap();
+                observed.push((
+                    osc.frequency().get_value(sample),
+                    osc.gain().get_value(sample),
+                ));
+            }
+        }
+
+        let expected = [(440.0, 1.0), (880.0, 0.25), (220.0, 1.0)];
+        for (step, (&(freq, gain), (expected_freq, expected_gain))) in
+            observed.iter().zip(expected).enumerate()
+        {
+            assert!(
+                (freq - expected_freq).abs() < 0.01,
+                "Step {}: Expected {} Hz, got {}",
+                step,
+                expected_freq,
+                freq
+            );
+            assert_eq!(gain, expected_gain, "Step {}: wrong locked gain", step);
+        }
+    }
+
+    #[test]
+    fn test_swing_probability_and_chaining() {
+        let context = setup();
+        let osc = Arc::new(Mutex::new(Oscillator::new(OscillatorType::Sine).unwrap()));
+        osc.lock().unwrap().frequency().set_value(100.0);
+
+        let mut sequencer = StepSequencer::new(4);
+        sequencer.add_target(Box::new(osc.clone()), Some("frequency"), None);
+        sequencer.swing().set_value(0.5);
+
+        let mut first = Pattern::new(2);
+        first.set_step(0, Step::new(69, 1.0));
+        first.set_step(1, Step::new(81, 1.0));
+        let mut second = Pattern::new(2);
+        let mut never = Step::new(93, 1.0);
+        never.probability = 0.0;
+        second.set_step(0, never);
+        second.set_step(1, Step::new(57, 1.0));
+        sequencer.set_pattern(0, first);
+        sequencer.set_pattern(1, second);
+        sequencer.set_chain(vec![0, 1]);
+        sequencer.start(0);
+
+        let swung = SAMPLES_PER_STEP + SAMPLES_PER_STEP / 2;
+        let check_points = [
+            (swung - 1, 440.0),
+            (swung, 880.0),
+            // Step 2 never fires, so the pitch holds
+            (2 * SAMPLES_PER_STEP, 880.0),
+            (3 * SAMPLES_PER_STEP + SAMPLES_PER_STEP / 2, 220.0),
+            // The chain loops back to the first pattern
+            (4 * SAMPLES_PER_STEP, 440.0),
+        ];
+
+        let mut checks = check_points.iter().peekable();
+        for sample in 0..5 * SAMPLES_PER_STEP {
+            sequencer.process(&context, sample);
+            if let Some(&&(check_sample, expected)) = checks.peek() {
+                if check_sample == sample {
+                    let freq = osc.lock().unwrap().frequency().get_value(sample);
+                    assert!(
+                        (freq - expected).abs() < 0.01,
+                        "Sample {}: Expected {} Hz, got {}",
+                        sample,
+                        expected,
+                        freq
+                    );
+                    checks.next();
+                }
+            }
+        }
+        assert!(checks.next().is_none(), "Not all check points reached");
+    }
+}
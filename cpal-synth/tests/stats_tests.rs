@@ -0,0 +1,130 @@
+use cpal_synth::{
+    AudioGraph, AudioProcessor, NodeStats, Oscillator, OscillatorType, PerformanceMonitor,
+};
+use std::time::Duration;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    #[test]
+    fn test_callback_times_and_load() {
+        let monitor = PerformanceMonitor::new();
+        // 480 frames last 10ms
+        for millis in [2, 4, 6] {
+            monitor.record_callback(Duration::from_millis(millis), 480, SAMPLE_RATE);
+        }
+        let stats = monitor.snapshot();
+        assert_eq!(stats.callbacks, 3);
+        assert_eq!(stats.frames, 1440);
+        assert_eq!(stats.min_callback_time, Duration::from_millis(2));
+        assert_eq!(stats.average_callback_time, Duration::from_millis(4));
+        assert_eq!(stats.max_callback_time, Duration::from_millis(6));
+        assert!((stats.load - 0.6).abs() < 1e-4);
+        assert!((stats.peak_load - 0.6).abs() < 1e-4);
+        assert!((stats.average_load - 0.4).abs() < 1e-4);
+        assert_eq!(stats.late_callbacks, 0);
+
+        // Taking longer than the buffer lasts is late
+        monitor.record_callback(Duration::from_millis(12), 480, SAMPLE_RATE);
+        let stats = monitor.snapshot();
+        assert_eq!(stats.late_callbacks, 1);
+        assert!((stats.peak_load - 1.2).abs() < 1e-4);
+
+        monitor.reset();
+        let stats = monitor.snapshot();
+        assert_eq!(stats.callbacks, 0);
+        assert_eq!(stats.min_callback_time, Duration::ZERO);
+        assert_eq!(stats.peak_load, 0.0);
+    }
+
+    #[test]
+    fn test_underruns_from_timestamps() {
+        let monitor = PerformanceMonitor::new();
+        let ms = Duration::from_millis;
+
+        // Called 10ms ahead of playback, every 10ms: always in time
+        for n in 0..10 {
+            monitor.record_timestamps(ms(10 * n), ms(10 * n + 10), 480, SAMPLE_RATE);
+        }
+        assert_eq!(monitor.snapshot().underruns, 0);
+
+        // The last buffer ends at 110ms; a callback at 125ms is too late
+        monitor.record_timestamps(ms(125), ms(135), 480, SAMPLE_RATE);
+        assert_eq!(monitor.snapshot().underruns, 1);
+        monitor.record_timestamps(ms(135), ms(145), 480, SAMPLE_RATE);
+        assert_eq!(monitor.snapshot().underruns, 1);
+    }
+
+    #[test]
+    fn test_offline_renders_are_recorded() {
+        let mut graph = AudioGraph::offline(SAMPLE_RATE);
+        graph.add_node(
+            "osc",
+            Box::new(Oscillator::new(OscillatorType::Sawtooth).unwrap()),
+        );
+        graph.set_output("osc");
+
+        graph.render_offline(4800);
+        graph.render_offline(4800);
+        let stats = graph.stats();
+        assert_eq!(stats.callbacks, 2);
+        assert_eq!(stats.frames, 9600);
+        assert!(stats.max_callback_time >= stats.min_callback_time);
+        assert!(stats.max_callback_time > Duration::ZERO);
+        assert!(stats.nodes.is_empty());
+
+        // Clones of the monitor share its counters
+        graph.performance_monitor().reset();
+        assert_eq!(graph.stats().callbacks, 0);
+    }
+
+    #[test]
+    fn test_per_node_timing() {
+        let mut graph = AudioGraph::offline(SAMPLE_RATE);
+        graph.add_node(
+            "untimed",
+            Box::new(Oscillator::new(OscillatorType::Sine).unwrap()),
+        );
+        graph.set_node_timing(true);
+        graph.add_node(
+            "osc",
+            Box::new(Oscillator::new(OscillatorType::Square).unwrap()),
+        );
+        graph.add_node("gain", Box::new(AudioProcessor::new("gain")));
+        graph.connect("osc", "gain", "input");
+        graph.set_output("gain");
+
+        graph.render_offline(1000);
+        let stats = graph.stats();
+        let names: Vec<&str> = stats.nodes.iter().map(|node| node.name.as_str()).collect();
+        assert_eq!(names, ["osc", "gain"]);
+        for node in &stats.nodes {
+            assert_eq!(node.calls, 1000);
+        }
+        // The gain node's time includes the oscillator it pulls from
+        assert!(stats.nodes[1].total >= stats.nodes[0].total);
+
+        graph.reset_stats();
+        assert!(graph.stats().nodes.iter().all(|node| node.calls == 0));
+    }
+
+    #[test]
+    fn test_node_average_with_large_call_counts() {
+        let stats = NodeStats {
+            name: "osc".to_string(),
+            calls: 1 << 32,
+            total: Duration::from_secs(1 << 32),
+        };
+        assert_eq!(stats.average(), Duration::from_secs(1));
+
+        let stats = NodeStats {
+            calls: (1 << 32) + 4,
+            total: Duration::from_nanos(((1u64 << 32) + 4) * 250),
+            ..stats
+        };
+        assert_eq!(stats.average(), Duration::from_nanos(250));
+    }
+}
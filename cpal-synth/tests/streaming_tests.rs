@@ -0,0 +1,230 @@
+use cpal_synth::{decode_audio_data, AudioContext, AudioNode, StreamingPlayerNode};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes interleaved 16-bit samples to a WAV file in the temp directory
+    fn write_wav(name: &str, channels: u16, sample_rate: u32, samples: &[i16]) -> PathBuf {
+        let data: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let mut file = b"RIFF".to_vec();
+        file.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+        file.extend_from_slice(b"WAVEfmt ");
+        file.extend_from_slice(&16u32.to_le_bytes());
+        file.extend_from_slice(&1u16.to_le_bytes());
+        file.extend_from_slice(&channels.to_le_bytes());
+        file.extend_from_slice(&sample_rate.to_le_bytes());
+        file.extend_from_slice(&(sample_rate * channels as u32 * 2).to_le_bytes());
+        file.extend_from_slice(&(channels * 2).to_le_bytes());
+        file.extend_from_slice(&16u16.to_le_bytes());
+        file.extend_from_slice(b"data");
+        file.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        file.extend_from_slice(&data);
+
+        let path = std::env::temp_dir().join(format!("cpal_synth_{}_{}", std::process::id(), name));
+        std::fs::write(&path, file).unwrap();
+        path
+    }
+
+    // Processes one frame once the reader has caught up, like a real-time callback would
+    fn next_frame(
+        player: &mut StreamingPlayerNode,
+        context: &AudioContext,
+        sample: u64,
+    ) -> (f32, f32) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !player.is_ready() || player.status().buffered == 0.0 {
+            assert!(Instant::now() < deadline, "Stream never became ready");
+            // Seeks are acknowledged by processing, which is silent until ready
+            if !player.is_ready() {
+                player.process_stereo(context, sample);
+            }
+            std::thread::sleep(Duration::from_micros(200));
+        }
+        player.process_stereo(context, sample)
+    }
+
+    #[test]
+    fn test_streams_whole_file() {
+        let context = AudioContext::new(1000.0);
+        let frames = 3000;
+        let samples: Vec<i16> = (0..frames)
+            .flat_map(|i| [i as i16 * 8, -(i as i16) * 8])
+            .collect();
+        let path = write_wav("whole.wav", 2, 1000, &samples);
+
+        // The ring holds a third of the file, so the reader must refill it as we go
+        let mut player = StreamingPlayerNode::open_with_buffer(&path, &context, 1.0).unwrap();
+        player.play();
+        for i in 0..frames {
+            let (left, right) = next_frame(&mut player, &context, i as u64);
+            let expected = (i * 8) as f32 / 32768.0;
+            assert_eq!(left, expected, "Frame {}: wrong left sample", i);
+            assert_eq!(right, -expected, "Frame {}: wrong right sample", i);
+        }
+
+        let status = player.status();
+        assert_eq!(status.underruns, 0);
+        assert!((status.position - 3.0).abs() < 1e-9);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !player.status().finished && Instant::now() < deadline {
+            player.process_stereo(&context, frames as u64);
+        }
+        let status = player.status();
+        assert!(
+            status.finished,
+            "Stream should finish at the end of the file"
+        );
+        assert!(!status.playing);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_seek_and_loop() {
+        let context = AudioContext::new(1000.0);
+        let samples: Vec<i16> = (0..2000).map(|i| i as i16 * 8).collect();
+        let path = write_wav("loop.wav", 1, 1000, &samples);
+
+        let mut player = StreamingPlayerNode::open_with_buffer(&path, &context, 0.5).unwrap();
+        player.set_loop(true, 0.5, 1.0);
+        player.seek(0.75);
+        player.play();
+
+        for i in 0..1000u64 {
+            let expected_frame = if i < 250 {
+                750 + i
+            } else {
+                500 + (i - 250) % 500
+            };
+            let output = next_frame(&mut player, &context, i);
+            assert_eq!(
+                output.0,
+                (expected_frame * 8) as f32 / 32768.0,
+                "Frame {}: expected file frame {}",
+                i,
+                expected_frame
+            );
+        }
+
+        // 750 + 1000 frames folded into the 500..1000 loop
+        let status = player.status();
+        assert!((status.position - 0.75).abs() < 1e-9, "{:?}", status);
+        assert!(!status.finished);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_seek_and_loop_in_flac_and_vorbis() {
+        // Seeks go through the seek table, the frames read so far and Ogg pages.
+        // Each case is a fixture with its loop start, loop end and seek target in
+        // seconds.
+        let cases = [
+            ("stereo_16bit.flac", 0.2, 0.4, 0.3),
+            ("mono_24bit.flac", 1.25, 2.5, 2.0),
+            ("stereo.ogg", 0.25, 0.75, 0.5),
+        ];
+        for (name, loop_start, loop_end, seek) in cases {
+            let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("tests/fixtures")
+                .join(name);
+            let expected = decode_audio_data(&std::fs::read(&path).unwrap()).unwrap();
+            let rate = expected.sample_rate();
+            let right = expected.number_of_channels() - 1;
+            let frame = |seconds: f64| (seconds * rate as f64) as u64;
+            let (start, end) = (frame(loop_start), frame(loop_end));
+
+            let context = AudioContext::new(rate);
+            let mut player = StreamingPlayerNode::open_with_buffer(&path, &context, 0.25).unwrap();
+            player.set_loop(true, loop_start, loop_end);
+            player.seek(seek);
+            player.play();
+
+            for i in 0..3 * (end - start) {
+                let mut expected_frame = frame(seek) + i;
+                if expected_frame >= end {
+                    expected_frame = start + (expected_frame - end) % (end - start);
+                }
+                let output = next_frame(&mut player, &context, i);
+                let f = expected_frame as usize;
+                assert_eq!(
+                    output,
+                    (expected.channel(0)[f], expected.channel(right)[f]),
+                    "{} frame {}: expected file frame {}",
+                    name,
+                    i,
+                    expected_frame
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_loop_past_end_of_file_finishes() {
+        let context = AudioContext::new(1000.0);
+        let samples: Vec<i16> = (0..1000).map(|i| i as i16 * 8).collect();
+        let path = write_wav("loop_past_end.wav", 1, 1000, &samples);
+
+        let mut player = StreamingPlayerNode::open_with_buffer(&path, &context, 0.5).unwrap();
+        player.set_loop(true, 2.0, 3.0);
+        player.play();
+        for i in 0..1000u64 {
+            let output = next_frame(&mut player, &context, i);
+            assert_eq!(output.0, (i * 8) as f32 / 32768.0, "Frame {}", i);
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !player.status().finished && Instant::now() < deadline {
+            player.process_stereo(&context, 1000);
+        }
+        assert!(player.status().finished, "{:?}", player.status());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_underruns_and_resampling() {
+        assert!(
+            StreamingPlayerNode::open("/nonexistent/stem.wav", &AudioContext::new(48000.0))
+                .is_err()
+        );
+
+        let context = AudioContext::new(48000.0);
+        let frequency = 440.0f32;
+        let samples: Vec<i16> = (0..44100 * 2)
+            .map(|i| {
+                let phase = 2.0 * std::f32::consts::PI * frequency * i as f32 / 44100.0;
+                (phase.sin() * 0.5 * 32767.0) as i16
+            })
+            .collect();
+        let path = write_wav("underrun.wav", 1, 44100, &samples);
+
+        // The stream arrives at the context rate
+        let mut player = StreamingPlayerNode::open_with_buffer(&path, &context, 0.1).unwrap();
+        player.play();
+        let outputs: Vec<f32> = (0..4800)
+            .map(|i| next_frame(&mut player, &context, i).0)
+            .collect();
+        let max_error = (100..4800)
+            .map(|i| {
+                let phase = 2.0 * std::f32::consts::PI * frequency * i as f32 / 48000.0;
+                (outputs[i] - phase.sin() * 0.5).abs()
+            })
+            .fold(0.0f32, f32::max);
+        assert!(max_error < 1e-3, "Resampled stream error: {}", max_error);
+        assert_eq!(player.status().underruns, 0);
+
+        // With the reader stopped, the buffered audio runs out mid-file. The ring
+        // holds 4800 frames and the reader may push once more as it stops, so
+        // 20000 frames is well past the end of what it had.
+        player.close();
+        for sample in 4800..24800 {
+            player.process_stereo(&context, sample);
+        }
+        let status = player.status();
+        assert_eq!(status.underruns, 1, "{:?}", status);
+        assert!(status.playing && !status.finished);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,160 @@
+mod common;
+
+use common::{amplitude, partial, render, SAMPLE_RATE};
+use cpal_synth::synth::step_sequencer::midi_to_frequency;
+use cpal_synth::{AudioContext, AudioNode, Excitation, StringNode};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Frequency of the partial near `expected`, from how far its phase moves
+    // between two windows `hop` samples apart
+    fn measure_frequency(samples: &[f32], expected: f32, window: usize, hop: usize) -> f32 {
+        let (_, first) = partial(&samples[..window], expected, SAMPLE_RATE);
+        let (_, second) = partial(&samples[hop..hop + window], expected, SAMPLE_RATE);
+        let advance = (second - first) as f64;
+        let nominal =
+            2.0 * std::f64::consts::PI * expected as f64 * hop as f64 / SAMPLE_RATE as f64;
+        let error = (advance - nominal + std::f64::consts::PI)
+            .rem_euclid(2.0 * std::f64::consts::PI)
+            - std::f64::consts::PI;
+        expected + (error * SAMPLE_RATE as f64 / (2.0 * std::f64::consts::PI * hop as f64)) as f32
+    }
+
+    #[test]
+    fn test_tuning_across_keyboard() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        for note in (28..=100).step_by(6) {
+            let frequency = midi_to_frequency(note);
+            let mut string = StringNode::new(Excitation::Noise, &context);
+            string.decay().set_value(10.0);
+            string.note_on(note, 1.0, 0);
+
+            // Long enough windows to resolve the fundamental from its harmonics
+            let window = ((8.0 * SAMPLE_RATE / frequency) as usize).max(4096);
+            let samples = render(&mut string, &context, window + 1024 + 2000);
+            let measured = measure_frequency(&samples[2000..], frequency, window, 1024);
+            let cents = 1200.0 * (measured / frequency).log2();
+            assert!(
+                cents.abs() < 1.0,
+                "note {} is {:.3} cents out ({} Hz against {} Hz)",
+                note,
+                cents,
+                measured,
+                frequency
+            );
+        }
+    }
+
+    #[test]
+    fn test_decay_time() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let mut string = StringNode::new(Excitation::Noise, &context);
+        string.frequency().set_value(220.0);
+        string.decay().set_value(1.0);
+        string.pluck(0, 1.0);
+        let samples = render(&mut string, &context, SAMPLE_RATE as usize);
+
+        // Half the decay time apart the fundamental should have fallen 30dB
+        let window = 4096;
+        let early = amplitude(&samples[4410..4410 + window], 220.0);
+        let late = amplitude(&samples[26460..26460 + window], 220.0);
+        let drop = 20.0 * (late / early).log10();
+        assert!((drop + 30.0).abs() < 2.0, "fell {} dB", drop);
+    }
+
+    #[test]
+    fn test_pick_position_notches_harmonics() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let even_to_odd = |pick_position: f32| {
+            let mut string = StringNode::new(Excitation::Noise, &context);
+            string.frequency().set_value(110.0);
+            string.pick_position().set_value(pick_position);
+            string.damping().set_value(0.0);
+            string.brightness().set_value(1.0);
+            string.pluck(0, 1.0);
+            let samples = render(&mut string, &context, 12000);
+            let window = &samples[2000..10192];
+            let even: f32 = (1..=4).map(|k| amplitude(window, 220.0 * k as f32)).sum();
+            let odd: f32 = (1..=4)
+                .map(|k| amplitude(window, 110.0 * (2 * k - 1) as f32))
+                .sum();
+            even / odd
+        };
+
+        // Plucked in the middle, the even harmonics have a node at the pick point
+        let middle = even_to_odd(0.5);
+        let near_end = even_to_odd(0.1);
+        assert!(middle < 0.1, "even harmonics at {} of the odd", middle);
+        assert!(near_end > 0.3, "even harmonics at {} of the odd", near_end);
+    }
+
+    #[test]
+    fn test_gate_plucks_with_velocity_and_release_damps() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let peak = |samples: &[f32]| samples.iter().fold(0.0f32, |m, x| m.max(x.abs()));
+
+        let mut string = StringNode::new(Excitation::Noise, &context);
+        string.set_parameter("frequency", 330.0);
+        assert_eq!(peak(&render(&mut string, &context, 1000)), 0.0);
+
+        let mut loud = string.clone();
+        loud.set_parameter_at("gate", 1.0, 1000);
+        let mut soft = string.clone();
+        soft.set_parameter_at("gate", 0.3, 1000);
+        let loud = render(&mut loud, &context, 10000);
+        let soft = render(&mut soft, &context, 10000);
+        assert_eq!(peak(&loud[..1000]), 0.0);
+        assert!(peak(&loud[1000..]) > 0.1);
+        assert!(peak(&soft[1000..]) < 0.5 * peak(&loud[1000..]));
+
+        // Closing the gate shortens the ring to the release time
+        let mut held = StringNode::new(Excitation::Noise, &context);
+        let mut released = held.clone();
+        held.note_on(57, 1.0, 0);
+        released.note_on(57, 1.0, 0);
+        released.note_off(4410);
+        let held = render(&mut held, &context, 22050);
+        let released = render(&mut released, &context, 22050);
+        assert!(peak(&released[17640..]) < 0.01 * peak(&held[17640..]));
+    }
+
+    #[test]
+    fn test_plucks_queue_up() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let mut once = StringNode::new(Excitation::Noise, &context);
+        once.frequency().set_value(220.0);
+        let mut twice = once.clone();
+        once.pluck(1000, 1.0);
+        // Scheduled out of order, and neither replaces the other
+        twice.pluck(5000, 1.0);
+        twice.pluck(1000, 1.0);
+
+        let once = render(&mut once, &context, 8000);
+        let twice = render(&mut twice, &context, 8000);
+        assert!(twice[..1000].iter().all(|&x| x == 0.0));
+        assert_eq!(&once[..5000], &twice[..5000]);
+        assert_ne!(&once[5000..], &twice[5000..]);
+    }
+
+    #[test]
+    fn test_bowing_sustains_while_gate_is_open() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let mut string = StringNode::new(Excitation::Bow, &context);
+        string.decay().set_value(0.5);
+        string.note_on(45, 0.8, 0);
+        string.note_off(3 * SAMPLE_RATE as u64);
+        let samples = render(&mut string, &context, 4 * SAMPLE_RATE as usize);
+
+        // Still sounding, at pitch, long after a pluck would have died
+        let window = &samples[2 * SAMPLE_RATE as usize..2 * SAMPLE_RATE as usize + 8192];
+        let fundamental = amplitude(window, 110.0);
+        assert!(fundamental > 0.01, "fundamental at {}", fundamental);
+        assert!(amplitude(window, 165.0) < 0.05 * fundamental);
+
+        // And fades once the bow lifts
+        let tail = &samples[samples.len() - 4096..];
+        assert!(amplitude(tail, 110.0) < 0.01 * fundamental);
+    }
+}
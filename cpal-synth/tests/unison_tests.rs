@@ -0,0 +1,93 @@
+mod common;
+
+use common::{amplitude, SAMPLE_RATE};
+use cpal_synth::{
+    AudioContext, AudioNode, DetuneCurve, OscillatorType, UnisonOscillator, MAX_UNISON_VOICES,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_voice_count_is_clamped() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let mut unison = UnisonOscillator::new(OscillatorType::Sawtooth, 0, &context).unwrap();
+        assert_eq!(unison.voices(), 1);
+        unison.set_voices(40);
+        assert_eq!(unison.voices(), MAX_UNISON_VOICES);
+
+        for sample in 0..1000 {
+            let (left, right) = unison.process_stereo(&context, sample);
+            assert!(left.is_finite() && right.is_finite());
+            assert!(left.abs() < 4.0 && right.abs() < 4.0);
+        }
+    }
+
+    #[test]
+    fn test_detune_curves() {
+        assert_eq!(DetuneCurve::Linear.amount(0.3), 0.3);
+        assert_eq!(DetuneCurve::Linear.amount(2.0), 1.0);
+
+        // Fine at the bottom of the range, steep at the top
+        let supersaw = DetuneCurve::Supersaw;
+        assert!(supersaw.amount(0.0) < 0.01);
+        assert!(supersaw.amount(0.5) < 0.15);
+        assert!((supersaw.amount(1.0) - 1.0).abs() < 1e-3);
+        let mut previous = 0.0;
+        for i in 0..=20 {
+            let amount = supersaw.amount(i as f32 / 20.0);
+            assert!(amount >= previous, "Curve falls at {}", i as f32 / 20.0);
+            previous = amount;
+        }
+    }
+
+    #[test]
+    fn test_detune_spaces_voices() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let mut unison = UnisonOscillator::new(OscillatorType::Sine, 3, &context).unwrap();
+        unison.frequency().set_value(1000.0);
+        unison.detune().set_value(0.5);
+
+        // Three voices at -5.5%, 0 and +5.5%
+        let samples: Vec<f32> = (0..8192)
+            .map(|sample| unison.process(&context, sample))
+            .collect();
+        let voice_level = 1.0 / 3.0f32.sqrt();
+        for frequency in [945.0, 1000.0, 1055.0] {
+            let level = amplitude(&samples, frequency);
+            assert!(
+                (level - voice_level).abs() < 0.02,
+                "Expected a voice at {} Hz, got level {}",
+                frequency,
+                level
+            );
+        }
+        assert!(amplitude(&samples, 1110.0) < 0.01);
+    }
+
+    #[test]
+    fn test_stereo_spread() {
+        let context = AudioContext::new(SAMPLE_RATE);
+        let mut unison = UnisonOscillator::new(OscillatorType::Sine, 2, &context).unwrap();
+        unison.frequency().set_value(1000.0);
+        unison.detune().set_value(1.0);
+
+        // Without spread both voices sit in the centre
+        unison.spread().set_value(0.0);
+        for sample in 0..500 {
+            let (left, right) = unison.process_stereo(&context, sample);
+            assert!((left - right).abs() < 1e-6);
+        }
+
+        // At full spread the lower voice is hard left and the upper hard right
+        unison.spread().set_value(1.0);
+        let (left, right): (Vec<f32>, Vec<f32>) = (500..8692)
+            .map(|sample| unison.process_stereo(&context, sample))
+            .unzip();
+        assert!(amplitude(&left, 890.0) > 0.9);
+        assert!(amplitude(&right, 890.0) < 0.01);
+        assert!(amplitude(&right, 1110.0) > 0.9);
+        assert!(amplitude(&left, 1110.0) < 0.01);
+    }
+}
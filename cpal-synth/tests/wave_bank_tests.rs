@@ -0,0 +1,130 @@
+use cpal_synth::synth::bandlimited_wavetableoscillator::are_wave_banks_initialized;
+use cpal_synth::{
+    initialize_wave_banks, AudioContext, AudioGraph, BandlimitedWavetableOscillator,
+    OscillatorType, PeriodicWave, WaveBankRegistry,
+};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contexts_own_or_share_registries() {
+        let first = AudioContext::new(44100.0);
+        let second = AudioContext::new(44100.0);
+        BandlimitedWavetableOscillator::new(OscillatorType::Sawtooth, &first).unwrap();
+        assert_eq!(first.wave_banks().bank_count(), 1);
+        assert_eq!(second.wave_banks().bank_count(), 0);
+
+        let registry = Arc::new(WaveBankRegistry::new());
+        let shared = AudioContext::with_wave_banks(44100.0, registry.clone());
+        let other = AudioContext::with_wave_banks(44100.0, registry.clone());
+        BandlimitedWavetableOscillator::new(OscillatorType::Sawtooth, &shared).unwrap();
+        BandlimitedWavetableOscillator::new(OscillatorType::Sawtooth, &other).unwrap();
+        assert_eq!(registry.bank_count(), 1);
+    }
+
+    #[test]
+    fn test_realtime_lookup_builds_in_background() {
+        let context = AudioContext::new(48000.0);
+        assert!(
+            BandlimitedWavetableOscillator::try_new(OscillatorType::Square, &context).is_none()
+        );
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let oscillator = loop {
+            if let Some(oscillator) =
+                BandlimitedWavetableOscillator::try_new(OscillatorType::Square, &context)
+            {
+                break oscillator;
+            }
+            assert!(Instant::now() < deadline, "bank never built");
+            std::thread::sleep(Duration::from_millis(5));
+        };
+        assert_eq!(oscillator.sample_rate(), 48000.0);
+        assert!(context
+            .wave_banks()
+            .is_loaded(OscillatorType::Square, 48000.0));
+    }
+
+    #[test]
+    fn test_failed_builds_wait_for_the_wave() {
+        let context = AudioContext::new(48000.0);
+        let wave = PeriodicWave::new(&[0.0, 0.0], &[0.0, 1.0], true).unwrap();
+        let waveform = wave.oscillator_type();
+
+        // Not registered yet, so the build fails and isn't queued again
+        assert!(BandlimitedWavetableOscillator::try_new(waveform, &context).is_none());
+        assert!(BandlimitedWavetableOscillator::new(waveform, &context).is_err());
+        for _ in 0..10 {
+            assert!(BandlimitedWavetableOscillator::try_new(waveform, &context).is_none());
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(context.wave_banks().bank_count(), 0);
+
+        // Registering the wave lets it be built
+        context.wave_banks().register_periodic_wave(&wave);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while BandlimitedWavetableOscillator::try_new(waveform, &context).is_none() {
+            assert!(Instant::now() < deadline, "bank never built");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(context.wave_banks().is_loaded(waveform, 48000.0));
+    }
+
+    #[test]
+    fn test_memory_usage_and_eviction() {
+        let registry = WaveBankRegistry::new();
+        assert_eq!(registry.memory_usage(), 0);
+        registry.load(OscillatorType::Sine, 44100.0).unwrap();
+        let one_bank = registry.memory_usage();
+        assert!(one_bank > 0);
+
+        let context = AudioContext::with_wave_banks(22050.0, Arc::new(registry));
+        let oscillator =
+            BandlimitedWavetableOscillator::new(OscillatorType::Sine, &context).unwrap();
+        let registry = context.wave_banks();
+        registry.load(OscillatorType::Triangle, 22050.0).unwrap();
+        assert_eq!(registry.bank_count(), 3);
+        assert!(registry.memory_usage() > one_bank);
+
+        // Unused banks at other rates go; the current rate is kept
+        assert_eq!(registry.evict_unused_rates(22050.0), 1);
+        assert!(!registry.is_loaded(OscillatorType::Sine, 44100.0));
+        assert!(registry.is_loaded(OscillatorType::Triangle, 22050.0));
+
+        // Banks an oscillator reads are kept at any rate
+        assert_eq!(registry.evict_unused_rates(44100.0), 1);
+        assert!(registry.is_loaded(OscillatorType::Sine, 22050.0));
+        drop(oscillator);
+        assert_eq!(registry.evict_unused_rates(44100.0), 1);
+        assert_eq!(registry.bank_count(), 0);
+        assert_eq!(registry.memory_usage(), 0);
+    }
+
+    #[test]
+    fn test_rate_change_frees_old_banks() {
+        let mut graph = AudioGraph::offline(44100.0);
+        let oscillator =
+            BandlimitedWavetableOscillator::new(OscillatorType::Sine, &graph.context).unwrap();
+        graph.add_node("osc", Box::new(oscillator));
+        graph.set_output("osc");
+
+        graph.set_sample_rate(22050.0).unwrap();
+        let registry = graph.context.wave_banks();
+        assert!(!registry.is_loaded(OscillatorType::Sine, 44100.0));
+        assert!(registry.is_loaded(OscillatorType::Sine, 22050.0));
+        assert_eq!(registry.bank_count(), 1);
+    }
+
+    #[test]
+    fn test_initialize_wave_banks_loads_builtins() {
+        let context = AudioContext::new(32000.0);
+        assert!(!are_wave_banks_initialized(&context));
+        initialize_wave_banks(&context).unwrap();
+        assert!(are_wave_banks_initialized(&context));
+        assert_eq!(context.wave_banks().bank_count(), 5);
+    }
+}
@@ -0,0 +1,191 @@
+use cpal_synth::{
+    load_wavetable, AudioContext, AudioNode, BandlimitedWavetableOscillator, OscillatorType,
+    PeriodicWave, WAVETABLE_FRAME_SIZE,
+};
+use std::f32::consts::PI;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Mono 32-bit float WAV
+    fn wav_bytes(samples: &[f32]) -> Vec<u8> {
+        let data: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let mut file = b"RIFF".to_vec();
+        file.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+        file.extend_from_slice(b"WAVEfmt ");
+        file.extend_from_slice(&16u32.to_le_bytes());
+        file.extend_from_slice(&3u16.to_le_bytes());
+        file.extend_from_slice(&1u16.to_le_bytes());
+        file.extend_from_slice(&44100u32.to_le_bytes());
+        file.extend_from_slice(&(44100u32 * 4).to_le_bytes());
+        file.extend_from_slice(&4u16.to_le_bytes());
+        file.extend_from_slice(&32u16.to_le_bytes());
+        file.extend_from_slice(b"data");
+        file.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        file.extend_from_slice(&data);
+        file
+    }
+
+    fn cycle(len: usize, f: impl Fn(f32) -> f32) -> Vec<f32> {
+        (0..len)
+            .map(|i| f(2.0 * PI * i as f32 / len as f32))
+            .collect()
+    }
+
+    #[test]
+    fn test_multi_frame_import() {
+        let mut samples = cycle(WAVETABLE_FRAME_SIZE, |x| 0.5 * x.sin());
+        samples.extend(cycle(WAVETABLE_FRAME_SIZE, |x| 0.25 * (3.0 * x).cos()));
+        samples.extend(cycle(WAVETABLE_FRAME_SIZE, |x| (2.0 * x).sin()));
+
+        let context = AudioContext::new(44100.0);
+        let wave = context
+            .load_wavetable(&wav_bytes(&samples), WAVETABLE_FRAME_SIZE)
+            .unwrap();
+        assert_eq!(wave.frame_count(), 3);
+        assert!(wave.is_normalized());
+
+        // Normalized to the loudest frame, so the first keeps half scale
+        assert!((wave.scale() - 1.0).abs() < 1e-3);
+        assert!((wave.frame_imag(0)[1] - 0.5).abs() < 1e-4);
+        assert!((wave.frame_real(1)[3] - 0.25).abs() < 1e-4);
+        assert!((wave.frame_imag(2)[2] - 1.0).abs() < 1e-4);
+        assert!(wave.frame_imag(2)[1].abs() < 1e-4);
+
+        // Loading through the context registers the wave with it
+        assert!(context.wave_banks().periodic_wave(wave.id()).is_some());
+        let frequency = 441.0;
+        let mut oscillator =
+            BandlimitedWavetableOscillator::new(wave.oscillator_type(), &context).unwrap();
+        oscillator.frequency().set_value(frequency);
+        for sample in 0..1000u64 {
+            let phase = (sample as f32 * frequency / 44100.0).fract();
+            let expected = 0.5 * (2.0 * PI * phase).sin();
+            let output = oscillator.process(&context, sample);
+            assert!(
+                (output - expected).abs() < 1e-2,
+                "Sample {}: Expected {}, got {}",
+                sample,
+                expected,
+                output
+            );
+        }
+    }
+
+    #[test]
+    fn test_single_cycle_import() {
+        // Shorter than a frame: the whole file is one cycle
+        let samples = cycle(600, |x| x.sin() + 0.3 * (4.0 * x).sin() + 0.2);
+        let wave = load_wavetable(&wav_bytes(&samples), WAVETABLE_FRAME_SIZE).unwrap();
+        assert_eq!(wave.frame_count(), 1);
+        assert_eq!(wave.harmonics(), 299);
+
+        // DC is dropped and the rest peaks at 1.0
+        let peak = samples
+            .iter()
+            .map(|x| (x - 0.2).abs())
+            .fold(0.0f32, f32::max);
+        assert!((wave.scale() - 1.0 / peak).abs() < 1e-4);
+        for (i, sample) in samples.iter().enumerate() {
+            let phase = i as f32 / 600.0;
+            let expected = (sample - 0.2) / peak;
+            assert!((wave.evaluate(phase, usize::MAX) - expected).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_invalid_wavetables() {
+        let samples = vec![0.1f32; WAVETABLE_FRAME_SIZE + 100];
+        assert!(load_wavetable(&wav_bytes(&samples), WAVETABLE_FRAME_SIZE).is_err());
+        assert!(load_wavetable(&wav_bytes(&[]), WAVETABLE_FRAME_SIZE).is_err());
+        assert!(load_wavetable(b"not a wav file", WAVETABLE_FRAME_SIZE).is_err());
+        assert!(PeriodicWave::from_frames::<Vec<f32>>(&[], true).is_err());
+    }
+
+    #[test]
+    fn test_high_notes_are_band_limited() {
+        let samples = cycle(WAVETABLE_FRAME_SIZE, |x| x.sin() + 0.5 * (5.0 * x).sin());
+        let peak = samples.iter().fold(0.0f32, |max, x| max.max(x.abs()));
+        let context = AudioContext::new(44100.0);
+        let wave = context
+            .load_wavetable(&wav_bytes(&samples), WAVETABLE_FRAME_SIZE)
+            .unwrap();
+
+        // At 6kHz the fifth harmonic would be above Nyquist, so only the fundamental plays
+        let frequency = 6000.0;
+        let mut oscillator =
+            BandlimitedWavetableOscillator::new(wave.oscillator_type(), &context).unwrap();
+        oscillator.frequency().set_value(frequency);
+        for sample in 0..500u64 {
+            let phase = (sample as f32 * frequency / 44100.0).fract();
+            let expected = (2.0 * PI * phase).sin() / peak;
+            let output = oscillator.process(&context, sample);
+            assert!(
+                (output - expected).abs() < 2e-2,
+                "Sample {}: Expected {}, got {}",
+                sample,
+                expected,
+                output
+            );
+        }
+    }
+
+    #[test]
+    fn test_position_morphing() {
+        let mut samples = cycle(WAVETABLE_FRAME_SIZE, |x| x.sin());
+        samples.extend(cycle(WAVETABLE_FRAME_SIZE, |x| (2.0 * x).sin()));
+        samples.extend(cycle(WAVETABLE_FRAME_SIZE, |x| (3.0 * x).sin()));
+        let context = AudioContext::new(44100.0);
+        let wave = context
+            .load_wavetable(&wav_bytes(&samples), WAVETABLE_FRAME_SIZE)
+            .unwrap();
+        let frequency = 441.0;
+        let mut oscillator =
+            BandlimitedWavetableOscillator::new(wave.oscillator_type(), &context).unwrap();
+        assert_eq!(oscillator.frame_count(), 3);
+        oscillator.frequency().set_value(frequency);
+
+        // Sweep across all three frames over the first 1000 samples
+        oscillator.position().set_value(0.0);
+        oscillator
+            .position()
+            .linear_ramp_to_value_at_time(1.0, 1000.0 / 44100.0, 0, 44100.0);
+
+        for sample in 0..1200u64 {
+            let phase = (sample as f32 * frequency / 44100.0).fract();
+            let position = oscillator.position().get_value(sample) * 2.0;
+            let frame = (position as usize).min(1);
+            let blend = position - frame as f32;
+            let a = ((frame + 1) as f32 * 2.0 * PI * phase).sin();
+            let b = ((frame + 2) as f32 * 2.0 * PI * phase).sin();
+            let expected = a + (b - a) * blend;
+
+            let output = oscillator.process(&context, sample);
+            assert!(
+                (output - expected).abs() < 2e-2,
+                "Sample {} at position {}: Expected {}, got {}",
+                sample,
+                position,
+                expected,
+                output
+            );
+        }
+    }
+
+    #[test]
+    fn test_built_in_shapes_ignore_position() {
+        let context = AudioContext::new(44100.0);
+        let mut first =
+            BandlimitedWavetableOscillator::new(OscillatorType::Sawtooth, &context).unwrap();
+        let mut second = first.clone();
+        assert_eq!(first.frame_count(), 1);
+        second.set_parameter("position", 1.0);
+        for sample in 0..200 {
+            assert_eq!(
+                first.process(&context, sample),
+                second.process(&context, sample)
+            );
+        }
+    }
+}
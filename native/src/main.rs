@@ -1,10 +1,5 @@
 use cpal_synth::{
-    initialize_wave_banks,
-    AudioGraph,
-    AudioNode, // Added AudioNode
-    AudioProcessor,
-    BandlimitedWavetableOscillator,
-    Oscillator,
+    initialize_wave_banks, AudioGraph, AudioProcessor, BandlimitedWavetableOscillator, Oscillator,
     OscillatorType,
 };
 use std::sync::{Arc, Mutex};
@@ -52,9 +47,10 @@ fn main() -> anyhow::Result<()> {
         // Bandlimited Wavetable Oscillator
         {
             println!("Creating wavetable oscillator...");
-            let wavetable_osc = Arc::new(Mutex::new(BandlimitedWavetableOscillator::new(
-                osc_type, &context,
-            )?));
+            let mut wavetable_osc = BandlimitedWavetableOscillator::new(osc_type, &context)?;
+            // Avoid timbre steps at the octave boundaries during the sweep
+            wavetable_osc.set_mip_crossfade(true);
+            let wavetable_osc = Arc::new(Mutex::new(wavetable_osc));
             let wavetable_gain = Arc::new(Mutex::new(AudioProcessor::new("gain")));
 
             graph.add_node("wavetable_osc", Box::new(wavetable_osc.clone()));
@@ -107,7 +103,7 @@ fn main() -> anyhow::Result<()> {
         // Regular Oscillator
         {
             println!("Creating regular oscillator...");
-            let regular_osc = Arc::new(Mutex::new(Oscillator::new(osc_type)));
+            let regular_osc = Arc::new(Mutex::new(Oscillator::new(osc_type)?));
             let regular_gain = Arc::new(Mutex::new(AudioProcessor::new("gain")));
 
             graph.add_node("regular_osc", Box::new(regular_osc.clone()));
@@ -1,6 +1,6 @@
 use cpal_synth::{
-    initialize_wave_banks, AudioGraph, AudioNode, AudioProcessor, BandlimitedWavetableOscillator,
-    Oscillator, OscillatorType,
+    initialize_wave_banks, AnalyserNode, AnalyserWindow, AudioBuffer, AudioGraph, AudioProcessor,
+    BandlimitedWavetableOscillator, BufferSourceNode, Oscillator, OscillatorType,
 };
 use std::sync::{Arc, Mutex};
 use wasm_bindgen::prelude::*;
@@ -22,11 +22,15 @@ pub fn main_js() -> Result<(), JsValue> {
 #[wasm_bindgen]
 pub struct Handle {
     graph: AudioGraph,
+    #[allow(dead_code)]
     master_gain: Arc<Mutex<AudioProcessor>>,
     wavetable_gain: Option<Arc<Mutex<AudioProcessor>>>,
     regular_gain: Option<Arc<Mutex<AudioProcessor>>>,
     wavetable_osc: Option<Arc<Mutex<BandlimitedWavetableOscillator>>>,
     regular_osc: Option<Arc<Mutex<Oscillator>>>,
+    sample_buffer: Option<Arc<AudioBuffer>>,
+    // Reading side of the analyser on the output
+    analyser: AnalyserNode,
     end_sample: u64, // Track when the current sweep should end
 }
 
@@ -43,7 +47,12 @@ impl Handle {
 
         let master_gain = Arc::new(Mutex::new(AudioProcessor::new("gain")));
         graph.add_node("master_gain", Box::new(master_gain.clone()));
-        graph.set_output("master_gain");
+
+        // The analyser passes the master mix through to the output
+        let analyser = AnalyserNode::new();
+        graph.add_node("analyser", Box::new(analyser.clone()));
+        graph.connect("master_gain", "analyser", "input");
+        graph.set_output("analyser");
         web_sys::console::log_1(&"Master gain node created and set as output".into());
 
         // Set master gain to maximum
@@ -59,6 +68,8 @@ impl Handle {
             regular_gain: None,
             wavetable_osc: None,
             regular_osc: None,
+            sample_buffer: None,
+            analyser,
             end_sample: 0,
         })
     }
@@ -72,6 +83,97 @@ impl Handle {
         Ok(())
     }
 
+    // Decode a WAV, FLAC or Ogg Vorbis file (e.g. a fetched ArrayBuffer) and keep it
+    // for play_sample. Returns the duration in seconds.
+    #[wasm_bindgen]
+    pub fn load_sample(&mut self, bytes: &[u8]) -> Result<f64, JsValue> {
+        let buffer = self
+            .graph
+            .context
+            .decode_audio_data(bytes)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        let duration = buffer.duration();
+        web_sys::console::log_1(
+            &format!(
+                "Loaded sample: {} channels, {:.2}s",
+                buffer.number_of_channels(),
+                duration
+            )
+            .into(),
+        );
+        self.sample_buffer = Some(Arc::new(buffer));
+        Ok(duration)
+    }
+
+    #[wasm_bindgen]
+    pub fn analyser_fft_size(&self) -> usize {
+        self.analyser.fft_size()
+    }
+
+    #[wasm_bindgen]
+    pub fn set_analyser_fft_size(&mut self, fft_size: usize) -> Result<(), JsValue> {
+        self.analyser
+            .set_fft_size(fft_size)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    #[wasm_bindgen]
+    pub fn set_analyser_smoothing(&mut self, value: f32) -> Result<(), JsValue> {
+        self.analyser
+            .set_smoothing_time_constant(value)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    #[wasm_bindgen]
+    pub fn set_analyser_decibel_range(&mut self, min: f32, max: f32) -> Result<(), JsValue> {
+        self.analyser
+            .set_decibel_range(min, max)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    #[wasm_bindgen]
+    pub fn set_analyser_window(&mut self, window: String) -> Result<(), JsValue> {
+        let window = match window.as_str() {
+            "blackman" => AnalyserWindow::Blackman,
+            "blackman-harris" => AnalyserWindow::BlackmanHarris,
+            "hann" => AnalyserWindow::Hann,
+            "rectangular" => AnalyserWindow::Rectangular,
+            _ => return Err(JsValue::from_str("Invalid analyser window")),
+        };
+        self.analyser.set_window(window);
+        Ok(())
+    }
+
+    // These fill a Float32Array or Uint8Array in place, as in Web Audio
+    #[wasm_bindgen]
+    pub fn get_float_frequency_data(&mut self, out: &mut [f32]) {
+        self.analyser.get_float_frequency_data(out);
+    }
+
+    #[wasm_bindgen]
+    pub fn get_byte_frequency_data(&mut self, out: &mut [u8]) {
+        self.analyser.get_byte_frequency_data(out);
+    }
+
+    #[wasm_bindgen]
+    pub fn get_float_time_domain_data(&mut self, out: &mut [f32]) {
+        self.analyser.get_float_time_domain_data(out);
+    }
+
+    #[wasm_bindgen]
+    pub fn play_sample(&mut self) -> Result<(), JsValue> {
+        let buffer = self
+            .sample_buffer
+            .clone()
+            .ok_or_else(|| JsValue::from_str("No sample loaded"))?;
+
+        let mut source = BufferSourceNode::new(Some(buffer));
+        source.start(self.graph.context.current_sample(), 0.0);
+        self.graph.add_node("sample_source", Box::new(source));
+        self.graph.connect("sample_source", "master_gain", "sample");
+        Ok(())
+    }
+
     #[wasm_bindgen]
     pub fn sweep_wavetable(
         &mut self,
@@ -88,10 +190,11 @@ impl Handle {
             "square" => OscillatorType::Square,
             "sawtooth" => OscillatorType::Sawtooth,
             "triangle" => OscillatorType::Triangle,
+            "pulse" => OscillatorType::Pulse,
             _ => return Err(JsValue::from_str("Invalid oscillator type")),
         };
 
-        web_sys::console::log_1(&format!("Creating wavetable oscillator...").into());
+        web_sys::console::log_1(&"Creating wavetable oscillator...".into());
 
         let context = self.graph.context.clone();
         let wavetable_osc = Arc::new(Mutex::new(
@@ -170,12 +273,15 @@ impl Handle {
             "square" => OscillatorType::Square,
             "sawtooth" => OscillatorType::Sawtooth,
             "triangle" => OscillatorType::Triangle,
+            "pulse" => OscillatorType::Pulse,
             _ => return Err(JsValue::from_str("Invalid oscillator type")),
         };
 
         web_sys::console::log_1(&"Creating regular oscillator...".into());
 
-        let regular_osc = Arc::new(Mutex::new(Oscillator::new(osc_type)));
+        let regular_osc =
+            Oscillator::new(osc_type).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        let regular_osc = Arc::new(Mutex::new(regular_osc));
         let regular_gain = Arc::new(Mutex::new(AudioProcessor::new("gain")));
 
         // Calculate exact timing